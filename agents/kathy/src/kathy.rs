@@ -4,7 +4,7 @@ use color_eyre::Result;
 
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use tokio::{sync::Mutex, task::JoinHandle, time::sleep};
+use tokio::{sync::Mutex, task::JoinHandle};
 use tracing::instrument::Instrumented;
 use tracing::{info, Instrument};
 
@@ -84,6 +84,7 @@ impl NomadAgent for Kathy {
     fn run(channel: Self::Channel) -> Instrumented<JoinHandle<Result<()>>> {
         tokio::spawn(async move {
             let home = channel.home();
+            let shutdown = channel.shutdown();
             let destination = channel.replica().local_domain();
             let mut generator = channel.generator;
             let home_lock = channel.home_lock;
@@ -122,7 +123,10 @@ impl NomadAgent for Kathy {
                     }
                 }
 
-                sleep(Duration::from_secs(interval)).await;
+                if shutdown.sleep(Duration::from_secs(interval)).await {
+                    info!("Shutdown triggered. Stopping message dispatch.");
+                    return Ok(());
+                }
             }
         })
         .in_current_span()
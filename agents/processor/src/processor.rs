@@ -1,27 +1,27 @@
 use async_trait::async_trait;
 use color_eyre::{eyre::bail, Result};
 use ethers::prelude::H256;
-use futures_util::future::select_all;
 use std::{
     collections::{HashMap, HashSet},
     sync::Arc,
     time::Duration,
 };
-use tokio::{sync::RwLock, task::JoinHandle, time::sleep};
+use tokio::{sync::RwLock, task::JoinHandle};
 use tracing::{debug, error, info, info_span, instrument, instrument::Instrumented, Instrument};
 
 use nomad_base::{
-    cancel_task, decl_agent, decl_channel, AgentCore, CachingHome, CachingReplica, NomadAgent,
-    NomadDB, ProcessorError,
+    decl_agent, decl_channel, AgentCore, CachingHome, CachingReplica, NomadAgent, NomadDB,
+    ProcessorError, ShutdownSignal,
 };
 use nomad_core::{
-    accumulator::merkle::Proof, CommittedMessage, Common, Home, HomeEvents, MessageStatus,
+    accumulator::merkle::Proof, Common, Home, HomeEvents, MessageStatus, NomadMessageRef,
 };
 
 use crate::{
     prover_sync::ProverSync,
+    prune::{now, Pruner},
     push::Pusher,
-    settings::{ProcessorSettings as Settings, S3Config},
+    settings::{ProcessorSettings as Settings, RetentionConfig, S3Config},
 };
 
 const AGENT_NAME: &str = "processor";
@@ -43,6 +43,7 @@ pub(crate) struct Replica {
     allowed: Option<Arc<HashSet<H256>>>,
     denied: Option<Arc<HashSet<H256>>>,
     next_message_nonce: prometheus::IntGauge,
+    shutdown: ShutdownSignal,
 }
 
 impl std::fmt::Display for Replica {
@@ -91,6 +92,19 @@ impl Replica {
                 );
 
                 loop {
+                    // Stop between messages, after the nonce of the last
+                    // processed message was stored
+                    if self.shutdown.is_triggered() {
+                        info!(
+                            replica_domain,
+                            nonce = next_message_nonce,
+                            "Shutdown triggered. Stopping processor for {} at nonce {}",
+                            self.replica.name(),
+                            next_message_nonce
+                        );
+                        return Ok(());
+                    }
+
                     let seq_span = tracing::trace_span!(
                         "ReplicaProcessor",
                         name = self.replica.name(),
@@ -122,7 +136,7 @@ impl Replica {
                                 next_message_nonce,
                                 replica_domain,
                             );
-                            sleep(Duration::from_secs(self.interval)).await
+                            self.shutdown.sleep(Duration::from_secs(self.interval)).await;
                         }
                         Err(e) => {
                             error!("fatal error in processor::Replica: {}", e);
@@ -147,7 +161,9 @@ impl Replica {
     async fn try_msg_by_domain_and_nonce(&self, domain: u32, nonce: u32) -> Result<Flow> {
         use nomad_core::Replica;
 
-        let message = match self.home.message_by_nonce(domain, nonce).await {
+        // Messages are viewed in place. An owned copy is only made to submit
+        // them to the replica.
+        let raw = match self.home.raw_message_by_nonce(domain, nonce).await {
             Ok(Some(m)) => m,
             Ok(None) => {
                 info!(
@@ -161,9 +177,11 @@ impl Replica {
             }
             Err(e) => bail!(e),
         };
+        let message = raw.message_ref()?;
+        let leaf = message.to_leaf();
 
-        info!(target: "seen_committed_messages", leaf_index = message.leaf_index);
-        let sender = message.message.sender;
+        info!(target: "seen_committed_messages", leaf_index = raw.leaf_index);
+        let sender = message.sender();
 
         // if we have an allow list, filter senders not on it
         if let Some(false) = self.allowed.as_ref().map(|set| set.contains(&sender)) {
@@ -191,12 +209,18 @@ impl Replica {
             return Ok(Flow::Advance);
         }
 
-        let proof = match self.db.proof_by_leaf_index(message.leaf_index) {
+        let proof = match self.db.proof_by_leaf_index(raw.leaf_index) {
             Ok(Some(p)) => p,
+            // Pruned under the retention policy
+            Ok(None) if self.db.is_pruned(raw.leaf_index)? => {
+                let proof = self.db.regenerate_proof(raw.leaf_index)?;
+                self.db.store_proof(raw.leaf_index, &proof)?;
+                proof
+            }
             Ok(None) => {
                 info!(
-                    leaf_hash = ?message.to_leaf(),
-                    leaf_index = message.leaf_index,
+                    leaf_hash = ?leaf,
+                    leaf_index = raw.leaf_index,
                     "Proof not yet found"
                 );
                 return Ok(Flow::Repeat);
@@ -204,74 +228,89 @@ impl Replica {
             Err(e) => bail!(e),
         };
 
-        if proof.leaf != message.to_leaf() {
+        if proof.leaf != leaf {
             bail!(ProcessorError::ProverConflictError {
-                index: message.leaf_index,
-                calculated_leaf: message.to_leaf(),
+                index: raw.leaf_index,
+                calculated_leaf: leaf,
                 proof_leaf: proof.leaf,
             });
         }
 
         while !self.replica.acceptable_root(proof.root()).await? {
             info!(
-                leaf_hash = ?message.to_leaf(),
-                leaf_index = message.leaf_index,
+                leaf_hash = ?leaf,
+                leaf_index = raw.leaf_index,
                 "Proof under {root} not yet valid here, waiting until Replica confirms",
                 root = proof.root(),
             );
-            sleep(Duration::from_secs(self.interval)).await;
+            if self
+                .shutdown
+                .sleep(Duration::from_secs(self.interval))
+                .await
+            {
+                return Ok(Flow::Repeat);
+            }
         }
 
         info!(
-            leaf_hash = ?message.to_leaf(),
-            leaf_index = message.leaf_index,
+            leaf_hash = ?leaf,
+            leaf_index = raw.leaf_index,
             "Dispatching a message for processing {}:{}",
             domain,
             nonce
         );
 
-        self.process(message, proof).await?;
+        self.process(raw.leaf_index, message, proof).await?;
 
         Ok(Flow::Advance)
     }
 
-    #[instrument(err, level = "trace", skip(self), fields(self = %self))]
+    #[instrument(err, level = "trace", skip(self, message), fields(self = %self, message = %message))]
     /// Dispatch a message for processing. If the message is already proven, process only.
-    async fn process(&self, message: CommittedMessage, proof: Proof) -> Result<()> {
+    async fn process(
+        &self,
+        leaf_index: u32,
+        message: NomadMessageRef<'_>,
+        proof: Proof,
+    ) -> Result<()> {
         use nomad_core::Replica;
-        let status = self.replica.message_status(message.to_leaf()).await?;
+        let leaf = message.to_leaf();
+        let status = self.replica.message_status(leaf).await?;
 
         match status {
             MessageStatus::None => {
                 self.replica
-                    .prove_and_process(message.as_ref(), &proof)
+                    .prove_and_process(&message.to_message(), &proof)
                     .await?;
             }
             MessageStatus::Proven => {
-                self.replica.process(message.as_ref()).await?;
+                self.replica.process(&message.to_message()).await?;
             }
             MessageStatus::Processed => {
                 info!(
-                    domain = message.message.destination,
-                    nonce = message.message.nonce,
-                    leaf_index = message.leaf_index,
-                    leaf = ?message.message.to_leaf(),
+                    domain = message.destination(),
+                    nonce = message.nonce(),
+                    leaf_index,
+                    leaf = ?leaf,
                     "Message {}:{} already processed",
-                    message.message.destination,
-                    message.message.nonce
+                    message.destination(),
+                    message.nonce()
                 )
             }
         };
 
+        // Proofs of processed messages may be pruned after the retention age
+        self.db.store_processed(leaf_index, now())?;
+
         info!(
-            domain = message.message.destination,
-            nonce = message.message.nonce,
-            leaf_index = message.leaf_index,
-            leaf = ?message.message.to_leaf(),
+            domain = message.destination(),
+            nonce = message.nonce(),
+            leaf_index,
+            leaf = ?leaf,
             "Processed message. Destination: {}. Nonce: {}. Leaf index: {}.",
-            message.message.destination,
-            message.message.nonce,
-            message.leaf_index,
+            message.destination(),
+            message.nonce(),
+            leaf_index,
         );
 
         Ok(())
@@ -288,6 +327,7 @@ decl_agent!(
         index_only: bool,
         next_message_nonces: prometheus::IntGaugeVec,
         config: Option<S3Config>,
+        retention: Option<RetentionConfig>,
     }
 );
 
@@ -300,6 +340,7 @@ impl Processor {
         denied: Option<HashSet<H256>>,
         index_only: bool,
         config: Option<S3Config>,
+        retention: Option<RetentionConfig>,
     ) -> Self {
         let next_message_nonces = core
             .metrics
@@ -310,6 +351,30 @@ impl Processor {
             )
             .expect("processor metric already registered -- should have be a singleton");
 
+        let db = NomadDB::new(core.home.name(), core.db.clone());
+        let replicas: Vec<(String, u32)> = core
+            .replicas
+            .iter()
+            .map(|(name, replica)| {
+                (
+                    name.clone(),
+                    nomad_core::Replica::local_domain(replica.as_ref()),
+                )
+            })
+            .collect();
+        core.status.add_section("nextNonces", move || {
+            let next_nonces = replicas
+                .iter()
+                .map(|(name, domain)| {
+                    let nonce: Option<u32> = db.retrieve_keyed_decodable(CURRENT_NONCE, domain)?;
+                    let next_nonce = nonce.map(|n| n + 1).unwrap_or_default();
+                    Ok((name.clone(), serde_json::Value::from(next_nonce)))
+                })
+                .collect::<Result<serde_json::Map<String, serde_json::Value>>>()
+                .map(serde_json::Value::Object);
+            std::future::ready(next_nonces)
+        });
+
         Self {
             interval,
             core,
@@ -319,6 +384,7 @@ impl Processor {
             next_message_nonces,
             index_only,
             config,
+            retention,
         }
     }
 }
@@ -350,6 +416,7 @@ impl NomadAgent for Processor {
             settings.denied,
             settings.indexon.is_some(),
             settings.s3,
+            settings.retention,
         ))
     }
 
@@ -374,6 +441,7 @@ impl NomadAgent for Processor {
                 replica: channel.replica(),
                 home: channel.home(),
                 db: channel.db(),
+                shutdown: channel.shutdown(),
                 allowed: channel.allowed,
                 denied: channel.denied,
                 next_message_nonce: channel.next_message_nonce,
@@ -397,10 +465,10 @@ impl NomadAgent for Processor {
             info!("Starting ProverSync");
             let db = NomadDB::new(self.home().name().to_owned(), self.db());
             let sync = ProverSync::from_disk(db.clone());
-            let prover_sync_task = sync.spawn();
+            let prover_sync_task = sync.spawn(self.shutdown());
 
             info!("Starting indexer");
-            let home_sync_task = self.home().sync();
+            let home_sync_task = self.home().sync(self.shutdown());
 
             let home_fail_watch_task = self.watch_home_fail(self.interval);
 
@@ -425,18 +493,20 @@ impl NomadAgent for Processor {
                         config.region.parse().expect("invalid s3 region"),
                         db.clone(),
                     )
-                    .spawn(),
+                    .spawn(self.shutdown()),
                 )
             }
 
-            // find the first task to shut down. Then cancel all others
-            debug!(tasks = tasks.len(), "Selecting across Processor tasks");
-            let (res, _, remaining) = select_all(tasks).await;
-            for task in remaining.into_iter() {
-                cancel_task!(task);
+            // if we have a retention policy, add a task to enforce it
+            if let Some(retention) = &self.retention {
+                info!(age = %retention.age, bodies = retention.bodies, "Starting proof pruning task");
+                tasks.push(Pruner::new(db.clone(), retention).spawn(self.shutdown()));
             }
 
-            res?
+            // find the first task to shut down. Then cancel all others, or
+            // wait for them to stop if shutting down
+            debug!(tasks = tasks.len(), "Selecting across Processor tasks");
+            self.shutdown().select_all(tasks).await
         })
         .instrument(info_span!("Processor::run_all"))
     }
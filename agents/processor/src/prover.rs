@@ -36,7 +36,6 @@ pub enum ProverError {
     MerkleTreeError(#[from] MerkleTreeError),
     /// Failed proof verification
     #[error("Proof verification failed. Root is {expected}, produced is {actual}")]
-    #[allow(dead_code)]
     VerificationFailed {
         /// The expected root (this tree's current root)
         expected: H256,
@@ -94,7 +93,6 @@ impl Prover {
     }
 
     /// Verify a proof against this tree's root.
-    #[allow(dead_code)]
     pub fn verify(&self, proof: &Proof) -> Result<(), ProverError> {
         let actual = merkle_root_from_branch(proof.leaf, &proof.path, TREE_DEPTH, proof.index);
         let expected = self.root();
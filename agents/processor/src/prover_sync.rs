@@ -1,13 +1,10 @@
 use crate::prover::{Prover, ProverError};
 use color_eyre::eyre::{bail, Result};
 use ethers::core::types::H256;
-use nomad_base::NomadDB;
+use nomad_base::{NomadDB, ShutdownSignal};
 use nomad_core::{accumulator::INITIAL_ROOT, db::DbError, ChainCommunicationError};
-use std::{fmt::Display, time::Duration};
-use tokio::{
-    task::JoinHandle,
-    time::{sleep, timeout},
-};
+use std::{collections::HashSet, fmt::Display, time::Duration};
+use tokio::{task::JoinHandle, time::timeout};
 use tracing::{debug, error, info, info_span, instrument, instrument::Instrumented, Instrument};
 
 /// Struct to sync prover.
@@ -82,6 +79,8 @@ impl ProverSync {
     fn store_proof(&self, leaf_index: u32) -> Result<(), ProverSyncError> {
         match self.prover.prove(leaf_index as usize) {
             Ok(proof) => {
+                // Never store a proof the processor would submit in vain
+                self.prover.verify(&proof)?;
                 self.db.store_proof(leaf_index, &proof)?;
                 info!(
                     leaf_index,
@@ -99,45 +98,62 @@ impl ProverSync {
         }
     }
 
-    /// Given rocksdb handle `db` containing merkle tree leaves,
-    /// instantiates new `ProverSync` and fills prover's merkle tree
-    #[instrument(level = "debug", skip(db))]
-    pub fn from_disk(db: NomadDB) -> Self {
-        // Ingest all leaves in db into prover tree
+    /// Fill a new prover's merkle tree with the leaves in `db`, up to the
+    /// prover's latest committed root
+    fn prover_from_disk(db: &NomadDB) -> Result<Prover, DbError> {
         let mut prover = Prover::default();
 
-        if let Some(root) = db.retrieve_prover_latest_committed().expect("db error") {
-            for i in 0.. {
-                match db.leaf_by_leaf_index(i) {
-                    Ok(Some(leaf)) => {
-                        debug!(leaf_index = i, "Ingesting leaf from_disk");
-                        prover.ingest(leaf).expect("!tree full");
-                        if prover.root() == root {
-                            break;
-                        }
-                    }
-                    Ok(None) => break,
-                    Err(e) => {
-                        error!(error = %e, "Error in ProverSync::from_disk");
-                        panic!("Error in ProverSync::from_disk");
-                    }
+        if let Some(root) = db.retrieve_prover_latest_committed()? {
+            for res in db.leaves_by_leaf_index(..) {
+                let (leaf_index, leaf) = res?;
+                // Stop at the first missing leaf
+                if leaf_index as usize != prover.count() {
+                    break;
+                }
+
+                debug!(leaf_index, "Ingesting leaf from_disk");
+                prover.ingest(leaf).expect("!tree full");
+                if prover.root() == root {
+                    break;
                 }
             }
             info!(target_latest_root = ?root, root = ?prover.root(), "Reloaded ProverSync from disk");
         }
 
-        let sync = Self { prover, db };
+        Ok(prover)
+    }
 
-        // Ensure proofs exist for all leaves
-        for i in 0..sync.prover.count() as u32 {
-            match (
-                sync.db.leaf_by_leaf_index(i).expect("db error"),
-                sync.db.proof_by_leaf_index(i).expect("db error"),
-            ) {
-                (Some(_), None) => sync.store_proof(i).expect("db error"),
-                (None, _) => break,
-                _ => {}
+    /// Given rocksdb handle `db` containing merkle tree leaves,
+    /// instantiates new `ProverSync` and fills prover's merkle tree
+    #[instrument(level = "debug", skip(db))]
+    pub fn from_disk(db: NomadDB) -> Self {
+        // Ingest all leaves in db into prover tree
+        let prover = match Self::prover_from_disk(&db) {
+            Ok(prover) => prover,
+            Err(e) => {
+                error!(error = %e, "Error in ProverSync::from_disk");
+                panic!("Error in ProverSync::from_disk");
             }
+        };
+
+        let sync = Self { prover, db };
+
+        // Ensure proofs exist for all leaves, except those pruned under the
+        // retention policy
+        let count = sync.prover.count() as u32;
+        let proven = sync
+            .db
+            .proofs_by_leaf_index(..count)
+            .map(|res| res.map(|(leaf_index, _)| leaf_index))
+            .chain(
+                sync.db
+                    .pruned_by_leaf_index(..count)
+                    .map(|res| res.map(|(leaf_index, _)| leaf_index)),
+            )
+            .collect::<Result<HashSet<_>, _>>()
+            .expect("db error");
+        for i in (0..count).filter(|i| !proven.contains(i)) {
+            sync.store_proof(i).expect("db error");
         }
 
         sync
@@ -178,8 +194,9 @@ impl ProverSync {
     /// Consume self and poll for signed updates at regular interval. Update
     /// local merkle tree with all leaves between local root and
     /// new root. Use short interval for bootup syncing and longer
-    /// interval for regular polling.
-    pub fn spawn(mut self) -> Instrumented<JoinHandle<Result<()>>> {
+    /// interval for regular polling. Stops between updates once shutdown is
+    /// triggered.
+    pub fn spawn(mut self, shutdown: ShutdownSignal) -> Instrumented<JoinHandle<Result<()>>> {
         let span = info_span!("ProverSync", self = %self);
         tokio::spawn(async move {
             loop {
@@ -229,7 +246,9 @@ impl ProverSync {
                 }
 
                 // kludge
-                sleep(Duration::from_millis(100)).await;
+                if shutdown.sleep(Duration::from_millis(100)).await {
+                    return Ok(());
+                }
             }
         })
         .instrument(span)
@@ -0,0 +1,148 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use color_eyre::Result;
+use tokio::task::JoinHandle;
+use tracing::{info, info_span, instrument::Instrumented, Instrument};
+
+use nomad_base::{NomadDB, ShutdownSignal};
+
+use crate::settings::RetentionConfig;
+
+/// Prunes the proofs of processed messages once they are older than the
+/// retention age
+#[derive(Debug)]
+pub struct Pruner {
+    db: NomadDB,
+    age: Duration,
+    interval: Duration,
+    bodies: bool,
+}
+
+/// Current unix timestamp in seconds
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("!timestamp")
+        .as_secs()
+}
+
+impl Pruner {
+    /// Instantiate a new pruner enforcing `config`
+    pub fn new(db: NomadDB, config: &RetentionConfig) -> Self {
+        Self {
+            db,
+            age: Duration::from_secs(config.age.parse().expect("invalid integer")),
+            interval: Duration::from_secs(config.interval.parse().expect("invalid integer")),
+            bodies: config.bodies,
+        }
+    }
+
+    /// Prune every message processed at or before `cutoff` (unix timestamp in
+    /// seconds). Returns the number of messages pruned.
+    fn prune(&self, cutoff: u64) -> Result<usize> {
+        let expired = self
+            .db
+            .processed_by_leaf_index(..)
+            .filter(|res| !matches!(res, Ok((_, processed_at)) if *processed_at > cutoff))
+            .map(|res| res.map(|(leaf_index, _)| leaf_index))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for leaf_index in expired.iter() {
+            self.db.prune_message(*leaf_index, self.bodies)?;
+        }
+        Ok(expired.len())
+    }
+
+    /// Spawn the pruner task and return a joinhandle
+    ///
+    /// The pruner task prunes expired messages every interval until shutdown
+    /// is triggered
+    pub fn spawn(self, shutdown: ShutdownSignal) -> Instrumented<JoinHandle<Result<()>>> {
+        let span = info_span!(
+            "ProofPruner",
+            age = self.age.as_secs(),
+            bodies = self.bodies,
+        );
+        tokio::spawn(async move {
+            loop {
+                let cutoff = now().saturating_sub(self.age.as_secs());
+                let pruned = self.prune(cutoff)?;
+                if pruned > 0 {
+                    info!(pruned, cutoff, "Pruned proofs of processed messages");
+                }
+
+                if shutdown.sleep(self.interval).await {
+                    return Ok(());
+                }
+            }
+        })
+        .instrument(span)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::core::types::H256;
+    use nomad_core::{
+        accumulator::merkle::Proof, db::DB, Encode, MessageVersion, NomadMessage,
+        RawCommittedMessage,
+    };
+
+    #[test]
+    fn it_prunes_expired_proofs() {
+        let db = NomadDB::new("home_1", DB::in_memory());
+        for leaf_index in 0..3 {
+            let message = RawCommittedMessage {
+                leaf_index,
+                committed_root: H256::zero(),
+                version: MessageVersion::V0,
+                message: NomadMessage {
+                    origin: 1,
+                    sender: H256::repeat_byte(1),
+                    nonce: leaf_index,
+                    destination: 2,
+                    recipient: H256::repeat_byte(2),
+                    body: vec![1, 2, 3],
+                    ..Default::default()
+                }
+                .to_vec(),
+            };
+            db.store_latest_message(&message).unwrap();
+            let proof = Proof {
+                leaf: message.leaf(),
+                index: leaf_index as usize,
+                path: Default::default(),
+            };
+            db.store_proof(leaf_index, &proof).unwrap();
+        }
+        // Leaf 2 is not processed yet
+        db.store_processed(0, 100).unwrap();
+        db.store_processed(1, 200).unwrap();
+
+        let pruner = Pruner {
+            db: db.clone(),
+            age: Duration::from_secs(0),
+            interval: Duration::from_secs(0),
+            bodies: true,
+        };
+        assert_eq!(pruner.prune(150).unwrap(), 1);
+
+        assert!(db.is_pruned(0).unwrap());
+        assert!(db.proof_by_leaf_index(0).unwrap().is_none());
+        assert!(db.message_by_leaf_index(0).unwrap().is_none());
+        assert!(db.leaf_by_leaf_index(0).unwrap().is_some());
+        assert!(db.proof_by_leaf_index(1).unwrap().is_some());
+
+        // Pruned bodies are skipped
+        let remaining: Vec<_> = db
+            .messages_by_leaf_index(..)
+            .map(|res| res.unwrap().leaf_index)
+            .collect();
+        assert_eq!(remaining, vec![1, 2]);
+
+        assert_eq!(pruner.prune(200).unwrap(), 1);
+        assert_eq!(pruner.prune(200).unwrap(), 0);
+        assert!(db.proof_by_leaf_index(2).unwrap().is_some());
+    }
+}
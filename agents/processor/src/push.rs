@@ -6,10 +6,10 @@ use rusoto_s3::{GetObjectError, GetObjectRequest, PutObjectRequest, S3Client, S3
 
 use color_eyre::eyre::{bail, eyre, Result};
 
-use nomad_base::NomadDB;
+use nomad_base::{NomadDB, ShutdownSignal};
 
 use nomad_core::accumulator::merkle::Proof;
-use tokio::{task::JoinHandle, time::sleep};
+use tokio::task::JoinHandle;
 use tracing::{debug, info, info_span, instrument::Instrumented, Instrument};
 
 static AWS_S3_PREFIX: &str = "OPT_PROCESSOR_S3";
@@ -106,8 +106,8 @@ impl Pusher {
     /// Spawn the pusher task and return a joinhandle
     ///
     /// The pusher task polls the DB for new proofs and attempts to push them
-    /// to an S3 bucket
-    pub fn spawn(self) -> Instrumented<JoinHandle<Result<()>>> {
+    /// to an S3 bucket until shutdown is triggered
+    pub fn spawn(self, shutdown: ShutdownSignal) -> Instrumented<JoinHandle<Result<()>>> {
         let span = info_span!(
             "ProofPusher",
             bucket = %self.bucket,
@@ -116,7 +116,7 @@ impl Pusher {
         );
         tokio::spawn(async move {
             let mut index = 0;
-            loop {
+            while !shutdown.is_triggered() {
                 let proof = self.db.proof_by_leaf_index(index)?;
                 match proof {
                     Some(proof) => {
@@ -134,9 +134,18 @@ impl Pusher {
 
                         index += 1;
                     }
-                    None => sleep(Duration::from_millis(500)).await,
+                    // Pruned under the retention policy before it was pushed
+                    None if self.db.is_pruned(index)? => {
+                        debug!(leaf_index = index, "Skipping pruned proof");
+                        index += 1;
+                    }
+                    None => {
+                        shutdown.sleep(Duration::from_millis(500)).await;
+                    }
                 }
             }
+
+            Ok(())
         })
         .instrument(span)
     }
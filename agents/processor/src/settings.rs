@@ -11,6 +11,19 @@ pub struct S3Config {
     pub region: String,
 }
 
+/// Retention policy for the proofs of processed messages
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionConfig {
+    /// Seconds after a message was processed before its proof is pruned
+    pub age: String,
+    /// The pruning interval (in seconds)
+    pub interval: String,
+    /// Prune message bodies along with proofs
+    #[serde(default)]
+    pub bodies: bool,
+}
+
 decl_settings!(Processor {
     /// The polling interval (in seconds)
     interval: String,
@@ -22,4 +35,6 @@ decl_settings!(Processor {
     indexon: Option<String>,
     /// An amazon aws s3 bucket to push proofs to
     s3: Option<S3Config>,
+    /// Prune proofs of processed messages. Proofs are kept forever if unset
+    retention: Option<RetentionConfig>,
 });
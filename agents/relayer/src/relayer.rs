@@ -1,10 +1,12 @@
 use async_trait::async_trait;
 use color_eyre::Result;
 use std::{sync::Arc, time::Duration};
-use tokio::{sync::Mutex, task::JoinHandle, time::sleep};
+use tokio::{sync::Mutex, task::JoinHandle};
 use tracing::{info, instrument::Instrumented, Instrument};
 
-use nomad_base::{decl_agent, decl_channel, AgentCore, CachingHome, CachingReplica, NomadAgent};
+use nomad_base::{
+    decl_agent, decl_channel, AgentCore, CachingHome, CachingReplica, NomadAgent, ShutdownSignal,
+};
 use nomad_core::{Common, CommonEvents};
 
 use crate::settings::RelayerSettings as Settings;
@@ -90,11 +92,13 @@ impl UpdatePoller {
         Ok(())
     }
 
-    fn spawn(self) -> JoinHandle<Result<()>> {
+    fn spawn(self, shutdown: ShutdownSignal) -> JoinHandle<Result<()>> {
         tokio::spawn(async move {
             loop {
                 self.poll_and_relay_update().await?;
-                sleep(Duration::from_secs(self.interval)).await;
+                if shutdown.sleep(Duration::from_secs(self.interval)).await {
+                    return Ok(());
+                }
             }
         })
     }
@@ -165,13 +169,14 @@ impl NomadAgent for Relayer {
     #[tracing::instrument]
     fn run(channel: Self::Channel) -> Instrumented<JoinHandle<Result<()>>> {
         tokio::spawn(async move {
+            let shutdown = channel.shutdown();
             let update_poller = UpdatePoller::new(
                 channel.home(),
                 channel.replica(),
                 channel.interval,
                 channel.updates_relayed_count,
             );
-            update_poller.spawn().await?
+            update_poller.spawn(shutdown).await?
         })
         .in_current_span()
     }
@@ -276,6 +281,8 @@ mod test {
                 metrics,
                 indexer: IndexSettings::default(),
                 settings,
+                status: Default::default(),
+                shutdown: Default::default(),
             };
 
             let agent = Relayer::new(2, core);
@@ -3,9 +3,9 @@ use prometheus::IntCounter;
 use std::{sync::Arc, time::Duration};
 
 use color_eyre::Result;
-use nomad_base::{CachingHome, NomadDB, UpdaterError};
+use nomad_base::{CachingHome, NomadDB, ShutdownSignal, UpdaterError};
 use nomad_core::{Common, Home, SignedUpdate, Signers};
-use tokio::{task::JoinHandle, time::sleep};
+use tokio::task::JoinHandle;
 use tracing::{debug, error, info, info_span, instrument::Instrumented, Instrument};
 
 #[derive(Debug)]
@@ -72,12 +72,17 @@ impl UpdateProducer {
     /// Note that all data retrieved from either contract calls or the
     /// updater's db are confirmed state in the chain, as both indexed data and
     /// contract state are retrieved with a timelag.
-    pub(crate) fn spawn(self) -> Instrumented<JoinHandle<Result<()>>> {
+    ///
+    /// Stops before producing the next update once shutdown is triggered.
+    pub(crate) fn spawn(self, shutdown: ShutdownSignal) -> Instrumented<JoinHandle<Result<()>>> {
         let span = info_span!("UpdateProducer");
         tokio::spawn(async move {
             loop {
                 // We sleep at the top to make continues work fine
-                sleep(Duration::from_secs(self.interval_seconds)).await;
+                if shutdown.sleep(Duration::from_secs(self.interval_seconds)).await {
+                    info!("Shutdown triggered. Stopping update production.");
+                    return Ok(());
+                }
 
                 // Get home indexer's latest seen update from home. This call 
                 // will only return a root from an update that is confirmed in 
@@ -1,12 +1,12 @@
 use std::sync::Arc;
 
-use nomad_base::{CachingHome, NomadDB};
+use nomad_base::{CachingHome, NomadDB, ShutdownSignal};
 use nomad_core::Common;
 use prometheus::IntCounter;
 use std::time::Duration;
 
 use color_eyre::Result;
-use tokio::{task::JoinHandle, time::sleep};
+use tokio::task::JoinHandle;
 use tracing::{info, info_span, instrument::Instrumented, Instrument};
 
 pub(crate) struct UpdateSubmitter {
@@ -31,7 +31,9 @@ impl UpdateSubmitter {
         }
     }
 
-    pub(crate) fn spawn(self) -> Instrumented<JoinHandle<Result<()>>> {
+    /// Spawn the updater's submit task. Stops before submitting the next
+    /// update once shutdown is triggered.
+    pub(crate) fn spawn(self, shutdown: ShutdownSignal) -> Instrumented<JoinHandle<Result<()>>> {
         let span = info_span!("UpdateSubmitter");
 
         tokio::spawn(async move {
@@ -39,7 +41,13 @@ impl UpdateSubmitter {
             let mut committed_root = self.home.committed_root().await?;
 
             loop {
-                sleep(Duration::from_secs(self.interval_seconds)).await;
+                if shutdown
+                    .sleep(Duration::from_secs(self.interval_seconds))
+                    .await
+                {
+                    info!("Shutdown triggered. Stopping update submission.");
+                    return Ok(());
+                }
 
                 // if we have produced an update building off the committed root
                 // submit it
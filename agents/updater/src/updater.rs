@@ -3,7 +3,6 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use color_eyre::{eyre::ensure, Result};
 use ethers::{signers::Signer, types::Address};
-use futures_util::future::select_all;
 use prometheus::IntCounter;
 use tokio::task::JoinHandle;
 use tracing::{info, instrument::Instrumented, Instrument};
@@ -54,6 +53,26 @@ impl Updater {
             .expect("failed to register submitted_update_count")
             .with_label_values(&[home_name, Self::AGENT_NAME]);
 
+        let home = core.home.clone();
+        let db = NomadDB::new(home_name, core.db.clone());
+        core.status.add_section("latestProducedUpdate", move || {
+            let home = home.clone();
+            let db = db.clone();
+            async move {
+                // Follow produced updates from the committed root
+                let mut root = home.committed_root().await?;
+                let mut latest = None;
+                while let Some(update) = db.retrieve_produced_update(root)? {
+                    if update.update.new_root == root {
+                        break;
+                    }
+                    root = update.update.new_root;
+                    latest = Some(update);
+                }
+                Ok::<_, color_eyre::Report>(serde_json::to_value(latest)?)
+            }
+        });
+
         Self {
             signer: Arc::new(signer),
             interval_seconds,
@@ -123,6 +142,7 @@ impl NomadAgent for Updater {
 
         let fail_check = self.assert_home_not_failed();
         let home_fail_watch_task = self.watch_home_fail(self.interval_seconds);
+        let shutdown = self.shutdown();
 
         tokio::spawn(async move {
             fail_check.await??;
@@ -136,25 +156,21 @@ impl NomadAgent for Updater {
             );
 
             info!("Spawning sync task for updater...");
-            let sync_task = home.sync();
+            let sync_task = home.sync(shutdown.clone());
 
             // Only spawn updater tasks once syncing has finished
             info!("Spawning produce and submit tasks...");
-            let produce_task = produce.spawn();
-            let submit_task = submit.spawn();
-
-            let (res, _, rem) = select_all(vec![
-                sync_task,
-                produce_task,
-                submit_task,
-                home_fail_watch_task,
-            ])
-            .await;
-
-            for task in rem.into_iter() {
-                task.into_inner().abort();
-            }
-            res?
+            let produce_task = produce.spawn(shutdown.clone());
+            let submit_task = submit.spawn(shutdown.clone());
+
+            shutdown
+                .select_all(vec![
+                    sync_task,
+                    produce_task,
+                    submit_task,
+                    home_fail_watch_task,
+                ])
+                .await
         })
         .in_current_span()
     }
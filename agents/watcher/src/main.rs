@@ -29,9 +29,10 @@ async fn _main() -> Result<()> {
         .settings
         .tracing
         .start_tracing(agent.metrics().span_duration())?;
-    let _ = agent.metrics().run_http_server();
+    let core = agent.as_ref().clone();
+    let _ = core.run_http_server();
 
-    agent.run_all().await??;
+    core.run_until_shutdown(agent.run_all()).await?;
     Ok(())
 }
 
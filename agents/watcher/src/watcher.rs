@@ -5,21 +5,28 @@ use thiserror::Error;
 use ethers::core::types::H256;
 use futures_util::future::{join, join_all, select_all};
 use prometheus::{IntGauge, IntGaugeVec};
-use std::{collections::HashMap, fmt::Display, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    sync::Arc,
+    time::Duration,
+};
 use tokio::{
     select,
     sync::{mpsc, RwLock},
     task::JoinHandle,
     time::sleep,
 };
-use tracing::{error, info, info_span, instrument::Instrumented, Instrument};
+use tracing::{error, info, info_span, instrument::Instrumented, warn, Instrument};
 
 use nomad_base::{
-    cancel_task, AgentCore, BaseError, CachingHome, ConnectionManagers, NomadAgent, NomadDB,
+    cancel_task, AgentCore, BaseError, CachingHome, ConnectionManagers, HomeIndexers, NomadAgent,
+    NomadDB, ShutdownSignal,
 };
 use nomad_core::{
-    ChainCommunicationError, Common, CommonEvents, ConnectionManager, DoubleUpdate,
-    FailureNotification, Home, SignedFailureNotification, SignedUpdate, Signers, TxOutcome,
+    accumulator::incremental::IncrementalMerkle, ChainCommunicationError, Common, CommonEvents,
+    CommonIndexer, ConnectionManager, DoubleUpdate, FailureNotification, Home, HomeIndexer,
+    SignedFailureNotification, SignedUpdate, Signers, TxOutcome,
 };
 
 use crate::settings::WatcherSettings as Settings;
@@ -30,6 +37,8 @@ const AGENT_NAME: &str = "watcher";
 enum WatcherError {
     #[error("Syncing finished")]
     SyncingFinished,
+    #[error("Home indexer skipped leaves. Expected leaf index {expected}, found {found}")]
+    MissingLeaves { expected: usize, found: usize },
 }
 
 #[derive(Debug)]
@@ -197,11 +206,127 @@ where
     }
 }
 
+/// The watcher's own view of the home's message tree. Built from dispatched
+/// messages fetched directly from the home indexer, so it does not depend on
+/// the home's db being in sync.
+///
+/// The view is only complete if indexing starts at or before the block that
+/// dispatched leaf 0. Otherwise the tree can't be rebuilt and the view stops
+/// syncing.
+#[derive(Debug)]
+pub struct HomeTreeView {
+    indexer: Arc<HomeIndexers>,
+    from: u32,
+    chunk_size: u32,
+    tree: IncrementalMerkle,
+    roots: HashSet<H256>,
+    incomplete: bool,
+}
+
+impl HomeTreeView {
+    pub fn new(indexer: Arc<HomeIndexers>, from: u32, chunk_size: u32) -> Self {
+        Self {
+            indexer,
+            from,
+            chunk_size,
+            tree: Default::default(),
+            roots: Default::default(),
+            incomplete: false,
+        }
+    }
+
+    /// Whether the view holds every leaf since leaf 0
+    fn is_complete(&self) -> bool {
+        !self.incomplete
+    }
+
+    /// Whether `root` has been the root of the home's tree at some point
+    fn contains(&self, root: H256) -> bool {
+        self.roots.contains(&root)
+    }
+
+    /// Ingest all messages dispatched up to the indexer's current tip
+    async fn sync(&mut self) -> Result<()> {
+        if self.incomplete {
+            return Ok(());
+        }
+
+        let tip = self.indexer.get_block_number().await?;
+
+        loop {
+            let to = std::cmp::min(self.from + self.chunk_size, tip);
+            let messages = self.indexer.fetch_sorted_messages(self.from, to).await?;
+
+            for message in messages.into_iter().map(|m| m.raw_message) {
+                let expected = self.tree.count();
+                let found = message.leaf_index as usize;
+
+                // Consecutive ranges overlap by one block
+                if found < expected {
+                    continue;
+                }
+                if expected == 0 && found > 0 {
+                    warn!(
+                        found,
+                        "Home indexer starts after leaf 0. Watcher view of home tree is incomplete. Set the index `from` block to the home's deploy block to check updates for impropriety."
+                    );
+                    self.incomplete = true;
+                    return Ok(());
+                }
+                if found > expected {
+                    bail!(WatcherError::MissingLeaves { expected, found });
+                }
+
+                self.tree.ingest(message.leaf());
+                self.roots.insert(self.tree.root());
+            }
+
+            self.from = to;
+            if to >= tip {
+                break;
+            }
+        }
+
+        info!(
+            count = self.tree.count(),
+            root = ?self.tree.root(),
+            "Synced watcher view of home tree up to block {}.",
+            tip
+        );
+
+        Ok(())
+    }
+}
+
+/// Fraudulent updates detected by the UpdateHandler
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fraud {
+    /// Two updates building off the same previous root
+    DoubleUpdate(DoubleUpdate),
+    /// An update to a root the home's tree never had, building off the
+    /// home's committed root
+    ImproperUpdate(SignedUpdate),
+}
+
+/// Outcome of checking an update against the watcher's view of the home
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UpdateCheck {
+    Proper,
+    /// Improper, and provable on the home
+    Improper,
+    /// Improper, but the home rejects `improperUpdate` unless the update
+    /// builds off its committed root
+    Unprovable,
+}
+
 #[derive(Debug)]
 pub struct UpdateHandler {
     rx: mpsc::Receiver<SignedUpdate>,
     watcher_db: NomadDB,
     home: Arc<CachingHome>,
+    tree: HomeTreeView,
+    interval: u64,
+    improper_updates_observed: IntGauge,
 }
 
 impl UpdateHandler {
@@ -209,11 +334,93 @@ impl UpdateHandler {
         rx: mpsc::Receiver<SignedUpdate>,
         watcher_db: NomadDB,
         home: Arc<CachingHome>,
+        tree: HomeTreeView,
+        interval: u64,
+        improper_updates_observed: IntGauge,
     ) -> Self {
         Self {
             rx,
             watcher_db,
             home,
+            tree,
+            interval,
+            improper_updates_observed,
+        }
+    }
+
+    /// Check whether the update's new root was ever a root of the home's
+    /// tree. Roots missing from the watcher's view are only reported once the
+    /// view has caught up with the indexer and the home does not have the
+    /// root queued. Nothing is reported while the view is incomplete.
+    async fn is_improper_update(&mut self, update: &SignedUpdate) -> Result<bool> {
+        let new_root = update.update.new_root;
+        if self.tree.contains(new_root) {
+            return Ok(false);
+        }
+
+        self.tree.sync().await?;
+        if self.tree.contains(new_root) {
+            return Ok(false);
+        }
+
+        if !self.tree.is_complete() {
+            warn!(
+                "UpdateHandler cannot check update for impropriety, as its view of the home tree is incomplete. Update: {:?}.",
+                &update
+            );
+            return Ok(false);
+        }
+
+        // The indexer may lag behind the home's latest state
+        if self.home.queue_contains(new_root).await? {
+            return Ok(false);
+        }
+
+        error!(
+            "UpdateHandler detected improper update! New root {} was never a root of the home's tree. Update: {:?}.",
+            new_root, &update
+        );
+        Ok(true)
+    }
+
+    /// Check the update for impropriety. An improper update can only be
+    /// proven on the home if it builds off the home's committed root.
+    async fn classify_update(&mut self, update: &SignedUpdate) -> Result<UpdateCheck> {
+        if !self.is_improper_update(update).await? {
+            return Ok(UpdateCheck::Proper);
+        }
+
+        let committed_root = self.home.committed_root().await?;
+        if committed_root == update.update.previous_root {
+            Ok(UpdateCheck::Improper)
+        } else {
+            Ok(UpdateCheck::Unprovable)
+        }
+    }
+
+    /// Check the update for impropriety, retrying every `interval` seconds
+    /// until the check succeeds. Errors here are transient (RPC failures, or
+    /// the indexer lagging behind the home) and must not end the handler.
+    /// Returns `None` if shutdown was triggered while retrying.
+    async fn check_improper_update(
+        &mut self,
+        update: &SignedUpdate,
+        shutdown: &ShutdownSignal,
+    ) -> Option<UpdateCheck> {
+        loop {
+            match self.classify_update(update).await {
+                Ok(check) => return Some(check),
+                Err(e) => {
+                    warn!(
+                        error = %e,
+                        "UpdateHandler could not check update for impropriety. Retrying in {} seconds. Update: {:?}.",
+                        self.interval, &update
+                    );
+                    if shutdown.sleep(Duration::from_secs(self.interval)).await {
+                        return None;
+                    }
+                }
+            }
         }
     }
 
@@ -247,12 +454,12 @@ impl UpdateHandler {
         Ok(())
     }
 
-    /// Receive updates and check them for fraud. If double update was
-    /// found, return Ok(double_update). This loop should never exit naturally
-    /// unless the channel for sending new updates was closed, in which case we
-    /// return an error.
-    #[tracing::instrument]
-    fn spawn(mut self) -> JoinHandle<Result<DoubleUpdate>> {
+    /// Receive updates and check them for fraud. If a double or improper
+    /// update was found, return Ok(fraud). This loop should never exit
+    /// naturally unless the channel for sending new updates was closed or
+    /// shutdown was triggered, in which case we return an error.
+    #[tracing::instrument(skip(shutdown))]
+    fn spawn(mut self, shutdown: ShutdownSignal) -> JoinHandle<Result<Fraud>> {
         tokio::spawn(async move {
             loop {
                 let update = self.rx.recv().await;
@@ -264,13 +471,36 @@ impl UpdateHandler {
                 let update = update.unwrap();
                 let old_root = update.update.previous_root;
 
-                if old_root == self.home.committed_root().await? {
-                    // It is okay if tx reverts
-                    let _ = self.home.update(&update).await;
+                if let Err(double_update) = self.check_double_update(&update) {
+                    return Ok(Fraud::DoubleUpdate(double_update));
                 }
 
-                if let Err(double_update) = self.check_double_update(&update) {
-                    return Ok(double_update);
+                match self.check_improper_update(&update, &shutdown).await {
+                    None => bail!("Shutdown triggered."),
+                    Some(UpdateCheck::Proper) => {}
+                    Some(UpdateCheck::Improper) => return Ok(Fraud::ImproperUpdate(update)),
+                    Some(UpdateCheck::Unprovable) => {
+                        error!(
+                            "UpdateHandler detected improper update not building off the home's committed root. It cannot be proven on the home. Update: {:?}.",
+                            &update
+                        );
+                        self.improper_updates_observed.inc();
+                        continue;
+                    }
+                }
+
+                match self.home.committed_root().await {
+                    Ok(committed_root) if committed_root == old_root => {
+                        // It is okay if tx reverts
+                        let _ = self.home.update(&update).await;
+                    }
+                    Ok(_) => {}
+                    // Submitting is best effort, so do not end the handler
+                    Err(e) => warn!(
+                        error = %e,
+                        "UpdateHandler could not fetch the home's committed root. Not submitting update: {:?}.",
+                        &update
+                    ),
                 }
             }
         })
@@ -286,8 +516,10 @@ pub struct Watcher {
     sync_tasks: TaskMap,
     watch_tasks: TaskMap,
     connection_managers: Vec<Arc<ConnectionManagers>>,
+    home_indexer: Arc<HomeIndexers>,
     core: AgentCore,
     double_updates_observed: IntGauge,
+    improper_updates_observed: IntGauge,
     updates_inspected_for_double: IntGaugeVec,
 }
 
@@ -304,6 +536,7 @@ impl Watcher {
         signer: Signers,
         interval_seconds: u64,
         connection_managers: Vec<Arc<ConnectionManagers>>,
+        home_indexer: Arc<HomeIndexers>,
         core: AgentCore,
     ) -> Self {
         let double_updates_observed = core
@@ -314,6 +547,14 @@ impl Watcher {
             )
             .expect("failed to register watcher metric");
 
+        let improper_updates_observed = core
+            .metrics
+            .new_int_gauge(
+                "improper_updates_observed",
+                "Number of times an improper update has been observed (anything > 0 is major red flag!)",
+            )
+            .expect("failed to register watcher metric");
+
         let updates_inspected_for_double = core
             .metrics
             .new_int_gauge_vec(
@@ -329,16 +570,18 @@ impl Watcher {
             sync_tasks: Default::default(),
             watch_tasks: Default::default(),
             connection_managers,
+            home_indexer,
             core,
             double_updates_observed,
+            improper_updates_observed,
             updates_inspected_for_double,
         }
     }
 
     /// Spawn UpdateHandler and sync tasks. Have sync tasks send UpdateHandler
-    /// signed updates through mpsc. Return Some(double_update) if any
-    /// conflicting updates are found.
-    fn watch_double_update(&self) -> Instrumented<JoinHandle<Result<Option<DoubleUpdate>>>> {
+    /// signed updates through mpsc. Return the fraud once any conflicting or
+    /// improper updates are found, or the error that ended the handler.
+    fn watch_fraud(&self) -> Instrumented<JoinHandle<Result<Fraud>>> {
         let home = self.home();
         let replicas = self.replicas().clone();
         let watcher_db_name = format!("{}_{}", home.name(), AGENT_NAME);
@@ -347,11 +590,26 @@ impl Watcher {
         let sync_tasks = self.sync_tasks.clone();
         let watch_tasks = self.watch_tasks.clone();
         let updates_inspected_for_double = self.updates_inspected_for_double.clone();
+        let improper_updates_observed = self.improper_updates_observed.clone();
+        let shutdown = self.core.shutdown.clone();
+        let tree = HomeTreeView::new(
+            self.home_indexer.clone(),
+            self.core.indexer.from(),
+            self.core.indexer.chunk_size(),
+        );
 
         tokio::spawn(async move {
             // Spawn update handler
             let (tx, rx) = mpsc::channel(200);
-            let handler = UpdateHandler::new(rx, watcher_db, home.clone()).spawn();
+            let handler = UpdateHandler::new(
+                rx,
+                watcher_db,
+                home.clone(),
+                tree,
+                interval_seconds,
+                improper_updates_observed,
+            )
+            .spawn(shutdown);
 
             // For each replica, spawn polling and history syncing tasks
             info!("Spawning replica watch and sync tasks...");
@@ -398,17 +656,14 @@ impl Watcher {
 
             // Wait for update handler to finish (should only happen watcher is
             // manually shut down)
-            let double_update_res = handler.await?;
+            let fraud_res = handler.await?;
 
             // Cancel running tasks
             tracing::info!("Update handler has resolved. Cancelling all other tasks");
             cancel_task!(home_watcher);
             cancel_task!(home_sync);
 
-            // Map Result<DoubleUpdate> into Option. If handler returned error
-            // no double update. If handler returned Ok(double_update), map into
-            // Some(double_update).
-            Ok(double_update_res.ok())
+            fraud_res
         })
         .in_current_span()
     }
@@ -474,6 +729,23 @@ impl Watcher {
         join_all(unenroll_futs).await
     }
 
+    /// Handle an improper update once it has been detected. Submit the
+    /// improper update to the home and failure notifications to all
+    /// connection managers.
+    #[tracing::instrument]
+    async fn handle_improper_update(
+        &self,
+        update: &SignedUpdate,
+    ) -> Vec<Result<TxOutcome, ChainCommunicationError>> {
+        let (improper_res, unenroll_res) = join(
+            self.core.home.improper_update(update),
+            self.handle_improper_update_failure(),
+        )
+        .await;
+
+        std::iter::once(improper_res).chain(unenroll_res).collect()
+    }
+
     async fn shutdown(&self) {
         for (_, v) in self.watch_tasks.write().await.drain() {
             cancel_task!(v);
@@ -498,13 +770,20 @@ impl NomadAgent for Watcher {
     where
         Self: Sized,
     {
+        let core = settings.as_ref().try_into_core("watcher").await?;
+
         let mut connection_managers = vec![];
         for chain_setup in settings.managers.values() {
             let signer = settings.base.get_signer(&chain_setup.name).await;
             let xapp_timelag = None;
 
             let manager = chain_setup
-                .try_into_connection_manager(signer, xapp_timelag)
+                .try_into_connection_manager(
+                    signer,
+                    Some(core.db.clone()),
+                    Some(core.shutdown.subscribe()),
+                    xapp_timelag,
+                )
                 .await;
             connection_managers.push(manager);
         }
@@ -524,12 +803,13 @@ impl NomadAgent for Watcher {
             .map(Arc::new)
             .collect();
 
-        let core = settings.as_ref().try_into_core("watcher").await?;
+        let home_indexer = Arc::new(settings.as_ref().try_home_indexer().await?);
 
         Ok(Self::new(
             settings.watcher.try_into_signer().await?,
             settings.interval.parse().expect("invalid uint"),
             connection_managers,
+            home_indexer,
             core,
         ))
     }
@@ -553,55 +833,95 @@ impl NomadAgent for Watcher {
     {
         tokio::spawn(async move {
             info!("Starting Watcher tasks");
+            let shutdown = self.core.shutdown.clone();
 
             let home_sync_task = self
                 .home()
-                .sync();
+                .sync(shutdown.clone());
 
             let replica_sync_tasks: Vec<Instrumented<JoinHandle<Result<()>>>> = self.replicas().values().map(|replica| {
-                replica.sync()
+                replica.sync(shutdown.clone())
             }).collect();
 
             let mut sync_tasks = vec![home_sync_task];
             sync_tasks.extend(replica_sync_tasks);
             let sync_task_unified = select_all(sync_tasks);
 
-            let double_update_watch_task = self.watch_double_update();
+            let fraud_watch_task = self.watch_fraud();
             let improper_update_watch_task = self.watch_home_fail(self.interval_seconds);
 
             // Race index and run tasks
             info!("Selecting across tasks...");
             select! {
+                _ = shutdown.wait() => {
+                    info!("Shutdown triggered. Stopping watch and sync tasks.");
+                    self.shutdown().await;
+                },
                 _ = sync_task_unified => {
                     info!("Syncing tasks finished early!");
                     self.shutdown().await;
                 },
-                double_res = double_update_watch_task => {
-                    let opt_double = double_res??;
-                    if let Some(double) = opt_double {
-                        tracing::error!(
-                            double_update = ?double,
-                            "Double update detected! Notifying all contracts and unenrolling replicas! Double update: {:?}",
-                            double
-                        );
-                        self.double_updates_observed.inc();
+                fraud_res = fraud_watch_task => {
+                    let fraud = match fraud_res? {
+                        Ok(fraud) => fraud,
+                        Err(_) if shutdown.is_triggered() => {
+                            info!("Shutdown triggered. Stopping watch and sync tasks.");
+                            self.shutdown().await;
+                            return Ok(());
+                        }
+                        Err(e) => {
+                            error!(error = %e, "Update handler failed. Shutting down.");
+                            self.shutdown().await;
+                            return Err(e);
+                        }
+                    };
 
-                        self.handle_double_update_failure(&double)
-                            .await
-                            .iter()
-                            .for_each(|res| tracing::info!("{:#?}", res));
-
-                        bail!(
-                            r#"
-                            Double update detected!
-                            All contracts notified!
-                            Replicas unenrolled!
-                            Watcher has been shut down!
-                        "#
-                        )
-                    }
+                    match fraud {
+                        Fraud::DoubleUpdate(double) => {
+                            tracing::error!(
+                                double_update = ?double,
+                                "Double update detected! Notifying all contracts and unenrolling replicas! Double update: {:?}",
+                                double
+                            );
+                            self.double_updates_observed.inc();
 
-                    self.shutdown().await;
+                            self.handle_double_update_failure(&double)
+                                .await
+                                .iter()
+                                .for_each(|res| tracing::info!("{:#?}", res));
+
+                            bail!(
+                                r#"
+                                Double update detected!
+                                All contracts notified!
+                                Replicas unenrolled!
+                                Watcher has been shut down!
+                            "#
+                            )
+                        }
+                        Fraud::ImproperUpdate(update) => {
+                            tracing::error!(
+                                improper_update = ?update,
+                                "Improper update detected! Notifying home and unenrolling replicas! Improper update: {:?}",
+                                update
+                            );
+                            self.improper_updates_observed.inc();
+
+                            self.handle_improper_update(&update)
+                                .await
+                                .iter()
+                                .for_each(|res| tracing::info!("{:#?}", res));
+
+                            bail!(
+                                r#"
+                                Improper update detected!
+                                Home notified!
+                                Replicas unenrolled!
+                                Watcher has been shut down!
+                            "#
+                            )
+                        }
+                    }
                 },
                 improper_res = improper_update_watch_task => {
                     if let Err(e) = improper_res? {
@@ -627,7 +947,9 @@ impl NomadAgent for Watcher {
                             return Err(some_base_error.into())
                         }
                     } else {
-                        error!("It should not happen that self.watch_home_fail() would return Ok.");
+                        if !shutdown.is_triggered() {
+                            error!("It should not happen that self.watch_home_fail() would return Ok.");
+                        }
                         self.shutdown().await;
                     }
                 }
@@ -653,7 +975,10 @@ mod test {
         CachingReplica, CommonIndexers, ContractSync, ContractSyncMetrics, CoreMetrics,
         HomeIndexers, Homes, Replicas,
     };
-    use nomad_core::{DoubleUpdate, SignedFailureNotification, State, Update};
+    use nomad_core::{
+        DoubleUpdate, MessageVersion, RawCommittedMessage, RawCommittedMessageWithMeta,
+        SignedFailureNotification, State, Update,
+    };
     use nomad_test::mocks::{MockConnectionManagerContract, MockHomeContract, MockReplicaContract};
     use nomad_test::test_utils;
 
@@ -903,6 +1228,10 @@ mod test {
                 rx,
                 watcher_db: nomad_db.clone(),
                 home,
+                tree: HomeTreeView::new(home_indexer, 0, 1999),
+                interval: 1,
+                improper_updates_observed: IntGauge::new("improper_updates_observed", "test")
+                    .unwrap(),
             };
 
             let _first_update_ret = handler
@@ -924,6 +1253,243 @@ mod test {
         .await
     }
 
+    #[tokio::test]
+    async fn update_handler_detects_improper_update() {
+        test_utils::run_test_db(|db| async move {
+            let signer: LocalWallet =
+                "1111111111111111111111111111111111111111111111111111111111111111"
+                    .parse()
+                    .unwrap();
+
+            let messages: Vec<_> = (0..3)
+                .map(|leaf_index| RawCommittedMessage {
+                    leaf_index,
+                    committed_root: H256::zero(),
+                    version: MessageVersion::V0,
+                    message: vec![leaf_index as u8; 8],
+                })
+                .collect();
+
+            let mut tree = IncrementalMerkle::default();
+            let roots: Vec<_> = messages
+                .iter()
+                .map(|message| {
+                    tree.ingest(message.leaf());
+                    tree.root()
+                })
+                .collect();
+
+            let proper_update = Update {
+                home_domain: 1,
+                previous_root: roots[0],
+                new_root: roots[2],
+            }
+            .sign_with(&signer)
+            .await
+            .expect("!sign");
+
+            let improper_update = Update {
+                home_domain: 1,
+                previous_root: roots[2],
+                new_root: H256::repeat_byte(9),
+            }
+            .sign_with(&signer)
+            .await
+            .expect("!sign");
+
+            let unprovable_update = Update {
+                home_domain: 1,
+                previous_root: roots[1],
+                new_root: H256::repeat_byte(8),
+            }
+            .sign_with(&signer)
+            .await
+            .expect("!sign");
+
+            let metrics = Arc::new(
+                CoreMetrics::new(
+                    "contract_sync_test",
+                    None,
+                    Arc::new(prometheus::Registry::new()),
+                )
+                .expect("could not make metrics"),
+            );
+            let sync_metrics = ContractSyncMetrics::new(metrics.clone());
+
+            let mut mock_home = MockHomeContract::new();
+            let mut mock_indexer = MockIndexer::new();
+            {
+                mock_home.expect__name().return_const("home_1".to_owned());
+
+                // Improper roots are not queued on the home either
+                mock_home
+                    .expect__queue_contains()
+                    .times(2)
+                    .returning(|_| Ok(false));
+
+                let committed_root = roots[2];
+                mock_home
+                    .expect__committed_root()
+                    .times(2)
+                    .returning(move || Ok(committed_root));
+
+                mock_indexer.expect__get_block_number().returning(|| Ok(10));
+                mock_indexer
+                    .expect__fetch_sorted_messages()
+                    .returning(move |_, _| {
+                        Ok(messages
+                            .iter()
+                            .cloned()
+                            .map(|raw_message| RawCommittedMessageWithMeta {
+                                raw_message,
+                                metadata: Default::default(),
+                            })
+                            .collect())
+                    });
+            }
+
+            let nomad_db = NomadDB::new("home_1_watcher", db);
+            let home_indexer: Arc<HomeIndexers> = Arc::new(mock_indexer.into());
+            let home_sync = ContractSync::new(
+                AGENT_NAME.to_owned(),
+                "home_1".to_owned(),
+                nomad_db.clone(),
+                home_indexer.clone(),
+                IndexSettings::default(),
+                Default::default(),
+                sync_metrics.clone(),
+            );
+
+            let home: Arc<CachingHome> =
+                CachingHome::new(mock_home.into(), home_sync, nomad_db.clone()).into();
+
+            let (_tx, rx) = mpsc::channel(200);
+            let mut handler = UpdateHandler {
+                rx,
+                watcher_db: nomad_db.clone(),
+                home,
+                tree: HomeTreeView::new(home_indexer, 0, 1999),
+                interval: 1,
+                improper_updates_observed: IntGauge::new("improper_updates_observed", "test")
+                    .unwrap(),
+            };
+
+            assert!(!handler
+                .is_improper_update(&proper_update)
+                .await
+                .expect("Update check should have succeeded"));
+            assert_eq!(handler.tree.tree.count(), 3);
+
+            assert_eq!(
+                handler
+                    .classify_update(&improper_update)
+                    .await
+                    .expect("Update check should have succeeded"),
+                UpdateCheck::Improper
+            );
+
+            // The home only accepts improper updates off its committed root
+            assert_eq!(
+                handler
+                    .classify_update(&unprovable_update)
+                    .await
+                    .expect("Update check should have succeeded"),
+                UpdateCheck::Unprovable
+            );
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn update_handler_skips_improper_check_without_leaf_zero() {
+        test_utils::run_test_db(|db| async move {
+            let signer: LocalWallet =
+                "1111111111111111111111111111111111111111111111111111111111111111"
+                    .parse()
+                    .unwrap();
+
+            let update = Update {
+                home_domain: 1,
+                previous_root: H256::repeat_byte(1),
+                new_root: H256::repeat_byte(2),
+            }
+            .sign_with(&signer)
+            .await
+            .expect("!sign");
+
+            let metrics = Arc::new(
+                CoreMetrics::new(
+                    "contract_sync_test",
+                    None,
+                    Arc::new(prometheus::Registry::new()),
+                )
+                .expect("could not make metrics"),
+            );
+            let sync_metrics = ContractSyncMetrics::new(metrics.clone());
+
+            let mut mock_home = MockHomeContract::new();
+            let mut mock_indexer = MockIndexer::new();
+            {
+                mock_home.expect__name().return_const("home_1".to_owned());
+
+                // Indexing starts after leaf 0 was dispatched
+                mock_indexer.expect__get_block_number().returning(|| Ok(10));
+                mock_indexer
+                    .expect__fetch_sorted_messages()
+                    .times(1)
+                    .returning(|_, _| {
+                        Ok(vec![RawCommittedMessageWithMeta {
+                            raw_message: RawCommittedMessage {
+                                leaf_index: 5,
+                                committed_root: H256::zero(),
+                                version: MessageVersion::V0,
+                                message: vec![5; 8],
+                            },
+                            metadata: Default::default(),
+                        }])
+                    });
+            }
+
+            let nomad_db = NomadDB::new("home_1_watcher", db);
+            let home_indexer: Arc<HomeIndexers> = Arc::new(mock_indexer.into());
+            let home_sync = ContractSync::new(
+                AGENT_NAME.to_owned(),
+                "home_1".to_owned(),
+                nomad_db.clone(),
+                home_indexer.clone(),
+                IndexSettings::default(),
+                Default::default(),
+                sync_metrics.clone(),
+            );
+
+            let home: Arc<CachingHome> =
+                CachingHome::new(mock_home.into(), home_sync, nomad_db.clone()).into();
+
+            let (_tx, rx) = mpsc::channel(200);
+            let mut handler = UpdateHandler {
+                rx,
+                watcher_db: nomad_db.clone(),
+                home,
+                tree: HomeTreeView::new(home_indexer, 0, 1999),
+                interval: 1,
+                improper_updates_observed: IntGauge::new("improper_updates_observed", "test")
+                    .unwrap(),
+            };
+
+            // Incomplete views never flag updates and stop syncing
+            for _ in 0..2 {
+                assert_eq!(
+                    handler
+                        .check_improper_update(&update, &ShutdownSignal::default())
+                        .await,
+                    Some(UpdateCheck::Proper)
+                );
+            }
+            assert!(!handler.tree.is_complete());
+        })
+        .await
+    }
+
     #[tokio::test]
     async fn it_fails_contracts_and_unenrolls_replicas_on_double_update() {
         test_utils::run_test_db(|db| async move {
@@ -1146,6 +1712,8 @@ mod test {
                     db,
                     indexer: IndexSettings::default(),
                     settings: nomad_base::Settings::default(),
+                    status: Default::default(),
+                    shutdown: Default::default(),
                     metrics: Arc::new(
                         nomad_base::CoreMetrics::new(
                             "watcher_test",
@@ -1157,8 +1725,13 @@ mod test {
                 };
 
                 {
-                    let watcher =
-                        Watcher::new(updater.into(), 1, connection_managers.clone(), core);
+                    let watcher = Watcher::new(
+                        updater.into(),
+                        1,
+                        connection_managers.clone(),
+                        home_indexer.clone(),
+                        core,
+                    );
                     watcher.handle_double_update_failure(&double).await;
                 }
 
@@ -1334,6 +1907,8 @@ mod test {
                     db,
                     indexer: IndexSettings::default(),
                     settings: nomad_base::Settings::default(),
+                    status: Default::default(),
+                    shutdown: Default::default(),
                     metrics: Arc::new(
                         nomad_base::CoreMetrics::new(
                             "watcher_test",
@@ -1344,7 +1919,13 @@ mod test {
                     ),
                 };
 
-                let watcher = Watcher::new(updater.into(), 1, connection_managers.clone(), core);
+                let watcher = Watcher::new(
+                    updater.into(),
+                    1,
+                    connection_managers.clone(),
+                    home_indexer.clone(),
+                    core,
+                );
                 let state = watcher
                     .watch_home_fail(1)
                     .await
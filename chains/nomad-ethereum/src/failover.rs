@@ -0,0 +1,497 @@
+use std::{
+    fmt::Debug,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use ethers::providers::{HttpClientError, JsonRpcClient, MockError, ProviderError};
+use ethers::types::U64;
+use futures_util::future::join_all;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use tokio::time::timeout;
+use tracing::{instrument, warn};
+
+/// Requests taking longer than this are treated as failed
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Methods answered by a quorum of endpoints when quorum mode is enabled.
+/// Contract reads such as `committed_root` and `state` are `eth_call`s.
+const QUORUM_METHODS: &[&str] = &["eth_call"];
+
+/// Errors of the JSON-RPC clients a FailoverProvider can wrap
+pub trait FailoverClientError {
+    /// Whether the endpoint answered with a JSON-RPC error response, such as
+    /// a revert or a nonce that is too low. Any other endpoint would answer
+    /// the same, so these are not failed over.
+    fn is_error_response(&self) -> bool;
+}
+
+impl FailoverClientError for HttpClientError {
+    fn is_error_response(&self) -> bool {
+        matches!(self, HttpClientError::JsonRpcError(_))
+    }
+}
+
+impl FailoverClientError for MockError {
+    fn is_error_response(&self) -> bool {
+        false
+    }
+}
+
+/// A provider over several endpoints of the same chain. Requests go to the
+/// current endpoint, switching to the next one when it fails to answer or
+/// times out. With a quorum set, critical reads at the latest block are
+/// pinned to the lowest tip across endpoints, sent to every endpoint, and
+/// only succeed if at least `quorum` of them return the same response.
+#[derive(Debug)]
+pub struct FailoverProvider<P> {
+    endpoints: Vec<P>,
+    current: AtomicUsize,
+    quorum: Option<usize>,
+    timeout: Duration,
+}
+
+/// Invalid FailoverProvider configuration
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FailoverConfigError {
+    /// No endpoints were given
+    #[error("FailoverProvider needs at least one endpoint")]
+    NoEndpoints,
+    /// Quorum is 0 or larger than the number of endpoints
+    #[error("Quorum of {quorum} must be between 1 and the number of endpoints ({endpoints})")]
+    InvalidQuorum {
+        /// The configured quorum
+        quorum: usize,
+        /// The number of endpoints
+        endpoints: usize,
+    },
+    /// An endpoint url could not be parsed
+    #[error("Invalid endpoint url {url}: {reason}")]
+    InvalidUrl {
+        /// The url
+        url: String,
+        /// Why it could not be parsed
+        reason: String,
+    },
+}
+
+impl<P> FailoverProvider<P> {
+    /// Instantiate a FailoverProvider. Endpoints are tried in order.
+    pub fn new(endpoints: Vec<P>, quorum: Option<usize>) -> Result<Self, FailoverConfigError> {
+        if endpoints.is_empty() {
+            return Err(FailoverConfigError::NoEndpoints);
+        }
+        if let Some(quorum) = quorum {
+            if quorum == 0 || quorum > endpoints.len() {
+                return Err(FailoverConfigError::InvalidQuorum {
+                    quorum,
+                    endpoints: endpoints.len(),
+                });
+            }
+        }
+
+        Ok(Self {
+            endpoints,
+            current: AtomicUsize::new(0),
+            quorum,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Set the time after which a request to an endpoint is treated as failed
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Index of the endpoint requests are currently sent to
+    pub fn current(&self) -> usize {
+        self.current.load(Ordering::SeqCst)
+    }
+
+    /// Move on from endpoint `failed`, unless another request already did
+    fn fail_over(&self, failed: usize) {
+        let next = (failed + 1) % self.endpoints.len();
+        let _ = self
+            .current
+            .compare_exchange(failed, next, Ordering::SeqCst, Ordering::SeqCst);
+    }
+}
+
+impl<P> FailoverProvider<P>
+where
+    P: FromStr,
+    <P as FromStr>::Err: std::fmt::Display,
+{
+    /// Instantiate a FailoverProvider from a list of urls
+    pub fn from_urls(urls: &[String], quorum: Option<usize>) -> Result<Self, FailoverConfigError> {
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                url.parse()
+                    .map_err(|e: <P as FromStr>::Err| FailoverConfigError::InvalidUrl {
+                        url: url.clone(),
+                        reason: e.to_string(),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(endpoints, quorum)
+    }
+}
+
+/// Error type for the FailoverProvider
+#[derive(Error, Debug)]
+pub enum FailoverProviderError<P>
+where
+    P: JsonRpcClient,
+{
+    /// An endpoint returned an error
+    #[error("Endpoint {0} errored: {1}")]
+    EndpointError(usize, P::Error),
+    /// An endpoint answered with a JSON-RPC error response
+    #[error(transparent)]
+    ErrorResponse(P::Error),
+    /// An endpoint did not respond in time
+    #[error("Endpoint {0} timed out")]
+    Timeout(usize),
+    /// Every endpoint failed
+    #[error("All endpoints failed: {0:?}")]
+    AllFailed(Vec<FailoverProviderError<P>>),
+    /// Fewer than `quorum` endpoints returned the same response
+    #[error("Quorum of {quorum} not reached for {method}. Largest agreement: {agreeing}")]
+    QuorumNotReached {
+        /// Method requested
+        method: String,
+        /// Required number of agreeing endpoints
+        quorum: usize,
+        /// Number of endpoints returning the most common response
+        agreeing: usize,
+    },
+    /// Response could not be deserialized
+    #[error("{0}")]
+    SerdeJson(#[from] serde_json::Error),
+}
+
+impl<P> From<FailoverProviderError<P>> for ProviderError
+where
+    P: JsonRpcClient + 'static,
+    <P as JsonRpcClient>::Error: Send + Sync,
+{
+    fn from(src: FailoverProviderError<P>) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(src))
+    }
+}
+
+impl<P> FailoverProvider<P>
+where
+    P: JsonRpcClient + 'static,
+    <P as JsonRpcClient>::Error: FailoverClientError + Send + Sync,
+{
+    /// Send a request to a single endpoint, bounded by the timeout
+    async fn request_endpoint(
+        &self,
+        index: usize,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, FailoverProviderError<P>> {
+        let endpoint = &self.endpoints[index];
+        let fut = match params {
+            Value::Null => endpoint.request(method, ()),
+            _ => endpoint.request(method, params),
+        };
+
+        match timeout(self.timeout, fut).await {
+            Ok(Ok(res)) => Ok(res),
+            Ok(Err(e)) if e.is_error_response() => Err(FailoverProviderError::ErrorResponse(e)),
+            Ok(Err(e)) => Err(FailoverProviderError::EndpointError(index, e)),
+            Err(_) => Err(FailoverProviderError::Timeout(index)),
+        }
+    }
+
+    /// Send a request to the current endpoint, failing over to the others in
+    /// turn. Error responses are returned as is.
+    async fn request_failover(
+        &self,
+        method: &str,
+        params: &Value,
+    ) -> Result<Value, FailoverProviderError<P>> {
+        let mut errors = vec![];
+
+        for _ in 0..self.endpoints.len() {
+            let index = self.current();
+            match self.request_endpoint(index, method, params).await {
+                Ok(res) => return Ok(res),
+                Err(e @ FailoverProviderError::ErrorResponse(_)) => return Err(e),
+                Err(e) => {
+                    warn!(
+                        endpoint = index,
+                        error = %e,
+                        method = %method,
+                        "Error in failover provider, switching endpoint",
+                    );
+                    self.fail_over(index);
+                    errors.push(e);
+                }
+            }
+        }
+
+        Err(FailoverProviderError::AllFailed(errors))
+    }
+
+    /// Pin an `eth_call` at the latest block to the lowest tip across
+    /// endpoints, so that every endpoint answers for the same state.
+    /// Endpoints that fail to report their tip are left out.
+    async fn pin_to_lowest_tip(&self, params: &Value) -> Value {
+        let mut params = params.clone();
+        let call = match &mut params {
+            Value::Array(call) => call,
+            _ => return params,
+        };
+        match call.get(1) {
+            None => {}
+            Some(Value::String(tag)) if tag == "latest" => {}
+            // Already pinned to a block
+            Some(_) => return params,
+        }
+
+        let tips = join_all(
+            (0..self.endpoints.len())
+                .map(|index| self.request_endpoint(index, "eth_blockNumber", &Value::Null)),
+        )
+        .await;
+        let lowest_tip = tips
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter_map(|tip| serde_json::from_value::<U64>(tip).ok())
+            .min();
+
+        if let Some(tip) = lowest_tip {
+            call.truncate(1);
+            call.push(serde_json::to_value(tip).expect("!serialize"));
+        }
+        params
+    }
+
+    /// Send a request to every endpoint and return the most common response
+    /// if at least `quorum` endpoints returned it
+    async fn request_quorum(
+        &self,
+        method: &str,
+        params: &Value,
+        quorum: usize,
+    ) -> Result<Value, FailoverProviderError<P>> {
+        let params = &self.pin_to_lowest_tip(params).await;
+        let responses = join_all(
+            (0..self.endpoints.len()).map(|index| self.request_endpoint(index, method, params)),
+        )
+        .await;
+
+        let mut tallies: Vec<(Value, usize)> = vec![];
+        for response in responses {
+            match response {
+                Ok(res) => match tallies.iter_mut().find(|(value, _)| value == &res) {
+                    Some((_, count)) => *count += 1,
+                    None => tallies.push((res, 1)),
+                },
+                Err(e) => warn!(
+                    error = %e,
+                    method = %method,
+                    "Error in failover provider quorum request",
+                ),
+            }
+        }
+
+        match tallies.into_iter().max_by_key(|(_, count)| *count) {
+            Some((res, count)) if count >= quorum => Ok(res),
+            best => Err(FailoverProviderError::QuorumNotReached {
+                method: method.to_owned(),
+                quorum,
+                agreeing: best.map_or(0, |(_, count)| count),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl<P> JsonRpcClient for FailoverProvider<P>
+where
+    P: JsonRpcClient + 'static,
+    <P as JsonRpcClient>::Error: FailoverClientError + Send + Sync,
+{
+    type Error = FailoverProviderError<P>;
+
+    #[instrument(
+        level = "debug",
+        err,
+        skip(self, params),
+        fields(params = %serde_json::to_string(&params).unwrap()))
+    ]
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        let params = serde_json::to_value(params)?;
+
+        let res = match self.quorum {
+            Some(quorum) if QUORUM_METHODS.contains(&method) => {
+                self.request_quorum(method, &params, quorum).await?
+            }
+            _ => self.request_failover(method, &params).await?,
+        };
+
+        Ok(serde_json::from_value(res)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::providers::MockProvider;
+    use serde_json::json;
+
+    use super::*;
+
+    /// Client answering every request with a JSON-RPC error response
+    #[derive(Debug)]
+    struct Reverting;
+
+    #[derive(Error, Debug)]
+    #[error("execution reverted")]
+    struct Reverted;
+
+    impl From<Reverted> for ProviderError {
+        fn from(src: Reverted) -> Self {
+            ProviderError::JsonRpcClientError(Box::new(src))
+        }
+    }
+
+    impl FailoverClientError for Reverted {
+        fn is_error_response(&self) -> bool {
+            true
+        }
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for Reverting {
+        type Error = Reverted;
+
+        async fn request<T, R>(&self, _method: &str, _params: T) -> Result<R, Self::Error>
+        where
+            T: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned,
+        {
+            Err(Reverted)
+        }
+    }
+
+    fn provider(responses: &[Value]) -> MockProvider {
+        let mock = MockProvider::new();
+        // Responses are popped in reverse order
+        for res in responses.iter().rev() {
+            mock.push::<Value, _>(res.clone()).unwrap();
+        }
+        mock
+    }
+
+    #[tokio::test]
+    async fn it_fails_over_to_the_next_endpoint() {
+        // An empty mock errors on every request
+        let failover = FailoverProvider::new(
+            vec![MockProvider::new(), provider(&[Value::from("0x1")])],
+            None,
+        )
+        .unwrap();
+
+        let res: Value = failover.request("eth_blockNumber", ()).await.unwrap();
+        assert_eq!(res, Value::from("0x1"));
+        assert_eq!(failover.current(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_errors_when_all_endpoints_fail() {
+        let failover =
+            FailoverProvider::new(vec![MockProvider::new(), MockProvider::new()], None).unwrap();
+
+        let res: Result<Value, _> = failover.request("eth_blockNumber", ()).await;
+        assert!(matches!(res, Err(FailoverProviderError::AllFailed(errors)) if errors.len() == 2));
+    }
+
+    #[tokio::test]
+    async fn it_returns_error_responses_without_failing_over() {
+        let failover = FailoverProvider::new(vec![Reverting, Reverting], None).unwrap();
+
+        let res: Result<Value, _> = failover.request("eth_sendRawTransaction", ()).await;
+        assert!(matches!(
+            res,
+            Err(FailoverProviderError::ErrorResponse(Reverted))
+        ));
+        assert_eq!(failover.current(), 0);
+    }
+
+    #[tokio::test]
+    async fn it_pins_quorum_reads_to_the_lowest_tip() {
+        let endpoints = vec![
+            provider(&[Value::from("0x12"), Value::from("0xaa")]),
+            provider(&[Value::from("0x10"), Value::from("0xaa")]),
+        ];
+        let failover = FailoverProvider::new(endpoints.clone(), Some(2)).unwrap();
+
+        let call = json!({ "to": "0x0000000000000000000000000000000000000001" });
+        let res: Value = failover
+            .request("eth_call", (call.clone(), "latest"))
+            .await
+            .unwrap();
+        assert_eq!(res, Value::from("0xaa"));
+
+        for endpoint in endpoints {
+            endpoint.assert_request("eth_blockNumber", ()).unwrap();
+            endpoint
+                .assert_request("eth_call", (call.clone(), "0x10"))
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn it_requires_a_quorum_for_critical_reads() {
+        let failover = FailoverProvider::new(
+            vec![
+                provider(&[Value::from("0xaa"), Value::from("0xaa")]),
+                provider(&[Value::from("0xbb"), Value::from("0xcc")]),
+                provider(&[Value::from("0xaa"), Value::from("0xdd")]),
+            ],
+            Some(2),
+        )
+        .unwrap();
+
+        // 2 of 3 endpoints agree
+        let res: Value = failover.request("eth_call", ()).await.unwrap();
+        assert_eq!(res, Value::from("0xaa"));
+
+        // Responses all differ
+        let res: Result<Value, _> = failover.request("eth_call", ()).await;
+        assert!(matches!(
+            res,
+            Err(FailoverProviderError::QuorumNotReached { agreeing: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn it_rejects_invalid_config() {
+        assert_eq!(
+            FailoverProvider::<MockProvider>::new(vec![], None).unwrap_err(),
+            FailoverConfigError::NoEndpoints
+        );
+        for quorum in [0, 2].iter() {
+            assert_eq!(
+                FailoverProvider::new(vec![MockProvider::new()], Some(*quorum)).unwrap_err(),
+                FailoverConfigError::InvalidQuorum {
+                    quorum: *quorum,
+                    endpoints: 1
+                }
+            );
+        }
+    }
+}
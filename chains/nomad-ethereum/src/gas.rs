@@ -1,16 +1,138 @@
 use ethers::providers::{FromErr, Middleware};
-use ethers::types::{transaction::eip2718::TypedTransaction, BlockId, U256};
-use std::fmt;
+use ethers::types::{
+    transaction::{eip2718::TypedTransaction, eip2930::Eip2930TransactionRequest},
+    BlockId, Eip1559TransactionRequest, U256,
+};
+use serde::Deserialize;
+use std::{cmp::min, fmt};
 use thiserror::Error;
 
-/// Closure that will be used for gas calculation. Takes existing gas
-type GasPolicy = Box<dyn Fn(U256) -> U256 + Send + Sync>;
+/// Wei per gwei
+const GWEI: u64 = 1_000_000_000;
 
-/// Middleware used for adjusting gas using predefined policy
+/// Transaction type dispatched on a chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TxType {
+    /// Legacy transactions with a single gas price
+    Legacy,
+    /// EIP-1559 transactions with a max fee and priority fee
+    Eip1559,
+}
+
+impl Default for TxType {
+    fn default() -> Self {
+        Self::Legacy
+    }
+}
+
+/// Per-chain gas policy. Multipliers are percentages of the node's estimate
+/// (e.g. 150 for 1.5x).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GasSettings {
+    /// Transaction type to dispatch
+    #[serde(default)]
+    pub tx_type: TxType,
+    /// Multiplier for gas limit estimates
+    pub gas_limit_percent: u64,
+    /// Multiplier for legacy gas prices
+    pub gas_price_percent: u64,
+    /// Multiplier for EIP-1559 max fees
+    pub max_fee_percent: u64,
+    /// Multiplier for EIP-1559 priority fees
+    pub priority_fee_percent: u64,
+    /// Cap on legacy gas prices and EIP-1559 max fees, in gwei
+    #[serde(default)]
+    pub max_gas_price_gwei: Option<u64>,
+    /// Cap on EIP-1559 priority fees, in gwei
+    #[serde(default)]
+    pub max_priority_fee_gwei: Option<u64>,
+}
+
+impl GasSettings {
+    /// Policy used for chains without configured gas settings. Triples gas
+    /// estimates and pays 1.5x the gas price on Ethereum (chain id 1), 2x
+    /// elsewhere.
+    pub fn default_for_chain(chain_id: u64) -> Self {
+        let price_percent = if chain_id == 1 { 150 } else { 200 };
+        Self {
+            tx_type: TxType::Legacy,
+            gas_limit_percent: 300,
+            gas_price_percent: price_percent,
+            max_fee_percent: price_percent,
+            priority_fee_percent: price_percent,
+            max_gas_price_gwei: None,
+            max_priority_fee_gwei: None,
+        }
+    }
+
+    fn max_gas_price(&self) -> Option<U256> {
+        self.max_gas_price_gwei.map(|gwei| U256::from(gwei) * GWEI)
+    }
+
+    fn max_priority_fee(&self) -> Option<U256> {
+        self.max_priority_fee_gwei
+            .map(|gwei| U256::from(gwei) * GWEI)
+    }
+
+    /// Adjust a node estimated gas limit
+    pub fn gas_limit(&self, estimate: U256) -> U256 {
+        estimate * self.gas_limit_percent / 100
+    }
+
+    /// Adjust a node estimated legacy gas price
+    pub fn gas_price(&self, estimate: U256) -> U256 {
+        adjust(estimate, self.gas_price_percent, self.max_gas_price())
+    }
+
+    /// Adjust node estimated EIP-1559 max fee and priority fee. The priority
+    /// fee never exceeds the max fee.
+    pub fn eip1559_fees(&self, max_fee: U256, priority_fee: U256) -> (U256, U256) {
+        let max_fee = adjust(max_fee, self.max_fee_percent, self.max_gas_price());
+        let priority_fee = adjust(
+            priority_fee,
+            self.priority_fee_percent,
+            self.max_priority_fee(),
+        );
+        (max_fee, min(priority_fee, max_fee))
+    }
+}
+
+/// Multiply `fee` by `percent`, capped at `cap`
+fn adjust(fee: U256, percent: u64, cap: Option<U256>) -> U256 {
+    let fee = fee * percent / 100;
+    cap.map_or(fee, |cap| min(fee, cap))
+}
+
+/// Convert a legacy or EIP-2930 transaction into an EIP-1559 transaction
+/// with unset fees
+fn to_eip1559(tx: &TypedTransaction) -> TypedTransaction {
+    let (inner, access_list) = match tx {
+        TypedTransaction::Legacy(inner) => (inner, Default::default()),
+        TypedTransaction::Eip2930(Eip2930TransactionRequest { tx, access_list }) => {
+            (tx, access_list.clone())
+        }
+        TypedTransaction::Eip1559(_) => return tx.clone(),
+    };
+
+    Eip1559TransactionRequest {
+        from: inner.from,
+        to: inner.to.clone(),
+        gas: inner.gas,
+        value: inner.value,
+        data: inner.data.clone(),
+        nonce: inner.nonce,
+        access_list,
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Middleware used for adjusting gas using a per-chain policy
 pub struct GasAdjusterMiddleware<M> {
     inner: M,
-    gas_estimate_policy: GasPolicy,
-    gas_price_policy: GasPolicy,
+    settings: GasSettings,
 }
 
 impl<M> fmt::Debug for GasAdjusterMiddleware<M>
@@ -20,6 +142,7 @@ where
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("GasAdjusterMiddleware")
             .field("inner", &self.inner)
+            .field("settings", &self.settings)
             .finish()
     }
 }
@@ -28,35 +151,9 @@ impl<M> GasAdjusterMiddleware<M>
 where
     M: Middleware,
 {
-    /// Instantiates the gas multiplier middleware. Policy takes gas
-    /// estimate to calculates new gas which will be used for transaction
-    pub fn new(inner: M, gas_estimate_policy: GasPolicy, gas_price_policy: GasPolicy) -> Self {
-        Self {
-            inner,
-            gas_estimate_policy,
-            gas_price_policy,
-        }
-    }
-
-    pub fn with_default_policy(inner: M, chain_id: u64) -> Self {
-        // triple gas estimate
-        let gas_estimate_policy = move |gas| gas * 3;
-
-        // 1.5x gas price for ethereum, 2x elsewhere
-        let is_ethereum = chain_id == 1;
-        let gas_price_policy = move |price| {
-            if is_ethereum {
-                price + price / 2
-            } else {
-                price * 2
-            }
-        };
-
-        Self::new(
-            inner,
-            Box::new(gas_estimate_policy),
-            Box::new(gas_price_policy),
-        )
+    /// Instantiates the gas adjuster middleware with the given policy
+    pub fn new(inner: M, settings: GasSettings) -> Self {
+        Self { inner, settings }
     }
 }
 
@@ -94,14 +191,34 @@ where
         tx: &mut TypedTransaction,
         block: Option<BlockId>,
     ) -> Result<(), Self::Error> {
+        // Leave prices set by the caller alone (e.g. bumped fees of a
+        // replacement transaction)
+        let price_set = tx.gas_price().is_some();
+
+        if !price_set && self.settings.tx_type == TxType::Eip1559 {
+            *tx = to_eip1559(tx);
+        }
+
         self.inner
             .fill_transaction(tx, block)
             .await
             .map_err(FromErr::from)?;
 
-        // Increase gas price
-        let adjusted_price = self.get_gas_price().await?;
-        tx.set_gas_price(adjusted_price);
+        if price_set {
+            return Ok(());
+        }
+
+        // Increase fees
+        if matches!(*tx, TypedTransaction::Eip1559(_)) {
+            let (max_fee, priority_fee) = self.estimate_eip1559_fees(None).await?;
+            if let TypedTransaction::Eip1559(inner) = tx {
+                inner.max_fee_per_gas = Some(max_fee);
+                inner.max_priority_fee_per_gas = Some(priority_fee);
+            }
+        } else {
+            let adjusted_price = self.get_gas_price().await?;
+            tx.set_gas_price(adjusted_price);
+        }
 
         Ok(())
     }
@@ -110,7 +227,18 @@ where
         self.inner()
             .get_gas_price()
             .await
-            .map(&self.gas_price_policy)
+            .map(|price| self.settings.gas_price(price))
+            .map_err(FromErr::from)
+    }
+
+    async fn estimate_eip1559_fees(
+        &self,
+        estimator: Option<fn(U256, Vec<Vec<U256>>) -> (U256, U256)>,
+    ) -> Result<(U256, U256), Self::Error> {
+        self.inner()
+            .estimate_eip1559_fees(estimator)
+            .await
+            .map(|(max_fee, priority_fee)| self.settings.eip1559_fees(max_fee, priority_fee))
             .map_err(FromErr::from)
     }
 
@@ -118,7 +246,91 @@ where
         self.inner()
             .estimate_gas(tx)
             .await
-            .map(&self.gas_estimate_policy)
+            .map(|gas| self.settings.gas_limit(gas))
             .map_err(FromErr::from)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use ethers::types::{NameOrAddress, TransactionRequest, H160};
+
+    use super::*;
+
+    #[test]
+    fn it_applies_default_policies() {
+        let mainnet = GasSettings::default_for_chain(1);
+        assert_eq!(mainnet.gas_limit(100.into()), 300.into());
+        assert_eq!(mainnet.gas_price(100.into()), 150.into());
+
+        let other = GasSettings::default_for_chain(1284);
+        assert_eq!(other.gas_price(100.into()), 200.into());
+    }
+
+    #[test]
+    fn it_caps_fees() {
+        let settings = GasSettings {
+            tx_type: TxType::Eip1559,
+            gas_limit_percent: 100,
+            gas_price_percent: 200,
+            max_fee_percent: 200,
+            priority_fee_percent: 300,
+            max_gas_price_gwei: Some(100),
+            max_priority_fee_gwei: None,
+        };
+        let gwei = |n: u64| U256::from(n) * GWEI;
+
+        assert_eq!(settings.gas_price(gwei(80)), gwei(100));
+        assert_eq!(
+            settings.eip1559_fees(gwei(30), gwei(2)),
+            (gwei(60), gwei(6))
+        );
+
+        // Priority fee capped at the max fee
+        assert_eq!(
+            settings.eip1559_fees(gwei(80), gwei(50)),
+            (gwei(100), gwei(100))
+        );
+    }
+
+    #[test]
+    fn it_converts_legacy_txs_to_eip1559() {
+        let to = NameOrAddress::Address(H160::repeat_byte(1));
+        let legacy: TypedTransaction = TransactionRequest::new()
+            .to(to.clone())
+            .gas(21000)
+            .value(5)
+            .nonce(3)
+            .into();
+
+        match to_eip1559(&legacy) {
+            TypedTransaction::Eip1559(inner) => {
+                assert_eq!(inner.to, Some(to));
+                assert_eq!(inner.gas, Some(21000.into()));
+                assert_eq!(inner.value, Some(5.into()));
+                assert_eq!(inner.nonce, Some(3.into()));
+                assert_eq!(inner.max_fee_per_gas, None);
+            }
+            _ => panic!("expected an EIP-1559 transaction"),
+        }
+    }
+
+    #[test]
+    fn it_deserializes_settings() {
+        let settings: GasSettings = serde_json::from_str(
+            r#"{
+                "txType": "eip1559",
+                "gasLimitPercent": 150,
+                "gasPricePercent": 120,
+                "maxFeePercent": 200,
+                "priorityFeePercent": 100,
+                "maxGasPriceGwei": 500
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(settings.tx_type, TxType::Eip1559);
+        assert_eq!(settings.max_gas_price_gwei, Some(500));
+        assert_eq!(settings.max_priority_fee_gwei, None);
+    }
+}
@@ -4,16 +4,15 @@
 use async_trait::async_trait;
 use color_eyre::Result;
 use ethers::core::types::{Signature, H256};
-use futures_util::future::join_all;
 use nomad_core::{
     ChainCommunicationError, Common, CommonIndexer, ContractLocator, DoubleUpdate, Home,
-    HomeIndexer, Message, RawCommittedMessage, SignedUpdate, SignedUpdateWithMeta, State,
-    TxOutcome, Update, UpdateMeta,
+    HomeIndexer, Message, MessageVersion, RawCommittedMessage, RawCommittedMessageWithMeta,
+    SignedUpdate, SignedUpdateWithMeta, State, TxOutcome, Update,
 };
 use std::{convert::TryFrom, error::Error as StdError, sync::Arc};
 use tracing::instrument;
 
-use crate::{bindings::home::Home as EthereumHomeInternal, report_tx};
+use crate::{bindings::home::Home as EthereumHomeInternal, block_timestamps, report_tx, tx_meta};
 
 impl<M> std::fmt::Display for EthereumHomeInternal<M>
 where
@@ -70,6 +69,15 @@ where
         Ok(self.provider.get_block_number().await?.as_u32())
     }
 
+    #[instrument(err, skip(self))]
+    async fn get_block_hash(&self, height: u32) -> Result<Option<H256>> {
+        Ok(self
+            .provider
+            .get_block(height)
+            .await?
+            .and_then(|block| block.hash))
+    }
+
     #[instrument(err, skip(self))]
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         let mut events = self
@@ -89,9 +97,12 @@ where
             ordering
         });
 
-        let update_futs: Vec<_> = events
+        let timestamps =
+            block_timestamps(self.provider.as_ref(), events.iter().map(|event| &event.1)).await;
+
+        Ok(events
             .iter()
-            .map(|event| async {
+            .map(|event| {
                 let signature = Signature::try_from(event.0.signature.as_ref())
                     .expect("chain accepted invalid signature");
 
@@ -101,26 +112,12 @@ where
                     new_root: event.0.new_root.into(),
                 };
 
-                let block_number = event.1.block_number.as_u64();
-                let timestamp = self
-                    .provider
-                    .get_block(block_number)
-                    .await
-                    .ok()
-                    .flatten()
-                    .map(|b| b.timestamp.as_u64());
-
                 SignedUpdateWithMeta {
                     signed_update: SignedUpdate { update, signature },
-                    metadata: UpdateMeta {
-                        block_number,
-                        timestamp,
-                    },
+                    metadata: tx_meta(&event.1, &timestamps),
                 }
             })
-            .collect();
-
-        Ok(join_all(update_futs).await)
+            .collect())
     }
 }
 
@@ -130,23 +127,42 @@ where
     M: ethers::providers::Middleware + 'static,
 {
     #[instrument(err, skip(self))]
-    async fn fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessage>> {
+    async fn fetch_sorted_messages(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> Result<Vec<RawCommittedMessageWithMeta>> {
         let mut events = self
             .contract
             .dispatch_filter()
             .from_block(from)
             .to_block(to)
-            .query()
+            .query_with_meta()
             .await?;
 
-        events.sort_by(|a, b| a.leaf_index.cmp(&b.leaf_index));
+        events.sort_by(|a, b| a.0.leaf_index.cmp(&b.0.leaf_index));
+
+        if events.is_empty() {
+            return Ok(vec![]);
+        }
+        // Read at the tip rather than at the block of each dispatch, as
+        // historical state needs an archive node. Messages must be indexed
+        // before the home is upgraded to a new message format.
+        let version = MessageVersion::try_from(self.contract.version().call().await?)?;
+
+        let timestamps =
+            block_timestamps(self.provider.as_ref(), events.iter().map(|event| &event.1)).await;
 
         Ok(events
-            .into_iter()
-            .map(|f| RawCommittedMessage {
-                leaf_index: f.leaf_index.as_u32(),
-                committed_root: f.committed_root.into(),
-                message: f.message.to_vec(),
+            .iter()
+            .map(|event| RawCommittedMessageWithMeta {
+                raw_message: RawCommittedMessage {
+                    leaf_index: event.0.leaf_index.as_u32(),
+                    committed_root: event.0.committed_root.into(),
+                    version,
+                    message: event.0.message.to_vec(),
+                },
+                metadata: tx_meta(&event.1, &timestamps),
             })
             .collect())
     }
@@ -272,8 +288,27 @@ where
         Ok(self.contract.nonces(destination).call().await?)
     }
 
+    #[tracing::instrument(err, skip(self))]
+    async fn version(&self) -> Result<MessageVersion, ChainCommunicationError> {
+        Ok(MessageVersion::try_from(
+            self.contract.version().call().await?,
+        )?)
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn max_message_body_bytes(&self) -> Result<usize, ChainCommunicationError> {
+        Ok(self
+            .contract
+            .max_message_body_bytes()
+            .call()
+            .await?
+            .as_usize())
+    }
+
     #[tracing::instrument(err, skip(self))]
     async fn dispatch(&self, message: &Message) -> Result<TxOutcome, ChainCommunicationError> {
+        message.check_body_size(self.max_message_body_bytes().await?)?;
+
         let tx = self.contract.dispatch(
             message.destination,
             message.recipient.to_fixed_bytes(),
@@ -5,10 +5,14 @@
 #![warn(unused_extern_crates)]
 
 use color_eyre::eyre::Result;
-use ethers::prelude::*;
+use ethers::{contract::LogMeta, prelude::*};
+use futures_util::future::join_all;
 use nomad_core::*;
 use num::Num;
-use std::sync::Arc;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
 
 #[macro_use]
 mod macros;
@@ -17,6 +21,12 @@ mod macros;
 mod retrying;
 pub use retrying::{RetryingProvider, RetryingProviderError};
 
+/// Multi-endpoint Provider
+mod failover;
+pub use failover::{
+    FailoverClientError, FailoverConfigError, FailoverProvider, FailoverProviderError,
+};
+
 /// Contract binding
 #[cfg(not(doctest))]
 pub(crate) mod bindings;
@@ -35,6 +45,11 @@ mod xapp;
 
 /// Gas increasing Middleware
 mod gas;
+pub use gas::{GasSettings, TxType};
+
+/// Nonce managing, fee escalating Middleware
+mod tx_manager;
+pub use tx_manager::{InFlightTx, TxManager, TxManagerConfig, TxManagerError, TxManagerSettings};
 
 /// Ethereum connection configuration
 #[derive(Debug, serde::Deserialize, Clone)]
@@ -50,6 +65,15 @@ pub enum Connection {
         /// Fully qualified string to connect to
         url: String,
     },
+    /// Several HTTP endpoints for the same chain, failing over between them
+    Failover {
+        /// Fully qualified strings to connect to, in order of preference
+        urls: Vec<String>,
+        /// Number of endpoints that must return the same response to
+        /// contract reads. Unset to read from a single endpoint.
+        #[serde(default)]
+        quorum: Option<usize>,
+    },
 }
 
 impl Default for Connection {
@@ -92,6 +116,45 @@ boxed_trait!(
     ConnectionManager,
 );
 
+/// Timestamps of the blocks the logs described by `metas` were emitted in,
+/// by block number. Each block is fetched once. Timestamps of blocks that
+/// could not be fetched are unset.
+pub(crate) async fn block_timestamps<'a, M: Middleware>(
+    provider: &M,
+    metas: impl IntoIterator<Item = &'a LogMeta>,
+) -> HashMap<u64, Option<u64>> {
+    let block_numbers: BTreeSet<u64> = metas
+        .into_iter()
+        .map(|meta| meta.block_number.as_u64())
+        .collect();
+
+    let timestamps = join_all(block_numbers.iter().map(|&block_number| async move {
+        provider
+            .get_block(block_number)
+            .await
+            .ok()
+            .flatten()
+            .map(|b| b.timestamp.as_u64())
+    }))
+    .await;
+
+    block_numbers.into_iter().zip(timestamps).collect()
+}
+
+/// Transaction metadata of the log described by `meta`, with the timestamp
+/// of its block taken from `timestamps` (see `block_timestamps`)
+pub(crate) fn tx_meta(meta: &LogMeta, timestamps: &HashMap<u64, Option<u64>>) -> TxMeta {
+    let block_number = meta.block_number.as_u64();
+
+    TxMeta {
+        block_number,
+        timestamp: timestamps.get(&block_number).copied().flatten(),
+        transaction_hash: meta.transaction_hash,
+        block_hash: meta.block_hash,
+        log_index: meta.log_index.as_u64(),
+    }
+}
+
 #[async_trait::async_trait]
 impl nomad_core::Chain for Chain {
     async fn query_balance(&self, addr: nomad_core::Address) -> Result<nomad_core::Balance> {
@@ -1,42 +1,10 @@
-/// Dispatches a transaction, logs the tx id, and returns the result
+/// Dispatches a transaction, logs the tx id, and returns the result.
+///
+/// Contracts built with a signer dispatch through a `TxManager`, which owns
+/// nonces and escalates fees until the transaction is mined.
 #[macro_export]
 macro_rules! report_tx {
     ($tx:expr, $($tail:tt)*) => {{
-        // Simple switch between 2 implementations:
-        //  * @escalating for new implementation with gas escalation
-        //  * @legacy for simple transaction send implementation
-        report_tx!(@legacy $tx, $($tail)*)
-    }};
-
-    // Escalating cutting edge implementation, but we have to put it aside, while there is a bug
-    (@escalating $tx:expr, $provider:expr) => {{
-        log_tx_details!($tx);
-
-        let dispatch_fut = $provider.send_escalating(
-            &$tx.tx,
-            5,
-            Box::new(|original, index| original * (index + 1))
-        );
-
-        let escalator = dispatch_fut
-            .await
-            .map_err(Box::new)
-            .map_err(|e| ChainCommunicationError::CustomError(e))?
-            .with_broadcast_interval(std::time::Duration::from_secs(60));
-
-        let result = escalator
-            .await?;
-
-        tracing::info!(
-            "confirmed transaction with tx_hash {:?}",
-            result.transaction_hash
-        );
-
-        result
-    }};
-
-    // Legacy way of sending transactions.
-    (@legacy $tx:expr, $($tail:tt)*) => {{
         log_tx_details!($tx);
 
         let dispatch_fut = $tx.send();
@@ -72,7 +40,7 @@ macro_rules! log_tx_details {
 }
 
 macro_rules! boxed_trait {
-    (@finish $provider:expr, $abi:ident, $signer:ident, $($tail:tt)*) => {{
+    (@finish $provider:expr, $abi:ident, $signer:ident, $db:ident, $shutdown:ident, $gas:ident, $txm:ident, $($tail:tt)*) => {{
         if let Some(signer) = $signer {
             // If there's a provided signer, we want to manage every aspect
             // locally
@@ -81,19 +49,27 @@ macro_rules! boxed_trait {
             let provider_chain_id = $provider.get_chainid().await?;
             let signer = ethers::signers::Signer::with_chain_id(signer, provider_chain_id.as_u64());
 
-            // Manage the nonce locally
             let address = ethers::prelude::Signer::address(&signer);
-            let provider =
-                ethers::middleware::nonce_manager::NonceManagerMiddleware::new($provider, address);
 
-            // Kludge. Increase the gas by multiplication of every estimated gas by 2
-            // except the gas for chain id 1 (Ethereum Mainnet)
-            let provider = crate::gas::GasAdjusterMiddleware::with_default_policy(provider, provider_chain_id.as_u64());
+            // Adjust fees using the chain's gas policy, or the default policy
+            // if none is configured
+            let gas = $gas.unwrap_or_else(|| crate::gas::GasSettings::default_for_chain(provider_chain_id.as_u64()));
+            let provider = crate::gas::GasAdjusterMiddleware::new($provider, gas);
 
             // Manage signing locally
             let signing_provider = ethers::middleware::SignerMiddleware::new(provider, signer);
 
-            Box::new(crate::$abi::new(signing_provider.into(), $($tail)*))
+            // Manage nonces, fee escalation and stuck transactions locally
+            let tx_manager = crate::tx_manager::TxManager::new(
+                signing_provider,
+                address,
+                provider_chain_id.as_u64(),
+                $db,
+                $txm.unwrap_or_default(),
+                $shutdown,
+            );
+
+            Box::new(crate::$abi::new(tx_manager.into(), $($tail)*))
         } else {
             Box::new(crate::$abi::new($provider, $($tail)*))
         }
@@ -121,15 +97,29 @@ macro_rules! boxed_trait {
             boxed_trait!(@finish provider, $($tail)*)
         }
     }};
+    (@failover $urls:expr, $quorum:expr, $timelag:ident, $($tail:tt)*) => {{
+        let provider: crate::failover::FailoverProvider<ethers::providers::Http> =
+            crate::failover::FailoverProvider::from_urls(&$urls, $quorum)?;
+        let provider = ethers::providers::Provider::new(provider);
+        let provider = Arc::new(provider);
+        if let Some(lag) = $timelag {
+            boxed_trait!(@timelag provider, lag, $($tail)*)
+        } else {
+            boxed_trait!(@finish provider, $($tail)*)
+        }
+    }};
     ($name:ident, $abi:ident, $trait:ident, $($n:ident:$t:ty),*)  => {
         #[doc = "Cast a contract locator to a live contract handle"]
-        pub async fn $name(conn: Connection, locator: &ContractLocator, signer: Option<Signers>, timelag: Option<u8>, $($n:$t),*) -> color_eyre::Result<Box<dyn $trait>> {
+        pub async fn $name(conn: Connection, locator: &ContractLocator, signer: Option<Signers>, tx_db: Option<nomad_core::db::DB>, shutdown: Option<tokio::sync::watch::Receiver<bool>>, gas: Option<crate::GasSettings>, tx_manager: Option<crate::TxManagerConfig>, timelag: Option<u8>, $($n:$t),*) -> color_eyre::Result<Box<dyn $trait>> {
             let b: Box<dyn $trait> = match conn {
                 Connection::Http { url } => {
-                    boxed_trait!(@http url, timelag, $abi, signer, locator, $($n),*)
+                    boxed_trait!(@http url, timelag, $abi, signer, tx_db, shutdown, gas, tx_manager, locator, $($n),*)
                 }
                 Connection::Ws { url } => {
-                    boxed_trait!(@ws url, timelag, $abi, signer, locator, $($n),*)
+                    boxed_trait!(@ws url, timelag, $abi, signer, tx_db, shutdown, gas, tx_manager, locator, $($n),*)
+                }
+                Connection::Failover { urls, quorum } => {
+                    boxed_trait!(@failover urls, quorum, timelag, $abi, signer, tx_db, shutdown, gas, tx_manager, locator, $($n),*)
                 }
             };
             Ok(b)
@@ -4,16 +4,17 @@
 use async_trait::async_trait;
 use color_eyre::Result;
 use ethers::core::types::{Signature, H256};
-use futures_util::future::join_all;
 use nomad_core::{
     accumulator::merkle::Proof, ChainCommunicationError, Common, CommonIndexer, ContractLocator,
     DoubleUpdate, Encode, MessageStatus, NomadMessage, Replica, SignedUpdate, SignedUpdateWithMeta,
-    State, TxOutcome, Update, UpdateMeta,
+    State, TxOutcome, Update,
 };
 use std::{convert::TryFrom, error::Error as StdError, sync::Arc};
 use tracing::instrument;
 
-use crate::{bindings::replica::Replica as EthereumReplicaInternal, report_tx};
+use crate::{
+    bindings::replica::Replica as EthereumReplicaInternal, block_timestamps, report_tx, tx_meta,
+};
 
 #[derive(Debug)]
 /// Struct that retrieves indexes event data for Ethereum replica
@@ -61,6 +62,15 @@ where
         Ok(self.provider.get_block_number().await?.as_u32())
     }
 
+    #[instrument(err, skip(self))]
+    async fn get_block_hash(&self, height: u32) -> Result<Option<H256>> {
+        Ok(self
+            .provider
+            .get_block(height)
+            .await?
+            .and_then(|block| block.hash))
+    }
+
     #[instrument(err, skip(self))]
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         let mut events = self
@@ -80,9 +90,12 @@ where
             ordering
         });
 
-        let update_futs: Vec<_> = events
+        let timestamps =
+            block_timestamps(self.provider.as_ref(), events.iter().map(|event| &event.1)).await;
+
+        Ok(events
             .iter()
-            .map(|event| async {
+            .map(|event| {
                 let signature = Signature::try_from(event.0.signature.as_ref())
                     .expect("chain accepted invalid signature");
 
@@ -92,26 +105,12 @@ where
                     new_root: event.0.new_root.into(),
                 };
 
-                let block_number = event.1.block_number.as_u64();
-                let timestamp = self
-                    .provider
-                    .get_block(block_number)
-                    .await
-                    .ok()
-                    .flatten()
-                    .map(|b| b.timestamp.as_u64());
-
                 SignedUpdateWithMeta {
                     signed_update: SignedUpdate { update, signature },
-                    metadata: UpdateMeta {
-                        block_number,
-                        timestamp,
-                    },
+                    metadata: tx_meta(&event.1, &timestamps),
                 }
             })
-            .collect();
-
-        Ok(join_all(update_futs).await)
+            .collect())
     }
 }
 
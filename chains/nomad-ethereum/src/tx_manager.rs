@@ -0,0 +1,786 @@
+use ethers::providers::{FromErr, Middleware, PendingTransaction};
+use ethers::types::{
+    transaction::eip2718::TypedTransaction, Address, BlockId, BlockNumber, H256, U256,
+};
+use nomad_core::db::{Column, DbError, TypedDB, DB};
+use nomad_core::{Decode, Encode, NomadError};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::{
+    sync::{watch, Mutex as AsyncMutex},
+    time::{sleep, timeout},
+};
+use tracing::{error, info, warn};
+
+/// Prefix for in-flight transactions, keyed by nonce
+static IN_FLIGHT: &str = "in_flight_";
+
+const GWEI: u64 = 1_000_000_000;
+
+/// Process-wide registry of signer state, keyed by chain id and signer
+/// address. Every TxManager for the same signer on the same chain shares
+/// nonces.
+static SIGNERS: Lazy<Mutex<HashMap<(u64, Address), Arc<SignerState>>>> =
+    Lazy::new(Default::default);
+
+/// Fee escalation schedule for transactions dispatched by a TxManager
+#[derive(Debug, Clone)]
+pub struct TxManagerConfig {
+    /// Interval at which in-flight transactions are checked
+    pub poll_interval: Duration,
+    /// Time a transaction may stay unmined before its fees are bumped
+    pub escalation_interval: Duration,
+    /// Percentage by which fees are bumped. Most nodes reject replacements
+    /// bumped by less than 10%.
+    pub bump_percent: u64,
+    /// Maximum number of fee bumps per transaction. A transaction still
+    /// unmined one `escalation_interval` after its last bump is given up on.
+    pub max_escalations: usize,
+    /// Fees are never bumped past this gas price
+    pub max_gas_price: Option<U256>,
+}
+
+impl Default for TxManagerConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(15),
+            escalation_interval: Duration::from_secs(60),
+            bump_percent: 20,
+            max_escalations: 5,
+            max_gas_price: None,
+        }
+    }
+}
+
+/// TxManager settings as read from agent config. Unset fields take the
+/// `TxManagerConfig` defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TxManagerSettings {
+    /// Interval at which in-flight transactions are checked, in seconds
+    #[serde(default)]
+    pub poll_interval_secs: Option<u64>,
+    /// Time a transaction may stay unmined before its fees are bumped, in
+    /// seconds
+    #[serde(default)]
+    pub escalation_interval_secs: Option<u64>,
+    /// Percentage by which fees are bumped
+    #[serde(default)]
+    pub bump_percent: Option<u64>,
+    /// Maximum number of fee bumps per transaction
+    #[serde(default)]
+    pub max_escalations: Option<usize>,
+    /// Fees are never bumped past this gas price, in gwei
+    #[serde(default)]
+    pub max_gas_price_gwei: Option<u64>,
+}
+
+impl From<TxManagerSettings> for TxManagerConfig {
+    fn from(settings: TxManagerSettings) -> Self {
+        let default = Self::default();
+        Self {
+            poll_interval: settings
+                .poll_interval_secs
+                .map_or(default.poll_interval, Duration::from_secs),
+            escalation_interval: settings
+                .escalation_interval_secs
+                .map_or(default.escalation_interval, Duration::from_secs),
+            bump_percent: settings.bump_percent.unwrap_or(default.bump_percent),
+            max_escalations: settings.max_escalations.unwrap_or(default.max_escalations),
+            max_gas_price: settings
+                .max_gas_price_gwei
+                .map(|gwei| U256::from(gwei) * GWEI)
+                .or(default.max_gas_price),
+        }
+    }
+}
+
+/// State of an in-flight transaction's nonce on chain
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TxStatus {
+    /// Not mined yet
+    Pending,
+    /// A version of the transaction was mined with this hash
+    Mined(H256),
+    /// The nonce was consumed by a transaction not dispatched by this
+    /// manager
+    NonceConsumed,
+    /// Not mined, and fees can't be bumped any further
+    Stuck,
+}
+
+/// A transaction broadcast by a TxManager that has not been mined yet
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct InFlightTx {
+    /// Latest version of the transaction, with nonce and fees filled
+    pub tx: TypedTransaction,
+    /// Hashes of every version broadcast, oldest first
+    pub hashes: Vec<H256>,
+    /// Number of fee bumps so far
+    pub escalations: usize,
+}
+
+impl InFlightTx {
+    fn nonce(&self) -> U256 {
+        *self.tx.nonce().expect("!nonce")
+    }
+
+    fn latest_hash(&self) -> H256 {
+        *self.hashes.last().expect("!hashes")
+    }
+}
+
+impl Encode for InFlightTx {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        let buf = serde_json::to_vec(self)?;
+        writer.write_all(&buf)?;
+        Ok(buf.len())
+    }
+}
+
+impl Decode for InFlightTx {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+        Self: Sized,
+    {
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf)?;
+        Ok(serde_json::from_slice(&buf).map_err(std::io::Error::from)?)
+    }
+}
+
+/// Nonce and persistence state shared by every TxManager for one signer
+#[derive(Debug)]
+struct SignerState {
+    /// Next nonce to assign. `None` until read from chain, and reset after a
+    /// failed broadcast.
+    next_nonce: AsyncMutex<Option<U256>>,
+    /// Whether in-flight transactions persisted by a previous run have been
+    /// picked up
+    resumed: AtomicBool,
+    /// Store for in-flight transactions
+    db: Option<TypedDB>,
+}
+
+fn nonce_key(nonce: U256) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    nonce.to_big_endian(&mut key);
+    key
+}
+
+/// Raise the fees of `tx` by `percent`, and by at least 1 wei. Returns false
+/// and leaves `tx` unchanged if the new gas price would exceed
+/// `max_gas_price`.
+fn bump_fees(tx: &mut TypedTransaction, percent: u64, max_gas_price: Option<U256>) -> bool {
+    let bump = |fee: U256| fee + std::cmp::max(fee * percent / 100, U256::one());
+
+    let price = match tx.gas_price() {
+        Some(price) => bump(price),
+        None => return false,
+    };
+    if max_gas_price.map_or(false, |max| price > max) {
+        return false;
+    }
+
+    let priority_fee = match &*tx {
+        TypedTransaction::Eip1559(inner) => inner.max_priority_fee_per_gas,
+        _ => None,
+    };
+    tx.set_gas_price(price);
+    if let TypedTransaction::Eip1559(inner) = tx {
+        inner.max_priority_fee_per_gas = priority_fee.map(bump);
+    }
+
+    true
+}
+
+/// Middleware that owns the nonces of a signer. Dispatched transactions are
+/// tracked until mined: fees are bumped on a schedule, dropped transactions
+/// are re-broadcast, and in-flight transactions are persisted so they can be
+/// resumed after a restart.
+///
+/// Must wrap a signing middleware, as bumped transactions are re-signed.
+pub struct TxManager<M> {
+    inner: Arc<M>,
+    address: Address,
+    state: Arc<SignerState>,
+    config: TxManagerConfig,
+    shutdown: Option<watch::Receiver<bool>>,
+}
+
+impl<M> Clone for TxManager<M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            address: self.address,
+            state: self.state.clone(),
+            config: self.config.clone(),
+            shutdown: self.shutdown.clone(),
+        }
+    }
+}
+
+impl<M> fmt::Debug for TxManager<M>
+where
+    M: Middleware,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TxManager")
+            .field("inner", &self.inner)
+            .field("address", &self.address)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+#[derive(Error, Debug)]
+/// Thrown when an error happens at the TxManager
+pub enum TxManagerError<M: Middleware> {
+    /// Thrown when the internal middleware errors
+    #[error("{0}")]
+    MiddlewareError(M::Error),
+    /// Thrown when persisting in-flight transactions fails
+    #[error("{0}")]
+    DbError(#[from] DbError),
+    /// The nonce of a transaction was used by a transaction not dispatched
+    /// by this manager
+    #[error("Nonce {nonce} of transaction {tx_hash:?} was consumed by another transaction")]
+    NonceConsumed {
+        /// The consumed nonce
+        nonce: U256,
+        /// Hash of the latest version broadcast
+        tx_hash: H256,
+    },
+    /// A transaction was not mined after its fees were bumped as far as
+    /// allowed. It stays persisted and is resumed on restart.
+    #[error(
+        "Transaction {tx_hash:?} with nonce {nonce} was not mined after exhausting fee escalations"
+    )]
+    EscalationsExhausted {
+        /// The transaction's nonce
+        nonce: U256,
+        /// Hash of the latest version broadcast
+        tx_hash: H256,
+    },
+    /// Shutdown was triggered while waiting for a transaction to be mined.
+    /// It stays persisted and is resumed on restart.
+    #[error("Shutdown while waiting for transaction {tx_hash:?} with nonce {nonce}")]
+    Shutdown {
+        /// The transaction's nonce
+        nonce: U256,
+        /// Hash of the latest version broadcast
+        tx_hash: H256,
+    },
+}
+
+/// Convert inner Middleware error into TxManagerError
+impl<M: Middleware> FromErr<M::Error> for TxManagerError<M> {
+    fn from(src: M::Error) -> Self {
+        TxManagerError::MiddlewareError(src)
+    }
+}
+
+impl<M> TxManager<M>
+where
+    M: Middleware + 'static,
+{
+    /// Instantiate a TxManager for `address` on chain `chain_id`. State is
+    /// shared with every other TxManager for the same signer and chain. The
+    /// first one instantiated determines where in-flight transactions are
+    /// persisted. Waiting for transactions to be mined stops once `shutdown`
+    /// turns true.
+    pub fn new(
+        inner: M,
+        address: Address,
+        chain_id: u64,
+        db: Option<DB>,
+        config: TxManagerConfig,
+        shutdown: Option<watch::Receiver<bool>>,
+    ) -> Self {
+        let state = SIGNERS
+            .lock()
+            .expect("!lock")
+            .entry((chain_id, address))
+            .or_insert_with(|| {
+                Arc::new(SignerState {
+                    next_nonce: AsyncMutex::new(None),
+                    resumed: AtomicBool::new(false),
+                    db: db.map(|db| {
+                        TypedDB::new(format!("tx_manager_{}_{:x}", chain_id, address), db)
+                    }),
+                })
+            })
+            .clone();
+
+        Self {
+            inner: Arc::new(inner),
+            address,
+            state,
+            config,
+            shutdown,
+        }
+    }
+
+    /// Sleep for `duration`, waking early on shutdown. Returns `true` if
+    /// shutdown was triggered.
+    async fn sleep(&self, duration: Duration) -> bool {
+        let mut shutdown = match &self.shutdown {
+            Some(shutdown) => shutdown.clone(),
+            None => {
+                sleep(duration).await;
+                return false;
+            }
+        };
+
+        let triggered = async {
+            while !*shutdown.borrow() {
+                if shutdown.changed().await.is_err() {
+                    // Shutdown can no longer be triggered
+                    std::future::pending::<()>().await;
+                }
+            }
+        };
+        timeout(duration, triggered).await.is_ok()
+    }
+
+    fn persist(&self, in_flight: &InFlightTx) -> Result<(), TxManagerError<M>> {
+        if let Some(db) = &self.state.db {
+            db.store_encodable(IN_FLIGHT, nonce_key(in_flight.nonce()), in_flight)?;
+        }
+        Ok(())
+    }
+
+    fn forget(&self, nonce: U256) -> Result<(), TxManagerError<M>> {
+        if let Some(db) = &self.state.db {
+            db.delete_value(IN_FLIGHT, nonce_key(nonce))?;
+        }
+        Ok(())
+    }
+
+    /// Read the next nonce from chain. On first call, resume watching
+    /// in-flight transactions persisted by a previous run.
+    async fn sync_nonce(&self) -> Result<U256, TxManagerError<M>> {
+        let mined = self
+            .inner
+            .get_transaction_count(self.address, Some(BlockNumber::Latest.into()))
+            .await
+            .map_err(FromErr::from)?;
+        let pending = self
+            .inner
+            .get_transaction_count(self.address, Some(BlockNumber::Pending.into()))
+            .await
+            .map_err(FromErr::from)?;
+
+        let mut next = std::cmp::max(mined, pending);
+
+        if let Some(db) = &self.state.db {
+            if !self.state.resumed.swap(true, Ordering::SeqCst) {
+                next = std::cmp::max(next, self.resume(db, mined)?);
+            }
+        }
+
+        Ok(next)
+    }
+
+    /// Resume watching the in-flight transactions persisted by a previous
+    /// run with consecutive nonces from `mined`. Others are dropped: their
+    /// nonce was either mined already or will be assigned again. Returns the
+    /// nonce after the last one resumed.
+    fn resume(&self, db: &TypedDB, mined: U256) -> Result<U256, TxManagerError<M>> {
+        let persisted = db
+            .column_range_iterator::<U256, InFlightTx>(Column::Agent, IN_FLIGHT, ..)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut next = mined;
+        for (nonce, in_flight) in persisted {
+            if nonce != next {
+                info!(
+                    nonce = %nonce,
+                    mined = %mined,
+                    tx_hash = ?in_flight.latest_hash(),
+                    "Dropping persisted transaction"
+                );
+                self.forget(nonce)?;
+                continue;
+            }
+
+            info!(
+                nonce = %nonce,
+                tx_hash = ?in_flight.latest_hash(),
+                "Resuming in-flight transaction"
+            );
+            let manager = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = manager.watch(in_flight).await {
+                    error!(error = %e, "Resumed transaction failed");
+                }
+            });
+            next += U256::one();
+        }
+
+        Ok(next)
+    }
+
+    /// Assign the next nonce to `tx`, fill and broadcast it
+    async fn dispatch(
+        &self,
+        mut tx: TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<InFlightTx, TxManagerError<M>> {
+        let mut next_nonce = self.state.next_nonce.lock().await;
+        let nonce = match *next_nonce {
+            Some(nonce) => nonce,
+            None => self.sync_nonce().await?,
+        };
+        *next_nonce = Some(nonce);
+
+        tx.set_from(self.address);
+        tx.set_nonce(nonce);
+        self.inner
+            .fill_transaction(&mut tx, block)
+            .await
+            .map_err(FromErr::from)?;
+
+        let tx_hash = match self.inner.send_transaction(tx.clone(), block).await {
+            Ok(pending) => *pending,
+            Err(e) => {
+                // The nonce may have been used elsewhere. Re-read it on next
+                // dispatch
+                *next_nonce = None;
+                return Err(FromErr::from(e));
+            }
+        };
+
+        let in_flight = InFlightTx {
+            tx,
+            hashes: vec![tx_hash],
+            escalations: 0,
+        };
+        self.persist(&in_flight)?;
+        *next_nonce = Some(nonce + U256::one());
+
+        info!(nonce = %nonce, tx_hash = ?tx_hash, "Broadcast transaction");
+        Ok(in_flight)
+    }
+
+    /// Broadcast the latest version of `in_flight`. Errors are logged rather
+    /// than returned, as an earlier version may still be mined.
+    async fn rebroadcast(&self, in_flight: &mut InFlightTx) -> Result<(), TxManagerError<M>> {
+        match self
+            .inner
+            .send_transaction(in_flight.tx.clone(), None)
+            .await
+        {
+            Ok(pending) => {
+                let tx_hash = *pending;
+                if !in_flight.hashes.contains(&tx_hash) {
+                    in_flight.hashes.push(tx_hash);
+                }
+            }
+            Err(e) => warn!(
+                nonce = %in_flight.nonce(),
+                error = %e,
+                "Failed to re-broadcast transaction"
+            ),
+        }
+        self.persist(in_flight)
+    }
+
+    /// Hash of the mined version of `in_flight`, if any
+    async fn mined_hash(&self, in_flight: &InFlightTx) -> Result<Option<H256>, TxManagerError<M>> {
+        for tx_hash in in_flight.hashes.iter().rev() {
+            if self
+                .inner
+                .get_transaction_receipt(*tx_hash)
+                .await
+                .map_err(FromErr::from)?
+                .is_some()
+            {
+                return Ok(Some(*tx_hash));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Wait for a version of `in_flight` to be mined, bumping fees every
+    /// `escalation_interval` and re-broadcasting it if dropped from the
+    /// mempool. Returns the hash of the mined version. RPC and db errors are
+    /// retried, so this only returns once the persisted transaction has been
+    /// forgotten, the escalation budget ran out, or shutdown was triggered.
+    /// In the latter two cases the transaction stays persisted.
+    async fn watch(&self, mut in_flight: InFlightTx) -> Result<H256, TxManagerError<M>> {
+        let nonce = in_flight.nonce();
+        let mut last_broadcast = Instant::now();
+
+        loop {
+            if self.sleep(self.config.poll_interval).await {
+                return Err(TxManagerError::Shutdown {
+                    nonce,
+                    tx_hash: in_flight.latest_hash(),
+                });
+            }
+
+            let status = match self.poll(&mut in_flight, &mut last_broadcast).await {
+                Ok(TxStatus::Pending) => continue,
+                Ok(TxStatus::Stuck) => {
+                    error!(
+                        nonce = %nonce,
+                        escalations = in_flight.escalations,
+                        tx_hash = ?in_flight.latest_hash(),
+                        "Giving up on stuck transaction"
+                    );
+                    return Err(TxManagerError::EscalationsExhausted {
+                        nonce,
+                        tx_hash: in_flight.latest_hash(),
+                    });
+                }
+                Ok(status) => status,
+                Err(e) => {
+                    warn!(
+                        nonce = %nonce,
+                        error = %e,
+                        "Failed to check in-flight transaction. Retrying"
+                    );
+                    continue;
+                }
+            };
+
+            if let Err(e) = self.forget(nonce) {
+                warn!(
+                    nonce = %nonce,
+                    error = %e,
+                    "Failed to forget resolved transaction. Retrying"
+                );
+                continue;
+            }
+
+            return match status {
+                TxStatus::Mined(tx_hash) => Ok(tx_hash),
+                _ => Err(TxManagerError::NonceConsumed {
+                    nonce,
+                    tx_hash: in_flight.latest_hash(),
+                }),
+            };
+        }
+    }
+
+    /// Check whether `in_flight` was mined. If not, bump its fees or
+    /// re-broadcast it as needed. Reports it stuck once it is due for a bump
+    /// that can't be made.
+    async fn poll(
+        &self,
+        in_flight: &mut InFlightTx,
+        last_broadcast: &mut Instant,
+    ) -> Result<TxStatus, TxManagerError<M>> {
+        let nonce = in_flight.nonce();
+
+        if let Some(tx_hash) = self.mined_hash(in_flight).await? {
+            return Ok(TxStatus::Mined(tx_hash));
+        }
+
+        let mined = self
+            .inner
+            .get_transaction_count(self.address, Some(BlockNumber::Latest.into()))
+            .await
+            .map_err(FromErr::from)?;
+        if mined > nonce {
+            // A version may have been mined since the receipt check
+            return Ok(self
+                .mined_hash(in_flight)
+                .await?
+                .map_or(TxStatus::NonceConsumed, TxStatus::Mined));
+        }
+
+        let due = last_broadcast.elapsed() >= self.config.escalation_interval;
+        let escalate = due
+            && in_flight.escalations < self.config.max_escalations
+            && bump_fees(
+                &mut in_flight.tx,
+                self.config.bump_percent,
+                self.config.max_gas_price,
+            );
+
+        if escalate {
+            in_flight.escalations += 1;
+            info!(
+                nonce = %nonce,
+                escalations = in_flight.escalations,
+                gas_price = ?in_flight.tx.gas_price(),
+                "Bumping fees of stuck transaction"
+            );
+        } else if self
+            .inner
+            .get_transaction(in_flight.latest_hash())
+            .await
+            .map_err(FromErr::from)?
+            .is_some()
+        {
+            return Ok(if due {
+                TxStatus::Stuck
+            } else {
+                TxStatus::Pending
+            });
+        } else {
+            warn!(
+                nonce = %nonce,
+                tx_hash = ?in_flight.latest_hash(),
+                "Transaction dropped from mempool, re-broadcasting"
+            );
+        }
+
+        *last_broadcast = Instant::now();
+        self.rebroadcast(in_flight).await?;
+        Ok(TxStatus::Pending)
+    }
+}
+
+#[async_trait::async_trait]
+impl<M> Middleware for TxManager<M>
+where
+    M: Middleware + 'static,
+{
+    type Error = TxManagerError<M>;
+    type Provider = M::Provider;
+    type Inner = M;
+
+    fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    /// Dispatch `tx` with the signer's next nonce and wait until a version
+    /// of it is mined. The returned PendingTransaction resolves to the
+    /// receipt of the mined version.
+    async fn send_transaction<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        tx: T,
+        block: Option<BlockId>,
+    ) -> Result<PendingTransaction<'_, Self::Provider>, Self::Error> {
+        let in_flight = self.dispatch(tx.into(), block).await?;
+        let tx_hash = self.watch(in_flight).await?;
+
+        Ok(PendingTransaction::new(tx_hash, self.provider()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::providers::Provider;
+    use ethers::types::{Eip1559TransactionRequest, TransactionRequest};
+
+    use super::*;
+
+    #[test]
+    fn it_bumps_legacy_fees() {
+        let mut tx: TypedTransaction = TransactionRequest::new().gas_price(100).into();
+
+        assert!(bump_fees(&mut tx, 20, None));
+        assert_eq!(tx.gas_price(), Some(120.into()));
+
+        // Capped
+        assert!(!bump_fees(&mut tx, 20, Some(140.into())));
+        assert_eq!(tx.gas_price(), Some(120.into()));
+
+        // Bumped by at least 1 wei
+        let mut tx: TypedTransaction = TransactionRequest::new().gas_price(1).into();
+        assert!(bump_fees(&mut tx, 20, None));
+        assert_eq!(tx.gas_price(), Some(2.into()));
+    }
+
+    #[test]
+    fn it_bumps_eip1559_fees() {
+        let mut tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .max_fee_per_gas(200)
+            .max_priority_fee_per_gas(10)
+            .into();
+
+        assert!(bump_fees(&mut tx, 20, None));
+        match tx {
+            TypedTransaction::Eip1559(inner) => {
+                assert_eq!(inner.max_fee_per_gas, Some(240.into()));
+                assert_eq!(inner.max_priority_fee_per_gas, Some(12.into()));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn it_defaults_unset_settings() {
+        let config: TxManagerConfig = TxManagerSettings {
+            escalation_interval_secs: Some(30),
+            max_gas_price_gwei: Some(500),
+            ..Default::default()
+        }
+        .into();
+
+        assert_eq!(config.escalation_interval, Duration::from_secs(30));
+        assert_eq!(config.max_gas_price, Some(U256::from(500) * GWEI));
+        assert_eq!(
+            config.poll_interval,
+            TxManagerConfig::default().poll_interval
+        );
+        assert_eq!(config.bump_percent, TxManagerConfig::default().bump_percent);
+    }
+
+    #[tokio::test]
+    async fn it_stops_watching_on_shutdown() {
+        let (provider, _mock) = Provider::mocked();
+        let (trigger, shutdown) = watch::channel(false);
+        let manager = TxManager::new(
+            provider,
+            Address::repeat_byte(2),
+            1,
+            None,
+            TxManagerConfig {
+                poll_interval: Duration::from_secs(3600),
+                ..Default::default()
+            },
+            Some(shutdown),
+        );
+
+        let in_flight = InFlightTx {
+            tx: TransactionRequest::new().nonce(3).gas_price(100).into(),
+            hashes: vec![H256::repeat_byte(3)],
+            escalations: 0,
+        };
+
+        trigger.send(true).unwrap();
+        match manager.watch(in_flight).await.unwrap_err() {
+            TxManagerError::Shutdown { nonce, tx_hash } => {
+                assert_eq!(nonce, 3.into());
+                assert_eq!(tx_hash, H256::repeat_byte(3));
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn it_round_trips_in_flight_txs() {
+        let in_flight = InFlightTx {
+            tx: TransactionRequest::new()
+                .to(Address::repeat_byte(1))
+                .nonce(7)
+                .gas_price(100)
+                .into(),
+            hashes: vec![H256::repeat_byte(1), H256::repeat_byte(2)],
+            escalations: 1,
+        };
+
+        let decoded = InFlightTx::read_from(&mut in_flight.to_vec().as_slice()).unwrap();
+        assert_eq!(decoded, in_flight);
+        assert_eq!(decoded.nonce(), 7.into());
+    }
+}
@@ -0,0 +1,452 @@
+use ethers::{
+    core::types::{H160, H256},
+    utils::keccak256,
+};
+use nomad_core::{
+    models::{home, replica},
+    ChainCommunicationError, DoubleUpdate, NomadError, RawCommittedMessage, SignedUpdate, TxMeta,
+    TxOutcome,
+};
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{home::HomeModel, replica::ReplicaModel, xapp::ConnectionManagerModel};
+
+/// Timestamp of the genesis block of every simulated chain
+const GENESIS_TIMESTAMP: u64 = 1_600_000_000;
+
+/// Seconds between two consecutive blocks
+const BLOCK_TIME: u64 = 1;
+
+/// Process-wide registry of simulated chains, keyed by network name
+static CHAINS: Lazy<Mutex<HashMap<String, SimChain>>> = Lazy::new(Default::default);
+
+/// Error types for simulated contracts
+#[derive(Debug, thiserror::Error)]
+pub enum SimError {
+    /// No contract of the expected kind is deployed at the address
+    #[error("No {kind} deployed at {address:?}")]
+    NotDeployed {
+        /// The kind of contract that was expected
+        kind: &'static str,
+        /// The address that was called
+        address: H160,
+    },
+    /// A contract is already deployed at the address
+    #[error("Address already in use: {0:?}")]
+    AddressInUse(H160),
+    /// The transaction reverted
+    #[error("Transaction reverted: {0}")]
+    Reverted(&'static str),
+    /// Nomad Error
+    #[error("{0}")]
+    NomadError(#[from] NomadError),
+}
+
+impl From<SimError> for ChainCommunicationError {
+    fn from(e: SimError) -> Self {
+        ChainCommunicationError::CustomError(Box::new(e))
+    }
+}
+
+/// Revert unless `double` is two distinct updates building off the same root
+pub(crate) fn check_double_update(double: &DoubleUpdate) -> Result<(), SimError> {
+    let (first, second) = (&double.0.update, &double.1.update);
+    if first.previous_root != second.previous_root || first.new_root == second.new_root {
+        return Err(SimError::Reverted("!double update"));
+    }
+    Ok(())
+}
+
+/// An event emitted by a simulated contract
+#[derive(Debug, Clone)]
+pub enum SimEvent {
+    /// A signed update was accepted
+    Update(SignedUpdate),
+    /// A message was dispatched
+    Dispatch(RawCommittedMessage),
+}
+
+/// An event along with the block, transaction and contract that emitted it
+#[derive(Debug, Clone)]
+pub struct SimLog {
+    /// The block in which the event was emitted
+    pub block_number: u64,
+    /// The transaction that emitted the event
+    pub transaction_hash: H256,
+    /// The index of the event in its block
+    pub log_index: u64,
+    /// The address of the emitting contract
+    pub address: H160,
+    /// The event
+    pub event: SimEvent,
+}
+
+/// Contract state, snapshotted at every block in which it changed. Reads at
+/// a lagged height are served from the latest snapshot at or below it.
+#[derive(Debug)]
+pub(crate) struct Versioned<T> {
+    history: Vec<(u64, T)>,
+}
+
+impl<T: Clone> Versioned<T> {
+    fn new(block: u64, state: T) -> Self {
+        Self {
+            history: vec![(block, state)],
+        }
+    }
+
+    /// The state as of `block`, if the contract was deployed by then
+    pub(crate) fn at(&self, block: u64) -> Option<&T> {
+        self.history
+            .iter()
+            .rev()
+            .find(|(height, _)| *height <= block)
+            .map(|(_, state)| state)
+    }
+
+    /// The most recent state
+    pub(crate) fn latest(&self) -> &T {
+        &self.history.last().expect("!history").1
+    }
+
+    /// Apply `f` to a copy of the latest state and keep the result as the
+    /// state at `block`. The state is left untouched if `f` fails.
+    pub(crate) fn transition<R, F>(&mut self, block: u64, f: F) -> Result<R, SimError>
+    where
+        F: FnOnce(&mut T) -> Result<R, SimError>,
+    {
+        let mut next = self.latest().clone();
+        let res = f(&mut next)?;
+        match self.history.last_mut() {
+            Some((height, state)) if *height == block => *state = next,
+            _ => self.history.push((block, next)),
+        }
+        Ok(res)
+    }
+}
+
+/// The full state of a simulated chain
+#[derive(Debug, Default)]
+pub(crate) struct ChainState {
+    /// Timestamps of all mined blocks, indexed by block number
+    timestamps: Vec<u64>,
+    /// Seconds to add to the timestamp of the next block
+    time_offset: u64,
+    logs: Vec<SimLog>,
+    receipts: HashMap<H256, TxOutcome>,
+    homes: HashMap<H160, Versioned<HomeModel>>,
+    replicas: HashMap<H160, Versioned<ReplicaModel>>,
+    managers: HashMap<H160, Versioned<ConnectionManagerModel>>,
+}
+
+impl ChainState {
+    fn new() -> Self {
+        Self {
+            timestamps: vec![GENESIS_TIMESTAMP],
+            ..Default::default()
+        }
+    }
+
+    /// The latest mined block
+    pub(crate) fn block_number(&self) -> u64 {
+        self.timestamps.len() as u64 - 1
+    }
+
+    /// The height at which reads lagging `timelag` blocks are served
+    pub(crate) fn lagged(&self, timelag: u64) -> u64 {
+        self.block_number().saturating_sub(timelag)
+    }
+
+    /// The timestamp of a mined block
+    pub(crate) fn timestamp_at(&self, block: u64) -> Option<u64> {
+        self.timestamps.get(block as usize).copied()
+    }
+
+    /// The timestamp the next block will be mined with
+    pub(crate) fn pending_timestamp(&self) -> u64 {
+        self.timestamps.last().expect("!genesis") + BLOCK_TIME + self.time_offset
+    }
+
+    fn mine(&mut self) -> u64 {
+        let timestamp = self.pending_timestamp();
+        self.timestamps.push(timestamp);
+        self.time_offset = 0;
+        self.block_number()
+    }
+
+    fn ensure_unused(&self, address: H160) -> Result<(), SimError> {
+        if self.homes.contains_key(&address)
+            || self.replicas.contains_key(&address)
+            || self.managers.contains_key(&address)
+        {
+            return Err(SimError::AddressInUse(address));
+        }
+        Ok(())
+    }
+
+    /// All events emitted by `address` between blocks `from` and `to`
+    /// (inclusive), in the order they were emitted
+    pub(crate) fn logs(&self, address: H160, from: u64, to: u64) -> impl Iterator<Item = &SimLog> {
+        self.logs.iter().filter(move |log| {
+            log.address == address && log.block_number >= from && log.block_number <= to
+        })
+    }
+
+    pub(crate) fn receipt(&self, txid: H256) -> Option<TxOutcome> {
+        self.receipts.get(&txid).copied()
+    }
+
+    pub(crate) fn home(&self, address: H160) -> Result<&Versioned<HomeModel>, SimError> {
+        self.homes.get(&address).ok_or(SimError::NotDeployed {
+            kind: "home",
+            address,
+        })
+    }
+
+    pub(crate) fn home_mut(
+        &mut self,
+        address: H160,
+    ) -> Result<&mut Versioned<HomeModel>, SimError> {
+        self.homes.get_mut(&address).ok_or(SimError::NotDeployed {
+            kind: "home",
+            address,
+        })
+    }
+
+    pub(crate) fn replica(&self, address: H160) -> Result<&Versioned<ReplicaModel>, SimError> {
+        self.replicas.get(&address).ok_or(SimError::NotDeployed {
+            kind: "replica",
+            address,
+        })
+    }
+
+    pub(crate) fn replica_mut(
+        &mut self,
+        address: H160,
+    ) -> Result<&mut Versioned<ReplicaModel>, SimError> {
+        self.replicas
+            .get_mut(&address)
+            .ok_or(SimError::NotDeployed {
+                kind: "replica",
+                address,
+            })
+    }
+
+    pub(crate) fn manager(
+        &self,
+        address: H160,
+    ) -> Result<&Versioned<ConnectionManagerModel>, SimError> {
+        self.managers.get(&address).ok_or(SimError::NotDeployed {
+            kind: "connection manager",
+            address,
+        })
+    }
+
+    pub(crate) fn manager_mut(
+        &mut self,
+        address: H160,
+    ) -> Result<&mut Versioned<ConnectionManagerModel>, SimError> {
+        self.managers
+            .get_mut(&address)
+            .ok_or(SimError::NotDeployed {
+                kind: "connection manager",
+                address,
+            })
+    }
+}
+
+/// A handle to a deterministic in-process chain.
+///
+/// Every transaction is mined in its own block, and each block is
+/// `BLOCK_TIME` seconds after its parent. Handles are cheap to clone and all
+/// clones share the same state.
+#[derive(Clone)]
+pub struct SimChain {
+    network: String,
+    state: Arc<Mutex<ChainState>>,
+}
+
+impl std::fmt::Debug for SimChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SimChain {{ network: {} }}", self.network)
+    }
+}
+
+impl SimChain {
+    /// Connect to the chain named `network`, creating it if it does not exist
+    /// yet. All agents in the same process that connect to the same network
+    /// share its state.
+    pub fn connect(network: &str) -> Self {
+        CHAINS
+            .lock()
+            .expect("!lock")
+            .entry(network.to_owned())
+            .or_insert_with(|| Self {
+                network: network.to_owned(),
+                state: Arc::new(Mutex::new(ChainState::new())),
+            })
+            .clone()
+    }
+
+    /// The name of the network
+    pub fn network(&self) -> &str {
+        &self.network
+    }
+
+    fn lock(&self) -> MutexGuard<ChainState> {
+        self.state.lock().expect("!lock")
+    }
+
+    /// Run `f` against the current chain state
+    pub(crate) fn read<R>(&self, f: impl FnOnce(&ChainState) -> R) -> R {
+        f(&*self.lock())
+    }
+
+    /// Execute a transaction against the contract at `address` and mine it.
+    /// `f` is handed the number of the block being mined. Nothing is mined
+    /// if the transaction reverts.
+    pub(crate) fn transact<F>(&self, address: H160, f: F) -> Result<TxOutcome, SimError>
+    where
+        F: FnOnce(&mut ChainState, u64) -> Result<Vec<SimEvent>, SimError>,
+    {
+        let mut state = self.lock();
+        let block = state.block_number() + 1;
+        let events = f(&mut *state, block)?;
+        state.mine();
+
+        let txid: H256 =
+            keccak256([self.network.as_bytes(), &block.to_be_bytes()[..]].concat()).into();
+        // Simulated blocks hold a single transaction
+        state
+            .logs
+            .extend(events.into_iter().enumerate().map(|(i, event)| SimLog {
+                block_number: block,
+                transaction_hash: txid,
+                log_index: i as u64,
+                address,
+                event,
+            }));
+
+        let outcome = TxOutcome { txid };
+        state.receipts.insert(txid, outcome);
+        Ok(outcome)
+    }
+
+    /// The latest mined block
+    pub fn block_number(&self) -> u64 {
+        self.lock().block_number()
+    }
+
+    /// The hash of a mined block. Simulated chains never reorg, so hashes are
+    /// derived from the network name and height.
+    pub fn block_hash(&self, number: u64) -> Option<H256> {
+        if number > self.block_number() {
+            return None;
+        }
+        Some(self.hash_at(number))
+    }
+
+    fn hash_at(&self, number: u64) -> H256 {
+        keccak256([&number.to_be_bytes()[..], self.network.as_bytes()].concat()).into()
+    }
+
+    /// Transaction metadata of `log`, read from `state` without locking it
+    pub(crate) fn tx_meta(&self, state: &ChainState, log: &SimLog) -> TxMeta {
+        TxMeta {
+            block_number: log.block_number,
+            timestamp: state.timestamp_at(log.block_number),
+            transaction_hash: log.transaction_hash,
+            block_hash: self.hash_at(log.block_number),
+            log_index: log.log_index,
+        }
+    }
+
+    /// The timestamp of the latest mined block
+    pub fn timestamp(&self) -> u64 {
+        let state = self.lock();
+        state.timestamp_at(state.block_number()).expect("!genesis")
+    }
+
+    /// Mine `blocks` empty blocks
+    pub fn mine(&self, blocks: u64) {
+        let mut state = self.lock();
+        for _ in 0..blocks {
+            state.mine();
+        }
+    }
+
+    /// Increase the timestamp of the next mined block by `seconds`
+    pub fn increase_time(&self, seconds: u64) {
+        self.lock().time_offset += seconds;
+    }
+
+    /// Deploy a home for `local_domain` with the given updater
+    pub fn deploy_home(
+        &self,
+        address: H160,
+        local_domain: u32,
+        updater: H160,
+    ) -> Result<(), SimError> {
+        let mut state = self.lock();
+        state.ensure_unused(address)?;
+
+        let block = state.mine();
+        let home = home::Home::init(local_domain, updater);
+        state
+            .homes
+            .insert(address, Versioned::new(block, HomeModel::Waiting(home)));
+        Ok(())
+    }
+
+    /// Deploy a replica of the home on `remote_domain`, starting from
+    /// `committed_root`. Updates are accepted `optimistic_seconds` after they
+    /// were submitted.
+    pub fn deploy_replica(
+        &self,
+        address: H160,
+        local_domain: u32,
+        remote_domain: u32,
+        updater: H160,
+        optimistic_seconds: u64,
+        committed_root: H256,
+    ) -> Result<(), SimError> {
+        let mut state = self.lock();
+        state.ensure_unused(address)?;
+
+        let block = state.mine();
+        let replica = replica::Replica::init(
+            remote_domain,
+            local_domain,
+            updater,
+            optimistic_seconds.into(),
+        );
+        state.replicas.insert(
+            address,
+            Versioned::new(block, ReplicaModel::new(replica, committed_root)),
+        );
+        Ok(())
+    }
+
+    /// Deploy a connection manager owned by `owner`, pointing at the home at
+    /// `home`
+    pub fn deploy_connection_manager(
+        &self,
+        address: H160,
+        owner: H160,
+        home: H160,
+    ) -> Result<(), SimError> {
+        let mut state = self.lock();
+        state.ensure_unused(address)?;
+
+        let block = state.mine();
+        state.managers.insert(
+            address,
+            Versioned::new(block, ConnectionManagerModel::new(owner, home)),
+        );
+        Ok(())
+    }
+}
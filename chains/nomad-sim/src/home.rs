@@ -0,0 +1,333 @@
+use async_trait::async_trait;
+use ethers::core::types::{H160, H256};
+use nomad_core::{
+    db::DbError, models::home, ChainCommunicationError, Common, CommonEvents, ContractLocator,
+    DoubleUpdate, Home, HomeEvents, Message, MessageVersion, NomadError, NomadIdentifier,
+    RawCommittedMessage, SignedUpdate, State, TxOutcome, Update, MAX_MESSAGE_BODY_BYTES,
+};
+use std::collections::VecDeque;
+
+use crate::chain::{check_double_update, SimChain, SimError, SimEvent};
+
+/// State of a simulated home contract
+#[derive(Debug, Clone)]
+pub(crate) enum HomeModel {
+    Waiting(home::Home<home::Waiting>),
+    Failed(home::Home<home::Failed>),
+}
+
+impl HomeModel {
+    fn updater(&self) -> H160 {
+        match self {
+            HomeModel::Waiting(home) => home.updater(),
+            HomeModel::Failed(home) => home.updater(),
+        }
+    }
+
+    fn committed_root(&self) -> H256 {
+        match self {
+            HomeModel::Waiting(home) => home.committed_root(),
+            HomeModel::Failed(home) => home.committed_root(),
+        }
+    }
+
+    fn nonces(&self, destination: u32) -> u32 {
+        match self {
+            HomeModel::Waiting(home) => home.nonces(destination),
+            HomeModel::Failed(home) => home.nonces(destination),
+        }
+    }
+
+    fn queue(&self) -> &VecDeque<H256> {
+        match self {
+            HomeModel::Waiting(home) => home.state().queue(),
+            HomeModel::Failed(home) => home.state().queue(),
+        }
+    }
+
+    fn state(&self) -> State {
+        match self {
+            HomeModel::Waiting(_) => State::Active,
+            HomeModel::Failed(_) => State::Failed,
+        }
+    }
+
+    fn waiting_mut(&mut self) -> Result<&mut home::Home<home::Waiting>, SimError> {
+        match self {
+            HomeModel::Waiting(home) => Ok(home),
+            HomeModel::Failed(_) => Err(SimError::Reverted("failed state")),
+        }
+    }
+}
+
+/// A reference to a Home contract on a simulated chain
+#[derive(Debug)]
+pub struct SimHome {
+    chain: SimChain,
+    address: H160,
+    domain: u32,
+    name: String,
+    sender: H160,
+    timelag: u64,
+}
+
+impl SimHome {
+    /// Create a reference to a Home at a specific address on a simulated
+    /// chain. Transactions are sent from `sender` and contract state is read
+    /// `timelag` blocks behind the chain head.
+    pub fn new(
+        chain: SimChain,
+        ContractLocator {
+            name,
+            domain,
+            address,
+        }: &ContractLocator,
+        sender: H160,
+        timelag: u64,
+    ) -> Result<Self, SimError> {
+        let address = address.clone().into();
+        chain.read(|state| state.home(address).map(|_| ()))?;
+
+        Ok(Self {
+            chain,
+            address,
+            domain: *domain,
+            name: name.to_owned(),
+            sender,
+            timelag,
+        })
+    }
+
+    fn read<R>(&self, f: impl FnOnce(&HomeModel) -> R) -> Result<R, SimError> {
+        self.chain.read(|state| {
+            state
+                .home(self.address)?
+                .at(state.lagged(self.timelag))
+                .map(f)
+                .ok_or(SimError::NotDeployed {
+                    kind: "home",
+                    address: self.address,
+                })
+        })
+    }
+
+    fn transact<F>(&self, f: F) -> Result<TxOutcome, ChainCommunicationError>
+    where
+        F: FnOnce(&mut HomeModel) -> Result<Vec<SimEvent>, SimError>,
+    {
+        Ok(self.chain.transact(self.address, |state, block| {
+            state.home_mut(self.address)?.transition(block, f)
+        })?)
+    }
+
+    /// All messages dispatched up to the lagged height, in dispatch order
+    fn dispatched(&self) -> Vec<RawCommittedMessage> {
+        self.chain.read(|state| {
+            state
+                .logs(self.address, 0, state.lagged(self.timelag))
+                .filter_map(|log| match &log.event {
+                    SimEvent::Dispatch(message) => Some(message.clone()),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+
+    /// All updates accepted up to the lagged height, in submission order
+    fn updates(&self) -> Vec<SignedUpdate> {
+        self.chain.read(|state| {
+            state
+                .logs(self.address, 0, state.lagged(self.timelag))
+                .filter_map(|log| match &log.event {
+                    SimEvent::Update(update) => Some(update.clone()),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+}
+
+#[async_trait]
+impl Common for SimHome {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn status(&self, txid: H256) -> Result<Option<TxOutcome>, ChainCommunicationError> {
+        Ok(self.chain.read(|state| state.receipt(txid)))
+    }
+
+    async fn updater(&self) -> Result<H256, ChainCommunicationError> {
+        Ok(self.read(|home| home.updater().into())?)
+    }
+
+    async fn state(&self) -> Result<State, ChainCommunicationError> {
+        Ok(self.read(HomeModel::state)?)
+    }
+
+    async fn committed_root(&self) -> Result<H256, ChainCommunicationError> {
+        Ok(self.read(HomeModel::committed_root)?)
+    }
+
+    #[tracing::instrument(err, skip(self, update), fields(update = %update))]
+    async fn update(&self, update: &SignedUpdate) -> Result<TxOutcome, ChainCommunicationError> {
+        self.transact(|home| {
+            let waiting = home.waiting_mut()?;
+            match waiting.update(update) {
+                Ok(()) => Ok(vec![SimEvent::Update(update.clone())]),
+                // Like the contract, fail the home if the new root was never
+                // in the queue
+                Err(NomadError::UnknownNewRoot(_)) => {
+                    let failed = waiting
+                        .clone()
+                        .improper_update(update)
+                        .map_err(|_| SimError::Reverted("!improper"))?;
+                    *home = HomeModel::Failed(failed);
+                    Ok(vec![])
+                }
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    #[tracing::instrument(err, skip(self, double), fields(double = %double))]
+    async fn double_update(
+        &self,
+        double: &DoubleUpdate,
+    ) -> Result<TxOutcome, ChainCommunicationError> {
+        check_double_update(double)?;
+
+        self.transact(|home| {
+            let failed = home
+                .waiting_mut()?
+                .clone()
+                .double_update(&double.0, &double.1)
+                .map_err(|_| SimError::Reverted("!double update"))?;
+            *home = HomeModel::Failed(failed);
+            Ok(vec![])
+        })
+    }
+}
+
+#[async_trait]
+impl Home for SimHome {
+    fn local_domain(&self) -> u32 {
+        self.domain
+    }
+
+    async fn nonces(&self, destination: u32) -> Result<u32, ChainCommunicationError> {
+        Ok(self.read(|home| home.nonces(destination))?)
+    }
+
+    async fn version(&self) -> Result<MessageVersion, ChainCommunicationError> {
+        Ok(MessageVersion::LATEST)
+    }
+
+    async fn max_message_body_bytes(&self) -> Result<usize, ChainCommunicationError> {
+        Ok(MAX_MESSAGE_BODY_BYTES)
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn dispatch(&self, message: &Message) -> Result<TxOutcome, ChainCommunicationError> {
+        message.check_body_size(self.max_message_body_bytes().await?)?;
+        let sender: H256 = NomadIdentifier::from(self.sender).into();
+
+        self.transact(|home| {
+            let committed = home.waiting_mut()?.dispatch(
+                sender,
+                message.destination,
+                message.recipient,
+                &message.body,
+            );
+            Ok(vec![SimEvent::Dispatch(committed)])
+        })
+    }
+
+    async fn queue_contains(&self, root: H256) -> Result<bool, ChainCommunicationError> {
+        Ok(self.read(|home| home.queue().contains(&root))?)
+    }
+
+    #[tracing::instrument(err, skip(self, update), fields(update = %update))]
+    async fn improper_update(
+        &self,
+        update: &SignedUpdate,
+    ) -> Result<TxOutcome, ChainCommunicationError> {
+        self.transact(|home| {
+            let waiting = home.waiting_mut()?;
+            if update.update.previous_root != waiting.committed_root() {
+                return Err(SimError::Reverted("not a current update"));
+            }
+
+            let failed = waiting
+                .clone()
+                .improper_update(update)
+                .map_err(|_| SimError::Reverted("!improper"))?;
+            *home = HomeModel::Failed(failed);
+            Ok(vec![])
+        })
+    }
+
+    async fn produce_update(&self) -> Result<Option<Update>, ChainCommunicationError> {
+        Ok(self.read(|home| match home {
+            HomeModel::Waiting(home) if !home.state().queue().is_empty() => {
+                Some(home.produce_update())
+            }
+            _ => None,
+        })?)
+    }
+}
+
+#[async_trait]
+impl CommonEvents for SimHome {
+    async fn signed_update_by_old_root(
+        &self,
+        old_root: H256,
+    ) -> Result<Option<SignedUpdate>, DbError> {
+        Ok(self
+            .updates()
+            .into_iter()
+            .find(|update| update.update.previous_root == old_root))
+    }
+
+    async fn signed_update_by_new_root(
+        &self,
+        new_root: H256,
+    ) -> Result<Option<SignedUpdate>, DbError> {
+        Ok(self
+            .updates()
+            .into_iter()
+            .find(|update| update.update.new_root == new_root))
+    }
+}
+
+#[async_trait]
+impl HomeEvents for SimHome {
+    async fn raw_message_by_nonce(
+        &self,
+        destination: u32,
+        nonce: u32,
+    ) -> Result<Option<RawCommittedMessage>, DbError> {
+        for raw in self.dispatched() {
+            let message = raw.message_ref()?;
+            if message.destination() == destination && message.nonce() == nonce {
+                return Ok(Some(raw));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn raw_message_by_leaf(
+        &self,
+        leaf: H256,
+    ) -> Result<Option<RawCommittedMessage>, DbError> {
+        Ok(self.dispatched().into_iter().find(|raw| raw.leaf() == leaf))
+    }
+
+    async fn leaf_by_tree_index(&self, tree_index: usize) -> Result<Option<H256>, DbError> {
+        Ok(self
+            .dispatched()
+            .into_iter()
+            .find(|raw| raw.leaf_index as usize == tree_index)
+            .map(|raw| raw.leaf()))
+    }
+}
@@ -0,0 +1,76 @@
+use async_trait::async_trait;
+use color_eyre::Result;
+use ethers::core::types::{H160, H256};
+use nomad_core::{
+    CommonIndexer, ContractLocator, HomeIndexer, RawCommittedMessageWithMeta, SignedUpdateWithMeta,
+};
+
+use crate::chain::{SimChain, SimEvent};
+
+/// Struct that retrieves event data for a simulated home or replica
+#[derive(Debug)]
+pub struct SimIndexer {
+    chain: SimChain,
+    address: H160,
+}
+
+impl SimIndexer {
+    /// Create new SimIndexer
+    pub fn new(chain: SimChain, locator: &ContractLocator) -> Self {
+        Self {
+            chain,
+            address: locator.address.clone().into(),
+        }
+    }
+}
+
+#[async_trait]
+impl CommonIndexer for SimIndexer {
+    async fn get_block_number(&self) -> Result<u32> {
+        Ok(self.chain.block_number() as u32)
+    }
+
+    async fn get_block_hash(&self, height: u32) -> Result<Option<H256>> {
+        Ok(self.chain.block_hash(height as u64))
+    }
+
+    async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
+        Ok(self.chain.read(|state| {
+            state
+                .logs(self.address, from as u64, to as u64)
+                .filter_map(|log| match &log.event {
+                    SimEvent::Update(update) => Some(SignedUpdateWithMeta {
+                        signed_update: update.clone(),
+                        metadata: self.chain.tx_meta(state, log),
+                    }),
+                    _ => None,
+                })
+                .collect()
+        }))
+    }
+}
+
+#[async_trait]
+impl HomeIndexer for SimIndexer {
+    async fn fetch_sorted_messages(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> Result<Vec<RawCommittedMessageWithMeta>> {
+        let mut messages: Vec<_> = self.chain.read(|state| {
+            state
+                .logs(self.address, from as u64, to as u64)
+                .filter_map(|log| match &log.event {
+                    SimEvent::Dispatch(message) => Some(RawCommittedMessageWithMeta {
+                        raw_message: message.clone(),
+                        metadata: self.chain.tx_meta(state, log),
+                    }),
+                    _ => None,
+                })
+                .collect()
+        });
+
+        messages.sort_by(|a, b| a.raw_message.leaf_index.cmp(&b.raw_message.leaf_index));
+        Ok(messages)
+    }
+}
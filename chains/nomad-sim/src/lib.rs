@@ -0,0 +1,302 @@
+//! An in-process simulated chain for running the agents without RPC
+//! endpoints.
+//!
+//! Chains are kept in a process-wide registry keyed by network name, so every
+//! agent configured with the same network shares the same contracts. Each
+//! transaction is mined in its own block, which keeps runs deterministic.
+//! Contracts must be deployed through [`SimChain`] before agents connect.
+
+#![forbid(unsafe_code)]
+#![warn(missing_docs)]
+#![warn(unused_extern_crates)]
+
+use color_eyre::eyre::Result;
+use ethers::{core::types::H160, signers::Signer};
+use nomad_core::*;
+
+/// Chain state and block production
+mod chain;
+pub use chain::*;
+
+/// Simulated home
+mod home;
+pub use home::*;
+
+/// Simulated indexer
+mod indexer;
+pub use indexer::*;
+
+/// Simulated replica
+mod replica;
+pub use replica::*;
+
+/// Simulated XAppConnectionManager
+mod xapp;
+pub use xapp::*;
+
+/// Simulated chain connection configuration
+#[derive(Debug, Default, serde::Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SimConnection {
+    /// Name of the in-process network to connect to
+    pub network: String,
+}
+
+fn sender(signer: Option<Signers>) -> H160 {
+    signer.map(|signer| signer.address()).unwrap_or_default()
+}
+
+/// Create a home indexer on a simulated chain
+pub fn make_home_indexer(
+    conn: SimConnection,
+    locator: &ContractLocator,
+) -> Result<Box<dyn HomeIndexer>> {
+    Ok(Box::new(SimIndexer::new(
+        SimChain::connect(&conn.network),
+        locator,
+    )))
+}
+
+/// Create a replica indexer on a simulated chain
+pub fn make_replica_indexer(
+    conn: SimConnection,
+    locator: &ContractLocator,
+) -> Result<Box<dyn CommonIndexer>> {
+    Ok(Box::new(SimIndexer::new(
+        SimChain::connect(&conn.network),
+        locator,
+    )))
+}
+
+/// Create a home on a simulated chain
+pub fn make_home(
+    conn: SimConnection,
+    locator: &ContractLocator,
+    signer: Option<Signers>,
+    timelag: Option<u8>,
+) -> Result<Box<dyn Home>> {
+    Ok(Box::new(SimHome::new(
+        SimChain::connect(&conn.network),
+        locator,
+        sender(signer),
+        timelag.unwrap_or_default().into(),
+    )?))
+}
+
+/// Create a replica on a simulated chain
+pub fn make_replica(
+    conn: SimConnection,
+    locator: &ContractLocator,
+    timelag: Option<u8>,
+) -> Result<Box<dyn Replica>> {
+    Ok(Box::new(SimReplica::new(
+        SimChain::connect(&conn.network),
+        locator,
+        timelag.unwrap_or_default().into(),
+    )?))
+}
+
+/// Create a connection manager on a simulated chain
+pub fn make_conn_manager(
+    conn: SimConnection,
+    locator: &ContractLocator,
+    signer: Option<Signers>,
+    timelag: Option<u8>,
+) -> Result<Box<dyn ConnectionManager>> {
+    Ok(Box::new(SimConnectionManager::new(
+        SimChain::connect(&conn.network),
+        locator,
+        sender(signer),
+        timelag.unwrap_or_default().into(),
+    )?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::{core::types::H256, signers::LocalWallet};
+    use nomad_core::accumulator::{
+        merkle::{MerkleTree, Proof},
+        TREE_DEPTH,
+    };
+
+    fn locator(name: &str, domain: u32, address: H160) -> ContractLocator {
+        ContractLocator {
+            name: name.into(),
+            domain,
+            address: address.into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_relays_and_processes_messages() {
+        let updater: LocalWallet =
+            "1111111111111111111111111111111111111111111111111111111111111111"
+                .parse()
+                .unwrap();
+        let watcher: LocalWallet =
+            "2222222222222222222222222222222222222222222222222222222222222222"
+                .parse()
+                .unwrap();
+
+        let (home_domain, replica_domain) = (1000, 2000);
+        let home_address = H160::repeat_byte(1);
+        let replica_address = H160::repeat_byte(2);
+        let manager_address = H160::repeat_byte(3);
+
+        let home_chain = SimChain::connect("test_home_chain");
+        let replica_chain = SimChain::connect("test_replica_chain");
+        home_chain
+            .deploy_home(home_address, home_domain, updater.address())
+            .unwrap();
+        replica_chain
+            .deploy_replica(
+                replica_address,
+                replica_domain,
+                home_domain,
+                updater.address(),
+                10,
+                H256::zero(),
+            )
+            .unwrap();
+        replica_chain
+            .deploy_connection_manager(manager_address, watcher.address(), H160::zero())
+            .unwrap();
+
+        let home = SimHome::new(
+            home_chain.clone(),
+            &locator("home", home_domain, home_address),
+            H160::repeat_byte(9),
+            0,
+        )
+        .unwrap();
+        let replica = SimReplica::new(
+            replica_chain.clone(),
+            &locator("replica", replica_domain, replica_address),
+            0,
+        )
+        .unwrap();
+        let indexer = SimIndexer::new(
+            home_chain.clone(),
+            &locator("home", home_domain, home_address),
+        );
+        let manager = SimConnectionManager::new(
+            replica_chain.clone(),
+            &locator("manager", replica_domain, manager_address),
+            watcher.address(),
+            0,
+        )
+        .unwrap();
+        manager
+            .owner_enroll_replica(replica_address.into(), home_domain)
+            .await
+            .unwrap();
+        manager
+            .set_watcher_permission(watcher.address().into(), home_domain, true)
+            .await
+            .unwrap();
+
+        // Dispatch a message and sign the update the home suggests
+        let message = Message {
+            destination: replica_domain,
+            recipient: H256::repeat_byte(7),
+            body: vec![1, 2, 3],
+        };
+        home.dispatch(&message).await.unwrap();
+        assert_eq!(home.nonces(replica_domain).await.unwrap(), 1);
+
+        // Oversized bodies are rejected before reaching the chain
+        let oversized = Message {
+            body: vec![0; home.max_message_body_bytes().await.unwrap() + 1],
+            ..message.clone()
+        };
+        assert!(home.dispatch(&oversized).await.is_err());
+        assert_eq!(home.nonces(replica_domain).await.unwrap(), 1);
+
+        let update = home.produce_update().await.unwrap().unwrap();
+        let signed = update.sign_with(&updater).await.unwrap();
+        home.update(&signed).await.unwrap();
+        assert_eq!(home.committed_root().await.unwrap(), update.new_root);
+        assert!(home.produce_update().await.unwrap().is_none());
+
+        // Both events are visible to the indexer
+        let to = indexer.get_block_number().await.unwrap();
+        let updates = indexer.fetch_sorted_updates(0, to).await.unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].signed_update, signed);
+
+        let messages = indexer.fetch_sorted_messages(0, to).await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            home.raw_message_by_nonce(replica_domain, 0).await.unwrap(),
+            Some(messages[0].raw_message.clone())
+        );
+
+        // Relay the update and wait out the optimistic timer
+        replica.update(&signed).await.unwrap();
+        assert_eq!(replica.committed_root().await.unwrap(), update.new_root);
+        assert!(!replica.acceptable_root(update.new_root).await.unwrap());
+
+        replica_chain.increase_time(10);
+        replica_chain.mine(1);
+        assert!(replica.acceptable_root(update.new_root).await.unwrap());
+
+        // Prove and process the message against the confirmed root
+        let raw = &messages[0].raw_message;
+        let (leaf, path) =
+            MerkleTree::create(&[raw.leaf()], TREE_DEPTH).generate_proof(0, TREE_DEPTH);
+        let mut proof = Proof {
+            leaf,
+            index: 0,
+            path: Default::default(),
+        };
+        proof.path.copy_from_slice(&path);
+
+        let message = raw.message_ref().unwrap().to_message();
+        replica.prove_and_process(&message, &proof).await.unwrap();
+        assert_eq!(
+            replica.message_status(leaf).await.unwrap(),
+            MessageStatus::Processed
+        );
+
+        // A permitted watcher can unenroll the replica
+        let failure = FailureNotification {
+            home_domain,
+            updater: updater.address().into(),
+        }
+        .sign_with(&watcher)
+        .await
+        .unwrap();
+        manager.unenroll_replica(&failure).await.unwrap();
+        assert!(!manager.is_replica(replica_address.into()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn it_fails_home_on_improper_update() {
+        let updater: LocalWallet =
+            "1111111111111111111111111111111111111111111111111111111111111111"
+                .parse()
+                .unwrap();
+        let home_address = H160::repeat_byte(1);
+
+        let chain = SimChain::connect("test_improper_update_chain");
+        chain
+            .deploy_home(home_address, 1000, updater.address())
+            .unwrap();
+        let home =
+            SimHome::new(chain, &locator("home", 1000, home_address), H160::zero(), 0).unwrap();
+
+        let improper = Update {
+            home_domain: 1000,
+            previous_root: H256::zero(),
+            new_root: H256::repeat_byte(1),
+        }
+        .sign_with(&updater)
+        .await
+        .unwrap();
+
+        home.improper_update(&improper).await.unwrap();
+        assert_eq!(home.state().await.unwrap(), State::Failed);
+        assert!(home.dispatch(&Message::default()).await.is_err());
+    }
+}
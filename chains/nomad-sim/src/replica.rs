@@ -0,0 +1,315 @@
+use async_trait::async_trait;
+use ethers::core::types::{H160, H256, U256};
+use nomad_core::{
+    accumulator::{
+        merkle::{merkle_root_from_branch, Proof},
+        TREE_DEPTH,
+    },
+    db::DbError,
+    models::replica,
+    ChainCommunicationError, Common, CommonEvents, ContractLocator, DoubleUpdate, MessageStatus,
+    NomadMessage, Replica, SignedUpdate, State, TxOutcome,
+};
+use std::collections::HashMap;
+
+use crate::chain::{check_double_update, SimChain, SimError, SimEvent};
+
+#[derive(Debug, Clone, Copy)]
+enum ReplicaState {
+    Waiting(replica::Replica<replica::Waiting>),
+    Failed(replica::Replica<replica::Failed>),
+}
+
+/// State of a simulated replica contract
+///
+/// Signature checks and optimistic timeouts are delegated to the replica
+/// model. Like the contract, the committed root moves on every accepted
+/// update and each root becomes acceptable once its timeout has passed.
+#[derive(Debug, Clone)]
+pub(crate) struct ReplicaModel {
+    replica: ReplicaState,
+    committed_root: H256,
+    confirm_at: HashMap<H256, U256>,
+    messages: HashMap<H256, MessageStatus>,
+}
+
+impl ReplicaModel {
+    pub(crate) fn new(replica: replica::Replica<replica::Waiting>, committed_root: H256) -> Self {
+        let mut confirm_at = HashMap::new();
+        confirm_at.insert(committed_root, U256::one());
+
+        Self {
+            replica: ReplicaState::Waiting(replica),
+            committed_root,
+            confirm_at,
+            messages: Default::default(),
+        }
+    }
+
+    pub(crate) fn updater(&self) -> H160 {
+        match self.replica {
+            ReplicaState::Waiting(replica) => replica.updater(),
+            ReplicaState::Failed(replica) => replica.updater(),
+        }
+    }
+
+    fn local(&self) -> u32 {
+        match self.replica {
+            ReplicaState::Waiting(replica) => replica.local(),
+            ReplicaState::Failed(replica) => replica.local(),
+        }
+    }
+
+    fn remote(&self) -> u32 {
+        match self.replica {
+            ReplicaState::Waiting(replica) => replica.remote(),
+            ReplicaState::Failed(replica) => replica.remote(),
+        }
+    }
+
+    fn state(&self) -> State {
+        match self.replica {
+            ReplicaState::Waiting(_) => State::Active,
+            ReplicaState::Failed(_) => State::Failed,
+        }
+    }
+
+    fn waiting(&self) -> Result<replica::Replica<replica::Waiting>, SimError> {
+        match self.replica {
+            ReplicaState::Waiting(replica) => Ok(replica),
+            ReplicaState::Failed(_) => Err(SimError::Reverted("failed state")),
+        }
+    }
+
+    fn acceptable_root(&self, root: H256, now: u64) -> bool {
+        match self.confirm_at.get(&root) {
+            Some(confirm_at) => !confirm_at.is_zero() && *confirm_at <= now.into(),
+            None => false,
+        }
+    }
+
+    fn message_status(&self, leaf: H256) -> MessageStatus {
+        self.messages
+            .get(&leaf)
+            .copied()
+            .unwrap_or(MessageStatus::None)
+    }
+}
+
+/// A reference to a Replica contract on a simulated chain
+#[derive(Debug)]
+pub struct SimReplica {
+    chain: SimChain,
+    address: H160,
+    domain: u32,
+    name: String,
+    timelag: u64,
+}
+
+impl SimReplica {
+    /// Create a reference to a Replica at a specific address on a simulated
+    /// chain. Contract state is read `timelag` blocks behind the chain head.
+    pub fn new(
+        chain: SimChain,
+        ContractLocator {
+            name,
+            domain,
+            address,
+        }: &ContractLocator,
+        timelag: u64,
+    ) -> Result<Self, SimError> {
+        let address = address.clone().into();
+        chain.read(|state| state.replica(address).map(|_| ()))?;
+
+        Ok(Self {
+            chain,
+            address,
+            domain: *domain,
+            name: name.to_owned(),
+            timelag,
+        })
+    }
+
+    /// Read the replica state at the lagged height, along with the timestamp
+    /// of that block
+    fn read<R>(&self, f: impl FnOnce(&ReplicaModel, u64) -> R) -> Result<R, SimError> {
+        self.chain.read(|state| {
+            let block = state.lagged(self.timelag);
+            let timestamp = state.timestamp_at(block).expect("!block");
+            state
+                .replica(self.address)?
+                .at(block)
+                .map(|replica| f(replica, timestamp))
+                .ok_or(SimError::NotDeployed {
+                    kind: "replica",
+                    address: self.address,
+                })
+        })
+    }
+
+    /// Execute a transaction. `f` is handed the timestamp of the block the
+    /// transaction is mined in.
+    fn transact<F>(&self, f: F) -> Result<TxOutcome, ChainCommunicationError>
+    where
+        F: FnOnce(&mut ReplicaModel, u64) -> Result<Vec<SimEvent>, SimError>,
+    {
+        Ok(self.chain.transact(self.address, |state, block| {
+            let now = state.pending_timestamp();
+            state
+                .replica_mut(self.address)?
+                .transition(block, |replica| f(replica, now))
+        })?)
+    }
+
+    /// All updates accepted up to the lagged height, in submission order
+    fn updates(&self) -> Vec<SignedUpdate> {
+        self.chain.read(|state| {
+            state
+                .logs(self.address, 0, state.lagged(self.timelag))
+                .filter_map(|log| match &log.event {
+                    SimEvent::Update(update) => Some(update.clone()),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+}
+
+#[async_trait]
+impl Common for SimReplica {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn status(&self, txid: H256) -> Result<Option<TxOutcome>, ChainCommunicationError> {
+        Ok(self.chain.read(|state| state.receipt(txid)))
+    }
+
+    async fn updater(&self) -> Result<H256, ChainCommunicationError> {
+        Ok(self.read(|replica, _| replica.updater().into())?)
+    }
+
+    async fn state(&self) -> Result<State, ChainCommunicationError> {
+        Ok(self.read(|replica, _| replica.state())?)
+    }
+
+    async fn committed_root(&self) -> Result<H256, ChainCommunicationError> {
+        Ok(self.read(|replica, _| replica.committed_root)?)
+    }
+
+    #[tracing::instrument(err, skip(self, update), fields(update = %update))]
+    async fn update(&self, update: &SignedUpdate) -> Result<TxOutcome, ChainCommunicationError> {
+        self.transact(|replica, now| {
+            let waiting = replica.waiting()?;
+            if update.update.home_domain != waiting.remote() {
+                return Err(SimError::Reverted("!remote domain"));
+            }
+            if update.update.previous_root != replica.committed_root {
+                return Err(SimError::Reverted("not a current update"));
+            }
+
+            let pending = waiting
+                .update(update, || now.into())
+                .map_err(|_| SimError::Reverted("!updater sig"))?;
+            replica
+                .confirm_at
+                .insert(pending.new_root(), pending.timeout());
+            replica.committed_root = pending.new_root();
+
+            Ok(vec![SimEvent::Update(update.clone())])
+        })
+    }
+
+    #[tracing::instrument(err, skip(self, double), fields(double = %double))]
+    async fn double_update(
+        &self,
+        double: &DoubleUpdate,
+    ) -> Result<TxOutcome, ChainCommunicationError> {
+        check_double_update(double)?;
+
+        self.transact(|replica, _| {
+            let failed = replica
+                .waiting()?
+                .double_update(&double.0, &double.1)
+                .map_err(|_| SimError::Reverted("!double update"))?;
+            replica.replica = ReplicaState::Failed(failed);
+            Ok(vec![])
+        })
+    }
+}
+
+#[async_trait]
+impl Replica for SimReplica {
+    fn local_domain(&self) -> u32 {
+        self.domain
+    }
+
+    async fn remote_domain(&self) -> Result<u32, ChainCommunicationError> {
+        Ok(self.read(|replica, _| replica.remote())?)
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn prove(&self, proof: &Proof) -> Result<TxOutcome, ChainCommunicationError> {
+        self.transact(|replica, now| {
+            if replica.message_status(proof.leaf) != MessageStatus::None {
+                return Err(SimError::Reverted("!MessageStatus.None"));
+            }
+
+            let root = merkle_root_from_branch(proof.leaf, &proof.path, TREE_DEPTH, proof.index);
+            if !replica.acceptable_root(root, now) {
+                return Err(SimError::Reverted("!prove"));
+            }
+
+            replica.messages.insert(proof.leaf, MessageStatus::Proven);
+            Ok(vec![])
+        })
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn process(&self, message: &NomadMessage) -> Result<TxOutcome, ChainCommunicationError> {
+        self.transact(|replica, _| {
+            if message.destination != replica.local() {
+                return Err(SimError::Reverted("!destination"));
+            }
+
+            let leaf = message.to_leaf();
+            if replica.message_status(leaf) != MessageStatus::Proven {
+                return Err(SimError::Reverted("!proven"));
+            }
+
+            replica.messages.insert(leaf, MessageStatus::Processed);
+            Ok(vec![])
+        })
+    }
+
+    async fn message_status(&self, leaf: H256) -> Result<MessageStatus, ChainCommunicationError> {
+        Ok(self.read(|replica, _| replica.message_status(leaf))?)
+    }
+
+    async fn acceptable_root(&self, root: H256) -> Result<bool, ChainCommunicationError> {
+        Ok(self.read(|replica, now| replica.acceptable_root(root, now))?)
+    }
+}
+
+#[async_trait]
+impl CommonEvents for SimReplica {
+    async fn signed_update_by_old_root(
+        &self,
+        old_root: H256,
+    ) -> Result<Option<SignedUpdate>, DbError> {
+        Ok(self
+            .updates()
+            .into_iter()
+            .find(|update| update.update.previous_root == old_root))
+    }
+
+    async fn signed_update_by_new_root(
+        &self,
+        new_root: H256,
+    ) -> Result<Option<SignedUpdate>, DbError> {
+        Ok(self
+            .updates()
+            .into_iter()
+            .find(|update| update.update.new_root == new_root))
+    }
+}
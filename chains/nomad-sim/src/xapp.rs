@@ -0,0 +1,219 @@
+use async_trait::async_trait;
+use ethers::core::types::H160;
+use nomad_core::{
+    ChainCommunicationError, ConnectionManager, ContractLocator, NomadIdentifier,
+    SignedFailureNotification, TxOutcome,
+};
+use std::collections::{HashMap, HashSet};
+
+use crate::chain::{SimChain, SimError};
+
+/// State of a simulated XAppConnectionManager contract
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionManagerModel {
+    owner: H160,
+    home: H160,
+    domain_to_replica: HashMap<u32, H160>,
+    replica_to_domain: HashMap<H160, u32>,
+    watcher_permissions: HashSet<(H160, u32)>,
+}
+
+impl ConnectionManagerModel {
+    pub(crate) fn new(owner: H160, home: H160) -> Self {
+        Self {
+            owner,
+            home,
+            domain_to_replica: Default::default(),
+            replica_to_domain: Default::default(),
+            watcher_permissions: Default::default(),
+        }
+    }
+
+    fn only_owner(&self, sender: H160) -> Result<(), SimError> {
+        if sender != self.owner {
+            return Err(SimError::Reverted("!owner"));
+        }
+        Ok(())
+    }
+
+    fn unenroll(&mut self, replica: H160) {
+        if let Some(domain) = self.replica_to_domain.remove(&replica) {
+            self.domain_to_replica.remove(&domain);
+        }
+    }
+}
+
+/// A reference to a XAppConnectionManager contract on a simulated chain
+#[derive(Debug)]
+pub struct SimConnectionManager {
+    chain: SimChain,
+    address: H160,
+    domain: u32,
+    sender: H160,
+    timelag: u64,
+}
+
+impl SimConnectionManager {
+    /// Create a reference to a XAppConnectionManager at a specific address
+    /// on a simulated chain. Transactions are sent from `sender` and contract
+    /// state is read `timelag` blocks behind the chain head.
+    pub fn new(
+        chain: SimChain,
+        ContractLocator {
+            name: _,
+            domain,
+            address,
+        }: &ContractLocator,
+        sender: H160,
+        timelag: u64,
+    ) -> Result<Self, SimError> {
+        let address = address.clone().into();
+        chain.read(|state| state.manager(address).map(|_| ()))?;
+
+        Ok(Self {
+            chain,
+            address,
+            domain: *domain,
+            sender,
+            timelag,
+        })
+    }
+
+    fn read<R>(&self, f: impl FnOnce(&ConnectionManagerModel) -> R) -> Result<R, SimError> {
+        self.chain.read(|state| {
+            state
+                .manager(self.address)?
+                .at(state.lagged(self.timelag))
+                .map(f)
+                .ok_or(SimError::NotDeployed {
+                    kind: "connection manager",
+                    address: self.address,
+                })
+        })
+    }
+
+    fn transact<F>(&self, f: F) -> Result<TxOutcome, ChainCommunicationError>
+    where
+        F: FnOnce(&mut ConnectionManagerModel) -> Result<(), SimError>,
+    {
+        Ok(self.chain.transact(self.address, |state, block| {
+            state.manager_mut(self.address)?.transition(block, f)?;
+            Ok(vec![])
+        })?)
+    }
+}
+
+#[async_trait]
+impl ConnectionManager for SimConnectionManager {
+    fn local_domain(&self) -> u32 {
+        self.domain
+    }
+
+    async fn is_replica(&self, address: NomadIdentifier) -> Result<bool, ChainCommunicationError> {
+        let address = address.as_ethereum_address();
+        Ok(self.read(|manager| manager.replica_to_domain.contains_key(&address))?)
+    }
+
+    async fn watcher_permission(
+        &self,
+        address: NomadIdentifier,
+        domain: u32,
+    ) -> Result<bool, ChainCommunicationError> {
+        let address = address.as_ethereum_address();
+        Ok(self.read(|manager| manager.watcher_permissions.contains(&(address, domain)))?)
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn owner_enroll_replica(
+        &self,
+        replica: NomadIdentifier,
+        domain: u32,
+    ) -> Result<TxOutcome, ChainCommunicationError> {
+        let replica = replica.as_ethereum_address();
+        self.transact(|manager| {
+            manager.only_owner(self.sender)?;
+            manager.unenroll(replica);
+            if let Some(previous) = manager.domain_to_replica.get(&domain).copied() {
+                manager.unenroll(previous);
+            }
+            manager.domain_to_replica.insert(domain, replica);
+            manager.replica_to_domain.insert(replica, domain);
+            Ok(())
+        })
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn owner_unenroll_replica(
+        &self,
+        replica: NomadIdentifier,
+    ) -> Result<TxOutcome, ChainCommunicationError> {
+        let replica = replica.as_ethereum_address();
+        self.transact(|manager| {
+            manager.only_owner(self.sender)?;
+            manager.unenroll(replica);
+            Ok(())
+        })
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn set_home(&self, home: NomadIdentifier) -> Result<TxOutcome, ChainCommunicationError> {
+        self.transact(|manager| {
+            manager.only_owner(self.sender)?;
+            manager.home = home.as_ethereum_address();
+            Ok(())
+        })
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn set_watcher_permission(
+        &self,
+        watcher: NomadIdentifier,
+        domain: u32,
+        access: bool,
+    ) -> Result<TxOutcome, ChainCommunicationError> {
+        let watcher = watcher.as_ethereum_address();
+        self.transact(|manager| {
+            manager.only_owner(self.sender)?;
+            if access {
+                manager.watcher_permissions.insert((watcher, domain));
+            } else {
+                manager.watcher_permissions.remove(&(watcher, domain));
+            }
+            Ok(())
+        })
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn unenroll_replica(
+        &self,
+        signed_failure: &SignedFailureNotification,
+    ) -> Result<TxOutcome, ChainCommunicationError> {
+        let domain = signed_failure.notification.home_domain;
+        let watcher = signed_failure.recover()?;
+
+        Ok(self.chain.transact(self.address, |state, block| {
+            let replica = state
+                .manager(self.address)?
+                .latest()
+                .domain_to_replica
+                .get(&domain)
+                .copied()
+                .ok_or(SimError::Reverted("!replica exists"))?;
+
+            let updater = state.replica(replica)?.latest().updater();
+            if NomadIdentifier::from(updater) != signed_failure.notification.updater {
+                return Err(SimError::Reverted("!current updater"));
+            }
+
+            state
+                .manager_mut(self.address)?
+                .transition(block, |manager| {
+                    if !manager.watcher_permissions.contains(&(watcher, domain)) {
+                        return Err(SimError::Reverted("!valid watcher"));
+                    }
+                    manager.unenroll(replica);
+                    Ok(vec![])
+                })
+        })?)
+    }
+}
@@ -0,0 +1,543 @@
+//! JSON admin API exposing live agent status, health and readiness.
+
+use color_eyre::Result;
+use futures_util::{
+    future::{join_all, BoxFuture},
+    FutureExt,
+};
+use nomad_core::{Common, State};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    convert::Infallible,
+    fmt,
+    future::Future,
+    iter::once,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::task::JoinHandle;
+
+use crate::{
+    contract_sync::{CommonContractSyncDB, HomeContractSyncDB},
+    AgentCore, NomadDB, SnapshotError, SyncProgress,
+};
+
+/// Number of channel faults kept in the fault history
+const MAX_CHANNEL_FAULTS: usize = 100;
+
+/// Tasks failing this many times in a row are considered crash looping
+const CRASH_LOOP_FAILURES: u32 = 3;
+
+/// Failures of a task this long after its last restart are considered
+/// unrelated to previous ones
+pub(crate) const CRASH_LOOP_WINDOW_SECS: u64 = 300;
+
+/// Interval at which the report served on `/admin/status` is rebuilt.
+/// Building it makes RPC calls, so it is never built per request.
+const STATUS_REFRESH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Closure producing an agent-specific section of the status report
+pub type StatusSection = Box<dyn Fn() -> BoxFuture<'static, Result<Value>> + Send + Sync>;
+
+/// State of a long-running agent task
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "camelCase")]
+pub enum TaskState {
+    /// Task is running
+    #[serde(rename_all = "camelCase")]
+    Running {
+        /// Failures in a row before this run, each within 5 minutes of the
+        /// previous restart
+        failures: u32,
+        /// Unix timestamp of the start of this run
+        since: u64,
+    },
+    /// Task errored and will be restarted
+    #[serde(rename_all = "camelCase")]
+    Restarting {
+        /// Error the task failed with
+        error: String,
+        /// Seconds until the restart
+        backoff_secs: u64,
+        /// Failures in a row, each within 5 minutes of the previous restart
+        failures: u32,
+    },
+    /// Task completed
+    Finished,
+    /// Task errored and will not be restarted
+    Failed {
+        /// Error the task failed with
+        error: String,
+    },
+}
+
+impl TaskState {
+    /// Whether the task failed for good or keeps failing shortly after being
+    /// restarted. A crash looping task is unhealthy until it has run for 5
+    /// minutes.
+    pub fn is_unhealthy(&self) -> bool {
+        match self {
+            TaskState::Running { failures, since } => {
+                *failures >= CRASH_LOOP_FAILURES
+                    && unix_timestamp().saturating_sub(*since) < CRASH_LOOP_WINDOW_SECS
+            }
+            TaskState::Restarting { failures, .. } => *failures >= CRASH_LOOP_FAILURES,
+            TaskState::Failed { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// A home <> replica channel failure
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChannelFault {
+    /// Name of the replica
+    pub replica: String,
+    /// Unix timestamp of the failure
+    pub timestamp: u64,
+    /// Error the channel failed with
+    pub error: String,
+}
+
+/// Live status of an agent, served by the admin API
+#[derive(Default)]
+pub struct AgentStatus {
+    tasks: RwLock<BTreeMap<String, TaskState>>,
+    channel_faults: RwLock<VecDeque<ChannelFault>>,
+    sections: RwLock<BTreeMap<String, StatusSection>>,
+}
+
+impl fmt::Debug for AgentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AgentStatus")
+            .field("tasks", &self.tasks)
+            .field("channel_faults", &self.channel_faults)
+            .field(
+                "sections",
+                &self
+                    .sections
+                    .read()
+                    .expect("!lock")
+                    .keys()
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl AgentStatus {
+    /// Record the state of the task named `task`
+    pub fn set_task_state(&self, task: &str, state: TaskState) {
+        self.tasks
+            .write()
+            .expect("!lock")
+            .insert(task.to_owned(), state);
+    }
+
+    /// Current state of every task
+    pub fn tasks(&self) -> BTreeMap<String, TaskState> {
+        self.tasks.read().expect("!lock").clone()
+    }
+
+    /// Record a channel failure. Only the latest `MAX_CHANNEL_FAULTS` are
+    /// kept.
+    pub fn record_channel_fault(&self, replica: &str, error: String) {
+        let timestamp = unix_timestamp();
+
+        let mut faults = self.channel_faults.write().expect("!lock");
+        if faults.len() == MAX_CHANNEL_FAULTS {
+            faults.pop_front();
+        }
+        faults.push_back(ChannelFault {
+            replica: replica.to_owned(),
+            timestamp,
+            error,
+        });
+    }
+
+    /// Channel failures, oldest first
+    pub fn channel_faults(&self) -> Vec<ChannelFault> {
+        self.channel_faults
+            .read()
+            .expect("!lock")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Add an agent-specific section to the status report, e.g. the
+    /// processor's next nonces
+    pub fn add_section<F, Fut>(&self, name: &str, section: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value>> + Send + 'static,
+    {
+        self.sections
+            .write()
+            .expect("!lock")
+            .insert(name.to_owned(), Box::new(move || section().boxed()));
+    }
+
+    /// Evaluate every agent-specific section
+    async fn sections(&self) -> Map<String, Value> {
+        let (names, futs): (Vec<_>, Vec<_>) = self
+            .sections
+            .read()
+            .expect("!lock")
+            .iter()
+            .map(|(name, section)| (name.clone(), section()))
+            .unzip();
+
+        names
+            .into_iter()
+            .zip(join_all(futs).await)
+            .map(|(name, res)| (name, to_json(res)))
+            .collect()
+    }
+}
+
+/// Serialize a result, reporting errors as `{"error": "..."}`
+fn to_json<T, E>(res: std::result::Result<T, E>) -> Value
+where
+    T: Serialize,
+    E: fmt::Display,
+{
+    match res.map(|t| serde_json::to_value(t)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => json!({ "error": e.to_string() }),
+        Err(e) => json!({ "error": e.to_string() }),
+    }
+}
+
+/// Unix timestamp of now
+pub(crate) fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("!timestamp")
+        .as_secs()
+}
+
+/// JSON reply to a health or readiness check, with status 503 if the check
+/// failed
+fn check_reply(ok: bool, report: &Value) -> impl warp::Reply {
+    let status = if ok {
+        warp::http::StatusCode::OK
+    } else {
+        warp::http::StatusCode::SERVICE_UNAVAILABLE
+    };
+    warp::reply::with_status(warp::reply::json(report), status)
+}
+
+/// Snapshot the db off the async runtime and report the result
+async fn snapshot_reply(core: AgentCore) -> warp::reply::WithStatus<warp::reply::Json> {
+    let (status, report) = match tokio::task::spawn_blocking(move || core.snapshot()).await {
+        Ok(Ok(snapshot)) => (warp::http::StatusCode::OK, json!(snapshot)),
+        Ok(Err(SnapshotError::Disabled)) => (
+            warp::http::StatusCode::FORBIDDEN,
+            json!({ "error": SnapshotError::Disabled.to_string() }),
+        ),
+        Ok(Err(e)) => {
+            tracing::error!(error = %e, "Failed to snapshot db");
+            (
+                warp::http::StatusCode::SERVICE_UNAVAILABLE,
+                json!({ "error": e.to_string() }),
+            )
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "Snapshot task panicked");
+            (
+                warp::http::StatusCode::SERVICE_UNAVAILABLE,
+                json!({ "error": e.to_string() }),
+            )
+        }
+    };
+    warp::reply::with_status(warp::reply::json(&report), status)
+}
+
+/// Status of a home or replica contract
+async fn contract_status<C: Common + ?Sized>(contract: &C, db: NomadDB, home: bool) -> Value {
+    let mut indexed_heights = json!({ "updates": db.retrieve_update_latest_block_end() });
+    if home {
+        indexed_heights["messages"] = json!(db.retrieve_message_latest_block_end());
+    }
+
+    json!({
+        "committedRoot": to_json(contract.committed_root().await),
+        "indexedHeights": indexed_heights,
+    })
+}
+
+impl AgentCore {
+    /// Build the JSON status report served on `/admin/status`
+    pub async fn status_report(&self) -> Value {
+        let home = contract_status(
+            self.home.as_ref(),
+            NomadDB::new(self.home.name(), self.db.clone()),
+            true,
+        );
+        let replicas = join_all(self.replicas.iter().map(|(name, replica)| async move {
+            let db = NomadDB::new(replica.name(), self.db.clone());
+            (
+                name.clone(),
+                contract_status(replica.as_ref(), db, false).await,
+            )
+        }));
+        let (mut home, replicas) = futures_util::join!(home, replicas);
+        home["name"] = json!(self.home.name());
+
+        json!({
+            "agent": self.metrics.agent_name(),
+            "home": home,
+            "replicas": replicas.into_iter().collect::<Map<_, _>>(),
+            "tasks": self.status.tasks(),
+            "channelFaults": self.status.channel_faults(),
+            "agentStatus": self.status.sections().await,
+            "dbColumnBytes": to_json(self.db.column_sizes()),
+        })
+    }
+
+    /// Rebuild the status report every `STATUS_REFRESH_INTERVAL` until
+    /// shutdown, storing it in `report`
+    async fn refresh_status_report(self, report: Arc<RwLock<Value>>) {
+        loop {
+            let mut fresh = self.status_report().await;
+            fresh["updatedAt"] = json!(unix_timestamp());
+            *report.write().expect("!lock") = fresh;
+
+            if self.shutdown.sleep(STATUS_REFRESH_INTERVAL).await {
+                return;
+            }
+        }
+    }
+
+    /// Check agent liveness. The agent is unhealthy if any task failed or is
+    /// crash looping, or if the home is in a failed state.
+    pub async fn health_report(&self) -> (bool, Value) {
+        let unhealthy_tasks: BTreeMap<_, _> = self
+            .status
+            .tasks()
+            .into_iter()
+            .filter(|(_, state)| state.is_unhealthy())
+            .collect();
+        let home_state = self.home.state().await;
+
+        let healthy = unhealthy_tasks.is_empty() && !matches!(home_state, Ok(State::Failed));
+        let report = json!({
+            "healthy": healthy,
+            "homeState": to_json(home_state.map(|state| format!("{:?}", state))),
+            "unhealthyTasks": unhealthy_tasks,
+        });
+        (healthy, report)
+    }
+
+    /// Check agent readiness. The agent is ready once the home sync, which
+    /// every agent runs, has started, and every started contract sync has
+    /// indexed up to the chain tip minus finality.
+    pub fn readiness_report(&self) -> (bool, Value) {
+        let home = self.home.sync_progress();
+        let replicas: Vec<(String, Arc<SyncProgress>)> = self
+            .replicas
+            .iter()
+            .map(|(name, replica)| (name.clone(), replica.sync_progress()))
+            .filter(|(_, progress)| progress.is_started())
+            .collect();
+
+        let ready = syncs_ready(
+            &home,
+            replicas.iter().map(|(_, progress)| progress.as_ref()),
+        );
+        let report = json!({
+            "ready": ready,
+            "syncs": once((self.home.name().to_owned(), json!(home.data_types())))
+                .chain(
+                    replicas
+                        .iter()
+                        .map(|(name, progress)| (name.clone(), json!(progress.data_types()))),
+                )
+                .collect::<Map<_, _>>(),
+        });
+        (ready, report)
+    }
+
+    /// Run an HTTP server serving OpenMetrics format reports on `/metrics`,
+    /// the JSON status report on `/admin/status`, as last rebuilt by a
+    /// background task, and liveness and
+    /// readiness checks on `/health` and `/ready`. If enabled in the
+    /// settings, a `POST` to `/admin/snapshot` writes a db snapshot to the
+    /// snapshot directory.
+    ///
+    /// This is compatible with Prometheus, which ought to be configured to scrape me!
+    pub fn run_http_server(&self) -> JoinHandle<()> {
+        use warp::Filter;
+        match self.metrics.listen_port() {
+            None => {
+                tracing::info!("not starting prometheus server");
+                tokio::spawn(std::future::ready(()))
+            }
+            Some(port) => {
+                tracing::info!(
+                    port,
+                    "starting prometheus server on 0.0.0.0:{port}",
+                    port = port
+                );
+                let metrics = self.metrics.clone();
+                let core = self.clone();
+                let status_report = Arc::new(RwLock::new(
+                    json!({ "error": "Status report has not been built yet" }),
+                ));
+                tokio::spawn(self.clone().refresh_status_report(status_report.clone()));
+                tokio::spawn(async move {
+                    warp::serve(
+                        warp::path!("metrics")
+                            .map(move || {
+                                warp::reply::with_header(
+                                    metrics.gather().expect("failed to encode metrics"),
+                                    "Content-Type",
+                                    // OpenMetrics specs demands "application/openmetrics-text; version=1.0.0; charset=utf-8"
+                                    // but the prometheus scraper itself doesn't seem to care?
+                                    // try text/plain to make web browsers happy.
+                                    "text/plain; charset=utf-8",
+                                )
+                            })
+                            .or(warp::path!("admin" / "status").map(move || {
+                                warp::reply::json(&*status_report.read().expect("!lock"))
+                            }))
+                            .or(warp::path!("admin" / "snapshot").and(warp::post()).and_then({
+                                let core = core.clone();
+                                move || {
+                                    let core = core.clone();
+                                    async move {
+                                        Ok::<_, Infallible>(snapshot_reply(core).await)
+                                    }
+                                }
+                            }))
+                            .or(warp::path!("health").and_then({
+                                let core = core.clone();
+                                move || {
+                                    let core = core.clone();
+                                    async move {
+                                        let (healthy, report) = core.health_report().await;
+                                        Ok::<_, Infallible>(check_reply(healthy, &report))
+                                    }
+                                }
+                            }))
+                            .or(warp::path!("ready").map(move || {
+                                let (ready, report) = core.readiness_report();
+                                check_reply(ready, &report)
+                            }))
+                            .or(warp::any().map(|| {
+                                warp::reply::with_status(
+                                    "go look at /metrics, /admin/status, /admin/snapshot, /health or /ready",
+                                    warp::http::StatusCode::NOT_FOUND,
+                                )
+                            })),
+                    )
+                    .run(([0, 0, 0, 0], port))
+                    .await;
+                })
+            }
+        }
+    }
+}
+
+/// Whether the home sync has started and it and every replica sync have
+/// caught up. Not ready before the home sync starts, e.g. at boot.
+fn syncs_ready<'a>(
+    home: &SyncProgress,
+    replicas: impl IntoIterator<Item = &'a SyncProgress>,
+) -> bool {
+    home.is_started()
+        && once(home)
+            .chain(replicas)
+            .all(|progress| progress.is_synced())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_caps_channel_fault_history() {
+        let status = AgentStatus::default();
+        for i in 0..MAX_CHANNEL_FAULTS + 5 {
+            status.record_channel_fault("replica", format!("error {}", i));
+        }
+
+        let faults = status.channel_faults();
+        assert_eq!(faults.len(), MAX_CHANNEL_FAULTS);
+        assert_eq!(faults[0].error, "error 5");
+    }
+
+    #[test]
+    fn it_is_not_ready_before_syncs_start() {
+        let home = SyncProgress::default();
+        let replica = SyncProgress::default();
+        assert!(!syncs_ready(&home, vec![]));
+
+        home.start("updates", 100);
+        assert!(!syncs_ready(&home, vec![]));
+
+        home.record("updates", 100, 100, 5);
+        assert!(syncs_ready(&home, vec![]));
+
+        replica.start("updates", 0);
+        assert!(!syncs_ready(&home, vec![&replica]));
+    }
+
+    #[test]
+    fn it_detects_crash_loops() {
+        let restarting = |failures| TaskState::Restarting {
+            error: "oops".to_owned(),
+            backoff_secs: 2,
+            failures,
+        };
+
+        assert!(!TaskState::Running {
+            failures: 0,
+            since: unix_timestamp()
+        }
+        .is_unhealthy());
+        assert!(!restarting(CRASH_LOOP_FAILURES - 1).is_unhealthy());
+        assert!(restarting(CRASH_LOOP_FAILURES).is_unhealthy());
+
+        // Restarted crash loops stay unhealthy until they ran for a while
+        assert!(TaskState::Running {
+            failures: CRASH_LOOP_FAILURES,
+            since: unix_timestamp()
+        }
+        .is_unhealthy());
+        assert!(!TaskState::Running {
+            failures: CRASH_LOOP_FAILURES,
+            since: unix_timestamp() - CRASH_LOOP_WINDOW_SECS
+        }
+        .is_unhealthy());
+        assert!(TaskState::Failed {
+            error: "oops".to_owned()
+        }
+        .is_unhealthy());
+    }
+
+    #[tokio::test]
+    async fn it_reports_sections() {
+        let status = AgentStatus::default();
+        status.set_task_state(
+            "channel:replica",
+            TaskState::Running {
+                failures: 0,
+                since: 0,
+            },
+        );
+        status.add_section("ok", || async { Ok(json!(1)) });
+        status.add_section("err", || async { Err(color_eyre::eyre::eyre!("oops")) });
+
+        let sections = status.sections().await;
+        assert_eq!(sections["ok"], json!(1));
+        assert_eq!(sections["err"], json!({ "error": "oops" }));
+        assert_eq!(
+            serde_json::to_value(status.tasks()).unwrap(),
+            json!({ "channel:replica": { "state": "running", "failures": 0, "since": 0 } })
+        );
+    }
+}
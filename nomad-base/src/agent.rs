@@ -1,12 +1,11 @@
 use crate::{
-    cancel_task,
+    admin::{unix_timestamp, CRASH_LOOP_WINDOW_SECS},
     metrics::CoreMetrics,
     settings::{IndexSettings, Settings},
-    BaseError, CachingHome, CachingReplica, NomadDB,
+    AgentStatus, BaseError, CachingHome, CachingReplica, NomadDB, ShutdownSignal, TaskState,
 };
 use async_trait::async_trait;
 use color_eyre::{eyre::WrapErr, Result};
-use futures_util::future::select_all;
 use nomad_core::{db::DB, Common};
 use tracing::instrument::Instrumented;
 use tracing::{error, info_span, warn, Instrument};
@@ -16,7 +15,7 @@ use std::{
     sync::Arc,
     time::{Duration, SystemTime},
 };
-use tokio::{task::JoinHandle, time::sleep};
+use tokio::task::JoinHandle;
 
 const MAX_EXPONENTIAL: u32 = 7; // 2^7 = 128 second timeout
 
@@ -35,6 +34,10 @@ pub struct AgentCore {
     pub indexer: IndexSettings,
     /// Settings this agent was created with
     pub settings: crate::settings::Settings,
+    /// Live status served by the admin API
+    pub status: Arc<AgentStatus>,
+    /// Signal telling agent tasks to stop taking on new work
+    pub shutdown: ShutdownSignal,
 }
 
 /// Commmon data needed for a single agent channel
@@ -46,6 +49,8 @@ pub struct ChannelBase {
     pub replica: Arc<CachingReplica>,
     /// NomadDB keyed by home
     pub db: NomadDB,
+    /// Signal telling the channel to stop taking on new work
+    pub shutdown: ShutdownSignal,
 }
 
 /// A trait for an application:
@@ -77,6 +82,7 @@ pub trait NomadAgent: Send + Sync + Sized + std::fmt::Debug + AsRef<AgentCore> {
             home: self.home(),
             replica: self.replica_by_name(replica).expect("!replica exist"),
             db: NomadDB::new(self.home().name(), self.db()),
+            shutdown: self.shutdown(),
         }
     }
 
@@ -90,6 +96,11 @@ pub trait NomadAgent: Send + Sync + Sized + std::fmt::Debug + AsRef<AgentCore> {
         self.as_ref().db.clone()
     }
 
+    /// Return a handle to the shutdown signal
+    fn shutdown(&self) -> ShutdownSignal {
+        self.as_ref().shutdown.clone()
+    }
+
     /// Return a reference to a home contract
     fn home(&self) -> Arc<CachingHome> {
         self.as_ref().home.clone()
@@ -110,7 +121,8 @@ pub trait NomadAgent: Send + Sync + Sized + std::fmt::Debug + AsRef<AgentCore> {
 
     /// Run the agent for a given channel. If the channel dies, exponentially
     /// retry. If failures are more than 5 minutes apart, reset exponential
-    /// backoff (likely unrelated after that point).
+    /// backoff (likely unrelated after that point). Channels are not
+    /// restarted once shutdown was triggered.
     #[allow(clippy::unit_arg)]
     #[tracing::instrument]
     fn run_report_error(&self, replica: String) -> Instrumented<JoinHandle<Result<()>>> {
@@ -118,11 +130,22 @@ pub trait NomadAgent: Send + Sync + Sized + std::fmt::Debug + AsRef<AgentCore> {
         let channel_faults_gauge = self
             .metrics()
             .channel_faults_gauge(self.home().name(), &replica);
+        let status = self.as_ref().status.clone();
+        let shutdown = self.shutdown();
+        let task = format!("channel:{}", &replica);
 
         tokio::spawn(async move {
             let mut exponential = 0;
+            let mut failures = 0;
             loop {
                 let running_time = SystemTime::now();
+                status.set_task_state(
+                    &task,
+                    TaskState::Running {
+                        failures,
+                        since: unix_timestamp(),
+                    },
+                );
 
                 let handle = Self::run(channel.clone()).in_current_span();
                 let res = handle
@@ -130,29 +153,50 @@ pub trait NomadAgent: Send + Sync + Sized + std::fmt::Debug + AsRef<AgentCore> {
                     .wrap_err(format!("Task for replica named {} failed", &replica));
 
                 match res {
-                    Ok(_) => return Ok(()),
+                    Ok(_) => {
+                        status.set_task_state(&task, TaskState::Finished);
+                        return Ok(());
+                    }
                     Err(e) => {
                         error!(
                             "Channel for replica {} errored out! Error: {:?}",
                             &replica, e
                         );
                         channel_faults_gauge.inc();
+                        status.record_channel_fault(&replica, format!("{:#}", e));
 
                         // If running time >= 5 minutes, current failure likely
                         // unrelated to previous
-                        if running_time.elapsed().unwrap().as_secs() >= 300 {
+                        if running_time.elapsed().unwrap().as_secs() >= CRASH_LOOP_WINDOW_SECS {
                             exponential = 0;
-                        } else if exponential < MAX_EXPONENTIAL {
-                            exponential += 1;
+                            failures = 1;
+                        } else {
+                            if exponential < MAX_EXPONENTIAL {
+                                exponential += 1;
+                            }
+                            failures += 1;
                         }
 
                         let sleep_time = 2u64.pow(exponential);
+                        status.set_task_state(
+                            &task,
+                            TaskState::Restarting {
+                                error: format!("{:#}", e),
+                                backoff_secs: sleep_time,
+                                failures,
+                            },
+                        );
                         warn!(
                             "Restarting channel to {} in {} seconds",
                             &replica, sleep_time
                         );
 
-                        sleep(Duration::from_secs(sleep_time)).await;
+                        // Shutdown during backoff is not a failure of the
+                        // channel: there is just nothing left to restart
+                        if shutdown.sleep(Duration::from_secs(sleep_time)).await {
+                            status.set_task_state(&task, TaskState::Finished);
+                            return Ok(());
+                        }
                     }
                 }
             }
@@ -168,18 +212,10 @@ pub trait NomadAgent: Send + Sync + Sized + std::fmt::Debug + AsRef<AgentCore> {
             .iter()
             .map(|replica| self.run_report_error(replica.to_string()))
             .collect();
+        let shutdown = self.shutdown();
 
-        tokio::spawn(async move {
-            // This gets the first future to resolve.
-            let (res, _, remaining) = select_all(handles).await;
-
-            for task in remaining.into_iter() {
-                cancel_task!(task);
-            }
-
-            res?
-        })
-        .instrument(span)
+        // This gets the first future to resolve.
+        tokio::spawn(async move { shutdown.select_all(handles).await }).instrument(span)
     }
 
     /// Run several agents
@@ -200,43 +236,64 @@ pub trait NomadAgent: Send + Sync + Sized + std::fmt::Debug + AsRef<AgentCore> {
             if Self::AGENT_NAME != "kathy" {
                 // Only the processor needs to index messages so default is
                 // just indexing updates
-                let sync_task = self.home().sync();
+                let sync_task = self.home().sync(self.shutdown());
 
                 tasks.push(sync_task);
             }
 
-            let (res, _, remaining) = select_all(tasks).await;
-
-            for task in remaining.into_iter() {
-                cancel_task!(task);
-            }
-
-            res?
+            self.shutdown().select_all(tasks).await
         })
         .instrument(span)
     }
 
     /// Spawn a task which continuously watch home for getting into failed state
-    /// and resolve once it happened.
+    /// and resolve once it happened, or once shutdown was triggered.
     /// `Reported` flag turns `Ok(())` into `Err(Report)` on failed home.
+    /// Errors are recorded as the `home_watch` task failing.
     #[allow(clippy::unit_arg)]
     fn watch_home_fail(&self, interval: u64) -> Instrumented<JoinHandle<Result<()>>> {
         let span = info_span!("home_watch");
         let home = self.home();
         let home_failure_checks = self.metrics().home_failure_checks();
         let home_failure_observations = self.metrics().home_failure_observations();
+        let shutdown = self.shutdown();
+        let status = self.as_ref().status.clone();
 
         tokio::spawn(async move {
-            let home = home.clone();
-            loop {
-                if home.state().await? == nomad_core::State::Failed {
-                    home_failure_observations.inc();
-                    return Err(BaseError::FailedHome.into());
-                }
+            let task = "home_watch";
+            status.set_task_state(
+                task,
+                TaskState::Running {
+                    failures: 0,
+                    since: unix_timestamp(),
+                },
+            );
+
+            let res: Result<()> = async {
+                loop {
+                    if home.state().await? == nomad_core::State::Failed {
+                        home_failure_observations.inc();
+                        return Err(BaseError::FailedHome.into());
+                    }
 
-                home_failure_checks.inc();
-                sleep(Duration::from_secs(interval)).await;
+                    home_failure_checks.inc();
+                    if shutdown.sleep(Duration::from_secs(interval)).await {
+                        return Ok(());
+                    }
+                }
             }
+            .await;
+
+            status.set_task_state(
+                task,
+                match &res {
+                    Ok(_) => TaskState::Finished,
+                    Err(e) => TaskState::Failed {
+                        error: format!("{:#}", e),
+                    },
+                },
+            );
+            res
         })
         .instrument(span)
     }
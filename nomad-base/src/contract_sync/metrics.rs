@@ -15,6 +15,11 @@ pub struct ContractSyncMetrics {
     /// Unique occasions when agent missed an event (label values
     /// differentiate updates vs. messages)
     pub missed_events: IntGaugeVec,
+    /// Reorgs detected while indexing (label values differentiate updates
+    /// vs. messages)
+    pub reorgs: IntGaugeVec,
+    /// Histogram of blocks rolled back per detected reorg
+    pub reorg_depth: HistogramVec,
 }
 
 impl ContractSyncMetrics {
@@ -55,11 +60,32 @@ impl ContractSyncMetrics {
             )
             .expect("failed to register missed_events metric");
 
+        let reorgs = metrics
+            .new_int_gauge_vec(
+                "contract_sync_reorgs",
+                "Number of reorgs detected while indexing",
+                &["data_type", "contract_name", "agent"],
+            )
+            .expect("failed to register reorgs metric");
+
+        let reorg_depth = metrics
+            .new_histogram(
+                "contract_sync_reorg_depth",
+                "Number of blocks rolled back per detected reorg",
+                &["data_type", "contract_name", "agent"],
+                &[
+                    1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0, 256.0, 512.0, 1000.0,
+                ],
+            )
+            .expect("failed to register reorg_depth metric");
+
         ContractSyncMetrics {
             indexed_height,
             store_event_latency,
             stored_events,
             missed_events,
+            reorgs,
+            reorg_depth,
         }
     }
 }
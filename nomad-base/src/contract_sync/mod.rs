@@ -1,9 +1,9 @@
-use crate::{IndexDataTypes, IndexSettings, NomadDB};
+use crate::{IndexDataTypes, IndexSettings, NomadDB, ShutdownSignal};
 use color_eyre::Result;
-use futures_util::future::select_all;
+use ethers::core::types::H256;
 use nomad_core::{CommonIndexer, HomeIndexer};
-use tokio::{task::JoinHandle, time::sleep};
-use tracing::{info, info_span};
+use tokio::task::JoinHandle;
+use tracing::{info, info_span, warn};
 use tracing::{instrument::Instrumented, Instrument};
 
 use std::cmp::min;
@@ -11,14 +11,45 @@ use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 mod metrics;
+mod progress;
+mod reorg;
 mod schema;
 
 pub use metrics::ContractSyncMetrics;
-use schema::{CommonContractSyncDB, HomeContractSyncDB};
+pub use progress::{DataTypeProgress, SyncProgress};
+use reorg::{Checkpoints, ReorgCheck};
+pub(crate) use schema::{CommonContractSyncDB, HomeContractSyncDB};
+use schema::{MESSAGES_CHECKPOINT, UPDATES_CHECKPOINT};
 
 const UPDATES_LABEL: &str = "updates";
 const MESSAGES_LABEL: &str = "messages";
 
+/// How long to wait before checking for reorgs again when the indexer does
+/// not know a recorded block yet
+const UNKNOWN_BLOCK_RETRY: Duration = Duration::from_secs(5);
+
+/// Block range to index for the chunk `from..=to`. Without a timelag, ranges
+/// reaching into non-final blocks are widened to re-scan the chunk's length
+/// behind the last final block, catching events missed at the tip.
+fn index_range(from: u32, to: u32, tip: u32, finality: u32, timelag_on: bool) -> (u32, u32) {
+    if timelag_on {
+        return (from, to);
+    }
+
+    let range = to - from;
+    let last_final_block = tip.saturating_sub(finality);
+
+    // If range includes non-final blocks, include range blocks behind last
+    // final block
+    let from = if to >= last_final_block {
+        last_final_block.saturating_sub(range)
+    } else {
+        from
+    };
+
+    (from, to)
+}
+
 /// Entity that drives the syncing of an agent's db with on-chain data.
 /// Extracts chain-specific data (emitted updates, messages, etc) from an
 /// `indexer` and fills the agent's db with this data. A CachingHome or
@@ -33,6 +64,7 @@ pub struct ContractSync<I> {
     index_settings: IndexSettings,
     finality: u8,
     metrics: ContractSyncMetrics,
+    progress: Arc<SyncProgress>,
 }
 
 impl<I> std::fmt::Display for ContractSync<I>
@@ -63,25 +95,36 @@ impl<I> ContractSync<I> {
             index_settings,
             finality,
             metrics,
+            progress: Default::default(),
         }
     }
+
+    /// Indexing progress of the sync tasks
+    pub fn progress(&self) -> Arc<SyncProgress> {
+        self.progress.clone()
+    }
 }
 
 impl<I> ContractSync<I>
 where
     I: CommonIndexer + 'static,
 {
-    /// Spawn sync task to sync updates
-    pub fn spawn_common(self) -> Instrumented<JoinHandle<Result<()>>> {
+    /// Spawn sync task to sync updates until shutdown is triggered
+    pub fn spawn_common(self, shutdown: ShutdownSignal) -> Instrumented<JoinHandle<Result<()>>> {
         let span = info_span!("ContractSync: Common", self = %self);
-        tokio::spawn(async move { self.sync_updates().await? }).instrument(span)
+        tokio::spawn(async move { self.sync_updates(shutdown).await? }).instrument(span)
     }
 
     /// Spawn task that continuously looks for new on-chain updates and stores
     /// them in db. If run in timelag is off, will index at the tip
     /// but use a manual timelag to catch any missed updates. If timelag on,
-    /// update  syncing will be run timelag blocks behind the tip.
-    pub fn sync_updates(&self) -> Instrumented<JoinHandle<Result<()>>> {
+    /// update  syncing will be run timelag blocks behind the tip. Stops
+    /// between ranges once shutdown is triggered.
+    ///
+    /// The hash of the last block of each indexed range is recorded. If that
+    /// block is later replaced, updates stored since the most recent block
+    /// still on the canonical chain are rolled back and re-indexed.
+    pub fn sync_updates(&self, shutdown: ShutdownSignal) -> Instrumented<JoinHandle<Result<()>>> {
         let span = info_span!("UpdateContractSync");
 
         let db = self.db.clone();
@@ -103,47 +146,77 @@ where
             &self.agent_name,
         ]);
 
+        let reorgs = self.metrics.reorgs.with_label_values(&[
+            UPDATES_LABEL,
+            &self.contract_name,
+            &self.agent_name,
+        ]);
+        let reorg_depth = self.metrics.reorg_depth.with_label_values(&[
+            UPDATES_LABEL,
+            &self.contract_name,
+            &self.agent_name,
+        ]);
+
         let timelag_on = self.index_settings.timelag_on();
         let finality = self.finality as u32;
         let config_from = self.index_settings.from();
         let chunk_size = self.index_settings.chunk_size();
 
+        let checkpoints =
+            Checkpoints::new(indexer.clone(), db.clone(), UPDATES_CHECKPOINT, config_from);
+        let progress = self.progress.clone();
+
         tokio::spawn(async move {
             let mut from = db
                 .retrieve_update_latest_block_end()
                 .map_or_else(|| config_from, |h| h);
 
             info!(from = from, "[Updates]: resuming indexer from {}", from);
+            progress.start(UPDATES_LABEL, from);
 
-            loop {
+            while !shutdown.is_triggered() {
                 indexed_height.set(from as i64);
 
+                // Roll back updates indexed from blocks that have since been
+                // replaced
+                let reorg = match checkpoints.detect_reorg::<H256>(from).await? {
+                    ReorgCheck::Unchanged => None,
+                    ReorgCheck::Unknown => {
+                        shutdown.sleep(UNKNOWN_BLOCK_RETRY).await;
+                        continue;
+                    }
+                    ReorgCheck::Reorg(reorg) => Some(reorg),
+                };
+                if let Some(reorg) = reorg {
+                    warn!(
+                        from = from,
+                        ancestor = reorg.ancestor,
+                        depth = reorg.depth,
+                        "[Updates]: reorg detected, rolling back to block {}",
+                        reorg.ancestor,
+                    );
+                    reorgs.inc();
+                    reorg_depth.observe(reorg.depth as f64);
+
+                    db.write_batch(|db| -> Result<()> {
+                        db.rollback_updates(reorg.watermark)?;
+                        db.store_update_latest_block_end(reorg.ancestor)?;
+                        Ok(())
+                    })?;
+                    from = reorg.ancestor;
+                    continue;
+                }
+
                 let tip = indexer.get_block_number().await?;
+                progress.record(UPDATES_LABEL, from, tip, finality);
                 if tip <= from {
                     // Sleep if we caught up to tip
-                    sleep(Duration::from_secs(100)).await;
+                    shutdown.sleep(Duration::from_secs(100)).await;
                     continue;
                 }
 
                 let to = min(from + chunk_size, tip);
-
-                let (start, end) = if timelag_on {
-                    // if timelag on, don't modify range
-                    (from, to)
-                } else {
-                    let range = to - from;
-                    let last_final_block = tip - finality;
-
-                    // If range includes non-final blocks, include range
-                    // blocks behind last final block
-                    let from = if to >= last_final_block {
-                        last_final_block - range
-                    } else {
-                        from
-                    };
-
-                    (from, to)
-                };
+                let (start, end) = index_range(from, to, tip, finality, timelag_on);
 
                 info!(
                     start = start,
@@ -153,18 +226,27 @@ where
                     end,
                 );
 
+                let to_hash = indexer.get_block_hash(to).await?;
                 let sorted_updates = indexer.fetch_sorted_updates(start, end).await?;
 
-                // If no updates found, update last seen block and next height
-                // and continue
-                if sorted_updates.is_empty() {
-                    db.store_update_latest_block_end(to)?;
-                    from = to;
+                // Retry if the range's last block was replaced mid-query
+                if indexer.get_block_hash(to).await? != to_hash {
+                    info!(to = to, "[Updates]: block {} replaced while indexing", to);
                     continue;
                 }
 
-                // Store updates
-                db.store_updates_and_meta(&sorted_updates)?;
+                // Store updates, move forward next height and checkpoint the
+                // range as one atomic write
+                db.write_batch(|db| -> Result<()> {
+                    db.store_updates_and_meta(&sorted_updates)?;
+                    db.store_update_latest_block_end(to)?;
+                    if let Some(hash) = to_hash {
+                        let latest_root = db.retrieve_latest_root()?.unwrap_or_default();
+                        checkpoints.store(db, from, to, hash, latest_root)?;
+                    }
+                    Ok(())
+                })?;
+                from = to;
 
                 // Report latencies from emit to store if caught up
                 if to == tip {
@@ -193,11 +275,9 @@ where
 
                 // Report amount of updates stored into db
                 stored_updates.add(sorted_updates.len().try_into()?);
-
-                // Move forward next height
-                db.store_update_latest_block_end(to)?;
-                from = to;
             }
+
+            Ok(())
         })
         .instrument(span)
     }
@@ -207,35 +287,37 @@ impl<I> ContractSync<I>
 where
     I: HomeIndexer + 'static,
 {
-    /// Spawn sync task to sync home updates (and potentially messages)
-    pub fn spawn_home(self) -> Instrumented<JoinHandle<Result<()>>> {
+    /// Spawn sync task to sync home updates (and potentially messages) until
+    /// shutdown is triggered
+    pub fn spawn_home(self, shutdown: ShutdownSignal) -> Instrumented<JoinHandle<Result<()>>> {
         let span = info_span!("ContractSync: Home", self = %self);
         let data_types = self.index_settings.data_types();
 
         tokio::spawn(async move {
             let tasks = match data_types {
-                IndexDataTypes::Updates => vec![self.sync_updates()],
+                IndexDataTypes::Updates => vec![self.sync_updates(shutdown.clone())],
                 IndexDataTypes::UpdatesAndMessages => {
-                    vec![self.sync_updates(), self.sync_messages()]
+                    vec![
+                        self.sync_updates(shutdown.clone()),
+                        self.sync_messages(shutdown.clone()),
+                    ]
                 }
             };
 
-            let (_, _, remaining) = select_all(tasks).await;
-            for task in remaining.into_iter() {
-                cancel_task!(task);
-            }
-
+            let _ = shutdown.select_all(tasks).await;
             Ok(())
         })
         .instrument(span)
     }
 
     /// Spawn task that continuously looks for new on-chain messages and stores
-    /// them in db. Indexing messages should ALWAYS be done with a timelag, as
-    /// ordering of messages is not guaranteed like it is for updates. Running
-    /// without a timelag could cause messages with the incorrectly ordered
-    /// index to be stored.
-    pub fn sync_messages(&self) -> Instrumented<JoinHandle<Result<()>>> {
+    /// them in db. If timelag is off, will index at the tip but use a manual
+    /// timelag to catch any missed messages. Stops between ranges once
+    /// shutdown is triggered.
+    ///
+    /// As with updates, messages, leaves and proofs stored from blocks that
+    /// are later replaced are rolled back and re-indexed.
+    pub fn sync_messages(&self, shutdown: ShutdownSignal) -> Instrumented<JoinHandle<Result<()>>> {
         let span = info_span!("MessageContractSync");
 
         let db = self.db.clone();
@@ -252,36 +334,82 @@ where
             &self.agent_name,
         ]);
 
+        let reorgs = self.metrics.reorgs.with_label_values(&[
+            MESSAGES_LABEL,
+            &self.contract_name,
+            &self.agent_name,
+        ]);
+        let reorg_depth = self.metrics.reorg_depth.with_label_values(&[
+            MESSAGES_LABEL,
+            &self.contract_name,
+            &self.agent_name,
+        ]);
+
         let timelag_on = self.index_settings.timelag_on();
+        let finality = self.finality as u32;
         let config_from = self.index_settings.from();
         let chunk_size = self.index_settings.chunk_size();
 
+        let checkpoints = Checkpoints::new(
+            indexer.clone(),
+            db.clone(),
+            MESSAGES_CHECKPOINT,
+            config_from,
+        );
+        let progress = self.progress.clone();
+
         tokio::spawn(async move {
             let mut from = db
                 .retrieve_message_latest_block_end()
                 .map_or_else(|| config_from, |h| h);
 
             info!(from = from, "[Messages]: resuming indexer from {}", from);
+            progress.start(MESSAGES_LABEL, from);
 
-            loop {
+            while !shutdown.is_triggered() {
                 indexed_height.set(from as i64);
 
+                // Roll back messages indexed from blocks that have since been
+                // replaced
+                let reorg = match checkpoints.detect_reorg::<u32>(from).await? {
+                    ReorgCheck::Unchanged => None,
+                    ReorgCheck::Unknown => {
+                        shutdown.sleep(UNKNOWN_BLOCK_RETRY).await;
+                        continue;
+                    }
+                    ReorgCheck::Reorg(reorg) => Some(reorg),
+                };
+                if let Some(reorg) = reorg {
+                    warn!(
+                        from = from,
+                        ancestor = reorg.ancestor,
+                        depth = reorg.depth,
+                        "[Messages]: reorg detected, rolling back to block {}",
+                        reorg.ancestor,
+                    );
+                    reorgs.inc();
+                    reorg_depth.observe(reorg.depth as f64);
+
+                    db.write_batch(|db| -> Result<()> {
+                        db.rollback_messages(reorg.watermark)?;
+                        db.store_message_latest_block_end(reorg.ancestor)?;
+                        Ok(())
+                    })?;
+                    from = reorg.ancestor;
+                    continue;
+                }
+
                 let tip = indexer.get_block_number().await?;
+                progress.record(MESSAGES_LABEL, from, tip, finality);
                 if tip <= from {
                     // Sleep if caught up to tip
-                    sleep(Duration::from_secs(100)).await;
+                    shutdown.sleep(Duration::from_secs(100)).await;
                     continue;
                 }
 
                 let candidate = from + chunk_size;
                 let to = min(tip, candidate);
-
-                // timelag always applied
-                let (start, end) = if timelag_on {
-                    (from, to)
-                } else {
-                    panic!("Syncing messages with timelag off should never happen!");
-                };
+                let (start, end) = index_range(from, to, tip, finality, timelag_on);
 
                 info!(
                     start = start,
@@ -291,26 +419,33 @@ where
                     end
                 );
 
+                let to_hash = indexer.get_block_hash(to).await?;
                 let sorted_messages = indexer.fetch_sorted_messages(start, end).await?;
 
-                // If no messages found, update last seen block and next height
-                // and continue
-                if sorted_messages.is_empty() {
-                    db.store_message_latest_block_end(to)?;
-                    from = to;
+                // Retry if the range's last block was replaced mid-query
+                if indexer.get_block_hash(to).await? != to_hash {
+                    info!(to = to, "[Messages]: block {} replaced while indexing", to);
                     continue;
                 }
 
-                // Store messages
-                db.store_messages(&sorted_messages)?;
+                // Store messages, move forward next height and checkpoint the
+                // range as one atomic write
+                db.write_batch(|db| -> Result<()> {
+                    db.store_messages_and_meta(&sorted_messages)?;
+                    db.store_message_latest_block_end(to)?;
+                    if let Some(hash) = to_hash {
+                        let leaf_count = db.retrieve_latest_leaf_index()?.map_or(0, |i| i + 1);
+                        checkpoints.store(db, from, to, hash, leaf_count)?;
+                    }
+                    Ok(())
+                })?;
+                from = to;
 
                 // Report amount of messages stored into db
                 stored_messages.add(sorted_messages.len().try_into()?);
-
-                // Move forward next height
-                db.store_message_latest_block_end(to)?;
-                from = to;
             }
+
+            Ok(())
         })
         .instrument(span)
     }
@@ -322,11 +457,12 @@ mod test {
     use nomad_test::mocks::MockIndexer;
 
     use std::sync::Arc;
+    use tokio::time::sleep;
 
     use ethers::core::types::H256;
     use ethers::signers::LocalWallet;
 
-    use nomad_core::{SignedUpdateWithMeta, Update, UpdateMeta};
+    use nomad_core::{SignedUpdateWithMeta, TxMeta, Update};
     use nomad_test::test_utils;
 
     use super::*;
@@ -334,6 +470,14 @@ mod test {
 
     const FINALITY: u8 = 5;
 
+    fn expect_block_hash(mock: &mut MockIndexer, seq: &mut Sequence, height: u32, hash: H256) {
+        mock.expect__get_block_hash()
+            .withf(move |h: &u32| *h == height)
+            .times(1)
+            .in_sequence(seq)
+            .return_once(move |_| Ok(Some(hash)));
+    }
+
     /* RPC Behavior:
      *  Starting Tip: block 20
      *  Starting Last Final Block: block 15
@@ -407,33 +551,33 @@ mod test {
 
                 let first_update_with_meta = SignedUpdateWithMeta {
                     signed_update: first_update.clone(),
-                    metadata: UpdateMeta {
+                    metadata: TxMeta {
                         block_number: 18,
-                        timestamp: Default::default(),
+                        ..Default::default()
                     },
                 };
 
                 let second_update_with_meta = SignedUpdateWithMeta {
                     signed_update: second_update.clone(),
-                    metadata: UpdateMeta {
+                    metadata: TxMeta {
                         block_number: 26,
-                        timestamp: Default::default(),
+                        ..Default::default()
                     },
                 };
 
                 let third_update_with_meta = SignedUpdateWithMeta {
                     signed_update: third_update.clone(),
-                    metadata: UpdateMeta {
+                    metadata: TxMeta {
                         block_number: 37,
-                        timestamp: Default::default(),
+                        ..Default::default()
                     },
                 };
 
                 let fourth_update_with_meta = SignedUpdateWithMeta {
                     signed_update: fourth_update.clone(),
-                    metadata: UpdateMeta {
+                    metadata: TxMeta {
                         block_number: 48,
-                        timestamp: Default::default(),
+                        ..Default::default()
                     },
                 };
 
@@ -501,6 +645,11 @@ mod test {
                 mock_indexer
                     .expect__fetch_sorted_updates()
                     .return_once(move |_, _| Ok(vec![]));
+
+                // Block hashes never change
+                mock_indexer
+                    .expect__get_block_hash()
+                    .returning(|_| Ok(Some(H256::zero())));
             }
 
             let nomad_db = NomadDB::new("home_1", db);
@@ -509,6 +658,7 @@ mod test {
                 chunk: Some(10.to_string()),
                 data_types: IndexDataTypes::Updates,
                 use_timelag: false,
+                message_indexes: false,
             };
 
             let indexer = Arc::new(mock_indexer);
@@ -533,7 +683,7 @@ mod test {
                 sync_metrics,
             );
 
-            let sync_task = contract_sync.sync_updates();
+            let sync_task = contract_sync.sync_updates(Default::default());
             sleep(Duration::from_secs(3)).await;
             cancel_task!(sync_task);
 
@@ -568,4 +718,215 @@ mod test {
         })
         .await
     }
+
+    /* RPC Behavior:
+     *  Timelag on, chunk size: 10 blocks
+     *
+     * Responses
+     *  - 10-20: 1st update @ block 18, block 20 hash A
+     *  - 20-30: 2nd update @ block 26, block 30 hash B
+     *  - block 30 replaced by hash C: roll back to block 20, dropping the 2nd
+     *    update
+     *  - 20-30: replacement 2nd update @ block 27, block 30 hash C
+     */
+    #[tokio::test]
+    async fn rolls_back_updates_from_replaced_blocks() {
+        test_utils::run_test_db(|db| async move {
+            let signer: LocalWallet =
+                "1111111111111111111111111111111111111111111111111111111111111111"
+                    .parse()
+                    .unwrap();
+
+            let first_root = H256::from([0; 32]);
+            let second_root = H256::from([1; 32]);
+            let third_root = H256::from([2; 32]);
+            let replacement_root = H256::from([3; 32]);
+
+            let first_update = Update {
+                home_domain: 1,
+                previous_root: first_root,
+                new_root: second_root,
+            }
+            .sign_with(&signer)
+            .await
+            .expect("!sign");
+
+            let second_update = Update {
+                home_domain: 1,
+                previous_root: second_root,
+                new_root: third_root,
+            }
+            .sign_with(&signer)
+            .await
+            .expect("!sign");
+
+            let replacement_update = Update {
+                home_domain: 1,
+                previous_root: second_root,
+                new_root: replacement_root,
+            }
+            .sign_with(&signer)
+            .await
+            .expect("!sign");
+
+            let hash_a = H256::repeat_byte(0xa);
+            let hash_b = H256::repeat_byte(0xb);
+            let hash_c = H256::repeat_byte(0xc);
+
+            let mut mock_indexer = MockIndexer::new();
+            {
+                let mut seq = Sequence::new();
+
+                let first_update_with_meta = SignedUpdateWithMeta {
+                    signed_update: first_update.clone(),
+                    metadata: TxMeta {
+                        block_number: 18,
+                        ..Default::default()
+                    },
+                };
+
+                let second_update_with_meta = SignedUpdateWithMeta {
+                    signed_update: second_update.clone(),
+                    metadata: TxMeta {
+                        block_number: 26,
+                        ..Default::default()
+                    },
+                };
+
+                let replacement_update_with_meta = SignedUpdateWithMeta {
+                    signed_update: replacement_update.clone(),
+                    metadata: TxMeta {
+                        block_number: 27,
+                        ..Default::default()
+                    },
+                };
+
+                // Index first update in range 10-20
+                mock_indexer
+                    .expect__get_block_number()
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .return_once(|| Ok(20));
+                expect_block_hash(&mut mock_indexer, &mut seq, 20, hash_a);
+                mock_indexer
+                    .expect__fetch_sorted_updates()
+                    .withf(move |from: &u32, to: &u32| *from == 10 && *to == 20)
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .return_once(move |_, _| Ok(vec![first_update_with_meta]));
+                expect_block_hash(&mut mock_indexer, &mut seq, 20, hash_a);
+
+                // Block 20 unchanged, index second update in range 20-30
+                expect_block_hash(&mut mock_indexer, &mut seq, 20, hash_a);
+                mock_indexer
+                    .expect__get_block_number()
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .return_once(|| Ok(30));
+                expect_block_hash(&mut mock_indexer, &mut seq, 30, hash_b);
+                mock_indexer
+                    .expect__fetch_sorted_updates()
+                    .withf(move |from: &u32, to: &u32| *from == 20 && *to == 30)
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .return_once(move |_, _| Ok(vec![second_update_with_meta]));
+                expect_block_hash(&mut mock_indexer, &mut seq, 30, hash_b);
+
+                // Block 30 replaced, block 20 still canonical
+                expect_block_hash(&mut mock_indexer, &mut seq, 30, hash_c);
+                expect_block_hash(&mut mock_indexer, &mut seq, 20, hash_a);
+
+                // Re-index range 20-30
+                expect_block_hash(&mut mock_indexer, &mut seq, 20, hash_a);
+                mock_indexer
+                    .expect__get_block_number()
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .return_once(|| Ok(30));
+                expect_block_hash(&mut mock_indexer, &mut seq, 30, hash_c);
+                mock_indexer
+                    .expect__fetch_sorted_updates()
+                    .withf(move |from: &u32, to: &u32| *from == 20 && *to == 30)
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .return_once(move |_, _| Ok(vec![replacement_update_with_meta]));
+                expect_block_hash(&mut mock_indexer, &mut seq, 30, hash_c);
+
+                // Caught up to tip
+                expect_block_hash(&mut mock_indexer, &mut seq, 30, hash_c);
+                mock_indexer
+                    .expect__get_block_number()
+                    .times(1)
+                    .in_sequence(&mut seq)
+                    .return_once(|| Ok(30));
+            }
+
+            let nomad_db = NomadDB::new("home_1", db);
+            let index_settings = IndexSettings {
+                from: Some(10.to_string()),
+                chunk: Some(10.to_string()),
+                data_types: IndexDataTypes::Updates,
+                use_timelag: true,
+                message_indexes: false,
+            };
+
+            let indexer = Arc::new(mock_indexer);
+            let metrics = Arc::new(
+                CoreMetrics::new(
+                    "contract_sync_test",
+                    None,
+                    Arc::new(prometheus::Registry::new()),
+                )
+                .expect("could not make metrics"),
+            );
+
+            let sync_metrics = ContractSyncMetrics::new(metrics);
+            let reorgs = sync_metrics
+                .reorgs
+                .with_label_values(&[UPDATES_LABEL, "home_1", "agent"]);
+
+            let contract_sync = ContractSync::new(
+                "agent".to_owned(),
+                "home_1".to_owned(),
+                nomad_db.clone(),
+                indexer.clone(),
+                index_settings,
+                FINALITY,
+                sync_metrics,
+            );
+
+            let sync_task = contract_sync.sync_updates(Default::default());
+            sleep(Duration::from_secs(3)).await;
+            cancel_task!(sync_task);
+
+            assert_eq!(reorgs.get(), 1);
+            assert_eq!(
+                nomad_db
+                    .update_by_previous_root(first_root)
+                    .expect("!db")
+                    .expect("!update"),
+                first_update
+            );
+            assert_eq!(
+                nomad_db
+                    .update_by_previous_root(second_root)
+                    .expect("!db")
+                    .expect("!update"),
+                replacement_update
+            );
+            assert!(nomad_db
+                .update_by_new_root(third_root)
+                .expect("!db")
+                .is_none());
+            assert!(nomad_db
+                .retrieve_update_metadata(third_root)
+                .expect("!db")
+                .is_none());
+            assert_eq!(
+                nomad_db.retrieve_latest_root().expect("!db"),
+                Some(replacement_root)
+            );
+        })
+        .await
+    }
 }
@@ -0,0 +1,90 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, sync::RwLock};
+
+/// Indexing progress of one data type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DataTypeProgress {
+    /// Next block height to index
+    pub from: u32,
+    /// Chain tip as of the last poll. `None` until the tip was first polled.
+    pub tip: Option<u32>,
+    /// Whether indexing has caught up to the tip minus finality
+    pub caught_up: bool,
+}
+
+/// Indexing progress of a ContractSync's tasks, shared by its clones and
+/// used to report agent readiness
+#[derive(Debug, Default)]
+pub struct SyncProgress {
+    data_types: RwLock<BTreeMap<&'static str, DataTypeProgress>>,
+}
+
+impl SyncProgress {
+    /// Record that the task indexing `data_type` started at `from`
+    pub(crate) fn start(&self, data_type: &'static str, from: u32) {
+        self.data_types.write().expect("!lock").insert(
+            data_type,
+            DataTypeProgress {
+                from,
+                ..Default::default()
+            },
+        );
+    }
+
+    /// Record the task indexing `data_type` polled `tip` while at `from`
+    pub(crate) fn record(&self, data_type: &'static str, from: u32, tip: u32, finality: u32) {
+        self.data_types.write().expect("!lock").insert(
+            data_type,
+            DataTypeProgress {
+                from,
+                tip: Some(tip),
+                caught_up: from >= tip.saturating_sub(finality),
+            },
+        );
+    }
+
+    /// Whether any indexing task was started
+    pub fn is_started(&self) -> bool {
+        !self.data_types.read().expect("!lock").is_empty()
+    }
+
+    /// Whether every started indexing task has caught up to the tip minus
+    /// finality
+    pub fn is_synced(&self) -> bool {
+        self.data_types
+            .read()
+            .expect("!lock")
+            .values()
+            .all(|progress| progress.caught_up)
+    }
+
+    /// Progress of every started indexing task
+    pub fn data_types(&self) -> BTreeMap<&'static str, DataTypeProgress> {
+        self.data_types.read().expect("!lock").clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_is_synced_within_finality_of_tip() {
+        let progress = SyncProgress::default();
+        assert!(!progress.is_started());
+
+        progress.start("updates", 100);
+        progress.start("messages", 100);
+        assert!(!progress.is_synced());
+
+        progress.record("updates", 100, 105, 5);
+        assert!(!progress.is_synced());
+
+        progress.record("messages", 100, 106, 5);
+        assert!(!progress.is_synced());
+
+        progress.record("messages", 101, 106, 5);
+        assert!(progress.is_synced());
+    }
+}
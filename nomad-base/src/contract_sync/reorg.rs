@@ -0,0 +1,264 @@
+use super::schema::{BlockCheckpoint, CheckpointDB};
+use crate::NomadDB;
+use color_eyre::Result;
+use ethers::core::types::H256;
+use nomad_core::{CommonIndexer, Decode, Encode};
+use std::sync::Arc;
+use tracing::{error, warn};
+
+/// Checkpoints more than this many blocks behind the latest checkpoint are
+/// pruned. Reorgs deeper than this roll back to the start of indexing.
+const REORG_WINDOW: u32 = 1000;
+
+/// A replaced block found at or below the last indexed height
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Reorg<T> {
+    /// Height of the most recent checkpoint still on the canonical chain
+    pub(crate) ancestor: u32,
+    /// Db state recorded at `ancestor`
+    pub(crate) watermark: T,
+    /// Number of blocks rolled back
+    pub(crate) depth: u32,
+}
+
+/// Outcome of checking recorded block hashes against the chain
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ReorgCheck<T> {
+    /// The last indexed block is still canonical, or nothing was recorded
+    /// for it
+    Unchanged,
+    /// The indexer does not know a recorded block yet, e.g. because its
+    /// endpoint lags. Check again later.
+    Unknown,
+    /// Recorded blocks were replaced
+    Reorg(Reorg<T>),
+}
+
+/// Block hashes recorded at the end of each range indexed for one data type
+#[derive(Debug)]
+pub(crate) struct Checkpoints<I> {
+    indexer: Arc<I>,
+    db: NomadDB,
+    prefix: &'static str,
+    config_from: u32,
+}
+
+impl<I> Checkpoints<I>
+where
+    I: CommonIndexer,
+{
+    /// Instantiate new Checkpoints. Reorgs deeper than any recorded
+    /// checkpoint roll back to `config_from`.
+    pub(crate) fn new(
+        indexer: Arc<I>,
+        db: NomadDB,
+        prefix: &'static str,
+        config_from: u32,
+    ) -> Self {
+        Self {
+            indexer,
+            db,
+            prefix,
+            config_from,
+        }
+    }
+
+    /// Record the hash of `block`, the end of a range indexed after the
+    /// checkpoint at `previous`. Writes go through `db` so they can be
+    /// batched with the range's data.
+    pub(crate) fn store<T>(
+        &self,
+        db: &NomadDB,
+        previous: u32,
+        block: u32,
+        hash: H256,
+        watermark: T,
+    ) -> Result<()>
+    where
+        T: Encode + Decode,
+    {
+        // Link to self if nothing was recorded for the previous range (e.g.
+        // first range indexed)
+        let previous = match db.retrieve_checkpoint::<T>(self.prefix, previous)? {
+            Some(_) if previous < block => previous,
+            _ => block,
+        };
+
+        db.store_checkpoint(
+            self.prefix,
+            block,
+            &BlockCheckpoint {
+                hash,
+                previous,
+                watermark,
+            },
+        )?;
+        self.prune::<T>(db, block)
+    }
+
+    /// Drop checkpoints behind the first one at least `REORG_WINDOW` blocks
+    /// below `latest`, which becomes the oldest checkpoint
+    fn prune<T>(&self, db: &NomadDB, latest: u32) -> Result<()>
+    where
+        T: Encode + Decode,
+    {
+        let mut block = latest;
+        while let Some(mut checkpoint) = db.retrieve_checkpoint::<T>(self.prefix, block)? {
+            if checkpoint.previous == block {
+                break;
+            }
+
+            if latest - block >= REORG_WINDOW {
+                let mut stale = checkpoint.previous;
+                while let Some(older) = db.retrieve_checkpoint::<T>(self.prefix, stale)? {
+                    db.delete_checkpoint(self.prefix, stale)?;
+                    if older.previous == stale {
+                        break;
+                    }
+                    stale = older.previous;
+                }
+
+                checkpoint.previous = block;
+                db.store_checkpoint(self.prefix, block, &checkpoint)?;
+                break;
+            }
+
+            block = checkpoint.previous;
+        }
+
+        Ok(())
+    }
+
+    /// Check the block hash recorded at `from` against the chain. If the
+    /// block was replaced, walk back to the most recent checkpoint still on
+    /// the canonical chain, deleting the replaced ones. Blocks the indexer
+    /// has no hash for are not taken as replaced, and nothing is deleted.
+    pub(crate) async fn detect_reorg<T>(&self, from: u32) -> Result<ReorgCheck<T>>
+    where
+        T: Encode + Decode + Default,
+    {
+        let mut block = from;
+        let mut replaced = vec![];
+        while let Some(checkpoint) = self.db.retrieve_checkpoint::<T>(self.prefix, block)? {
+            match self.indexer.get_block_hash(block).await? {
+                Some(hash) if hash == checkpoint.hash => {
+                    if replaced.is_empty() {
+                        return Ok(ReorgCheck::Unchanged);
+                    }
+                    self.delete_checkpoints(&replaced)?;
+                    return Ok(ReorgCheck::Reorg(Reorg {
+                        ancestor: block,
+                        watermark: checkpoint.watermark,
+                        depth: from - block,
+                    }));
+                }
+                Some(_) => replaced.push(block),
+                None => {
+                    warn!(
+                        prefix = self.prefix,
+                        block,
+                        "Indexer has no hash for block {} yet. Checking for reorgs later.",
+                        block
+                    );
+                    return Ok(ReorgCheck::Unknown);
+                }
+            }
+
+            if checkpoint.previous == block {
+                break;
+            }
+            block = checkpoint.previous;
+        }
+
+        // Nothing recorded for the last indexed block
+        if replaced.is_empty() {
+            return Ok(ReorgCheck::Unchanged);
+        }
+        self.delete_checkpoints(&replaced)?;
+
+        error!(
+            from,
+            config_from = self.config_from,
+            "Reorg deeper than recorded block hashes. Rolling back to block {}.",
+            self.config_from
+        );
+        Ok(ReorgCheck::Reorg(Reorg {
+            ancestor: self.config_from,
+            watermark: Default::default(),
+            depth: from.saturating_sub(self.config_from),
+        }))
+    }
+
+    fn delete_checkpoints(&self, blocks: &[u32]) -> Result<()> {
+        for block in blocks {
+            self.db.delete_checkpoint(self.prefix, *block)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use mockall::*;
+    use nomad_test::{mocks::MockIndexer, test_utils};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_does_not_roll_back_unknown_blocks() {
+        test_utils::run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+            let (hash_a, hash_b) = (H256::repeat_byte(0xa), H256::repeat_byte(0xb));
+            for (block, previous, hash) in [(20, 20, hash_a), (30, 20, hash_b)].iter() {
+                db.store_checkpoint(
+                    "checkpoint_",
+                    *block,
+                    &BlockCheckpoint {
+                        hash: *hash,
+                        previous: *previous,
+                        watermark: *block,
+                    },
+                )
+                .unwrap();
+            }
+
+            let mut mock_indexer = MockIndexer::new();
+            {
+                let mut seq = Sequence::new();
+                // Lagging endpoint, then block 30 replaced
+                for hash in [None, Some(H256::repeat_byte(0xc)), Some(hash_a)].iter() {
+                    let hash = *hash;
+                    mock_indexer
+                        .expect__get_block_hash()
+                        .times(1)
+                        .in_sequence(&mut seq)
+                        .return_once(move |_| Ok(hash));
+                }
+            }
+
+            let checkpoints =
+                Checkpoints::new(Arc::new(mock_indexer), db.clone(), "checkpoint_", 10);
+
+            assert!(matches!(
+                checkpoints.detect_reorg::<u32>(30).await.unwrap(),
+                ReorgCheck::Unknown
+            ));
+            assert!(db
+                .retrieve_checkpoint::<u32>("checkpoint_", 30)
+                .unwrap()
+                .is_some());
+
+            match checkpoints.detect_reorg::<u32>(30).await.unwrap() {
+                ReorgCheck::Reorg(reorg) => {
+                    assert_eq!((reorg.ancestor, reorg.watermark, reorg.depth), (20, 20, 10))
+                }
+                check => panic!("Expected reorg, got {:?}", check),
+            }
+            assert!(db
+                .retrieve_checkpoint::<u32>("checkpoint_", 30)
+                .unwrap()
+                .is_none());
+        })
+        .await
+    }
+}
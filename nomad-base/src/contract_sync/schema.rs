@@ -1,9 +1,57 @@
 use crate::NomadDB;
 use color_eyre::Result;
-use nomad_core::db::DbError;
+use ethers::core::types::H256;
+use nomad_core::{db::DbError, Decode, Encode, NomadError};
 
 static UPDATES_LAST_BLOCK_END: &str = "updates_last_block";
 static MESSAGES_LAST_BLOCK_END: &str = "messages_last_block";
+pub(crate) static UPDATES_CHECKPOINT: &str = "updates_checkpoint_";
+pub(crate) static MESSAGES_CHECKPOINT: &str = "messages_checkpoint_";
+
+/// Hash of the last block of an indexed range, along with the db's state
+/// once the range was stored. Checkpoints link back to the checkpoint of the
+/// previous range. The oldest checkpoint links to itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct BlockCheckpoint<T> {
+    /// Hash of the checkpointed block
+    pub(crate) hash: H256,
+    /// Height of the previous checkpoint
+    pub(crate) previous: u32,
+    /// Latest root (updates) or leaf count (messages) once the range was
+    /// stored
+    pub(crate) watermark: T,
+}
+
+impl<T> Encode for BlockCheckpoint<T>
+where
+    T: Encode,
+{
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        let mut written = self.hash.write_to(writer)?;
+        written += self.previous.write_to(writer)?;
+        written += self.watermark.write_to(writer)?;
+        Ok(written)
+    }
+}
+
+impl<T> Decode for BlockCheckpoint<T>
+where
+    T: Decode,
+{
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+    {
+        Ok(Self {
+            hash: H256::read_from(reader)?,
+            previous: u32::read_from(reader)?,
+            watermark: T::read_from(reader)?,
+        })
+    }
+}
 
 pub(crate) trait CommonContractSyncDB {
     fn store_update_latest_block_end(&self, latest_block: u32) -> Result<(), DbError>;
@@ -15,6 +63,21 @@ pub(crate) trait HomeContractSyncDB {
     fn retrieve_message_latest_block_end(&self) -> Option<u32>;
 }
 
+pub(crate) trait CheckpointDB {
+    fn store_checkpoint<T: Encode>(
+        &self,
+        prefix: &str,
+        block: u32,
+        checkpoint: &BlockCheckpoint<T>,
+    ) -> Result<(), DbError>;
+    fn retrieve_checkpoint<T: Decode>(
+        &self,
+        prefix: &str,
+        block: u32,
+    ) -> Result<Option<BlockCheckpoint<T>>, DbError>;
+    fn delete_checkpoint(&self, prefix: &str, block: u32) -> Result<(), DbError>;
+}
+
 impl CommonContractSyncDB for NomadDB {
     fn store_update_latest_block_end(&self, latest_block: u32) -> Result<(), DbError> {
         self.store_encodable("", UPDATES_LAST_BLOCK_END, &latest_block)
@@ -36,3 +99,26 @@ impl HomeContractSyncDB for NomadDB {
             .expect("db failure")
     }
 }
+
+impl CheckpointDB for NomadDB {
+    fn store_checkpoint<T: Encode>(
+        &self,
+        prefix: &str,
+        block: u32,
+        checkpoint: &BlockCheckpoint<T>,
+    ) -> Result<(), DbError> {
+        self.store_keyed_encodable(prefix, &block, checkpoint)
+    }
+
+    fn retrieve_checkpoint<T: Decode>(
+        &self,
+        prefix: &str,
+        block: u32,
+    ) -> Result<Option<BlockCheckpoint<T>>, DbError> {
+        self.retrieve_keyed_decodable(prefix, &block)
+    }
+
+    fn delete_checkpoint(&self, prefix: &str, block: u32) -> Result<(), DbError> {
+        self.delete_keyed_value(prefix, &block)
+    }
+}
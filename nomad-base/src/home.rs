@@ -1,10 +1,10 @@
-use crate::{ContractSync, HomeIndexers, NomadDB};
+use crate::{ContractSync, HomeIndexers, NomadDB, ShutdownSignal, SyncProgress};
 use async_trait::async_trait;
 use color_eyre::eyre::Result;
 use ethers::core::types::H256;
 use nomad_core::{
     db::DbError, ChainCommunicationError, Common, CommonEvents, DoubleUpdate, Home, HomeEvents,
-    Message, RawCommittedMessage, SignedUpdate, State, TxOutcome, Update,
+    Message, MessageVersion, RawCommittedMessage, SignedUpdate, State, TxOutcome, Update,
 };
 use nomad_ethereum::EthereumHome;
 use nomad_test::mocks::MockHomeContract;
@@ -47,11 +47,16 @@ impl CachingHome {
         self.db.clone()
     }
 
+    /// Return handle on the indexing progress of the CachingHome's db
+    pub fn sync_progress(&self) -> Arc<SyncProgress> {
+        self.contract_sync.progress()
+    }
+
     /// Spawn a task that syncs the CachingHome's db with the on-chain event
-    /// data
-    pub fn sync(&self) -> Instrumented<JoinHandle<Result<()>>> {
+    /// data until shutdown is triggered
+    pub fn sync(&self, shutdown: ShutdownSignal) -> Instrumented<JoinHandle<Result<()>>> {
         let sync = self.contract_sync.clone();
-        sync.spawn_home()
+        sync.spawn_home(shutdown)
     }
 }
 
@@ -69,6 +74,14 @@ impl Home for CachingHome {
         self.home.nonces(destination).await
     }
 
+    async fn version(&self) -> Result<MessageVersion, ChainCommunicationError> {
+        self.home.version().await
+    }
+
+    async fn max_message_body_bytes(&self) -> Result<usize, ChainCommunicationError> {
+        self.home.max_message_body_bytes().await
+    }
+
     async fn dispatch(&self, message: &Message) -> Result<TxOutcome, ChainCommunicationError> {
         self.home.dispatch(message).await
     }
@@ -287,6 +300,22 @@ impl Home for HomeVariants {
         }
     }
 
+    async fn version(&self) -> Result<MessageVersion, ChainCommunicationError> {
+        match self {
+            HomeVariants::Ethereum(home) => home.version().await,
+            HomeVariants::Mock(mock_home) => mock_home.version().await,
+            HomeVariants::Other(home) => home.version().await,
+        }
+    }
+
+    async fn max_message_body_bytes(&self) -> Result<usize, ChainCommunicationError> {
+        match self {
+            HomeVariants::Ethereum(home) => home.max_message_body_bytes().await,
+            HomeVariants::Mock(mock_home) => mock_home.max_message_body_bytes().await,
+            HomeVariants::Other(home) => home.max_message_body_bytes().await,
+        }
+    }
+
     #[instrument(level = "trace", err)]
     async fn dispatch(&self, message: &Message) -> Result<TxOutcome, ChainCommunicationError> {
         match self {
@@ -1,6 +1,7 @@
 use async_trait::async_trait;
 use color_eyre::Result;
-use nomad_core::{CommonIndexer, HomeIndexer, RawCommittedMessage, SignedUpdateWithMeta};
+use ethers::core::types::H256;
+use nomad_core::{CommonIndexer, HomeIndexer, RawCommittedMessageWithMeta, SignedUpdateWithMeta};
 use nomad_test::mocks::MockIndexer;
 use std::{ops::Deref, sync::Arc};
 
@@ -42,6 +43,10 @@ impl CommonIndexer for CommonIndexers {
         self.deref().get_block_number().await
     }
 
+    async fn get_block_hash(&self, height: u32) -> Result<Option<H256>> {
+        self.deref().get_block_hash(height).await
+    }
+
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         self.deref().fetch_sorted_updates(from, to).await
     }
@@ -68,6 +73,14 @@ impl CommonIndexer for CommonIndexerVariants {
         }
     }
 
+    async fn get_block_hash(&self, height: u32) -> Result<Option<H256>> {
+        match self {
+            CommonIndexerVariants::Ethereum(indexer) => indexer.get_block_hash(height).await,
+            CommonIndexerVariants::Mock(indexer) => indexer.get_block_hash(height).await,
+            CommonIndexerVariants::Other(indexer) => indexer.get_block_hash(height).await,
+        }
+    }
+
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         match self {
             CommonIndexerVariants::Ethereum(indexer) => {
@@ -115,6 +128,10 @@ impl CommonIndexer for HomeIndexers {
         self.deref().get_block_number().await
     }
 
+    async fn get_block_hash(&self, height: u32) -> Result<Option<H256>> {
+        self.deref().get_block_hash(height).await
+    }
+
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         self.deref().fetch_sorted_updates(from, to).await
     }
@@ -122,7 +139,11 @@ impl CommonIndexer for HomeIndexers {
 
 #[async_trait]
 impl HomeIndexer for HomeIndexers {
-    async fn fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessage>> {
+    async fn fetch_sorted_messages(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> Result<Vec<RawCommittedMessageWithMeta>> {
         self.deref().fetch_sorted_messages(from, to).await
     }
 }
@@ -148,6 +169,14 @@ impl CommonIndexer for HomeIndexerVariants {
         }
     }
 
+    async fn get_block_hash(&self, height: u32) -> Result<Option<H256>> {
+        match self {
+            HomeIndexerVariants::Ethereum(indexer) => indexer.get_block_hash(height).await,
+            HomeIndexerVariants::Mock(indexer) => indexer.get_block_hash(height).await,
+            HomeIndexerVariants::Other(indexer) => indexer.get_block_hash(height).await,
+        }
+    }
+
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         match self {
             HomeIndexerVariants::Ethereum(indexer) => indexer.fetch_sorted_updates(from, to).await,
@@ -159,7 +188,11 @@ impl CommonIndexer for HomeIndexerVariants {
 
 #[async_trait]
 impl HomeIndexer for HomeIndexerVariants {
-    async fn fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessage>> {
+    async fn fetch_sorted_messages(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> Result<Vec<RawCommittedMessageWithMeta>> {
         match self {
             HomeIndexerVariants::Ethereum(indexer) => indexer.fetch_sorted_messages(from, to).await,
             HomeIndexerVariants::Mock(indexer) => indexer.fetch_sorted_messages(from, to).await,
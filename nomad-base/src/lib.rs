@@ -45,6 +45,18 @@ pub use xapp::*;
 mod metrics;
 pub use metrics::*;
 
+/// Admin API
+mod admin;
+pub use admin::*;
+
+/// Graceful shutdown
+mod shutdown;
+pub use shutdown::*;
+
+/// DB snapshots and restore
+mod snapshot;
+pub use snapshot::*;
+
 mod contract_sync;
 pub use contract_sync::*;
 
@@ -89,6 +89,10 @@ macro_rules! decl_channel {
                 pub fn db(&self) -> nomad_base::NomadDB {
                     self.as_ref().db.clone()
                 }
+
+                pub fn shutdown(&self) -> nomad_base::ShutdownSignal {
+                    self.as_ref().shutdown.clone()
+                }
             }
         }
     }
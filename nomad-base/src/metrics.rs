@@ -5,7 +5,6 @@ use prometheus::{
     Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
 };
 use std::sync::Arc;
-use tokio::task::JoinHandle;
 
 #[derive(Debug)]
 /// Metrics for a particular domain
@@ -170,6 +169,16 @@ impl CoreMetrics {
         Ok(histogram)
     }
 
+    /// Name of the agent these metrics are tracked for
+    pub fn agent_name(&self) -> &str {
+        &self.agent_name
+    }
+
+    /// Port the HTTP server listens on, if enabled
+    pub fn listen_port(&self) -> Option<u16> {
+        self.listen_port
+    }
+
     /// Call with the new balance when gas is spent.
     pub fn wallet_balance_changed(
         &self,
@@ -220,47 +229,4 @@ impl CoreMetrics {
         encoder.encode(&collected_metrics, &mut out_buf)?;
         Ok(out_buf)
     }
-
-    /// Run an HTTP server serving OpenMetrics format reports on `/metrics`
-    ///
-    /// This is compatible with Prometheus, which ought to be configured to scrape me!
-    pub fn run_http_server(self: Arc<CoreMetrics>) -> JoinHandle<()> {
-        use warp::Filter;
-        match self.listen_port {
-            None => {
-                tracing::info!("not starting prometheus server");
-                tokio::spawn(std::future::ready(()))
-            }
-            Some(port) => {
-                tracing::info!(
-                    port,
-                    "starting prometheus server on 0.0.0.0:{port}",
-                    port = port
-                );
-                tokio::spawn(async move {
-                    warp::serve(
-                        warp::path!("metrics")
-                            .map(move || {
-                                warp::reply::with_header(
-                                    self.gather().expect("failed to encode metrics"),
-                                    "Content-Type",
-                                    // OpenMetrics specs demands "application/openmetrics-text; version=1.0.0; charset=utf-8"
-                                    // but the prometheus scraper itself doesn't seem to care?
-                                    // try text/plain to make web browsers happy.
-                                    "text/plain; charset=utf-8",
-                                )
-                            })
-                            .or(warp::any().map(|| {
-                                warp::reply::with_status(
-                                    "go look at /metrics",
-                                    warp::http::StatusCode::NOT_FOUND,
-                                )
-                            })),
-                    )
-                    .run(([0, 0, 0, 0], port))
-                    .await;
-                })
-            }
-        }
-    }
 }
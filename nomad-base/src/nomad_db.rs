@@ -1,14 +1,19 @@
 use color_eyre::Result;
 use ethers::core::types::H256;
-use nomad_core::db::{DbError, TypedDB, DB};
+use nomad_core::db::{Column, DbError, TypedDB, DB};
 use nomad_core::{
-    accumulator::merkle::Proof, utils, CommittedMessage, Decode, NomadMessage, RawCommittedMessage,
-    SignedUpdate, SignedUpdateWithMeta, UpdateMeta,
+    accumulator::{
+        merkle::{MerkleTree, Proof},
+        TREE_DEPTH,
+    },
+    utils, NomadMessageRef, RawCommittedMessage, RawCommittedMessageWithMeta, SignedUpdate,
+    SignedUpdateWithMeta, TxMeta,
 };
 use tokio::time::sleep;
 use tracing::{debug, info};
 
 use std::future::Future;
+use std::ops::RangeBounds;
 use std::time::Duration;
 
 use nomad_core::db::iterator::PrefixIterator;
@@ -20,10 +25,41 @@ static PROOF: &str = "proof_";
 static MESSAGE: &str = "message_";
 static UPDATE: &str = "update_";
 static UPDATE_META: &str = "update_metadata_";
+static MESSAGE_META: &str = "message_metadata_";
+static TX_MESSAGE: &str = "tx_message_";
+static TX_UPDATE: &str = "tx_update_";
 static LATEST_ROOT: &str = "update_latest_root_";
 static LATEST_LEAF_INDEX: &str = "latest_known_leaf_index_";
 static UPDATER_PRODUCED_UPDATE: &str = "updater_produced_update_";
 static PROVER_LATEST_COMMITTED: &str = "prover_latest_committed_";
+static PROCESSED_AT: &str = "processed_at_";
+static PRUNED: &str = "pruned_";
+static MESSAGE_INDEXES: &str = "message_indexes_enabled_";
+static BY_SENDER: &str = "by_sender_";
+static BY_RECIPIENT: &str = "by_recipient_";
+static BY_DESTINATION: &str = "by_destination_";
+
+/// Number of messages indexed per write batch when backfilling indexes
+const INDEX_BACKFILL_BATCH_SIZE: usize = 1_000;
+
+/// Prefixes of the secondary index entries of `message`
+fn index_prefixes(message: &NomadMessageRef) -> [Vec<u8>; 3] {
+    [
+        [BY_SENDER.as_bytes(), message.sender().as_bytes()].concat(),
+        [BY_RECIPIENT.as_bytes(), message.recipient().as_bytes()].concat(),
+        [
+            BY_DESTINATION.as_bytes(),
+            &message.destination().to_be_bytes()[..],
+        ]
+        .concat(),
+    ]
+}
+
+/// Prefix of the entries indexing the events emitted by the transaction
+/// `tx_hash`. Entries are keyed by log index under the prefix.
+fn tx_prefix(prefix: &str, tx_hash: H256) -> Vec<u8> {
+    [prefix.as_bytes(), tx_hash.as_bytes()].concat()
+}
 
 /// DB handle for storing data tied to a specific home.
 ///
@@ -57,6 +93,16 @@ impl NomadDB {
         Self(TypedDB::new(entity.as_ref().to_owned(), db))
     }
 
+    /// Run `f` against a view of the db whose writes are committed as one
+    /// atomic write batch once `f` returns. Nothing is written if `f` errors.
+    pub fn write_batch<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&NomadDB) -> Result<T, E>,
+        E: From<DbError>,
+    {
+        self.0.write_batch(|db| f(&NomadDB(db.clone())))
+    }
+
     /// Check if db is empty
     pub fn is_empty(&self) -> Result<bool> {
         let no_updates = self.update_by_previous_root(H256::zero())?.is_none();
@@ -66,15 +112,37 @@ impl NomadDB {
 
     /// Store list of messages
     pub fn store_messages(&self, messages: &[RawCommittedMessage]) -> Result<()> {
-        for message in messages {
-            self.store_latest_message(message)?;
+        self.write_batch(|db| {
+            messages
+                .iter()
+                .try_for_each(|message| db.store_latest_message(message))
+        })?;
 
-            let committed_message: CommittedMessage = message.clone().try_into()?;
+        Self::log_stored_messages(messages.iter())
+    }
+
+    /// Store list of sorted messages and their metadata
+    pub fn store_messages_and_meta(&self, messages: &[RawCommittedMessageWithMeta]) -> Result<()> {
+        self.write_batch(|db| {
+            messages.iter().try_for_each(|message_with_meta| {
+                db.store_latest_message(&message_with_meta.raw_message)?;
+                db.store_message_metadata(message_with_meta)
+            })
+        })?;
+
+        Self::log_stored_messages(messages.iter().map(|m| &m.raw_message))
+    }
+
+    fn log_stored_messages<'a>(
+        messages: impl Iterator<Item = &'a RawCommittedMessage>,
+    ) -> Result<()> {
+        for message in messages {
+            let parsed = message.message_ref()?;
             info!(
-                leaf_index = &committed_message.leaf_index,
-                origin = &committed_message.message.origin,
-                destination = &committed_message.message.destination,
-                nonce = &committed_message.message.nonce,
+                leaf_index = message.leaf_index,
+                origin = parsed.origin(),
+                destination = parsed.destination(),
+                nonce = parsed.nonce(),
                 "Stored new message in db.",
             );
         }
@@ -88,26 +156,116 @@ impl NomadDB {
     /// - `destination_and_nonce` --> `leaf`
     /// - `leaf_index` --> `leaf`
     /// - `leaf` --> `message`
+    ///
+    /// If message indexes are enabled:
+    /// - `sender`, `recipient` and `destination` + `leaf_index` --> `leaf`
     pub fn store_raw_committed_message(&self, message: &RawCommittedMessage) -> Result<()> {
-        let parsed = NomadMessage::read_from(&mut message.message.clone().as_slice())?;
+        let parsed = message.message_ref()?;
 
         let destination_and_nonce = parsed.destination_and_nonce();
 
-        let leaf = message.leaf();
+        let leaf = parsed.to_leaf();
 
         debug!(
             leaf = ?leaf,
             destination_and_nonce,
-            destination = parsed.destination,
-            nonce = parsed.nonce,
+            destination = parsed.destination(),
+            nonce = parsed.nonce(),
             leaf_index = message.leaf_index,
             "storing raw committed message in db"
         );
-        self.store_leaf(message.leaf_index, destination_and_nonce, leaf)?;
-        self.store_keyed_encodable(MESSAGE, &leaf, message)?;
+        let indexed = self.message_indexes_enabled()?;
+        self.write_batch(|db| {
+            db.store_leaf(message.leaf_index, destination_and_nonce, leaf)?;
+            if indexed {
+                db.store_message_indexes(&parsed, message.leaf_index, leaf)?;
+            }
+            db.column(Column::Messages)
+                .store_keyed_encodable(MESSAGE, &leaf, message)
+        })?;
         Ok(())
     }
 
+    fn store_message_indexes(
+        &self,
+        message: &NomadMessageRef,
+        leaf_index: u32,
+        leaf: H256,
+    ) -> Result<(), DbError> {
+        index_prefixes(message).iter().try_for_each(|prefix| {
+            self.column(Column::Indexes)
+                .store_keyed_encodable(prefix, &leaf_index, &leaf)
+        })
+    }
+
+    /// Whether messages are indexed by sender, recipient and destination
+    pub fn message_indexes_enabled(&self) -> Result<bool, DbError> {
+        Ok(self
+            .retrieve_decodable("", MESSAGE_INDEXES)?
+            .unwrap_or_default())
+    }
+
+    /// Index messages by sender, recipient and destination from now on, and
+    /// backfill the indexes of the messages already stored. Messages must
+    /// not be stored meanwhile.
+    pub fn enable_message_indexes(&self) -> Result<(), DbError> {
+        if self.message_indexes_enabled()? {
+            return Ok(());
+        }
+
+        self.batch_stored_messages(|db, message| {
+            let parsed = message.message_ref()?;
+            db.store_message_indexes(&parsed, message.leaf_index, parsed.to_leaf())
+        })?;
+
+        info!("Enabled message indexes");
+        self.store_encodable("", MESSAGE_INDEXES, &true)
+    }
+
+    /// Stop indexing messages and delete the indexes of the messages already
+    /// stored. Messages must not be stored meanwhile.
+    pub fn disable_message_indexes(&self) -> Result<(), DbError> {
+        if !self.message_indexes_enabled()? {
+            return Ok(());
+        }
+
+        // The flag is cleared last, so interrupted deletes are resumed
+        self.batch_stored_messages(|db, message| {
+            let parsed = message.message_ref()?;
+            index_prefixes(&parsed).iter().try_for_each(|prefix| {
+                db.column(Column::Indexes)
+                    .delete_keyed_value(prefix, &message.leaf_index)
+            })
+        })?;
+
+        info!("Disabled message indexes");
+        self.store_encodable("", MESSAGE_INDEXES, &false)
+    }
+
+    /// Run `f` against each stored message, in leaf index order. Writes are
+    /// committed in atomic batches of `INDEX_BACKFILL_BATCH_SIZE` messages.
+    fn batch_stored_messages<F>(&self, f: F) -> Result<(), DbError>
+    where
+        F: Fn(&NomadDB, &RawCommittedMessage) -> Result<(), DbError>,
+    {
+        let mut from = 0;
+        loop {
+            let messages = self
+                .messages_by_leaf_index(from..)
+                .take(INDEX_BACKFILL_BATCH_SIZE)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            self.write_batch(|db| messages.iter().try_for_each(|message| f(db, message)))?;
+
+            match messages.last() {
+                Some(last) if messages.len() == INDEX_BACKFILL_BATCH_SIZE => {
+                    from = last.leaf_index + 1
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
     /// Store a raw committed message building off of the latest leaf index
     pub fn store_latest_message(&self, message: &RawCommittedMessage) -> Result<()> {
         // If there is no latest root, or if this update is on the latest root
@@ -130,6 +288,115 @@ impl NomadDB {
         self.store_raw_committed_message(message)
     }
 
+    /// Store message metadata (by message leaf)
+    ///
+    /// Keys --> Values:
+    /// - `leaf` --> `message_metadata`
+    /// - `tx_hash` + `log_index` --> `leaf`
+    pub fn store_message_metadata(
+        &self,
+        message_with_meta: &RawCommittedMessageWithMeta,
+    ) -> Result<(), DbError> {
+        let leaf = message_with_meta.raw_message.leaf();
+        let metadata = message_with_meta.metadata;
+
+        debug!(leaf = ?leaf, metadata = ?metadata, "storing message metadata in DB");
+
+        let messages = self.column(Column::Messages);
+        messages.write_batch(|db| {
+            db.store_keyed_encodable(MESSAGE_META, &leaf, &metadata)?;
+            if !metadata.transaction_hash.is_zero() {
+                db.store_keyed_encodable(
+                    tx_prefix(TX_MESSAGE, metadata.transaction_hash),
+                    &metadata.log_index,
+                    &leaf,
+                )?;
+            }
+            Ok::<_, DbError>(())
+        })
+    }
+
+    /// Retrieve message metadata (by message leaf)
+    pub fn retrieve_message_metadata(&self, leaf: H256) -> Result<Option<TxMeta>, DbError> {
+        self.column(Column::Messages)
+            .retrieve_keyed_decodable(MESSAGE_META, &leaf)
+    }
+
+    /// Iterate over the messages dispatched in the transaction `tx_hash`, by
+    /// log index. Messages whose bodies were pruned are skipped.
+    pub fn messages_by_tx_hash(
+        &self,
+        tx_hash: H256,
+    ) -> impl Iterator<Item = Result<RawCommittedMessage, DbError>> + '_ {
+        self.column_range_iterator::<u64, H256>(
+            Column::Messages,
+            tx_prefix(TX_MESSAGE, tx_hash),
+            ..,
+        )
+        .map(move |res| self.message_by_leaf(res?.1))
+        .filter_map(Result::transpose)
+    }
+
+    /// Roll back all messages at or above `leaf_index`, along with their
+    /// leaves and proofs. The latest known leaf index is reset to the leaf
+    /// below `leaf_index`.
+    pub fn rollback_messages(&self, leaf_index: u32) -> Result<()> {
+        let leaves = self
+            .leaves_by_leaf_index(leaf_index..)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (index, leaf) in leaves {
+            // Keys derived from pruned bodies were deleted along with them
+            if let Some(message) = self.message_by_leaf(leaf)? {
+                self.delete_message_keys(index, leaf, &message)?;
+            }
+            self.column(Column::Leaves)
+                .delete_keyed_value(LEAF_IDX, &index)?;
+            self.column(Column::Proofs)
+                .delete_keyed_value(PROOF, &index)?;
+            self.delete_keyed_value(PROCESSED_AT, &index)?;
+            self.delete_keyed_value(PRUNED, &index)?;
+
+            info!(leaf_index = index, leaf = ?leaf, "Rolled back message in db.");
+        }
+
+        match leaf_index.checked_sub(1) {
+            Some(latest) => self.update_latest_leaf_index(latest)?,
+            None => self.delete_value("", LATEST_LEAF_INDEX)?,
+        }
+
+        Ok(())
+    }
+
+    /// Delete the message of `leaf` at `index`, along with the keys derived
+    /// from its body: its destination and nonce key, its secondary indexes
+    /// and its transaction metadata
+    fn delete_message_keys(
+        &self,
+        index: u32,
+        leaf: H256,
+        message: &RawCommittedMessage,
+    ) -> Result<(), DbError> {
+        let parsed = message.message_ref()?;
+        self.column(Column::Leaves)
+            .delete_keyed_value(LEAF, &parsed.destination_and_nonce())?;
+        self.column(Column::Messages)
+            .delete_keyed_value(MESSAGE, &leaf)?;
+        if let Some(metadata) = self.retrieve_message_metadata(leaf)? {
+            self.column(Column::Messages).delete_keyed_value(
+                tx_prefix(TX_MESSAGE, metadata.transaction_hash),
+                &metadata.log_index,
+            )?;
+            self.column(Column::Messages)
+                .delete_keyed_value(MESSAGE_META, &leaf)?;
+        }
+        for prefix in index_prefixes(&parsed).iter() {
+            self.column(Column::Indexes)
+                .delete_keyed_value(prefix, &index)?;
+        }
+        Ok(())
+    }
+
     /// Store the latest known leaf_index
     ///
     /// Key --> value: `LATEST_LEAF_INDEX` --> `leaf_index`
@@ -154,24 +421,39 @@ impl NomadDB {
             leaf = ?leaf,
             "storing leaf hash keyed by index and dest+nonce"
         );
-        self.store_keyed_encodable(LEAF, &destination_and_nonce, &leaf)?;
-        self.store_keyed_encodable(LEAF, &leaf_index, &leaf)
+        self.write_batch(|db| {
+            db.column(Column::Leaves)
+                .store_keyed_encodable(LEAF, &destination_and_nonce, &leaf)?;
+            db.column(Column::Leaves)
+                .store_keyed_encodable(LEAF_IDX, &leaf_index, &leaf)
+        })
     }
 
     /// Retrieve a raw committed message by its leaf hash
     pub fn message_by_leaf(&self, leaf: H256) -> Result<Option<RawCommittedMessage>, DbError> {
-        self.retrieve_keyed_decodable(MESSAGE, &leaf)
+        self.column(Column::Messages)
+            .retrieve_keyed_decodable(MESSAGE, &leaf)
     }
 
     /// Retrieve the leaf hash keyed by leaf index
     pub fn leaf_by_leaf_index(&self, leaf_index: u32) -> Result<Option<H256>, DbError> {
-        self.retrieve_keyed_decodable(LEAF, &leaf_index)
+        self.column(Column::Leaves)
+            .retrieve_keyed_decodable(LEAF_IDX, &leaf_index)
+    }
+
+    /// Iterate over leaf hashes with leaf indices in `range`, by leaf index
+    pub fn leaves_by_leaf_index(
+        &self,
+        range: impl RangeBounds<u32>,
+    ) -> PrefixIterator<'_, u32, H256> {
+        self.column_range_iterator(Column::Leaves, LEAF_IDX, range)
     }
 
     /// Retrieve the leaf hash keyed by destination and nonce
     pub fn leaf_by_nonce(&self, destination: u32, nonce: u32) -> Result<Option<H256>, DbError> {
         let dest_and_nonce = utils::destination_and_nonce(destination, nonce);
-        self.retrieve_keyed_decodable(LEAF, &dest_and_nonce)
+        self.column(Column::Leaves)
+            .retrieve_keyed_decodable(LEAF, &dest_and_nonce)
     }
 
     /// Retrieve a raw committed message by its leaf hash
@@ -199,6 +481,88 @@ impl NomadDB {
         }
     }
 
+    /// Iterate over raw committed messages with leaf indices in `range`, by
+    /// leaf index. Messages whose bodies were pruned are skipped. Errors if
+    /// any other leaf has no stored message.
+    pub fn messages_by_leaf_index(
+        &self,
+        range: impl RangeBounds<u32>,
+    ) -> impl Iterator<Item = Result<RawCommittedMessage, DbError>> + '_ {
+        self.leaves_by_leaf_index(range)
+            .map(move |res| {
+                let (leaf_index, leaf) = res?;
+                self.indexed_message(leaf_index, leaf)
+            })
+            .filter_map(Result::transpose)
+    }
+
+    /// Retrieve the message of `leaf` at `leaf_index`. `None` if its body was
+    /// pruned.
+    fn indexed_message(
+        &self,
+        leaf_index: u32,
+        leaf: H256,
+    ) -> Result<Option<RawCommittedMessage>, DbError> {
+        match self.message_by_leaf(leaf)? {
+            Some(message) => Ok(Some(message)),
+            None if self.is_pruned(leaf_index)? => Ok(None),
+            None => Err(DbError::Inconsistent(format!(
+                "no message stored for leaf {:?} at leaf index {}",
+                leaf, leaf_index
+            ))),
+        }
+    }
+
+    fn messages_by_index(
+        &self,
+        prefix: Vec<u8>,
+        range: impl RangeBounds<u32>,
+    ) -> impl Iterator<Item = Result<RawCommittedMessage, DbError>> + '_ {
+        self.column_range_iterator(Column::Indexes, prefix, range)
+            .map(move |res| {
+                let (leaf_index, leaf) = res?;
+                self.indexed_message(leaf_index, leaf)
+            })
+            .filter_map(Result::transpose)
+    }
+
+    /// Iterate over the messages sent by `sender` with leaf indices in
+    /// `range`, by leaf index. Empty unless message indexes are enabled.
+    pub fn messages_by_sender(
+        &self,
+        sender: H256,
+        range: impl RangeBounds<u32>,
+    ) -> impl Iterator<Item = Result<RawCommittedMessage, DbError>> + '_ {
+        self.messages_by_index([BY_SENDER.as_bytes(), sender.as_bytes()].concat(), range)
+    }
+
+    /// Iterate over the messages sent to `recipient` with leaf indices in
+    /// `range`, by leaf index. Empty unless message indexes are enabled.
+    pub fn messages_by_recipient(
+        &self,
+        recipient: H256,
+        range: impl RangeBounds<u32>,
+    ) -> impl Iterator<Item = Result<RawCommittedMessage, DbError>> + '_ {
+        self.messages_by_index(
+            [BY_RECIPIENT.as_bytes(), recipient.as_bytes()].concat(),
+            range,
+        )
+    }
+
+    /// Iterate over the messages sent to the `destination` domain with leaf
+    /// indices in `range`, by leaf index. Empty unless message indexes are
+    /// enabled.
+    pub fn messages_by_destination(
+        &self,
+        destination: u32,
+        range: impl RangeBounds<u32>,
+    ) -> impl Iterator<Item = Result<RawCommittedMessage, DbError>> + '_ {
+        self.messages_by_index(
+            [BY_DESTINATION.as_bytes(), &destination.to_be_bytes()[..]].concat(),
+            range,
+        )
+    }
+
     /// Store the latest committed
     fn store_latest_root(&self, root: H256) -> Result<(), DbError> {
         debug!(root = ?root, "storing new latest root in DB");
@@ -210,15 +574,75 @@ impl NomadDB {
         self.retrieve_decodable("", LATEST_ROOT)
     }
 
+    /// Roll back the chain of updates from the latest root down to `root`,
+    /// along with their metadata. The latest root is reset to `root`, and so
+    /// is the prover's latest committed root if it was rolled back. A zero
+    /// `root` rolls back every update on the chain.
+    pub fn rollback_updates(&self, root: H256) -> Result<()> {
+        let prover_committed = self.retrieve_prover_latest_committed()?;
+        let mut reset_prover = false;
+
+        let mut current = self.retrieve_latest_root()?;
+        while let Some(new_root) = current.filter(|new_root| *new_root != root) {
+            let update = match self.update_by_new_root(new_root)? {
+                Some(update) => update,
+                None => break,
+            };
+
+            let previous_root = update.update.previous_root;
+            self.column(Column::Updates)
+                .delete_keyed_value(UPDATE, &previous_root)?;
+            self.column(Column::Updates)
+                .delete_keyed_value(PREV_ROOT, &new_root)?;
+            if let Some(metadata) = self.retrieve_update_metadata(new_root)? {
+                self.column(Column::Updates).delete_keyed_value(
+                    tx_prefix(TX_UPDATE, metadata.transaction_hash),
+                    &metadata.log_index,
+                )?;
+                self.column(Column::Updates)
+                    .delete_keyed_value(UPDATE_META, &new_root)?;
+            }
+            reset_prover |= prover_committed == Some(new_root);
+
+            info!(
+                previous_root = ?previous_root,
+                new_root = ?new_root,
+                "Rolled back update in db.",
+            );
+            current = Some(previous_root);
+        }
+
+        if root.is_zero() {
+            self.delete_value("", LATEST_ROOT)?;
+        } else {
+            self.store_latest_root(root)?;
+        }
+
+        if reset_prover {
+            if root.is_zero() {
+                self.delete_value("", PROVER_LATEST_COMMITTED)?;
+            } else {
+                self.store_prover_latest_committed(root)?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Store list of sorted updates and their metadata
     pub fn store_updates_and_meta(&self, updates: &[SignedUpdateWithMeta]) -> Result<()> {
-        for update_with_meta in updates {
-            self.store_latest_update(&update_with_meta.signed_update)?;
-            self.store_update_metadata(update_with_meta)?;
+        self.write_batch(|db| {
+            updates.iter().try_for_each(|update_with_meta| {
+                db.store_latest_update(&update_with_meta.signed_update)?;
+                db.store_update_metadata(update_with_meta)
+            })
+        })?;
 
+        for update_with_meta in updates {
             info!(
                 block_number = update_with_meta.metadata.block_number,
                 timestamp = ?update_with_meta.metadata.timestamp,
+                transaction_hash = ?update_with_meta.metadata.transaction_hash,
                 previous_root = ?&update_with_meta.signed_update.update.previous_root,
                 new_root = ?&update_with_meta.signed_update.update.new_root,
                 "Stored new update in db.",
@@ -232,6 +656,7 @@ impl NomadDB {
     ///
     /// Keys --> Values:
     /// - `update_new_root` --> `update_metadata`
+    /// - `tx_hash` + `log_index` --> `update_new_root`
     pub fn store_update_metadata(
         &self,
         update_with_meta: &SignedUpdateWithMeta,
@@ -241,12 +666,35 @@ impl NomadDB {
 
         debug!(new_root = ?new_root, metadata = ?metadata, "storing update metadata in DB");
 
-        self.store_keyed_encodable(UPDATE_META, &new_root, &metadata)
+        let updates = self.column(Column::Updates);
+        updates.write_batch(|db| {
+            db.store_keyed_encodable(UPDATE_META, &new_root, &metadata)?;
+            if !metadata.transaction_hash.is_zero() {
+                db.store_keyed_encodable(
+                    tx_prefix(TX_UPDATE, metadata.transaction_hash),
+                    &metadata.log_index,
+                    &new_root,
+                )?;
+            }
+            Ok::<_, DbError>(())
+        })
     }
 
     /// Retrieve update metadata (by update's new root)
-    pub fn retrieve_update_metadata(&self, new_root: H256) -> Result<Option<UpdateMeta>, DbError> {
-        self.retrieve_keyed_decodable(UPDATE_META, &new_root)
+    pub fn retrieve_update_metadata(&self, new_root: H256) -> Result<Option<TxMeta>, DbError> {
+        self.column(Column::Updates)
+            .retrieve_keyed_decodable(UPDATE_META, &new_root)
+    }
+
+    /// Iterate over the updates accepted in the transaction `tx_hash`, by log
+    /// index
+    pub fn updates_by_tx_hash(
+        &self,
+        tx_hash: H256,
+    ) -> impl Iterator<Item = Result<SignedUpdate, DbError>> + '_ {
+        self.column_range_iterator::<u64, H256>(Column::Updates, tx_prefix(TX_UPDATE, tx_hash), ..)
+            .map(move |res| self.update_by_new_root(res?.1))
+            .filter_map(Result::transpose)
     }
 
     /// Store a signed update building off latest root
@@ -287,12 +735,18 @@ impl NomadDB {
     /// - `new_root` --> `prev_root`
     /// - `prev_root` --> `update`
     pub fn store_update(&self, update: &SignedUpdate) -> Result<(), DbError> {
-        self.store_keyed_encodable(UPDATE, &update.update.previous_root, update)?;
-        self.store_keyed_encodable(
-            PREV_ROOT,
-            &update.update.new_root,
-            &update.update.previous_root,
-        )
+        self.write_batch(|db| {
+            db.column(Column::Updates).store_keyed_encodable(
+                UPDATE,
+                &update.update.previous_root,
+                update,
+            )?;
+            db.column(Column::Updates).store_keyed_encodable(
+                PREV_ROOT,
+                &update.update.new_root,
+                &update.update.previous_root,
+            )
+        })
     }
 
     /// Retrieve an update by its previous root
@@ -300,12 +754,15 @@ impl NomadDB {
         &self,
         previous_root: H256,
     ) -> Result<Option<SignedUpdate>, DbError> {
-        self.retrieve_keyed_decodable(UPDATE, &previous_root)
+        self.column(Column::Updates)
+            .retrieve_keyed_decodable(UPDATE, &previous_root)
     }
 
     /// Retrieve an update by its new root
     pub fn update_by_new_root(&self, new_root: H256) -> Result<Option<SignedUpdate>, DbError> {
-        let prev_root: Option<H256> = self.retrieve_keyed_decodable(PREV_ROOT, &new_root)?;
+        let prev_root: Option<H256> = self
+            .column(Column::Updates)
+            .retrieve_keyed_decodable(PREV_ROOT, &new_root)?;
 
         match prev_root {
             Some(prev_root) => self.update_by_previous_root(prev_root),
@@ -313,9 +770,21 @@ impl NomadDB {
         }
     }
 
-    /// Iterate over all leaves
-    pub fn leaf_iterator(&self) -> PrefixIterator<H256> {
-        PrefixIterator::new(self.0.as_ref().prefix_iterator(LEAF_IDX), LEAF_IDX.as_ref())
+    /// Iterate over the chain of updates starting with the update building
+    /// off `previous_root`, in root-chain order. Ends at the first root no
+    /// update builds off.
+    pub fn updates_from(
+        &self,
+        previous_root: H256,
+    ) -> impl Iterator<Item = Result<SignedUpdate, DbError>> + '_ {
+        let mut next_root = Some(previous_root);
+        std::iter::from_fn(move || {
+            let update = self.update_by_previous_root(next_root.take()?);
+            if let Ok(Some(update)) = &update {
+                next_root = Some(update.update.new_root);
+            }
+            update.transpose()
+        })
     }
 
     /// Store a proof by its leaf index
@@ -324,12 +793,125 @@ impl NomadDB {
     /// - `leaf_index` --> `proof`
     pub fn store_proof(&self, leaf_index: u32, proof: &Proof) -> Result<(), DbError> {
         debug!(leaf_index, "storing proof in DB");
-        self.store_keyed_encodable(PROOF, &leaf_index, proof)
+        self.column(Column::Proofs)
+            .store_keyed_encodable(PROOF, &leaf_index, proof)
     }
 
     /// Retrieve a proof by its leaf index
     pub fn proof_by_leaf_index(&self, leaf_index: u32) -> Result<Option<Proof>, DbError> {
-        self.retrieve_keyed_decodable(PROOF, &leaf_index)
+        self.column(Column::Proofs)
+            .retrieve_keyed_decodable(PROOF, &leaf_index)
+    }
+
+    /// Iterate over proofs with leaf indices in `range`, by leaf index
+    pub fn proofs_by_leaf_index(
+        &self,
+        range: impl RangeBounds<u32>,
+    ) -> PrefixIterator<'_, u32, Proof> {
+        self.column_range_iterator(Column::Proofs, PROOF, range)
+    }
+
+    /// Regenerate the proof of the message at `leaf_index`, e.g. after it
+    /// was pruned, from the leaves under the prover's latest committed root.
+    /// The proof is not stored. Rebuilds the tree, so this is slow.
+    pub fn regenerate_proof(&self, leaf_index: u32) -> Result<Proof, DbError> {
+        let root = self
+            .retrieve_prover_latest_committed()?
+            .ok_or_else(|| DbError::Inconsistent("no prover committed root stored".to_owned()))?;
+
+        let mut tree = MerkleTree::create(&[], TREE_DEPTH);
+        let mut count = 0;
+        for res in self.leaves_by_leaf_index(..) {
+            let (index, leaf) = res?;
+            // Stop at the first missing leaf
+            if index as usize != count {
+                break;
+            }
+            tree.push_leaf(leaf, TREE_DEPTH).expect("!tree full");
+            count += 1;
+            if tree.hash() == root {
+                break;
+            }
+        }
+
+        if tree.hash() != root || leaf_index as usize >= count {
+            return Err(DbError::Inconsistent(format!(
+                "leaf index {} is not under the prover committed root {:?}",
+                leaf_index, root
+            )));
+        }
+
+        let (leaf, hashes) = tree.generate_proof(leaf_index as usize, TREE_DEPTH);
+        let mut path = [H256::zero(); TREE_DEPTH];
+        path.copy_from_slice(&hashes[..TREE_DEPTH]);
+        info!(leaf_index, root = ?root, "Regenerated proof for leaf {}", leaf_index);
+        Ok(Proof {
+            leaf,
+            index: leaf_index as usize,
+            path,
+        })
+    }
+
+    /// Record that the message at `leaf_index` was processed at
+    /// `processed_at` (unix timestamp in seconds)
+    ///
+    /// Key --> value: `leaf_index` --> `processed_at`
+    pub fn store_processed(&self, leaf_index: u32, processed_at: u64) -> Result<(), DbError> {
+        self.store_keyed_encodable(PROCESSED_AT, &leaf_index, &processed_at)
+    }
+
+    /// Iterate over the times messages with leaf indices in `range` were
+    /// processed, by leaf index. Messages that were pruned are not included.
+    pub fn processed_by_leaf_index(
+        &self,
+        range: impl RangeBounds<u32>,
+    ) -> PrefixIterator<'_, u32, u64> {
+        self.column_range_iterator(Column::Agent, PROCESSED_AT, range)
+    }
+
+    /// Prune the proof of the processed message at `leaf_index`, and its
+    /// body if `prune_body` is set. Pruning the body also deletes the keys
+    /// derived from it (destination and nonce, indexes and transaction
+    /// metadata), so the message is no longer found by them. The leaf is
+    /// kept, so the proof can be regenerated.
+    pub fn prune_message(&self, leaf_index: u32, prune_body: bool) -> Result<(), DbError> {
+        let processed_at: u64 = self
+            .retrieve_keyed_decodable(PROCESSED_AT, &leaf_index)?
+            .unwrap_or_default();
+        let body = match self.leaf_by_leaf_index(leaf_index)? {
+            Some(leaf) if prune_body => self.message_by_leaf(leaf)?.map(|m| (leaf, m)),
+            _ => None,
+        };
+
+        self.write_batch(|db| {
+            db.column(Column::Proofs)
+                .delete_keyed_value(PROOF, &leaf_index)?;
+            if let Some((leaf, message)) = &body {
+                db.delete_message_keys(leaf_index, *leaf, message)?;
+            }
+            db.delete_keyed_value(PROCESSED_AT, &leaf_index)?;
+            db.store_keyed_encodable(PRUNED, &leaf_index, &processed_at)
+        })?;
+
+        debug!(leaf_index, prune_body, "pruned processed message in DB");
+        Ok(())
+    }
+
+    /// Whether the message at `leaf_index` was pruned. Its proof may have
+    /// been regenerated since.
+    pub fn is_pruned(&self, leaf_index: u32) -> Result<bool, DbError> {
+        Ok(self
+            .retrieve_keyed_decodable::<_, u64>(PRUNED, &leaf_index)?
+            .is_some())
+    }
+
+    /// Iterate over the leaf indices of pruned messages in `range`, along
+    /// with the time they were processed
+    pub fn pruned_by_leaf_index(
+        &self,
+        range: impl RangeBounds<u32>,
+    ) -> PrefixIterator<'_, u32, u64> {
+        self.column_range_iterator(Column::Agent, PRUNED, range)
     }
 
     // TODO(james): this is a quick-fix for the prover_sync and I don't like it
@@ -378,8 +960,12 @@ impl NomadDB {
 #[cfg(test)]
 mod test {
     use super::*;
+    use ethers::signers::LocalWallet;
     use ethers::types::H256;
-    use nomad_core::{accumulator::merkle::Proof, Encode, NomadMessage, RawCommittedMessage};
+    use nomad_core::{
+        accumulator::merkle::Proof, Encode, MessageVersion, NomadMessage, RawCommittedMessage,
+        Update,
+    };
     use nomad_test::test_utils::run_test_db;
 
     #[tokio::test]
@@ -395,11 +981,13 @@ mod test {
                 destination: 12,
                 recipient: H256::from_low_u64_be(5),
                 body: vec![1, 2, 3],
+                ..Default::default()
             };
 
             let message = RawCommittedMessage {
                 leaf_index: 100,
                 committed_root: H256::from_low_u64_be(3),
+                version: MessageVersion::V0,
                 message: m.to_vec(),
             };
             assert_eq!(m.to_leaf(), message.leaf());
@@ -442,4 +1030,339 @@ mod test {
         })
         .await;
     }
+
+    #[tokio::test]
+    async fn db_iterates_messages_and_proofs_by_leaf_index() {
+        run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+
+            let messages: Vec<_> = (0..3)
+                .map(|nonce| RawCommittedMessage {
+                    leaf_index: nonce,
+                    committed_root: H256::zero(),
+                    version: MessageVersion::V0,
+                    message: NomadMessage {
+                        origin: 10,
+                        sender: H256::from_low_u64_be(4),
+                        nonce,
+                        destination: 12,
+                        recipient: H256::from_low_u64_be(5),
+                        body: vec![1, 2, 3],
+                        ..Default::default()
+                    }
+                    .to_vec(),
+                })
+                .collect();
+            db.store_messages(&messages).unwrap();
+
+            let stored = db
+                .messages_by_leaf_index(1..)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(stored, messages[1..]);
+
+            // Leaves keyed by destination and nonce are not included
+            let leaves = db
+                .leaves_by_leaf_index(..)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let expected: Vec<_> = messages.iter().map(|m| (m.leaf_index, m.leaf())).collect();
+            assert_eq!(leaves, expected);
+
+            let proof = Proof {
+                leaf: messages[2].leaf(),
+                index: 2,
+                path: Default::default(),
+            };
+            db.store_proof(2, &proof).unwrap();
+            let proofs = db
+                .proofs_by_leaf_index(..=2)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(proofs, vec![(2, proof)]);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn db_indexes_messages_by_sender_recipient_and_destination() {
+        run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+
+            let messages: Vec<_> = (0..4)
+                .map(|nonce| RawCommittedMessage {
+                    leaf_index: nonce,
+                    committed_root: H256::zero(),
+                    version: MessageVersion::V0,
+                    message: NomadMessage {
+                        origin: 10,
+                        sender: H256::from_low_u64_be(nonce as u64 % 2),
+                        nonce,
+                        destination: 12 + nonce % 2,
+                        recipient: H256::from_low_u64_be(5 + nonce as u64 / 2),
+                        body: vec![1, 2, 3],
+                        ..Default::default()
+                    }
+                    .to_vec(),
+                })
+                .collect();
+
+            // Stored before indexes were enabled, and backfilled
+            db.store_messages(&messages[..2]).unwrap();
+            assert_eq!(
+                db.messages_by_sender(H256::from_low_u64_be(0), ..).count(),
+                0
+            );
+            db.enable_message_indexes().unwrap();
+            db.store_messages(&messages[2..]).unwrap();
+
+            fn leaf_indices(
+                iter: impl Iterator<Item = Result<RawCommittedMessage, DbError>>,
+            ) -> Vec<u32> {
+                iter.map(|res| res.unwrap().leaf_index).collect()
+            }
+            assert_eq!(
+                leaf_indices(db.messages_by_sender(H256::from_low_u64_be(1), ..)),
+                vec![1, 3]
+            );
+            assert_eq!(
+                leaf_indices(db.messages_by_recipient(H256::from_low_u64_be(6), ..)),
+                vec![2, 3]
+            );
+            assert_eq!(leaf_indices(db.messages_by_destination(12, 1..)), vec![2]);
+
+            db.rollback_messages(2).unwrap();
+            assert_eq!(leaf_indices(db.messages_by_destination(12, ..)), vec![0]);
+
+            db.disable_message_indexes().unwrap();
+            assert!(!db.message_indexes_enabled().unwrap());
+            assert_eq!(db.messages_by_destination(12, ..).count(), 0);
+            assert_eq!(
+                db.messages_by_sender(H256::from_low_u64_be(1), ..).count(),
+                0
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn db_iterates_updates_in_chain_order() {
+        run_test_db(|db| async move {
+            let signer: LocalWallet =
+                "1111111111111111111111111111111111111111111111111111111111111111"
+                    .parse()
+                    .unwrap();
+            let db = NomadDB::new("home_1", db);
+
+            let mut updates = vec![];
+            for i in 0..3u64 {
+                let update = Update {
+                    home_domain: 1,
+                    previous_root: H256::from_low_u64_be(i),
+                    new_root: H256::from_low_u64_be(i + 1),
+                }
+                .sign_with(&signer)
+                .await
+                .expect("!sign");
+                updates.push(update);
+            }
+            // Stored out of order
+            for update in updates.iter().rev() {
+                db.store_update(update).unwrap();
+            }
+
+            let chain = db
+                .updates_from(H256::zero())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(chain, updates);
+
+            let chain = db
+                .updates_from(H256::from_low_u64_be(2))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(chain, updates[2..]);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn db_looks_up_messages_and_updates_by_tx_hash() {
+        run_test_db(|db| async move {
+            let signer: LocalWallet =
+                "1111111111111111111111111111111111111111111111111111111111111111"
+                    .parse()
+                    .unwrap();
+            let db = NomadDB::new("home_1", db);
+
+            let tx_hash = H256::repeat_byte(1);
+            let meta = |transaction_hash, log_index| TxMeta {
+                block_number: 5,
+                timestamp: Some(100),
+                transaction_hash,
+                block_hash: H256::repeat_byte(2),
+                log_index,
+            };
+
+            // Two messages dispatched in one transaction, one in another
+            let messages: Vec<_> = [(tx_hash, 1), (H256::repeat_byte(3), 0), (tx_hash, 0)]
+                .iter()
+                .zip(0..)
+                .map(
+                    |(&(transaction_hash, log_index), nonce)| RawCommittedMessageWithMeta {
+                        raw_message: RawCommittedMessage {
+                            leaf_index: nonce,
+                            committed_root: H256::zero(),
+                            version: MessageVersion::V0,
+                            message: NomadMessage {
+                                origin: 10,
+                                sender: H256::from_low_u64_be(4),
+                                nonce,
+                                destination: 12,
+                                recipient: H256::from_low_u64_be(5),
+                                body: vec![1, 2, 3],
+                                ..Default::default()
+                            }
+                            .to_vec(),
+                        },
+                        metadata: meta(transaction_hash, log_index),
+                    },
+                )
+                .collect();
+            db.store_messages_and_meta(&messages).unwrap();
+
+            let leaf = messages[0].raw_message.leaf();
+            assert_eq!(
+                db.retrieve_message_metadata(leaf).unwrap(),
+                Some(messages[0].metadata)
+            );
+
+            // Ordered by log index
+            let in_tx = db
+                .messages_by_tx_hash(tx_hash)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(
+                in_tx,
+                vec![
+                    messages[2].raw_message.clone(),
+                    messages[0].raw_message.clone()
+                ]
+            );
+
+            let update = SignedUpdateWithMeta {
+                signed_update: Update {
+                    home_domain: 1,
+                    previous_root: H256::zero(),
+                    new_root: H256::from_low_u64_be(1),
+                }
+                .sign_with(&signer)
+                .await
+                .expect("!sign"),
+                metadata: meta(tx_hash, 2),
+            };
+            db.store_updates_and_meta(&[update.clone()]).unwrap();
+            let in_tx = db
+                .updates_by_tx_hash(tx_hash)
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(in_tx, vec![update.signed_update]);
+
+            db.rollback_messages(0).unwrap();
+            db.rollback_updates(H256::zero()).unwrap();
+            assert_eq!(db.retrieve_message_metadata(leaf).unwrap(), None);
+            assert_eq!(db.messages_by_tx_hash(tx_hash).count(), 0);
+            assert_eq!(db.updates_by_tx_hash(tx_hash).count(), 0);
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn db_deletes_keys_derived_from_pruned_bodies() {
+        run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+            db.enable_message_indexes().unwrap();
+
+            let tx_hash = H256::repeat_byte(1);
+            let message = RawCommittedMessageWithMeta {
+                raw_message: RawCommittedMessage {
+                    leaf_index: 0,
+                    committed_root: H256::zero(),
+                    version: MessageVersion::V0,
+                    message: NomadMessage {
+                        origin: 10,
+                        sender: H256::from_low_u64_be(4),
+                        nonce: 0,
+                        destination: 12,
+                        recipient: H256::from_low_u64_be(5),
+                        body: vec![1, 2, 3],
+                        ..Default::default()
+                    }
+                    .to_vec(),
+                },
+                metadata: TxMeta {
+                    block_number: 5,
+                    timestamp: Some(100),
+                    transaction_hash: tx_hash,
+                    block_hash: H256::repeat_byte(2),
+                    log_index: 0,
+                },
+            };
+            db.store_messages_and_meta(&[message.clone()]).unwrap();
+            db.store_processed(0, 100).unwrap();
+
+            db.prune_message(0, true).unwrap();
+            let leaf = message.raw_message.leaf();
+            assert_eq!(db.leaf_by_leaf_index(0).unwrap(), Some(leaf));
+            assert_eq!(db.leaf_by_nonce(12, 0).unwrap(), None);
+            assert_eq!(db.retrieve_message_metadata(leaf).unwrap(), None);
+            assert_eq!(db.messages_by_tx_hash(tx_hash).count(), 0);
+            assert_eq!(db.messages_by_destination(12, ..).count(), 0);
+
+            // The latest message was pruned, not lost
+            db.validate_latest("home_1").unwrap();
+
+            db.rollback_messages(0).unwrap();
+            assert_eq!(db.leaf_by_leaf_index(0).unwrap(), None);
+            assert!(!db.is_pruned(0).unwrap());
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn db_regenerates_proofs_under_the_committed_root() {
+        run_test_db(|db| async move {
+            let db = NomadDB::new("home_1", db);
+
+            let messages: Vec<_> = (0..3)
+                .map(|nonce| RawCommittedMessage {
+                    leaf_index: nonce,
+                    committed_root: H256::zero(),
+                    version: MessageVersion::V0,
+                    message: NomadMessage {
+                        origin: 10,
+                        sender: H256::from_low_u64_be(4),
+                        nonce,
+                        destination: 12,
+                        recipient: H256::from_low_u64_be(5),
+                        body: vec![1, 2, 3],
+                        ..Default::default()
+                    }
+                    .to_vec(),
+                })
+                .collect();
+            db.store_messages(&messages).unwrap();
+
+            // Committed root covers the first two leaves only
+            let mut tree = nomad_core::accumulator::incremental::IncrementalMerkle::default();
+            messages[..2].iter().for_each(|m| tree.ingest(m.leaf()));
+            db.store_prover_latest_committed(tree.root()).unwrap();
+
+            let proof = db.regenerate_proof(1).unwrap();
+            assert_eq!(proof.leaf, messages[1].leaf());
+            assert_eq!(proof.root(), tree.root());
+            assert!(db.regenerate_proof(2).is_err());
+        })
+        .await;
+    }
 }
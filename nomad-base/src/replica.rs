@@ -15,7 +15,7 @@ use tokio::task::JoinHandle;
 use tokio::time::{sleep, Duration};
 use tracing::{instrument, instrument::Instrumented};
 
-use crate::{CommonIndexers, ContractSync};
+use crate::{CommonIndexers, ContractSync, ShutdownSignal, SyncProgress};
 
 /// Caching replica type
 #[derive(Debug)]
@@ -55,11 +55,16 @@ impl CachingReplica {
         self.db.clone()
     }
 
+    /// Return handle on the indexing progress of the CachingReplica's db
+    pub fn sync_progress(&self) -> Arc<SyncProgress> {
+        self.contract_sync.progress()
+    }
+
     /// Spawn a task that syncs the CachingReplica's db with the on-chain event
-    /// data
-    pub fn sync(&self) -> Instrumented<JoinHandle<Result<()>>> {
+    /// data until shutdown is triggered
+    pub fn sync(&self, shutdown: ShutdownSignal) -> Instrumented<JoinHandle<Result<()>>> {
         let sync = self.contract_sync.clone();
-        sync.spawn_common()
+        sync.spawn_common(shutdown)
     }
 }
 
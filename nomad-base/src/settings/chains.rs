@@ -1,8 +1,12 @@
 use color_eyre::Report;
 use serde::Deserialize;
 
-use nomad_core::{ContractLocator, Signers};
-use nomad_ethereum::{make_conn_manager, make_home, make_replica, Connection};
+use nomad_core::{db::DB, ContractLocator, Signers};
+use nomad_ethereum::{
+    make_conn_manager, make_home, make_replica, Connection, GasSettings, TxManagerSettings,
+};
+use nomad_sim::SimConnection;
+use tokio::sync::watch;
 
 use crate::{
     home::Homes, replica::Replicas, xapp::ConnectionManagers, HomeVariants, ReplicaVariants,
@@ -17,6 +21,8 @@ use crate::{
 pub enum ChainConf {
     /// Ethereum configuration
     Ethereum(Connection),
+    /// In-process simulated chain configuration
+    Simulated(SimConnection),
 }
 
 impl Default for ChainConf {
@@ -40,6 +46,14 @@ pub struct ChainSetup {
     /// The chain connection details
     #[serde(flatten)]
     pub chain: ChainConf,
+    /// Gas policy for transactions dispatched to this chain. Uses the
+    /// default policy for the chain id if unset.
+    #[serde(default)]
+    pub gas: Option<GasSettings>,
+    /// Fee escalation of transactions dispatched to this chain. Uses the
+    /// default schedule if unset.
+    #[serde(default)]
+    pub tx_manager: Option<TxManagerSettings>,
     /// Set this key to disable the replica. Does nothing for homes.
     #[serde(default)]
     pub disabled: Option<String>,
@@ -50,6 +64,8 @@ impl ChainSetup {
     pub async fn try_into_home(
         &self,
         signer: Option<Signers>,
+        tx_db: Option<DB>,
+        shutdown: Option<watch::Receiver<bool>>,
         timelag: Option<u8>,
     ) -> Result<Homes, Report> {
         match &self.chain {
@@ -62,11 +78,26 @@ impl ChainSetup {
                         address: self.address.parse::<ethers::types::Address>()?.into(),
                     },
                     signer,
+                    tx_db,
+                    shutdown,
+                    self.gas.clone(),
+                    self.tx_manager.clone().map(Into::into),
                     timelag,
                 )
                 .await?,
             )
             .into()),
+            ChainConf::Simulated(conf) => Ok(HomeVariants::Other(nomad_sim::make_home(
+                conf.clone(),
+                &ContractLocator {
+                    name: self.name.clone(),
+                    domain: self.domain.parse().expect("invalid uint"),
+                    address: self.address.parse::<ethers::types::Address>()?.into(),
+                },
+                signer,
+                timelag,
+            )?)
+            .into()),
         }
     }
 
@@ -74,6 +105,8 @@ impl ChainSetup {
     pub async fn try_into_replica(
         &self,
         signer: Option<Signers>,
+        tx_db: Option<DB>,
+        shutdown: Option<watch::Receiver<bool>>,
         timelag: Option<u8>,
     ) -> Result<Replicas, Report> {
         match &self.chain {
@@ -86,11 +119,25 @@ impl ChainSetup {
                         address: self.address.parse::<ethers::types::Address>()?.into(),
                     },
                     signer,
+                    tx_db,
+                    shutdown,
+                    self.gas.clone(),
+                    self.tx_manager.clone().map(Into::into),
                     timelag,
                 )
                 .await?,
             )
             .into()),
+            ChainConf::Simulated(conf) => Ok(ReplicaVariants::Other(nomad_sim::make_replica(
+                conf.clone(),
+                &ContractLocator {
+                    name: self.name.clone(),
+                    domain: self.domain.parse().expect("invalid uint"),
+                    address: self.address.parse::<ethers::types::Address>()?.into(),
+                },
+                timelag,
+            )?)
+            .into()),
         }
     }
 
@@ -98,6 +145,8 @@ impl ChainSetup {
     pub async fn try_into_connection_manager(
         &self,
         signer: Option<Signers>,
+        tx_db: Option<DB>,
+        shutdown: Option<watch::Receiver<bool>>,
         timelag: Option<u8>,
     ) -> Result<ConnectionManagers, Report> {
         match &self.chain {
@@ -110,10 +159,26 @@ impl ChainSetup {
                         address: self.address.parse::<ethers::types::Address>()?.into(),
                     },
                     signer,
+                    tx_db,
+                    shutdown,
+                    self.gas.clone(),
+                    self.tx_manager.clone().map(Into::into),
                     timelag,
                 )
                 .await?,
             )),
+            ChainConf::Simulated(conf) => {
+                Ok(ConnectionManagers::Other(nomad_sim::make_conn_manager(
+                    conf.clone(),
+                    &ContractLocator {
+                        name: self.name.clone(),
+                        domain: self.domain.parse().expect("invalid uint"),
+                        address: self.address.parse::<ethers::types::Address>()?.into(),
+                    },
+                    signer,
+                    timelag,
+                )?))
+            }
         }
     }
 }
@@ -39,6 +39,7 @@
 use crate::{
     agent::AgentCore, CachingHome, CachingReplica, CommonIndexerVariants, CommonIndexers,
     ContractSync, ContractSyncMetrics, HomeIndexerVariants, HomeIndexers, Homes, NomadDB, Replicas,
+    ShutdownSignal,
 };
 use color_eyre::{eyre::bail, Report};
 use config::{Config, ConfigError, Environment, File};
@@ -49,6 +50,7 @@ use rusoto_core::{credential::EnvironmentProvider, HttpClient};
 use rusoto_kms::KmsClient;
 use serde::Deserialize;
 use std::{collections::HashMap, env, sync::Arc};
+use tokio::sync::watch;
 use tracing::instrument;
 
 /// Chain configuartion
@@ -161,6 +163,11 @@ pub struct IndexSettings {
     /// Whether or not to use timelag
     #[serde(default)]
     pub use_timelag: bool,
+    /// Whether to index home messages by sender, recipient and destination.
+    /// Messages already stored are indexed on startup, and their indexes are
+    /// deleted on startup once this is turned off.
+    #[serde(default)]
+    pub message_indexes: bool,
 }
 
 impl IndexSettings {
@@ -191,6 +198,28 @@ impl IndexSettings {
     }
 }
 
+/// Admin API snapshot settings
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotSettings {
+    /// Whether `POST /admin/snapshot` is served. The admin API has no
+    /// authentication, so this is off by default.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Directory snapshots are written to. Defaults to `<db>_snapshots`.
+    pub dir: Option<String>,
+    /// Number of snapshots to keep, at least 1. Older ones are deleted.
+    /// Defaults to 3.
+    pub keep: Option<usize>,
+}
+
+impl SnapshotSettings {
+    /// Get the `keep` setting
+    pub fn keep(&self) -> usize {
+        self.keep.unwrap_or(3).max(1)
+    }
+}
+
 /// Settings. Usually this should be treated as a base config and used as
 /// follows:
 ///
@@ -220,6 +249,9 @@ impl IndexSettings {
 pub struct Settings {
     /// The path to use for the DB file
     pub db: String,
+    /// Settings for db snapshots through the admin API
+    #[serde(default)]
+    pub snapshots: SnapshotSettings,
     /// Port to listen for prometheus scrape requests
     pub metrics: Option<String>,
     /// Settings for the home indexer
@@ -240,6 +272,7 @@ impl Settings {
     fn clone(&self) -> Self {
         Self {
             db: self.db.clone(),
+            snapshots: self.snapshots.clone(),
             metrics: self.metrics.clone(),
             index: self.index.clone(),
             home: self.home.clone(),
@@ -285,11 +318,18 @@ impl Settings {
         }
     }
 
-    /// Try to get a Homes object
-    pub async fn try_home(&self) -> Result<Homes, Report> {
+    /// Try to get a Homes object. In-flight transactions are persisted to
+    /// `tx_db`, if provided, and no longer awaited once `shutdown` turns true.
+    pub async fn try_home(
+        &self,
+        tx_db: Option<DB>,
+        shutdown: Option<watch::Receiver<bool>>,
+    ) -> Result<Homes, Report> {
         let signer = self.get_signer(&self.home.name).await;
         let opt_home_timelag = self.home_timelag();
-        self.home.try_into_home(signer, opt_home_timelag).await
+        self.home
+            .try_into_home(signer, tx_db, shutdown, opt_home_timelag)
+            .await
     }
 
     /// Try to get a home ContractSync
@@ -324,8 +364,11 @@ impl Settings {
         agent_name: &str,
         db: DB,
         metrics: ContractSyncMetrics,
+        shutdown: &ShutdownSignal,
     ) -> Result<CachingHome, Report> {
-        let home = self.try_home().await?;
+        let home = self
+            .try_home(Some(db.clone()), Some(shutdown.subscribe()))
+            .await?;
         let contract_sync = self
             .try_home_contract_sync(agent_name, db.clone(), metrics)
             .await?;
@@ -334,14 +377,20 @@ impl Settings {
         Ok(CachingHome::new(home, contract_sync, nomad_db))
     }
 
-    /// Try to get a Replicas object
-    pub async fn try_replica(&self, replica_name: &str) -> Result<Replicas, Report> {
+    /// Try to get a Replicas object. In-flight transactions are persisted to
+    /// `tx_db`, if provided, and no longer awaited once `shutdown` turns true.
+    pub async fn try_replica(
+        &self,
+        replica_name: &str,
+        tx_db: Option<DB>,
+        shutdown: Option<watch::Receiver<bool>>,
+    ) -> Result<Replicas, Report> {
         let replica_setup = self.replicas.get(replica_name).expect("!replica");
         let signer = self.get_signer(replica_name).await;
         let opt_replica_timelag = self.replica_timelag(replica_name);
 
         replica_setup
-            .try_into_replica(signer, opt_replica_timelag)
+            .try_into_replica(signer, tx_db, shutdown, opt_replica_timelag)
             .await
     }
 
@@ -381,8 +430,11 @@ impl Settings {
         agent_name: &str,
         db: DB,
         metrics: ContractSyncMetrics,
+        shutdown: &ShutdownSignal,
     ) -> Result<CachingReplica, Report> {
-        let replica = self.try_replica(replica_name).await?;
+        let replica = self
+            .try_replica(replica_name, Some(db.clone()), Some(shutdown.subscribe()))
+            .await?;
         let contract_sync = self
             .try_replica_contract_sync(replica_name, agent_name, db.clone(), metrics)
             .await?;
@@ -397,6 +449,7 @@ impl Settings {
         agent_name: &str,
         db: DB,
         metrics: ContractSyncMetrics,
+        shutdown: &ShutdownSignal,
     ) -> Result<HashMap<String, Arc<CachingReplica>>, Report> {
         let mut result = HashMap::default();
         for (k, v) in self.replicas.iter().filter(|(_, v)| v.disabled.is_none()) {
@@ -409,7 +462,7 @@ impl Settings {
             }
 
             let caching_replica = self
-                .try_caching_replica(k, agent_name, db.clone(), metrics.clone())
+                .try_caching_replica(k, agent_name, db.clone(), metrics.clone(), shutdown)
                 .await?;
             result.insert(v.name.clone(), Arc::new(caching_replica));
         }
@@ -433,6 +486,9 @@ impl Settings {
                         address: self.home.address.parse::<ethers::types::Address>()?.into(),
                     },
                     signer,
+                    None,
+                    None,
+                    None,
                     timelag,
                     self.index.from(),
                     self.index.chunk_size(),
@@ -440,6 +496,17 @@ impl Settings {
                 .await?,
             )
             .into()),
+            ChainConf::Simulated(conn) => {
+                Ok(HomeIndexerVariants::Other(nomad_sim::make_home_indexer(
+                    conn.clone(),
+                    &ContractLocator {
+                        name: self.home.name.clone(),
+                        domain: self.home.domain.parse().expect("invalid uint"),
+                        address: self.home.address.parse::<ethers::types::Address>()?.into(),
+                    },
+                )?)
+                .into())
+            }
         }
     }
 
@@ -460,6 +527,9 @@ impl Settings {
                         address: setup.address.parse::<ethers::types::Address>()?.into(),
                     },
                     signer,
+                    None,
+                    None,
+                    None,
                     timelag,
                     self.index.from(),
                     self.index.chunk_size(),
@@ -467,6 +537,17 @@ impl Settings {
                 .await?,
             )
             .into()),
+            ChainConf::Simulated(conn) => Ok(CommonIndexerVariants::Other(
+                nomad_sim::make_replica_indexer(
+                    conn.clone(),
+                    &ContractLocator {
+                        name: setup.name.clone(),
+                        domain: setup.domain.parse().expect("invalid uint"),
+                        address: setup.address.parse::<ethers::types::Address>()?.into(),
+                    },
+                )?,
+            )
+            .into()),
         }
     }
 
@@ -482,12 +563,19 @@ impl Settings {
         let sync_metrics = ContractSyncMetrics::new(metrics.clone());
 
         let db = DB::from_path(&self.db)?;
+        let shutdown = ShutdownSignal::default();
         let home = Arc::new(
-            self.try_caching_home(name, db.clone(), sync_metrics.clone())
+            self.try_caching_home(name, db.clone(), sync_metrics.clone(), &shutdown)
                 .await?,
         );
+        let home_db = NomadDB::new(home.name(), db.clone());
+        if self.index.message_indexes {
+            home_db.enable_message_indexes()?;
+        } else {
+            home_db.disable_message_indexes()?;
+        }
         let replicas = self
-            .try_caching_replicas(name, db.clone(), sync_metrics.clone())
+            .try_caching_replicas(name, db.clone(), sync_metrics.clone(), &shutdown)
             .await?;
 
         Ok(AgentCore {
@@ -497,6 +585,8 @@ impl Settings {
             settings: self.clone(),
             metrics,
             indexer: self.index.clone(),
+            status: Default::default(),
+            shutdown,
         })
     }
 
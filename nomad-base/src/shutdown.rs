@@ -0,0 +1,291 @@
+use color_eyre::Result;
+use futures_util::future::select_all;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::watch,
+    task::JoinHandle,
+    time::{sleep, timeout},
+};
+use tracing::{error, info, instrument::Instrumented, warn};
+
+use crate::{cancel_task, AgentCore, TaskState};
+
+/// Task name under which the outcome of the agent's `run_all` is reported
+const AGENT_TASK: &str = "agent";
+
+/// Time given to agent tasks to finish in-flight work once shutdown was
+/// triggered
+pub const SHUTDOWN_DEADLINE: Duration = Duration::from_secs(30);
+
+/// Signal telling agent tasks to stop taking on new work. Tasks check it at
+/// points where stopping leaves no work half done (e.g. between polls) and
+/// return `Ok(())`.
+#[derive(Debug, Clone)]
+pub struct ShutdownSignal {
+    tx: Arc<watch::Sender<bool>>,
+    rx: watch::Receiver<bool>,
+    orphans: Orphans,
+}
+
+/// Child tasks whose `select_all` was dropped before they finished
+type Orphans = Arc<Mutex<Vec<JoinHandle<Result<()>>>>>;
+
+/// Child tasks awaited by `ShutdownSignal::select_all`. If the select is
+/// dropped before they finish (i.e. its own task was cancelled), they are
+/// handed to the signal so they can be cancelled before the DB is flushed.
+struct Children {
+    tasks: Vec<Instrumented<JoinHandle<Result<()>>>>,
+    orphans: Orphans,
+}
+
+impl Drop for Children {
+    fn drop(&mut self) {
+        if self.tasks.is_empty() {
+            return;
+        }
+        self.orphans
+            .lock()
+            .expect("!poisoned")
+            .extend(self.tasks.drain(..).map(Instrumented::into_inner));
+    }
+}
+
+impl Default for ShutdownSignal {
+    fn default() -> Self {
+        let (tx, rx) = watch::channel(false);
+        Self {
+            tx: Arc::new(tx),
+            rx,
+            orphans: Default::default(),
+        }
+    }
+}
+
+impl ShutdownSignal {
+    /// Trigger shutdown
+    pub fn trigger(&self) {
+        // We hold a receiver, so this cannot fail
+        let _ = self.tx.send(true);
+    }
+
+    /// Receiver that turns true once shutdown is triggered, for crates that
+    /// can't depend on this one
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.rx.clone()
+    }
+
+    /// Whether shutdown was triggered
+    pub fn is_triggered(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Resolve once shutdown is triggered
+    pub async fn wait(&self) {
+        let mut rx = self.rx.clone();
+        while !*rx.borrow() {
+            if rx.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Sleep for `duration`, waking early if shutdown is triggered. Returns
+    /// `true` if shutdown was triggered.
+    pub async fn sleep(&self, duration: Duration) -> bool {
+        tokio::select! {
+            _ = sleep(duration) => self.is_triggered(),
+            _ = self.wait() => true,
+        }
+    }
+
+    /// Wait for the first of `tasks` to finish and return its result. The
+    /// others are cancelled, unless shutdown was triggered, in which case
+    /// they are left to finish their in-flight work.
+    pub async fn select_all(&self, tasks: Vec<Instrumented<JoinHandle<Result<()>>>>) -> Result<()> {
+        let mut children = Children {
+            tasks,
+            orphans: self.orphans.clone(),
+        };
+        let (res, index, _) = select_all(children.tasks.iter_mut()).await;
+        children.tasks.remove(index);
+
+        // Tasks are only removed once they finished, so that they are
+        // orphaned rather than detached if this future is dropped
+        if self.is_triggered() {
+            while let Some(task) = children.tasks.last_mut() {
+                let res = task.await;
+                children.tasks.pop();
+                match res {
+                    Ok(Err(e)) => error!("Task errored during shutdown: {:#}", e),
+                    Err(e) => error!("Task panicked during shutdown: {}", e),
+                    _ => {}
+                }
+            }
+        } else {
+            while let Some(task) = children.tasks.last_mut() {
+                task.inner().abort();
+                let _ = task.await;
+                children.tasks.pop();
+            }
+        }
+
+        res?
+    }
+
+    /// Cancel child tasks orphaned by a cancelled `select_all`, and any
+    /// tasks they orphan in turn
+    async fn cancel_orphans(&self) {
+        loop {
+            let orphans: Vec<_> = self.orphans.lock().expect("!poisoned").drain(..).collect();
+            if orphans.is_empty() {
+                return;
+            }
+            for task in orphans {
+                task.abort();
+                let _ = task.await;
+            }
+        }
+    }
+}
+
+/// Resolve once the process receives SIGTERM or SIGINT
+async fn termination() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = sigterm.recv() => {},
+            res = tokio::signal::ctrl_c() => res?,
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
+
+impl AgentCore {
+    /// Run the agent's `task` (usually returned by `NomadAgent::run_all`)
+    /// until it finishes or the process receives SIGTERM or SIGINT. On
+    /// termination, tasks are signaled to stop taking on new work and given
+    /// `SHUTDOWN_DEADLINE` to finish in-flight work. The DB is flushed before
+    /// returning.
+    pub async fn run_until_shutdown(
+        &self,
+        mut task: Instrumented<JoinHandle<Result<()>>>,
+    ) -> Result<()> {
+        tokio::select! {
+            res = &mut task => {
+                let res = res.map_err(color_eyre::Report::from).and_then(|res| res);
+                if let Err(e) = &res {
+                    self.status.set_task_state(
+                        AGENT_TASK,
+                        TaskState::Failed {
+                            error: format!("{:#}", e),
+                        },
+                    );
+                }
+                return res;
+            }
+            res = termination() => res?,
+        }
+
+        info!("Received termination signal. Shutting down agent tasks.");
+        self.shutdown.trigger();
+
+        match timeout(SHUTDOWN_DEADLINE, &mut task).await {
+            Ok(Ok(Err(e))) => error!("Agent errored during shutdown: {:#}", e),
+            Ok(Err(e)) => error!("Agent panicked during shutdown: {}", e),
+            Ok(Ok(Ok(()))) => info!("Agent tasks stopped"),
+            Err(_) => {
+                warn!(
+                    deadline = ?SHUTDOWN_DEADLINE,
+                    "Agent tasks did not stop before the deadline. Cancelling them."
+                );
+                cancel_task!(task);
+            }
+        }
+        // Child tasks must not keep writing while the DB is flushed
+        self.shutdown.cancel_orphans().await;
+
+        info!("Flushing db");
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tracing::Instrument;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_wakes_sleepers_on_trigger() {
+        let shutdown = ShutdownSignal::default();
+        assert!(!shutdown.sleep(Duration::from_millis(1)).await);
+
+        let sleeper = {
+            let shutdown = shutdown.clone();
+            tokio::spawn(async move { shutdown.sleep(Duration::from_secs(600)).await })
+        };
+        shutdown.trigger();
+
+        assert!(timeout(Duration::from_secs(1), sleeper)
+            .await
+            .unwrap()
+            .unwrap());
+        assert!(shutdown.is_triggered());
+    }
+
+    #[tokio::test]
+    async fn it_drains_tasks_on_shutdown() {
+        let shutdown = ShutdownSignal::default();
+        let finished = Arc::new(AtomicUsize::new(0));
+
+        let tasks = [1, 50]
+            .iter()
+            .map(|&delay| {
+                let shutdown = shutdown.clone();
+                let finished = finished.clone();
+                tokio::spawn(async move {
+                    shutdown.wait().await;
+                    sleep(Duration::from_millis(delay)).await;
+                    finished.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                })
+                .in_current_span()
+            })
+            .collect();
+        shutdown.trigger();
+
+        // The slower task is not cancelled
+        shutdown.select_all(tasks).await.unwrap();
+        assert_eq!(finished.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn it_cancels_orphaned_tasks() {
+        let shutdown = ShutdownSignal::default();
+        let child = tokio::spawn(futures_util::future::pending::<Result<()>>());
+        let parent = {
+            let shutdown = shutdown.clone();
+            let tasks = vec![child.in_current_span()];
+            tokio::spawn(async move { shutdown.select_all(tasks).await }).in_current_span()
+        };
+        tokio::task::yield_now().await;
+
+        cancel_task!(parent);
+        assert_eq!(shutdown.orphans.lock().unwrap().len(), 1);
+
+        shutdown.cancel_orphans().await;
+        assert!(shutdown.orphans.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,381 @@
+use ethers::core::types::H256;
+use nomad_core::db::{DbError, RocksStorage, DB};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::info;
+
+use crate::{AgentCore, NomadDB};
+
+/// Error validating a snapshot
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    /// The snapshot or restore destination already exists
+    #[error("Destination {0:?} already exists")]
+    DestinationExists(PathBuf),
+    /// No update ends at the latest root
+    #[error("{entity}: no update found for latest root {root:?}")]
+    MissingLatestUpdate {
+        /// Home or replica name
+        entity: String,
+        /// Latest root
+        root: H256,
+    },
+    /// No leaf, or neither a message nor a pruning record, stored at the
+    /// latest leaf index
+    #[error("{entity}: no message found for latest leaf index {leaf_index}")]
+    MissingLatestMessage {
+        /// Home or replica name
+        entity: String,
+        /// Latest leaf index
+        leaf_index: u32,
+    },
+    /// Snapshots through the admin API are not enabled in the settings
+    #[error("Admin API snapshots are disabled")]
+    Disabled,
+    /// Bubbled up from underlying
+    #[error("{0}")]
+    DbError(#[from] DbError),
+    /// Bubbled up from underlying
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Latest state recorded for a home or replica in a snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotState {
+    /// Latest update root
+    pub latest_root: Option<H256>,
+    /// Latest message leaf index
+    pub latest_leaf_index: Option<u32>,
+}
+
+/// A validated snapshot
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    /// Location of the snapshot
+    pub path: PathBuf,
+    /// Schema version of the snapshot
+    pub schema_version: Option<u32>,
+    /// Latest state by home or replica name
+    pub entities: BTreeMap<String, SnapshotState>,
+}
+
+impl NomadDB {
+    /// Check the latest root and latest leaf index are backed by a stored
+    /// update and message
+    pub fn validate_latest(&self, entity: &str) -> Result<SnapshotState, SnapshotError> {
+        let latest_root = self.retrieve_latest_root()?;
+        if let Some(root) = latest_root {
+            if self.update_by_new_root(root)?.is_none() {
+                return Err(SnapshotError::MissingLatestUpdate {
+                    entity: entity.to_owned(),
+                    root,
+                });
+            }
+        }
+
+        let latest_leaf_index = self.retrieve_latest_leaf_index()?;
+        if let Some(leaf_index) = latest_leaf_index {
+            // Pruned messages keep their leaf, but may have lost their body
+            let stored = match self.leaf_by_leaf_index(leaf_index)? {
+                Some(leaf) => {
+                    self.message_by_leaf(leaf)?.is_some() || self.is_pruned(leaf_index)?
+                }
+                None => false,
+            };
+            if !stored {
+                return Err(SnapshotError::MissingLatestMessage {
+                    entity: entity.to_owned(),
+                    leaf_index,
+                });
+            }
+        }
+
+        Ok(SnapshotState {
+            latest_root,
+            latest_leaf_index,
+        })
+    }
+}
+
+/// Validate the latest state of each of `entities` in `db`
+fn validate(db: &DB, path: &Path, entities: &[String]) -> Result<Snapshot, SnapshotError> {
+    let entities = entities
+        .iter()
+        .map(|entity| {
+            let state = NomadDB::new(entity, db.clone()).validate_latest(entity)?;
+            Ok((entity.clone(), state))
+        })
+        .collect::<Result<_, SnapshotError>>()?;
+
+    Ok(Snapshot {
+        path: path.to_owned(),
+        schema_version: db.schema_version()?,
+        entities,
+    })
+}
+
+/// Write a consistent snapshot of `db` to `path`, then validate it for
+/// `entities` (home and replica names). `db` stays available meanwhile.
+/// Nothing is left at `path` if the snapshot is invalid.
+pub fn create_snapshot(
+    db: &DB,
+    path: impl AsRef<Path>,
+    entities: &[String],
+) -> Result<Snapshot, SnapshotError> {
+    let path = path.as_ref();
+    if path.exists() {
+        return Err(SnapshotError::DestinationExists(path.to_owned()));
+    }
+
+    db.checkpoint(path)?;
+    let validated = RocksStorage::open(path)
+        .map_err(DbError::from)
+        .map_err(SnapshotError::from)
+        .and_then(|snapshot| validate(&DB::new(snapshot), path, entities));
+
+    match validated {
+        Ok(snapshot) => {
+            info!(path = ?path, "Created db snapshot");
+            Ok(snapshot)
+        }
+        Err(e) => {
+            fs::remove_dir_all(path)?;
+            Err(e)
+        }
+    }
+}
+
+/// Copy the snapshot at `snapshot` to `db_path`, migrate it to the current
+/// schema and validate it for `entities` (home and replica names). Nothing
+/// is left at `db_path` if the snapshot is invalid. Agents must not use
+/// `db_path` until this returns.
+pub fn restore_snapshot(
+    snapshot: impl AsRef<Path>,
+    db_path: impl AsRef<Path>,
+    entities: &[String],
+) -> Result<Snapshot, SnapshotError> {
+    let (snapshot, db_path) = (snapshot.as_ref(), db_path.as_ref());
+    if db_path.exists() {
+        return Err(SnapshotError::DestinationExists(db_path.to_owned()));
+    }
+
+    copy_dir(snapshot, db_path)?;
+    let restored = (|| {
+        let db = DB::new(RocksStorage::open(db_path).map_err(DbError::from)?);
+        db.migrate()?;
+        validate(&db, db_path, entities)
+    })();
+
+    match restored {
+        Ok(restored) => {
+            info!(snapshot = ?snapshot, db_path = ?db_path, "Restored db snapshot");
+            Ok(restored)
+        }
+        Err(e) => {
+            fs::remove_dir_all(db_path)?;
+            Err(e)
+        }
+    }
+}
+
+/// Copy the files of the directory at `from` to a new directory at `to`
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Delete all but the `keep` latest snapshots in `dir`. Only directories
+/// named after a unix timestamp are considered.
+fn rotate_snapshots(dir: &Path, keep: usize) -> std::io::Result<()> {
+    let mut snapshots: Vec<(u64, PathBuf)> = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let timestamp = entry.file_name().to_str()?.parse().ok()?;
+            Some((timestamp, entry.path()))
+        })
+        .collect();
+    snapshots.sort_unstable_by_key(|(timestamp, _)| std::cmp::Reverse(*timestamp));
+
+    for (_, path) in snapshots.iter().skip(keep) {
+        info!(path = ?path, "Deleting old db snapshot");
+        fs::remove_dir_all(path)?;
+    }
+    Ok(())
+}
+
+impl AgentCore {
+    /// Directory snapshots requested through the admin API are written to
+    pub fn snapshot_dir(&self) -> PathBuf {
+        self.settings
+            .snapshots
+            .dir
+            .clone()
+            .unwrap_or_else(|| format!("{}_snapshots", self.settings.db))
+            .into()
+    }
+
+    /// Write a validated snapshot of the agent's db to a new directory in
+    /// `snapshot_dir`, named after the current unix timestamp, then delete
+    /// all but the latest `snapshots.keep` snapshots. Errors if snapshots
+    /// are not enabled in the settings. Blocks while the snapshot is written.
+    pub fn snapshot(&self) -> Result<Snapshot, SnapshotError> {
+        if !self.settings.snapshots.enabled {
+            return Err(SnapshotError::Disabled);
+        }
+
+        let dir = self.snapshot_dir();
+        fs::create_dir_all(&dir)?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("!timestamp")
+            .as_secs();
+
+        let entities: Vec<String> = std::iter::once(self.home.name().to_owned())
+            .chain(self.replicas.values().map(|r| r.name().to_owned()))
+            .collect();
+
+        let snapshot = create_snapshot(&self.db, dir.join(timestamp.to_string()), &entities)?;
+        rotate_snapshots(&dir, self.settings.snapshots.keep())?;
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::signers::LocalWallet;
+    use nomad_core::{Encode, MessageVersion, NomadMessage, RawCommittedMessage, Update};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "nomad_snapshot_{}_{}",
+            name,
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        ));
+        let _ = fs::remove_dir_all(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn it_snapshots_and_restores_dbs() {
+        let signer: LocalWallet =
+            "1111111111111111111111111111111111111111111111111111111111111111"
+                .parse()
+                .unwrap();
+        let entities = vec!["home_1".to_owned()];
+        let db = DB::in_memory();
+        let nomad_db = NomadDB::new("home_1", db.clone());
+
+        let message = RawCommittedMessage {
+            leaf_index: 0,
+            committed_root: H256::zero(),
+            version: MessageVersion::V0,
+            message: NomadMessage {
+                origin: 1,
+                sender: H256::repeat_byte(1),
+                nonce: 0,
+                destination: 2,
+                recipient: H256::repeat_byte(2),
+                body: vec![1, 2, 3],
+                ..Default::default()
+            }
+            .to_vec(),
+        };
+        nomad_db.store_latest_message(&message).unwrap();
+
+        let update = Update {
+            home_domain: 1,
+            previous_root: H256::zero(),
+            new_root: H256::repeat_byte(3),
+        }
+        .sign_with(&signer)
+        .await
+        .expect("!sign");
+        nomad_db.store_latest_update(&update).unwrap();
+
+        let snapshot_path = temp_path("create");
+        let snapshot = create_snapshot(&db, &snapshot_path, &entities).unwrap();
+        let expected = SnapshotState {
+            latest_root: Some(update.update.new_root),
+            latest_leaf_index: Some(0),
+        };
+        assert_eq!(snapshot.entities["home_1"], expected);
+
+        // Refuses to overwrite
+        assert!(matches!(
+            create_snapshot(&db, &snapshot_path, &entities),
+            Err(SnapshotError::DestinationExists(_))
+        ));
+
+        let db_path = temp_path("restore");
+        let restored = restore_snapshot(&snapshot_path, &db_path, &entities).unwrap();
+        assert_eq!(restored.entities["home_1"], expected);
+
+        fs::remove_dir_all(snapshot_path).unwrap();
+        fs::remove_dir_all(db_path).unwrap();
+    }
+
+    #[test]
+    fn it_rejects_inconsistent_snapshots() {
+        let entities = vec!["home_1".to_owned()];
+        let db = DB::in_memory();
+        NomadDB::new("home_1", db.clone())
+            .update_latest_leaf_index(5)
+            .unwrap();
+
+        let snapshot_path = temp_path("inconsistent");
+        assert!(matches!(
+            create_snapshot(&db, &snapshot_path, &entities),
+            Err(SnapshotError::MissingLatestMessage { leaf_index: 5, .. })
+        ));
+        assert!(!snapshot_path.exists());
+
+        // Restoring an unvalidated checkpoint leaves nothing behind
+        db.checkpoint(&snapshot_path).unwrap();
+        let db_path = temp_path("inconsistent_restore");
+        assert!(restore_snapshot(&snapshot_path, &db_path, &entities).is_err());
+        assert!(!db_path.exists());
+
+        fs::remove_dir_all(snapshot_path).unwrap();
+    }
+
+    #[test]
+    fn it_rotates_snapshots() {
+        let dir = temp_path("rotate");
+        for name in &["1", "3", "2", "not_a_snapshot"] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+        }
+
+        rotate_snapshots(&dir, 2).unwrap();
+
+        let mut left: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["2", "3", "not_a_snapshot"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
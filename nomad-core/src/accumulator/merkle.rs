@@ -41,7 +41,7 @@ pub enum MerkleTree {
 
 /// A merkle proof object. The leaf, its path to the root, and its index in the
 /// tree.
-#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize, PartialEq)]
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize, PartialEq, Encode, Decode)]
 pub struct Proof {
     /// The leaf
     pub leaf: H256,
@@ -58,39 +58,38 @@ impl Proof {
     }
 }
 
-impl Encode for Proof {
-    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
-    where
-        W: std::io::Write,
-    {
-        writer.write_all(self.leaf.as_bytes())?;
-        writer.write_all(&self.index.to_be_bytes())?;
-        for hash in self.path.iter() {
-            writer.write_all(hash.as_bytes())?;
-        }
-        Ok(32 + 8 + TREE_DEPTH * 32)
-    }
+/// A proof that a merkle tree is an append-only extension of the tree formed
+/// by its first `old_count` leaves.
+///
+/// This is the inclusion proof of the first leaf appended after `old_count`.
+/// Wherever bit `i` of `old_count` is set, `path[i]` is a complete subtree of
+/// leaves shared by both trees. Replacing every other node with the zero
+/// subtree of that height yields the root of the older tree.
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize, PartialEq)]
+pub struct ConsistencyProof {
+    /// The number of leaves in the older tree
+    pub old_count: usize,
+    /// The first leaf appended to the older tree
+    pub leaf: H256,
+    /// The merkle branch of `leaf` in the newer tree
+    pub path: [H256; TREE_DEPTH],
 }
 
-impl Decode for Proof {
-    fn read_from<R>(reader: &mut R) -> Result<Self, crate::NomadError>
-    where
-        R: std::io::Read,
-        Self: Sized,
-    {
-        let mut leaf = H256::default();
-        let mut index_bytes = [0u8; 8];
-        let mut path = [H256::default(); 32];
-
-        reader.read_exact(leaf.as_bytes_mut())?;
-        reader.read_exact(&mut index_bytes)?;
-        for item in &mut path {
-            reader.read_exact(item.as_bytes_mut())?;
-        }
-
-        let index = u64::from_be_bytes(index_bytes) as usize;
+impl ConsistencyProof {
+    /// Calculate the root of the older tree
+    pub fn old_root(&self) -> H256 {
+        let mut branch = self.path;
+        branch.iter_mut().enumerate().for_each(|(i, node)| {
+            if (self.old_count >> i) & 0x01 == 0 {
+                *node = ZERO_HASHES[i];
+            }
+        });
+        merkle_root_from_branch(ZERO_HASHES[0], &branch, TREE_DEPTH, self.old_count)
+    }
 
-        Ok(Self { leaf, index, path })
+    /// Calculate the root of the newer tree
+    pub fn new_root(&self) -> H256 {
+        merkle_root_from_branch(self.leaf, self.path.as_ref(), TREE_DEPTH, self.old_count)
     }
 }
 
@@ -252,6 +251,23 @@ impl MerkleTree {
 
         (current_node.hash(), proof)
     }
+
+    /// Return a proof that this tree is an append-only extension of the tree
+    /// formed by its first `old_count` leaves.
+    ///
+    /// The tree must have depth `TREE_DEPTH` and contain more than
+    /// `old_count` leaves.
+    pub fn generate_consistency_proof(&self, old_count: usize) -> ConsistencyProof {
+        let (leaf, branch) = self.generate_proof(old_count, TREE_DEPTH);
+        let mut path = [H256::zero(); TREE_DEPTH];
+        path.copy_from_slice(&branch);
+
+        ConsistencyProof {
+            old_count,
+            leaf,
+            path,
+        }
+    }
 }
 
 /// Verify a proof that `leaf` exists at `index` in a Merkle tree rooted at `root`.
@@ -272,6 +288,34 @@ pub fn verify_merkle_proof(
     }
 }
 
+/// Verify a proof that the tree of `new_count` leaves rooted at `new_root` is
+/// an append-only extension of the tree of `old_count` leaves rooted at
+/// `old_root`.
+pub fn verify_consistency_proof(
+    old_root: H256,
+    old_count: usize,
+    new_root: H256,
+    new_count: usize,
+    proof: &ConsistencyProof,
+) -> bool {
+    if old_count > new_count || proof.old_count != old_count {
+        return false;
+    }
+    if old_count == new_count {
+        return old_root == new_root;
+    }
+
+    // Right siblings starting at or past `new_count` hold no leaves in the
+    // newer tree
+    let nonempty_past_end = proof.path.iter().enumerate().any(|(i, node)| {
+        let ith_bit = (old_count >> i) & 0x01;
+        let sibling_start = ((old_count >> i) | 0x01) << i;
+        ith_bit == 0 && sibling_start >= new_count && *node != ZERO_HASHES[i]
+    });
+
+    !nonempty_past_end && proof.old_root() == old_root && proof.new_root() == new_root
+}
+
 /// Compute a root hash from a leaf and a Merkle proof.
 pub fn merkle_root_from_branch(leaf: H256, branch: &[H256], depth: usize, index: usize) -> H256 {
     assert_eq!(branch.len(), depth, "proof length should equal depth");
@@ -493,6 +537,98 @@ mod tests {
         assert_eq!(second.hash(), incr.root());
         assert_eq!(full.hash(), incr.root());
     }
+
+    #[test]
+    fn it_proves_consistency() {
+        let leaves: Vec<_> = (1..=20).map(H256::from_low_u64_be).collect();
+        let new_tree = MerkleTree::create(&leaves, TREE_DEPTH);
+        let new_count = leaves.len();
+
+        for old_count in 0..new_count {
+            let old_root = MerkleTree::create(&leaves[..old_count], TREE_DEPTH).hash();
+            let proof = new_tree.generate_consistency_proof(old_count);
+
+            assert_eq!(proof.leaf, leaves[old_count]);
+            assert_eq!(proof.old_root(), old_root);
+            assert_eq!(proof.new_root(), new_tree.hash());
+            assert!(verify_consistency_proof(
+                old_root,
+                old_count,
+                new_tree.hash(),
+                new_count,
+                &proof
+            ));
+
+            // Wrong counts
+            assert!(!verify_consistency_proof(
+                old_root,
+                old_count + 1,
+                new_tree.hash(),
+                new_count,
+                &proof
+            ));
+            assert!(!verify_consistency_proof(
+                old_root,
+                old_count,
+                new_tree.hash(),
+                old_count,
+                &proof
+            ));
+        }
+    }
+
+    #[test]
+    fn it_rejects_inconsistent_trees() {
+        let leaves: Vec<_> = (1..=8).map(H256::from_low_u64_be).collect();
+        let old_tree = MerkleTree::create(&leaves[..5], TREE_DEPTH);
+
+        // Rewrite a leaf that was already committed to
+        let mut rewritten = leaves.clone();
+        rewritten[2] = H256::repeat_byte(0xff);
+        let new_tree = MerkleTree::create(&rewritten, TREE_DEPTH);
+        let proof = new_tree.generate_consistency_proof(5);
+        assert!(!verify_consistency_proof(
+            old_tree.hash(),
+            5,
+            new_tree.hash(),
+            8,
+            &proof
+        ));
+
+        // Claim fewer leaves than the tree actually contains
+        let new_tree = MerkleTree::create(&leaves, TREE_DEPTH);
+        let proof = new_tree.generate_consistency_proof(5);
+        assert!(verify_consistency_proof(
+            old_tree.hash(),
+            5,
+            new_tree.hash(),
+            8,
+            &proof
+        ));
+        assert!(!verify_consistency_proof(
+            old_tree.hash(),
+            5,
+            new_tree.hash(),
+            6,
+            &proof
+        ));
+
+        // Equal sizes only need equal roots
+        assert!(verify_consistency_proof(
+            old_tree.hash(),
+            5,
+            old_tree.hash(),
+            5,
+            &proof
+        ));
+        assert!(!verify_consistency_proof(
+            old_tree.hash(),
+            5,
+            new_tree.hash(),
+            5,
+            &proof
+        ));
+    }
 }
 
 /*
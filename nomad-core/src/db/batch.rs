@@ -0,0 +1,235 @@
+use super::{BatchOp, Column, KvIterator, Result, Storage, WriteBatch};
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    iter::Peekable,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+/// Storage buffering writes in memory on top of another backend. Reads see
+/// buffered writes. Buffered writes reach the backend as a single atomic
+/// [`WriteBatch`] on `commit`.
+#[derive(Debug)]
+pub(crate) struct BatchStorage {
+    inner: Arc<dyn Storage>,
+    /// Buffered writes. `None` marks a delete.
+    pending: RwLock<BTreeMap<(Column, Vec<u8>), Option<Vec<u8>>>>,
+}
+
+impl BatchStorage {
+    /// Instantiate a BatchStorage buffering writes to `inner`
+    pub(crate) fn new(inner: Arc<dyn Storage>) -> Self {
+        Self {
+            inner,
+            pending: Default::default(),
+        }
+    }
+
+    /// Write all buffered writes to the backend as one batch
+    pub(crate) fn commit(&self) -> Result<()> {
+        let pending = std::mem::take(&mut *self.pending.write().expect("!lock"));
+
+        let mut batch = WriteBatch::default();
+        for ((column, key), value) in pending {
+            match value {
+                Some(value) => batch.put(column, key, value),
+                None => batch.delete(column, key),
+            }
+        }
+
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.inner.write(batch)
+    }
+}
+
+/// Key-value pairs of a backend scan, with buffered writes to the scanned
+/// range merged in as the scan goes
+struct MergedScan<'a> {
+    stored: Peekable<KvIterator<'a>>,
+    /// Buffered writes to the scanned range, in key order. `None` marks a
+    /// delete.
+    buffered: Peekable<std::vec::IntoIter<(Vec<u8>, Option<Vec<u8>>)>>,
+}
+
+impl Iterator for MergedScan<'_> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let order = match (self.stored.peek(), self.buffered.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((stored, _)), Some((buffered, _))) => stored.cmp(buffered),
+            };
+            match order {
+                Ordering::Less => return self.stored.next(),
+                // The buffered write shadows the stored value
+                Ordering::Equal => {
+                    self.stored.next();
+                }
+                Ordering::Greater => {}
+            }
+
+            if let (key, Some(value)) = self.buffered.next().expect("!peeked") {
+                return Some((key, value));
+            }
+        }
+    }
+}
+
+impl Storage for BatchStorage {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self
+            .pending
+            .read()
+            .expect("!lock")
+            .get(&(column, key.to_vec()))
+        {
+            Some(value) => Ok(value.clone()),
+            None => self.inner.get(column, key),
+        }
+    }
+
+    fn put(&self, column: Column, key: &[u8], value: &[u8]) -> Result<()> {
+        self.pending
+            .write()
+            .expect("!lock")
+            .insert((column, key.to_vec()), Some(value.to_vec()));
+        Ok(())
+    }
+
+    fn delete(&self, column: Column, key: &[u8]) -> Result<()> {
+        self.pending
+            .write()
+            .expect("!lock")
+            .insert((column, key.to_vec()), None);
+        Ok(())
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        let mut pending = self.pending.write().expect("!lock");
+        for op in batch {
+            match op {
+                BatchOp::Put { column, key, value } => pending.insert((column, key), Some(value)),
+                BatchOp::Delete { column, key } => pending.insert((column, key), None),
+            };
+        }
+        Ok(())
+    }
+
+    fn range_scan<'a>(&'a self, column: Column, prefix: &[u8], from: &[u8]) -> KvIterator<'a> {
+        // Buffered writes are copied, so that the batch can keep writing
+        // while the scan is in progress
+        let buffered: Vec<_> = self
+            .pending
+            .read()
+            .expect("!lock")
+            .range((column, from.to_vec())..)
+            .take_while(|((c, k), _)| *c == column && k.starts_with(prefix))
+            .map(|((_, key), value)| (key.clone(), value.clone()))
+            .collect();
+
+        Box::new(MergedScan {
+            stored: self.inner.range_scan(column, prefix, from).peekable(),
+            buffered: buffered.into_iter().peekable(),
+        })
+    }
+
+    fn size(&self, column: Column) -> Result<u64> {
+        // Buffered writes are not accounted for
+        self.inner.size(column)
+    }
+
+    fn flush(&self) -> Result<()> {
+        // Nothing reaches the backend before commit
+        Ok(())
+    }
+
+    fn checkpoint(&self, path: &Path) -> Result<()> {
+        // Buffered writes are not included
+        self.inner.checkpoint(path)
+    }
+
+    fn catch_up(&self) -> Result<()> {
+        self.inner.catch_up()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{DbError, MemoryStorage, DB};
+    use super::*;
+    use crate::{Decode, NomadError};
+
+    #[test]
+    fn it_commits_batches_atomically() {
+        let db = DB::in_memory();
+        db.store_encodable("", "stale", &0u32).unwrap();
+
+        db.write_batch(|batch| {
+            batch.store_encodable("", "a", &1u32)?;
+            batch.delete_value("", "stale")?;
+
+            // Reads see buffered writes. The DB does not until commit.
+            assert_eq!(batch.retrieve_decodable::<u32>("", "a")?, Some(1));
+            assert_eq!(batch.retrieve_decodable::<u32>("", "stale")?, None);
+            assert_eq!(batch.prefix_iterator("").count(), 1);
+            assert_eq!(db.retrieve_decodable::<u32>("", "a")?, None);
+            Ok::<_, DbError>(())
+        })
+        .unwrap();
+
+        assert_eq!(db.retrieve_decodable::<u32>("", "a").unwrap(), Some(1));
+        assert_eq!(db.retrieve_decodable::<u32>("", "stale").unwrap(), None);
+    }
+
+    #[test]
+    fn it_merges_buffered_writes_into_scans() {
+        let db = DB::in_memory();
+        for (key, value) in [("a", 1u32), ("c", 3), ("e", 5)].iter() {
+            db.store_encodable("k_", key, value).unwrap();
+        }
+
+        db.write_batch(|batch| {
+            batch.store_encodable("k_", "b", &2u32)?;
+            batch.store_encodable("k_", "c", &33u32)?;
+            batch.delete_value("k_", "e")?;
+            batch.store_encodable("k_", "f", &6u32)?;
+
+            let scanned: Vec<_> = batch
+                .prefix_iterator("k_")
+                .map(|(key, value)| (key, u32::read_from(&mut value.as_slice()).unwrap()))
+                .collect();
+            assert_eq!(
+                scanned,
+                vec![
+                    (b"k_a".to_vec(), 1),
+                    (b"k_b".to_vec(), 2),
+                    (b"k_c".to_vec(), 33),
+                    (b"k_f".to_vec(), 6),
+                ]
+            );
+            Ok::<_, DbError>(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn it_discards_batches_on_error() {
+        let db = DB::new(MemoryStorage::default());
+
+        let res = db.write_batch(|batch| {
+            batch.store_encodable("", "a", &1u32)?;
+            Err::<(), _>(DbError::NomadError(NomadError::IoError(
+                std::io::ErrorKind::Other.into(),
+            )))
+        });
+
+        assert!(res.is_err());
+        assert_eq!(db.retrieve_decodable::<u32>("", "a").unwrap(), None);
+    }
+}
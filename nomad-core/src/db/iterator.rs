@@ -1,36 +1,169 @@
-use crate::{Decode, Encode};
-use rocksdb::DBIterator;
-use std::marker::PhantomData;
-
-/// An iterator over a prefix that deserializes values
-pub struct PrefixIterator<'a, V> {
-    iter: DBIterator<'a>,
-    prefix: &'a [u8],
-    _phantom: PhantomData<*const V>,
+use crate::{
+    db::{DbError, KvIterator},
+    Decode, Encode,
+};
+use std::{marker::PhantomData, ops::Bound};
+
+/// Encode a range bound on keys of type `K`
+pub(crate) fn encode_bound<K: Encode>(bound: Bound<&K>) -> Bound<Vec<u8>> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key.to_vec()),
+        Bound::Excluded(key) => Bound::Excluded(key.to_vec()),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// An iterator over a prefix that deserializes keys and values, in key
+/// order. Keys (less the prefix) are compared to the bounds by their
+/// encoding, so key types must encode big-endian for the order to be
+/// meaningful. Yields an error for undecodable entries.
+pub struct PrefixIterator<'a, K, V> {
+    iter: KvIterator<'a>,
+    prefix_len: usize,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    done: bool,
+    _phantom: PhantomData<*const (K, V)>,
 }
 
-impl<'a, V> PrefixIterator<'a, V> {
-    /// Return new prefix iterator
-    pub fn new(iter: DBIterator<'a>, prefix: &'a [u8]) -> Self {
+impl<'a, K, V> PrefixIterator<'a, K, V> {
+    /// Return new prefix iterator over the key-value pairs of a prefix scan,
+    /// limited to keys within `start` and `end` once the `prefix_len` byte
+    /// prefix is stripped
+    pub fn new(
+        iter: KvIterator<'a>,
+        prefix_len: usize,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+    ) -> Self {
         Self {
             iter,
-            prefix,
+            prefix_len,
+            start,
+            end,
+            done: false,
             _phantom: PhantomData,
         }
     }
+
+    fn before_start(&self, key: &[u8]) -> bool {
+        match &self.start {
+            Bound::Included(start) => key < start.as_slice(),
+            Bound::Excluded(start) => key <= start.as_slice(),
+            Bound::Unbounded => false,
+        }
+    }
+
+    fn after_end(&self, key: &[u8]) -> bool {
+        match &self.end {
+            Bound::Included(end) => key > end.as_slice(),
+            Bound::Excluded(end) => key >= end.as_slice(),
+            Bound::Unbounded => false,
+        }
+    }
 }
 
-impl<'a, V> Iterator for PrefixIterator<'a, V>
+impl<'a, K, V> Iterator for PrefixIterator<'a, K, V>
 where
-    V: Encode + Decode,
+    K: Decode,
+    V: Decode,
 {
-    type Item = V;
+    type Item = Result<(K, V), DbError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let prefix = self.prefix;
-        self.iter
-            .find(|(k, _)| k.strip_prefix(prefix).is_some())
-            .map(|(_, v)| v.to_vec())
-            .map(|v| V::read_from(&mut v.as_slice()).expect("!corrupt"))
+        if self.done {
+            return None;
+        }
+
+        loop {
+            let (key, value) = match self.iter.next() {
+                Some(pair) => pair,
+                None => {
+                    self.done = true;
+                    return None;
+                }
+            };
+
+            let mut suffix = &key[self.prefix_len..];
+            if self.before_start(suffix) {
+                continue;
+            }
+            if self.after_end(suffix) {
+                self.done = true;
+                return None;
+            }
+
+            let decoded = K::read_from(&mut suffix)
+                .map_err(DbError::from)
+                .and_then(|k| {
+                    if !suffix.is_empty() {
+                        return Err(DbError::Inconsistent(format!(
+                            "key {:?} has trailing bytes",
+                            key
+                        )));
+                    }
+                    let v = V::read_from(&mut value.as_slice())?;
+                    Ok((k, v))
+                });
+
+            // Stop after an error rather than skipping past corrupt data
+            self.done = decoded.is_err();
+            return Some(decoded);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::{Column, TypedDB, DB};
+
+    #[test]
+    fn it_iterates_ranges_in_key_order() {
+        let db = TypedDB::new("home_1".to_owned(), DB::in_memory());
+        for i in [300u32, 2, 1, 256, 3].iter() {
+            db.store_keyed_encodable("number_", i, &(i * 10)).unwrap();
+        }
+        // Neighbouring prefixes are not included
+        db.store_keyed_encodable("numbers_", &0u32, &0u32).unwrap();
+
+        let keys = |range: (Bound<u32>, Bound<u32>)| -> Vec<u32> {
+            db.column_range_iterator::<u32, u32>(Column::Agent, "number_", range)
+                .map(|res| res.unwrap().0)
+                .collect()
+        };
+
+        assert_eq!(
+            keys((Bound::Unbounded, Bound::Unbounded)),
+            vec![1, 2, 3, 256, 300]
+        );
+        assert_eq!(
+            keys((Bound::Included(2), Bound::Excluded(300))),
+            vec![2, 3, 256]
+        );
+        assert_eq!(keys((Bound::Excluded(2), Bound::Included(3))), vec![3]);
+
+        let values: Vec<_> = db
+            .column_range_iterator::<u32, u32>(Column::Agent, "number_", 256..)
+            .map(|res| res.unwrap().1)
+            .collect();
+        assert_eq!(values, vec![2560, 3000]);
+    }
+
+    #[test]
+    fn it_yields_errors_for_corrupt_entries() {
+        let db = TypedDB::new("home_1".to_owned(), DB::in_memory());
+        db.store_keyed_encodable("number_", &1u32, &10u32).unwrap();
+        // Too short to decode as a u32
+        let db_ref: &DB = db.as_ref();
+        db_ref
+            ._store(b"home_1_number_\x00\x00\x00\x02", [0u8; 2])
+            .unwrap();
+        db.store_keyed_encodable("number_", &3u32, &30u32).unwrap();
+
+        let mut iter = db.column_range_iterator::<u32, u32>(Column::Agent, "number_", ..);
+        assert_eq!(iter.next().unwrap().unwrap(), (1, 10));
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
     }
 }
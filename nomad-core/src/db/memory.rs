@@ -0,0 +1,147 @@
+use super::{BatchOp, Column, DbError, KvIterator, Result, RocksStorage, Storage, WriteBatch};
+use std::{collections::BTreeMap, io, path::Path, sync::RwLock};
+
+/// Storage backend keeping all data in memory. Intended for tests and
+/// tooling which do not need data to outlive the process.
+#[derive(Debug, Default)]
+pub struct MemoryStorage(RwLock<BTreeMap<(Column, Vec<u8>), Vec<u8>>>);
+
+impl Storage for MemoryStorage {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .0
+            .read()
+            .expect("!lock")
+            .get(&(column, key.to_vec()))
+            .cloned())
+    }
+
+    fn put(&self, column: Column, key: &[u8], value: &[u8]) -> Result<()> {
+        self.0
+            .write()
+            .expect("!lock")
+            .insert((column, key.to_vec()), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, column: Column, key: &[u8]) -> Result<()> {
+        self.0
+            .write()
+            .expect("!lock")
+            .remove(&(column, key.to_vec()));
+        Ok(())
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        // Holding the lock for the whole batch makes it atomic to readers
+        let mut map = self.0.write().expect("!lock");
+        for op in batch {
+            match op {
+                BatchOp::Put { column, key, value } => {
+                    map.insert((column, key), value);
+                }
+                BatchOp::Delete { column, key } => {
+                    map.remove(&(column, key));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn range_scan<'a>(&'a self, column: Column, prefix: &[u8], from: &[u8]) -> KvIterator<'a> {
+        let pairs: Vec<_> = self
+            .0
+            .read()
+            .expect("!lock")
+            .range((column, from.to_vec())..)
+            .take_while(|((c, k), _)| *c == column && k.starts_with(prefix))
+            .map(|((_, k), v)| (k.clone(), v.clone()))
+            .collect();
+        Box::new(pairs.into_iter())
+    }
+
+    fn size(&self, column: Column) -> Result<u64> {
+        Ok(self
+            .0
+            .read()
+            .expect("!lock")
+            .iter()
+            .filter(|((c, _), _)| *c == column)
+            .map(|((_, k), v)| (k.len() + v.len()) as u64)
+            .sum())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn checkpoint(&self, path: &Path) -> Result<()> {
+        if path.exists() {
+            return Err(DbError::Io(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("checkpoint path {:?} already exists", path),
+            )));
+        }
+
+        // Copy under the read lock so the snapshot is consistent
+        let map = self.0.read().expect("!lock");
+        let mut batch = WriteBatch::default();
+        for ((column, key), value) in map.iter() {
+            batch.put(*column, key, value);
+        }
+
+        let rocks = RocksStorage::open(path)?;
+        rocks.write(batch)?;
+        rocks.flush()
+    }
+
+    fn catch_up(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_scans_prefixes_in_key_order() {
+        let storage = MemoryStorage::default();
+        storage.put(Column::Agent, b"b_2", b"2").unwrap();
+        storage.put(Column::Agent, b"b_1", b"1").unwrap();
+        storage.put(Column::Agent, b"a_1", b"a").unwrap();
+        storage.put(Column::Agent, b"c_1", b"c").unwrap();
+        storage.put(Column::Leaves, b"b_3", b"3").unwrap();
+
+        let scanned: Vec<_> = storage.prefix_scan(Column::Agent, b"b_").collect();
+        assert_eq!(
+            scanned,
+            vec![
+                (b"b_1".to_vec(), b"1".to_vec()),
+                (b"b_2".to_vec(), b"2".to_vec())
+            ]
+        );
+
+        let scanned: Vec<_> = storage.range_scan(Column::Agent, b"b_", b"b_2").collect();
+        assert_eq!(scanned, vec![(b"b_2".to_vec(), b"2".to_vec())]);
+    }
+
+    #[test]
+    fn it_applies_batches() {
+        let storage = MemoryStorage::default();
+        storage.put(Column::Agent, b"stale", b"0").unwrap();
+
+        let mut batch = WriteBatch::default();
+        batch.put(Column::Proofs, b"key", b"1");
+        batch.delete(Column::Agent, b"stale");
+        storage.write(batch).unwrap();
+
+        assert_eq!(
+            storage.get(Column::Proofs, b"key").unwrap(),
+            Some(b"1".to_vec())
+        );
+        assert_eq!(storage.get(Column::Agent, b"key").unwrap(), None);
+        assert_eq!(storage.get(Column::Agent, b"stale").unwrap(), None);
+        assert_eq!(storage.size(Column::Proofs).unwrap(), 4);
+    }
+}
@@ -0,0 +1,493 @@
+use super::{Column, DbError, Result, DB};
+use tracing::info;
+
+/// Key the schema version is stored under. Not prefixed by any entity.
+static SCHEMA_VERSION_KEY: &str = "db_schema_version";
+
+/// Schema version written by this build. Databases created before schema
+/// versioning have no stored version and are treated as version 0.
+pub const SCHEMA_VERSION: u32 = 5;
+
+/// Number of keys moved per write batch when splitting columns
+const SPLIT_COLUMNS_BATCH_SIZE: usize = 10_000;
+
+/// Number of keys scanned per write batch when moving leaf index keys
+const LEAF_INDEX_BATCH_SIZE: usize = 10_000;
+
+/// Number of keys scanned per write batch when extending update metadata
+const UPDATE_METADATA_BATCH_SIZE: usize = 10_000;
+
+/// Number of keys scanned per write batch when recording message versions
+const MESSAGE_VERSION_BATCH_SIZE: usize = 10_000;
+
+/// Key the progress of `record_message_versions` is stored under, as
+/// rewriting messages twice would corrupt them. Not prefixed by any entity.
+static MESSAGE_VERSION_CURSOR_KEY: &str = "db_migration_message_version_cursor";
+
+/// Offset of the message in committed messages, past the leaf index and
+/// committed root
+const COMMITTED_MESSAGE_OFFSET: usize = 36;
+
+/// Length of the prefix of the only message format dispatched before
+/// version 5 (`MessageVersion::V0`)
+const V4_MESSAGE_PREFIX_LEN: usize = 76;
+
+/// Length of update metadata in the version 3 layout (block number and
+/// timestamp)
+const V3_UPDATE_METADATA_LEN: usize = 16;
+
+/// Length of the transaction hash, block hash and log index appended to
+/// update metadata in version 4
+const TX_METADATA_LEN: usize = 72;
+
+/// Key prefixes following the entity in the unversioned layout, and the
+/// column their data moved to in version 2. Bookkeeping prefixes are listed
+/// so they are not mistaken for data prefixes (e.g. `updater_produced_update_`
+/// for `update_`). Longer prefixes come first.
+static V2_COLUMN_PREFIXES: &[(&str, Column)] = &[
+    ("update_latest_root_", Column::Agent),
+    ("updater_produced_update_", Column::Agent),
+    ("prover_latest_committed_", Column::Agent),
+    ("latest_known_leaf_index_", Column::Agent),
+    ("updates_last_block", Column::Agent),
+    ("messages_last_block", Column::Agent),
+    ("updates_checkpoint_", Column::Agent),
+    ("messages_checkpoint_", Column::Agent),
+    ("update_prev_root_", Column::Updates),
+    ("update_metadata_", Column::Updates),
+    ("update_", Column::Updates),
+    ("message_", Column::Messages),
+    ("leaf_", Column::Leaves),
+    ("proof_", Column::Proofs),
+];
+
+/// A change to the db's key layout, upgrading it from `version - 1` to
+/// `version`
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    /// Schema version after the migration ran
+    pub version: u32,
+    /// Description of the layout change, logged when the migration runs
+    pub description: &'static str,
+    /// Rewrite the db's contents. The new schema version is recorded once
+    /// this returns, so it must be safe to run again if interrupted.
+    pub run: fn(&DB) -> Result<()>,
+}
+
+/// Migrations in the order they are applied. Each must target the version
+/// following the previous one's, ending at `SCHEMA_VERSION`.
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "record schema version of unversioned dbs",
+        run: |_| Ok(()),
+    },
+    Migration {
+        version: 2,
+        description: "move data out of the default column into per type columns",
+        run: split_columns,
+    },
+    Migration {
+        version: 3,
+        description: "move leaves keyed by leaf index to the leaf_index_ prefix",
+        run: move_leaf_indexes,
+    },
+    Migration {
+        version: 4,
+        description: "extend update metadata with transaction metadata",
+        run: extend_update_metadata,
+    },
+    Migration {
+        version: 5,
+        description: "record the format version of committed messages",
+        run: record_message_versions,
+    },
+];
+
+/// Column a key of the unversioned layout (`<entity>_<prefix><key>`) belongs
+/// in. Entities may contain `_`, so the first position a known prefix
+/// follows an `_` at is taken as the end of the entity.
+fn v2_column(key: &[u8]) -> Column {
+    key.iter()
+        .enumerate()
+        .filter(|(_, b)| **b == b'_')
+        .find_map(|(i, _)| {
+            V2_COLUMN_PREFIXES
+                .iter()
+                .find(|(prefix, _)| key[i + 1..].starts_with(prefix.as_bytes()))
+                .map(|(_, column)| *column)
+        })
+        .unwrap_or(Column::Agent)
+}
+
+/// Move every key but the schema version out of the default column
+fn split_columns(db: &DB) -> Result<()> {
+    let default = db.column(Column::Default);
+    loop {
+        let moved = default.write_batch(|batch| {
+            let entries: Vec<_> = default
+                .prefix_iterator("")
+                .filter(|(key, _)| key != SCHEMA_VERSION_KEY.as_bytes())
+                .take(SPLIT_COLUMNS_BATCH_SIZE)
+                .collect();
+
+            for (key, value) in entries.iter() {
+                batch.column(v2_column(key))._store(key, value)?;
+                batch._delete(key)?;
+            }
+            Ok::<_, DbError>(entries.len())
+        })?;
+
+        if moved < SPLIT_COLUMNS_BATCH_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+/// Whether `key` is a leaf keyed by leaf index in the version 2 layout
+/// (`<entity>_leaf_<u32>`), rather than by destination and nonce
+/// (`<entity>_leaf_<u64>`)
+fn is_v2_leaf_index_key(key: &[u8]) -> bool {
+    key.len() >= 10 && &key[key.len() - 10..key.len() - 4] == b"_leaf_"
+}
+
+/// Move leaves keyed by leaf index from `<entity>_leaf_<index>` to
+/// `<entity>_leaf_index_<index>`, so they no longer share a prefix with
+/// leaves keyed by destination and nonce
+fn move_leaf_indexes(db: &DB) -> Result<()> {
+    let leaves = db.column(Column::Leaves);
+    let mut from = vec![];
+    loop {
+        let scanned: Vec<_> = leaves
+            .column_range_iterator(Column::Leaves, "", &from)
+            .take(LEAF_INDEX_BATCH_SIZE)
+            .collect();
+
+        leaves.write_batch(|batch| {
+            for (key, value) in scanned.iter().filter(|(key, _)| is_v2_leaf_index_key(key)) {
+                let (entity_and_prefix, index) = key.split_at(key.len() - 4);
+                batch._store([entity_and_prefix, &b"index_"[..], index].concat(), value)?;
+                batch._delete(key)?;
+            }
+            Ok::<_, DbError>(())
+        })?;
+
+        match scanned.last() {
+            Some((key, _)) if scanned.len() == LEAF_INDEX_BATCH_SIZE => {
+                // Continue right after the last scanned key
+                from = [key.as_slice(), &[0u8][..]].concat();
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Whether `key` is update metadata (`<entity>_update_metadata_<H256>`)
+fn is_update_metadata_key(key: &[u8]) -> bool {
+    key.len() >= 49 && &key[key.len() - 49..key.len() - 32] == b"_update_metadata_"
+}
+
+/// Pad update metadata of the version 3 layout with a zero transaction hash,
+/// block hash and log index, so it decodes as transaction metadata
+fn extend_update_metadata(db: &DB) -> Result<()> {
+    let updates = db.column(Column::Updates);
+    let mut from = vec![];
+    loop {
+        let scanned: Vec<_> = updates
+            .column_range_iterator(Column::Updates, "", &from)
+            .take(UPDATE_METADATA_BATCH_SIZE)
+            .collect();
+
+        updates.write_batch(|batch| {
+            let outdated = scanned.iter().filter(|(key, value)| {
+                is_update_metadata_key(key) && value.len() == V3_UPDATE_METADATA_LEN
+            });
+            for (key, value) in outdated {
+                batch._store(
+                    key,
+                    [value.as_slice(), &[0u8; TX_METADATA_LEN][..]].concat(),
+                )?;
+            }
+            Ok::<_, DbError>(())
+        })?;
+
+        match scanned.last() {
+            Some((key, _)) if scanned.len() == UPDATE_METADATA_BATCH_SIZE => {
+                from = [key.as_slice(), &[0u8][..]].concat();
+            }
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Whether `key` is a committed message (`<entity>_message_<H256>`)
+fn is_message_key(key: &[u8]) -> bool {
+    key.len() >= 41 && &key[key.len() - 41..key.len() - 32] == b"_message_"
+}
+
+/// Insert the format version of the messages dispatched before versioning,
+/// `MessageVersion::V0` (0), between the committed root and the message of
+/// committed messages. Resumes after the last key rewritten by an
+/// interrupted run.
+fn record_message_versions(db: &DB) -> Result<()> {
+    let messages = db.column(Column::Messages);
+    let cursor = db.column(Column::Default);
+    let mut from = cursor
+        ._retrieve(MESSAGE_VERSION_CURSOR_KEY)?
+        .unwrap_or_default();
+    loop {
+        let scanned: Vec<_> = messages
+            .column_range_iterator(Column::Messages, "", &from)
+            .take(MESSAGE_VERSION_BATCH_SIZE)
+            .collect();
+        if let Some((key, _)) = scanned.last() {
+            from = [key.as_slice(), &[0u8][..]].concat();
+        }
+
+        messages.write_batch(|batch| {
+            let unversioned = scanned.iter().filter(|(key, value)| {
+                is_message_key(key)
+                    && value.len() >= COMMITTED_MESSAGE_OFFSET + V4_MESSAGE_PREFIX_LEN
+            });
+            for (key, value) in unversioned {
+                let (committed, message) = value.split_at(COMMITTED_MESSAGE_OFFSET);
+                batch._store(key, [committed, &[0u8][..], message].concat())?;
+            }
+            batch
+                .column(Column::Default)
+                ._store(MESSAGE_VERSION_CURSOR_KEY, &from)
+        })?;
+
+        if scanned.len() < MESSAGE_VERSION_BATCH_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+impl DB {
+    /// Retrieve the stored schema version. `None` if the db is unversioned.
+    pub fn schema_version(&self) -> Result<Option<u32>> {
+        self.column(Column::Default)
+            .retrieve_decodable("", SCHEMA_VERSION_KEY)
+    }
+
+    fn store_schema_version(&self, version: u32) -> Result<()> {
+        self.column(Column::Default)
+            .store_encodable("", SCHEMA_VERSION_KEY, &version)
+    }
+
+    /// Bring the db up to `SCHEMA_VERSION`, running outstanding migrations in
+    /// order. Empty dbs are stamped with `SCHEMA_VERSION` directly. Errors if
+    /// the db was written by a newer schema. Returns the resulting version.
+    pub fn migrate(&self) -> Result<u32> {
+        self.migrate_with(MIGRATIONS, SCHEMA_VERSION)
+    }
+
+    /// Whether no column holds any data
+    fn is_empty(&self) -> bool {
+        Column::ALL
+            .iter()
+            .all(|column| self.column(*column).prefix_iterator("").next().is_none())
+    }
+
+    fn migrate_with(&self, migrations: &[Migration], latest: u32) -> Result<u32> {
+        let mut version = match self.schema_version()? {
+            Some(version) => version,
+            None if self.is_empty() => {
+                self.store_schema_version(latest)?;
+                return Ok(latest);
+            }
+            None => 0,
+        };
+
+        if version > latest {
+            return Err(DbError::UnsupportedSchemaVersion {
+                found: version,
+                supported: latest,
+            });
+        }
+
+        for migration in migrations.iter().filter(|m| m.version > version) {
+            debug_assert_eq!(migration.version, version + 1, "!migration order");
+            info!(
+                from = version,
+                to = migration.version,
+                description = migration.description,
+                "Migrating db schema"
+            );
+
+            (migration.run)(self)?;
+            self.store_schema_version(migration.version)?;
+            version = migration.version;
+        }
+
+        Ok(version)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static TEST_MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: 1,
+            description: "noop",
+            run: |_| Ok(()),
+        },
+        Migration {
+            version: 2,
+            description: "rename a to b",
+            run: |db| {
+                if let Some(value) = db.retrieve_decodable::<u32>("", "a")? {
+                    db.store_encodable("", "b", &value)?;
+                    db.delete_value("", "a")?;
+                }
+                Ok(())
+            },
+        },
+    ];
+
+    #[test]
+    fn it_stamps_empty_dbs() {
+        let db = DB::in_memory();
+        assert_eq!(db.migrate_with(TEST_MIGRATIONS, 2).unwrap(), 2);
+        assert_eq!(db.schema_version().unwrap(), Some(2));
+    }
+
+    #[test]
+    fn it_runs_outstanding_migrations_in_order() {
+        let db = DB::in_memory();
+        db.store_encodable("", "a", &7u32).unwrap();
+
+        assert_eq!(db.migrate_with(TEST_MIGRATIONS, 2).unwrap(), 2);
+        assert_eq!(db.retrieve_decodable::<u32>("", "b").unwrap(), Some(7));
+        assert_eq!(db.retrieve_decodable::<u32>("", "a").unwrap(), None);
+
+        // Already up to date
+        assert_eq!(db.migrate_with(TEST_MIGRATIONS, 2).unwrap(), 2);
+    }
+
+    #[test]
+    fn it_splits_unversioned_dbs_into_columns() {
+        let db = DB::in_memory();
+        let keys = [
+            ("home_1_leaf_\x01", Column::Leaves),
+            ("home_1_message_\x02", Column::Messages),
+            ("home_1_proof_\x03", Column::Proofs),
+            ("home_1_update_metadata_\x04", Column::Updates),
+            ("home_1_update_latest_root_", Column::Agent),
+            ("home_1_updater_produced_update_\x05", Column::Agent),
+            ("tx_manager_1_ab_in_flight_\x06", Column::Agent),
+        ];
+        for (key, _) in keys.iter() {
+            db._store(key, key).unwrap();
+        }
+
+        assert_eq!(db.migrate().unwrap(), SCHEMA_VERSION);
+        for (key, column) in keys.iter() {
+            assert_eq!(
+                db.column(*column)._retrieve(key).unwrap(),
+                Some(key.as_bytes().to_vec()),
+                "{} not moved to {:?}",
+                key,
+                column
+            );
+            assert_eq!(db._retrieve(key).unwrap(), None);
+        }
+        assert_eq!(db.schema_version().unwrap(), Some(SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn it_moves_leaf_index_keys() {
+        let db = DB::in_memory();
+        db.store_schema_version(2).unwrap();
+
+        let leaves = db.column(Column::Leaves);
+        leaves
+            ._store(b"home_1_leaf_\x00\x00\x00\x07", b"by index")
+            .unwrap();
+        leaves
+            ._store(b"home_1_leaf_\x00\x00\x00\x02\x00\x00\x00\x07", b"by nonce")
+            .unwrap();
+
+        assert_eq!(db.migrate().unwrap(), SCHEMA_VERSION);
+        assert_eq!(
+            leaves
+                ._retrieve(b"home_1_leaf_index_\x00\x00\x00\x07")
+                .unwrap(),
+            Some(b"by index".to_vec())
+        );
+        assert_eq!(
+            leaves._retrieve(b"home_1_leaf_\x00\x00\x00\x07").unwrap(),
+            None
+        );
+        assert_eq!(
+            leaves
+                ._retrieve(b"home_1_leaf_\x00\x00\x00\x02\x00\x00\x00\x07")
+                .unwrap(),
+            Some(b"by nonce".to_vec())
+        );
+    }
+
+    #[test]
+    fn it_extends_update_metadata() {
+        let db = DB::in_memory();
+        db.store_schema_version(3).unwrap();
+
+        let updates = db.column(Column::Updates);
+        let key = [&b"home_1_update_metadata_"[..], &[7u8; 32][..]].concat();
+        let v3_meta = [12u64.to_be_bytes(), 1_600_000_000u64.to_be_bytes()].concat();
+        updates._store(&key, &v3_meta).unwrap();
+        // Updates themselves are left alone
+        let update_key = [&b"home_1_update_"[..], &[7u8; 32][..]].concat();
+        updates._store(&update_key, &[1u8; 16]).unwrap();
+
+        assert_eq!(db.migrate().unwrap(), SCHEMA_VERSION);
+        assert_eq!(
+            updates._retrieve(&key).unwrap(),
+            Some([v3_meta.as_slice(), &[0u8; TX_METADATA_LEN][..]].concat())
+        );
+        assert_eq!(updates._retrieve(&update_key).unwrap(), Some(vec![1u8; 16]));
+    }
+
+    #[test]
+    fn it_records_message_versions() {
+        let db = DB::in_memory();
+        db.store_schema_version(4).unwrap();
+
+        let messages = db.column(Column::Messages);
+        let key = [&b"home_1_message_"[..], &[7u8; 32][..]].concat();
+        let v4_message = [&[1u8; COMMITTED_MESSAGE_OFFSET][..], &[2u8; 80][..]].concat();
+        messages._store(&key, &v4_message).unwrap();
+        // Message metadata is left alone
+        let meta_key = [&b"home_1_message_metadata_"[..], &[7u8; 32][..]].concat();
+        messages._store(&meta_key, &[3u8; 120]).unwrap();
+
+        let versioned = [
+            &[1u8; COMMITTED_MESSAGE_OFFSET][..],
+            &[0u8][..],
+            &[2u8; 80][..],
+        ]
+        .concat();
+        assert_eq!(db.migrate().unwrap(), SCHEMA_VERSION);
+        assert_eq!(messages._retrieve(&key).unwrap(), Some(versioned.clone()));
+        assert_eq!(messages._retrieve(&meta_key).unwrap(), Some(vec![3u8; 120]));
+
+        // Resumed runs skip the messages already rewritten
+        record_message_versions(&db).unwrap();
+        assert_eq!(messages._retrieve(&key).unwrap(), Some(versioned));
+    }
+
+    #[test]
+    fn it_refuses_newer_schema_versions() {
+        let db = DB::in_memory();
+        db.store_schema_version(SCHEMA_VERSION + 1).unwrap();
+
+        assert!(matches!(
+            db.migrate(),
+            Err(DbError::UnsupportedSchemaVersion { found, supported })
+                if found == SCHEMA_VERSION + 1 && supported == SCHEMA_VERSION
+        ));
+    }
+}
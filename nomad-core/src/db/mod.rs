@@ -1,6 +1,9 @@
 use color_eyre::eyre::WrapErr;
-use rocksdb::{DBIterator, Options, DB as Rocks};
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tracing::info;
 
 /// Shared functionality surrounding use of rocksdb
@@ -10,15 +13,47 @@ pub mod iterator;
 mod typed_db;
 pub use typed_db::*;
 
+/// Storage backend trait
+mod storage;
+pub use storage::*;
+
+/// RocksDB storage backend
+mod rocks;
+pub use rocks::*;
+
+/// In-memory storage backend
+mod memory;
+pub use memory::*;
+
+/// Buffered writes committed as one atomic batch
+mod batch;
+use batch::BatchStorage;
+
+/// Schema versioning and migrations
+mod migration;
+pub use migration::*;
+
 use crate::{Decode, Encode, NomadError};
 
+/// Resolve `db_path` relative to the working directory
+fn canonical_path(db_path: &str) -> std::io::Result<PathBuf> {
+    // Canonicalize ensures existence, so we have to do that, then extend
+    let mut path = Path::new(".").canonicalize()?;
+    path.extend(&[db_path]);
+    Ok(path)
+}
+
 #[derive(Debug, Clone)]
-/// A KV Store
-pub struct DB(Arc<Rocks>);
+/// A KV Store. Reads and writes go to one column, `Column::Default` unless
+/// selected with `column`.
+pub struct DB {
+    storage: Arc<dyn Storage>,
+    column: Column,
+}
 
-impl From<Rocks> for DB {
-    fn from(rocks: Rocks) -> Self {
-        Self(Arc::new(rocks))
+impl From<RocksStorage> for DB {
+    fn from(rocks: RocksStorage) -> Self {
+        Self::new(rocks)
     }
 }
 
@@ -31,17 +66,72 @@ pub enum DbError {
     /// Nomad Error
     #[error("{0}")]
     NomadError(#[from] NomadError),
+    /// IO Error
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    /// Stored data is malformed or references data that is missing
+    #[error("DB is inconsistent: {0}")]
+    Inconsistent(String),
+    /// Write to a db opened read-only or as a secondary instance
+    #[error("DB was opened read-only")]
+    ReadOnly,
+    /// DB opened without write access is behind the current schema version
+    #[error(
+        "DB schema version {found:?} needs migrating to {required}. Open the db read-write first."
+    )]
+    MigrationRequired {
+        /// Stored schema version
+        found: Option<u32>,
+        /// Schema version of this build
+        required: u32,
+    },
+    /// DB was written by a newer schema than this build supports
+    #[error("DB schema version {found} is newer than supported version {supported}")]
+    UnsupportedSchemaVersion {
+        /// Stored schema version
+        found: u32,
+        /// Latest schema version supported by this build
+        supported: u32,
+    },
 }
 
 type Result<T> = std::result::Result<T, DbError>;
 
 impl DB {
-    /// Opens db at `db_path` and creates if missing
+    /// Instantiate a DB backed by `storage`
+    pub fn new(storage: impl Storage + 'static) -> Self {
+        Self {
+            storage: Arc::new(storage),
+            column: Column::Default,
+        }
+    }
+
+    /// Get a handle to `column` of the same db
+    pub fn column(&self, column: Column) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            column,
+        }
+    }
+
+    /// Estimated size of each column, in bytes, keyed by column name
+    pub fn column_sizes(&self) -> Result<BTreeMap<&'static str, u64>> {
+        Column::ALL
+            .iter()
+            .map(|column| Ok((column.name(), self.storage.size(*column)?)))
+            .collect()
+    }
+
+    /// Instantiate an empty DB kept in memory
+    pub fn in_memory() -> Self {
+        Self::new(MemoryStorage::default())
+    }
+
+    /// Opens db at `db_path` and creates if missing. Migrates the db to the
+    /// current schema version, refusing dbs written by a newer schema.
     #[tracing::instrument(err)]
     pub fn from_path(db_path: &str) -> color_eyre::Result<DB> {
-        // Canonicalize ensures existence, so we have to do that, then extend
-        let mut path = Path::new(".").canonicalize()?;
-        path.extend(&[db_path]);
+        let path = canonical_path(db_path)?;
 
         match path.is_dir() {
             true => info!(
@@ -51,25 +141,101 @@ impl DB {
             false => info!("Creating db at {path}", path = path.to_str().unwrap()),
         }
 
-        let mut opts = Options::default();
-        opts.create_if_missing(true);
+        let db: DB = RocksStorage::open(&path)
+            .wrap_err(format!(
+                "Failed to open db path {}, canonicalized as {:?}",
+                db_path, path
+            ))?
+            .into();
+
+        let version = db
+            .migrate()
+            .wrap_err(format!("Failed to migrate db at {:?}", path))?;
+        info!(version, "DB schema at version {}", version);
+
+        Ok(db)
+    }
+
+    /// Opens the existing db at `db_path` in `mode`. Without write access,
+    /// the db is not migrated and must be at the current schema version.
+    #[tracing::instrument(err)]
+    pub fn from_path_with_mode(db_path: &str, mode: OpenMode) -> color_eyre::Result<DB> {
+        if mode == OpenMode::ReadWrite {
+            return Self::from_path(db_path);
+        }
+
+        let path = canonical_path(db_path)?;
+        info!(mode = ?mode, "Opening existing db at {:?}", path);
 
-        Rocks::open(&opts, &path)
+        let db: DB = RocksStorage::open_with_mode(&path, &mode)
             .wrap_err(format!(
                 "Failed to open db path {}, canonicalized as {:?}",
                 db_path, path
-            ))
-            .map(Into::into)
+            ))?
+            .into();
+
+        match db.schema_version()? {
+            Some(SCHEMA_VERSION) => Ok(db),
+            Some(found) if found > SCHEMA_VERSION => Err(DbError::UnsupportedSchemaVersion {
+                found,
+                supported: SCHEMA_VERSION,
+            }
+            .into()),
+            found => Err(DbError::MigrationRequired {
+                found,
+                required: SCHEMA_VERSION,
+            }
+            .into()),
+        }
+    }
+
+    /// Catch up with writes made by the agent owning the db, if it was
+    /// opened as a secondary instance. No-op otherwise.
+    pub fn catch_up(&self) -> Result<()> {
+        self.storage.catch_up()
+    }
+
+    /// Write a consistent snapshot of the db to a new RocksDB database at
+    /// `path`. The db stays available for reads and writes meanwhile.
+    pub fn checkpoint(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.storage.checkpoint(path.as_ref())
+    }
+
+    /// Persist buffered writes (e.g. flush RocksDB memtables to disk)
+    pub fn flush(&self) -> Result<()> {
+        self.storage.flush()
+    }
+
+    /// Run `f` against a view of the DB whose writes are buffered, then
+    /// commit them as one atomic write batch. Reads made by `f` see its own
+    /// writes. Nothing is written if `f` errors.
+    pub fn write_batch<T, E, F>(&self, f: F) -> std::result::Result<T, E>
+    where
+        F: FnOnce(&DB) -> std::result::Result<T, E>,
+        E: From<DbError>,
+    {
+        let storage = Arc::new(BatchStorage::new(self.storage.clone()));
+        let res = f(&DB {
+            storage: storage.clone(),
+            column: self.column,
+        })?;
+        storage.commit()?;
+        Ok(res)
     }
 
     /// Store a value in the DB
     fn _store(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<()> {
-        Ok(self.0.put(key, value)?)
+        self.storage.put(self.column, key.as_ref(), value.as_ref())
     }
 
     /// Retrieve a value from the DB
     fn _retrieve(&self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
-        Ok(self.0.get(key)?)
+        self.storage.get(self.column, key.as_ref())
+    }
+
+    /// Delete a value from the DB
+    fn _delete(&self, key: impl AsRef<[u8]>) -> Result<()> {
+        self.storage.delete(self.column, key.as_ref())
     }
 
     /// Prefix a key and store in the DB
@@ -97,6 +263,14 @@ impl DB {
         self._retrieve(buf)
     }
 
+    /// Prefix the key and delete
+    fn prefix_delete(&self, prefix: impl AsRef<[u8]>, key: impl AsRef<[u8]>) -> Result<()> {
+        let mut buf = vec![];
+        buf.extend(prefix.as_ref());
+        buf.extend(key.as_ref());
+        self._delete(buf)
+    }
+
     /// Store any encodeable
     pub fn store_encodable<V: Encode>(
         &self,
@@ -138,8 +312,42 @@ impl DB {
         self.retrieve_decodable(prefix, key.to_vec())
     }
 
-    /// Get prefix db iterator for `prefix`
-    pub fn prefix_iterator(&self, prefix: impl AsRef<[u8]>) -> DBIterator {
-        self.0.prefix_iterator(prefix)
+    /// Delete the value stored under a prefixed key
+    pub fn delete_value(&self, prefix: impl AsRef<[u8]>, key: impl AsRef<[u8]>) -> Result<()> {
+        self.prefix_delete(prefix, key)
+    }
+
+    /// Delete the value stored under a prefixed encodable key
+    pub fn delete_keyed_value<K: Encode>(&self, prefix: impl AsRef<[u8]>, key: &K) -> Result<()> {
+        self.delete_value(prefix, key.to_vec())
+    }
+
+    /// Get an iterator over all key-value pairs whose key starts with
+    /// `prefix`, in key order
+    pub fn prefix_iterator(&self, prefix: impl AsRef<[u8]>) -> KvIterator<'_> {
+        self.column_prefix_iterator(self.column, prefix)
+    }
+
+    /// Get an iterator over all key-value pairs in `column` whose key starts
+    /// with `prefix`, in key order
+    pub fn column_prefix_iterator(
+        &self,
+        column: Column,
+        prefix: impl AsRef<[u8]>,
+    ) -> KvIterator<'_> {
+        self.storage.prefix_scan(column, prefix.as_ref())
+    }
+
+    /// Get an iterator over the key-value pairs in `column` whose key starts
+    /// with `prefix`, in key order, starting at the first key at or after
+    /// `from`. `from` must start with `prefix`.
+    pub fn column_range_iterator(
+        &self,
+        column: Column,
+        prefix: impl AsRef<[u8]>,
+        from: impl AsRef<[u8]>,
+    ) -> KvIterator<'_> {
+        self.storage
+            .range_scan(column, prefix.as_ref(), from.as_ref())
     }
 }
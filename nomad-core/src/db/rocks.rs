@@ -0,0 +1,276 @@
+use super::{BatchOp, Column, DbError, KvIterator, Result, Storage, WriteBatch};
+use rocksdb::{
+    checkpoint::Checkpoint, ColumnFamily, ColumnFamilyDescriptor, DBCompressionType, Direction,
+    IteratorMode, Options, DB as Rocks,
+};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Write buffer size for the proofs column. Proofs are written for every
+/// leaf and are the bulk of the db.
+const PROOFS_WRITE_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+
+/// Block cache size for columns read by point lookups, in MB
+const POINT_LOOKUP_CACHE_MB: u64 = 32;
+
+/// How a RocksDB database is opened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Exclusive read-write access. Takes the RocksDB lock.
+    ReadWrite,
+    /// Reads of the database as of opening. Does not take the lock, so the
+    /// database may be open in an agent.
+    ReadOnly,
+    /// Reads as a RocksDB secondary instance of a database open elsewhere.
+    /// Secondary instances see the primary's writes after catching up.
+    Secondary {
+        /// Directory the secondary instance keeps its own logs in
+        secondary_path: PathBuf,
+    },
+}
+
+/// Storage backend persisting data to a RocksDB database, with one column
+/// family per [`Column`]
+pub struct RocksStorage {
+    rocks: Rocks,
+    mode: OpenMode,
+}
+
+impl fmt::Debug for RocksStorage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RocksStorage")
+            .field("path", &self.rocks.path())
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
+impl RocksStorage {
+    /// Open the database at `path`, creating it and any missing column
+    /// families
+    pub fn open(path: impl AsRef<Path>) -> std::result::Result<Self, rocksdb::Error> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+
+        let descriptors = Column::ALL
+            .iter()
+            .map(|column| ColumnFamilyDescriptor::new(column.name(), Self::options(*column)));
+
+        Rocks::open_cf_descriptors(&opts, path, descriptors).map(|rocks| Self {
+            rocks,
+            mode: OpenMode::ReadWrite,
+        })
+    }
+
+    /// Open the database at `path` in `mode`. Writes error unless opened
+    /// read-write.
+    pub fn open_with_mode(
+        path: impl AsRef<Path>,
+        mode: &OpenMode,
+    ) -> std::result::Result<Self, rocksdb::Error> {
+        let opts = Options::default();
+        let rocks = match mode {
+            OpenMode::ReadWrite => return Self::open(path),
+            OpenMode::ReadOnly => {
+                let columns = Self::existing_columns(&opts, path.as_ref())?;
+                Rocks::open_cf_for_read_only(&opts, path, columns, false)?
+            }
+            OpenMode::Secondary { secondary_path } => {
+                let columns = Self::existing_columns(&opts, path.as_ref())?;
+                // Secondary instances need to keep every file open
+                let mut opts = opts;
+                opts.set_max_open_files(-1);
+                Rocks::open_cf_as_secondary(&opts, path.as_ref(), secondary_path, columns)?
+            }
+        };
+
+        Ok(Self {
+            rocks,
+            mode: mode.clone(),
+        })
+    }
+
+    /// Names of the column families of the db at `path`. Dbs last opened
+    /// read-write by older builds may miss newer column families, which then
+    /// read as empty.
+    fn existing_columns(
+        opts: &Options,
+        path: &Path,
+    ) -> std::result::Result<Vec<&'static str>, rocksdb::Error> {
+        let existing = Rocks::list_cf(opts, path)?;
+        Ok(Column::ALL
+            .iter()
+            .map(Column::name)
+            .filter(|name| existing.iter().any(|cf| cf == name))
+            .collect())
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.mode != OpenMode::ReadWrite {
+            return Err(DbError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Options the column family of `column` is opened with
+    pub fn options(column: Column) -> Options {
+        let mut opts = Options::default();
+        match column {
+            // Looked up by key, scanned by prefix only for leaves
+            Column::Leaves | Column::Updates => {
+                opts.optimize_for_point_lookup(POINT_LOOKUP_CACHE_MB);
+            }
+            // Message bodies compress well
+            Column::Messages => opts.set_compression_type(DBCompressionType::Lz4),
+            // Hashes do not compress. Large memtables mean fewer, larger
+            // flushes for the most written column.
+            Column::Proofs => {
+                opts.set_compression_type(DBCompressionType::None);
+                opts.set_write_buffer_size(PROOFS_WRITE_BUFFER_SIZE);
+            }
+            Column::Default | Column::Agent | Column::Indexes => {}
+        }
+        opts
+    }
+
+    fn cf(&self, column: Column) -> &ColumnFamily {
+        self.rocks
+            .cf_handle(column.name())
+            .expect("!column family opened")
+    }
+
+    /// Column family to read `column` from. `None` if the db was opened
+    /// without write access and has no such column family.
+    fn read_cf(&self, column: Column) -> Option<&ColumnFamily> {
+        self.rocks.cf_handle(column.name())
+    }
+}
+
+impl Storage for RocksStorage {
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.read_cf(column) {
+            Some(cf) => Ok(self.rocks.get_cf(cf, key)?),
+            None => Ok(None),
+        }
+    }
+
+    fn put(&self, column: Column, key: &[u8], value: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        Ok(self.rocks.put_cf(self.cf(column), key, value)?)
+    }
+
+    fn delete(&self, column: Column, key: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        Ok(self.rocks.delete_cf(self.cf(column), key)?)
+    }
+
+    fn write(&self, batch: WriteBatch) -> Result<()> {
+        self.check_writable()?;
+        let mut rocks_batch = rocksdb::WriteBatch::default();
+        for op in batch {
+            match op {
+                BatchOp::Put { column, key, value } => {
+                    rocks_batch.put_cf(self.cf(column), key, value)
+                }
+                BatchOp::Delete { column, key } => rocks_batch.delete_cf(self.cf(column), key),
+            }
+        }
+        Ok(self.rocks.write(rocks_batch)?)
+    }
+
+    fn range_scan<'a>(&'a self, column: Column, prefix: &[u8], from: &[u8]) -> KvIterator<'a> {
+        let cf = match self.read_cf(column) {
+            Some(cf) => cf,
+            None => return Box::new(std::iter::empty()),
+        };
+
+        // Without a prefix extractor, the iterator runs past the prefix
+        let prefix = prefix.to_vec();
+        Box::new(
+            self.rocks
+                .iterator_cf(cf, IteratorMode::From(from, Direction::Forward))
+                .take_while(move |(k, _)| k.starts_with(&prefix))
+                .map(|(k, v)| (k.to_vec(), v.to_vec())),
+        )
+    }
+
+    fn size(&self, column: Column) -> Result<u64> {
+        let cf = match self.read_cf(column) {
+            Some(cf) => cf,
+            None => return Ok(0),
+        };
+        Ok(self
+            .rocks
+            .property_int_value_cf(cf, "rocksdb.estimate-live-data-size")?
+            .unwrap_or_default())
+    }
+
+    fn flush(&self) -> Result<()> {
+        // Read-only instances have nothing to flush
+        if self.mode != OpenMode::ReadWrite {
+            return Ok(());
+        }
+        for column in Column::ALL.iter() {
+            self.rocks.flush_cf(self.cf(*column))?;
+        }
+        Ok(())
+    }
+
+    fn checkpoint(&self, path: &Path) -> Result<()> {
+        // Hard links SST files where possible, so this is cheap
+        Ok(Checkpoint::new(&self.rocks)?.create_checkpoint(path)?)
+    }
+
+    fn catch_up(&self) -> Result<()> {
+        match self.mode {
+            OpenMode::Secondary { .. } => Ok(self.rocks.try_catch_up_with_primary()?),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_reads_live_dbs_without_write_access() {
+        let dir = std::env::temp_dir().join(format!("nomad_rocks_modes_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let (path, secondary_path) = (dir.join("primary"), dir.join("secondary"));
+
+        let primary = RocksStorage::open(&path).unwrap();
+        primary.put(Column::Agent, b"a", b"1").unwrap();
+        primary.flush().unwrap();
+
+        let read_only = RocksStorage::open_with_mode(&path, &OpenMode::ReadOnly).unwrap();
+        assert_eq!(
+            read_only.get(Column::Agent, b"a").unwrap(),
+            Some(b"1".to_vec())
+        );
+        assert!(matches!(
+            read_only.put(Column::Agent, b"a", b"2"),
+            Err(DbError::ReadOnly)
+        ));
+
+        let secondary = RocksStorage::open_with_mode(
+            &path,
+            &OpenMode::Secondary {
+                secondary_path: secondary_path.clone(),
+            },
+        )
+        .unwrap();
+        primary.put(Column::Agent, b"b", b"2").unwrap();
+        secondary.catch_up().unwrap();
+        assert_eq!(
+            secondary.get(Column::Agent, b"b").unwrap(),
+            Some(b"2".to_vec())
+        );
+
+        drop((primary, read_only, secondary));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+use super::Result;
+use std::{fmt::Debug, path::Path};
+
+/// Key-value pairs yielded by a prefix scan
+pub type KvIterator<'a> = Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a>;
+
+/// Column families data is split across. Backends supporting them store
+/// and tune each column separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Column {
+    /// Db-wide data (e.g. the schema version)
+    Default,
+    /// Agent bookkeeping (e.g. sync cursors, latest roots, nonces)
+    Agent,
+    /// Raw committed messages by leaf
+    Messages,
+    /// Leaves by leaf index and by destination and nonce
+    Leaves,
+    /// Merkle proofs by leaf index
+    Proofs,
+    /// Signed updates and their metadata
+    Updates,
+    /// Optional secondary indexes of messages (e.g. by sender)
+    Indexes,
+}
+
+impl Column {
+    /// Every column
+    pub const ALL: [Column; 7] = [
+        Column::Default,
+        Column::Agent,
+        Column::Messages,
+        Column::Leaves,
+        Column::Proofs,
+        Column::Updates,
+        Column::Indexes,
+    ];
+
+    /// Name of the column family
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Default => "default",
+            Column::Agent => "agent",
+            Column::Messages => "messages",
+            Column::Leaves => "leaves",
+            Column::Proofs => "proofs",
+            Column::Updates => "updates",
+            Column::Indexes => "indexes",
+        }
+    }
+}
+
+/// A single write in a [`WriteBatch`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOp {
+    /// Store `value` under `key`
+    Put {
+        /// Column
+        column: Column,
+        /// Key
+        key: Vec<u8>,
+        /// Value
+        value: Vec<u8>,
+    },
+    /// Delete the value under `key`
+    Delete {
+        /// Column
+        column: Column,
+        /// Key
+        key: Vec<u8>,
+    },
+}
+
+/// Writes applied atomically by a [`Storage`] backend
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+}
+
+impl WriteBatch {
+    /// Add a put to the batch
+    pub fn put(&mut self, column: Column, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+        self.ops.push(BatchOp::Put {
+            column,
+            key: key.as_ref().to_vec(),
+            value: value.as_ref().to_vec(),
+        });
+    }
+
+    /// Add a delete to the batch
+    pub fn delete(&mut self, column: Column, key: impl AsRef<[u8]>) {
+        self.ops.push(BatchOp::Delete {
+            column,
+            key: key.as_ref().to_vec(),
+        });
+    }
+
+    /// Number of writes in the batch
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Whether the batch has no writes
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Writes in the order they were added
+    pub fn ops(&self) -> &[BatchOp] {
+        &self.ops
+    }
+}
+
+impl IntoIterator for WriteBatch {
+    type Item = BatchOp;
+    type IntoIter = std::vec::IntoIter<BatchOp>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ops.into_iter()
+    }
+}
+
+/// A key-value storage backend for [`DB`](super::DB)
+pub trait Storage: Debug + Send + Sync {
+    /// Retrieve the value under `key` in `column`
+    fn get(&self, column: Column, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    /// Store `value` under `key` in `column`
+    fn put(&self, column: Column, key: &[u8], value: &[u8]) -> Result<()>;
+
+    /// Delete the value under `key` in `column`
+    fn delete(&self, column: Column, key: &[u8]) -> Result<()>;
+
+    /// Apply all writes in `batch` atomically
+    fn write(&self, batch: WriteBatch) -> Result<()>;
+
+    /// Iterate over the key-value pairs in `column` whose key starts with
+    /// `prefix`, in key order, starting at the first key at or after `from`.
+    /// `from` must start with `prefix`.
+    fn range_scan<'a>(&'a self, column: Column, prefix: &[u8], from: &[u8]) -> KvIterator<'a>;
+
+    /// Iterate over all key-value pairs in `column` whose key starts with
+    /// `prefix`, in key order
+    fn prefix_scan<'a>(&'a self, column: Column, prefix: &[u8]) -> KvIterator<'a> {
+        self.range_scan(column, prefix, prefix)
+    }
+
+    /// Estimated size of the data in `column`, in bytes
+    fn size(&self, column: Column) -> Result<u64>;
+
+    /// Persist buffered writes
+    fn flush(&self) -> Result<()>;
+
+    /// Write a consistent snapshot of all columns to a new RocksDB database
+    /// at `path`, which must not exist yet
+    fn checkpoint(&self, path: &Path) -> Result<()>;
+
+    /// Catch up with writes made to the underlying database by another
+    /// process (e.g. for RocksDB secondary instances). No-op for backends
+    /// which always see the latest writes.
+    fn catch_up(&self) -> Result<()>;
+}
@@ -1,12 +1,19 @@
 use crate::{
-    db::{DbError, DB},
+    db::{
+        iterator::{encode_bound, PrefixIterator},
+        Column, DbError, DB,
+    },
     Decode, Encode,
 };
 use color_eyre::Result;
+use std::ops::{Bound, RangeBounds};
 
 /// DB handle for storing data tied to a specific type/entity.
 ///
 /// Key structure: ```<type_prefix>_<additional_prefix(es)>_<key>```
+///
+/// Data is kept in `Column::Agent` unless another column is selected with
+/// `column`.
 #[derive(Debug, Clone)]
 pub struct TypedDB {
     entity: String,
@@ -22,7 +29,18 @@ impl AsRef<DB> for TypedDB {
 impl TypedDB {
     /// Instantiate new `TypedDB`
     pub fn new(entity: String, db: DB) -> Self {
-        Self { entity, db }
+        Self {
+            entity,
+            db: db.column(Column::Agent),
+        }
+    }
+
+    /// Get a handle to the same entity's data in `column`
+    pub fn column(&self, column: Column) -> Self {
+        Self {
+            entity: self.entity.clone(),
+            db: self.db.column(column),
+        }
     }
 
     fn full_prefix(&self, prefix: impl AsRef<[u8]>) -> Vec<u8> {
@@ -33,6 +51,21 @@ impl TypedDB {
         full_prefix
     }
 
+    /// Run `f` against a view of the DB whose writes are committed as one
+    /// atomic write batch once `f` returns. See [`DB::write_batch`].
+    pub fn write_batch<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(&TypedDB) -> Result<T, E>,
+        E: From<DbError>,
+    {
+        self.db.write_batch(|db| {
+            f(&TypedDB {
+                entity: self.entity.clone(),
+                db: db.clone(),
+            })
+        })
+    }
+
     /// Store encodable value
     pub fn store_encodable<V: Encode>(
         &self,
@@ -73,4 +106,47 @@ impl TypedDB {
         self.db
             .retrieve_keyed_decodable(self.full_prefix(prefix), key)
     }
+
+    /// Delete value
+    pub fn delete_value(
+        &self,
+        prefix: impl AsRef<[u8]>,
+        key: impl AsRef<[u8]>,
+    ) -> Result<(), DbError> {
+        self.db.delete_value(self.full_prefix(prefix), key)
+    }
+
+    /// Iterate over the kv pairs under `prefix` in `column` whose keys are
+    /// within `range`, in key order
+    pub fn column_range_iterator<K: Encode + Decode, V: Decode>(
+        &self,
+        column: Column,
+        prefix: impl AsRef<[u8]>,
+        range: impl RangeBounds<K>,
+    ) -> PrefixIterator<'_, K, V> {
+        let prefix = self.full_prefix(prefix);
+        let start = encode_bound(range.start_bound());
+        let from = match &start {
+            Bound::Included(key) | Bound::Excluded(key) => {
+                [prefix.as_slice(), key.as_slice()].concat()
+            }
+            Bound::Unbounded => prefix.clone(),
+        };
+
+        PrefixIterator::new(
+            self.db.column_range_iterator(column, &prefix, from),
+            prefix.len(),
+            start,
+            encode_bound(range.end_bound()),
+        )
+    }
+
+    /// Delete value given encodable key
+    pub fn delete_keyed_value<K: Encode>(
+        &self,
+        prefix: impl AsRef<[u8]>,
+        key: &K,
+    ) -> Result<(), DbError> {
+        self.db.delete_keyed_value(self.full_prefix(prefix), key)
+    }
 }
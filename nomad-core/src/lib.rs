@@ -8,6 +8,10 @@
 #![forbid(unsafe_code)]
 #![forbid(where_clauses_object_safety)]
 
+// Lets `#[derive(Encode, Decode)]` name this crate as `::nomad_core` from
+// within it
+extern crate self as nomad_core;
+
 /// Accumulator management
 pub mod accumulator;
 
@@ -17,10 +21,10 @@ pub mod db;
 /// Model instantatiations of the on-chain structures
 pub mod models {
     /// A simple Home chain Nomad implementation
-    mod home;
+    pub mod home;
 
     /// A simple Replica chain Nomad implementation
-    mod replica;
+    pub mod replica;
 
     pub use self::{home::*, replica::*};
 }
@@ -30,6 +34,9 @@ mod traits;
 use ethers_signers::WalletError;
 pub use traits::*;
 
+/// Derive `Encode` and `Decode` for structs of encodable fields
+pub use nomad_derive::{Decode, Encode};
+
 /// Utilities to match contract values
 pub mod utils;
 
@@ -40,6 +47,9 @@ pub mod test_utils;
 mod types;
 pub use types::*;
 
+/// Typed bodies of token bridge and governance messages
+pub mod xapp;
+
 /// Test functions that output json files for Solidity tests
 #[cfg(feature = "output")]
 pub mod test_output;
@@ -93,6 +103,31 @@ pub enum NomadError {
     /// IO error from Read/Write usage
     #[error(transparent)]
     IoError(#[from] std::io::Error),
+    /// Message body has a type tag this build does not know
+    #[error("Unknown message type: {0}")]
+    UnknownMessageType(u8),
+    /// Message is in a format version this build cannot decode
+    #[error("Unsupported message version: {0}")]
+    UnsupportedMessageVersion(u8),
+    /// Message body is larger than the Home accepts
+    #[error("Message body of {size} bytes exceeds the maximum of {max} bytes")]
+    MessageBodyTooLarge {
+        /// The body's size. Bodies decoded from untrusted data are read up
+        /// to one byte past the maximum, so this is a lower bound for them.
+        size: usize,
+        /// The maximum body size
+        max: usize,
+    },
+    /// Trailing field of a derived `Decode` is longer than its `max_len`
+    #[error("Encoded {field} of more than {max} bytes")]
+    EncodedFieldTooLong {
+        /// The field's name
+        field: &'static str,
+        /// Bytes read, up to one past the maximum
+        size: usize,
+        /// The maximum length
+        max: usize,
+    },
 }
 
 /// Error types for Signers
@@ -1,9 +1,9 @@
 use ethers::core::types::{Address, H256};
-use std::{collections::VecDeque, io::Write};
+use std::collections::{HashMap, VecDeque};
 
 use crate::{
-    accumulator::{hash, incremental::IncrementalMerkle},
-    NomadError, SignedUpdate, Update,
+    accumulator::incremental::IncrementalMerkle, Encode, MessageVersion, NomadError, NomadMessage,
+    RawCommittedMessage, SignedUpdate, Update,
 };
 
 /// Waiting state
@@ -44,28 +44,13 @@ impl Failed {
     }
 }
 
-fn format_message(
-    origin: u32,
-    sender: H256,
-    destination: u32,
-    recipient: H256,
-    body: &[u8],
-) -> Vec<u8> {
-    let mut buf = vec![];
-    buf.write_all(&origin.to_be_bytes()).unwrap();
-    buf.write_all(sender.as_ref()).unwrap();
-    buf.write_all(&destination.to_be_bytes()).unwrap();
-    buf.write_all(recipient.as_ref()).unwrap();
-    buf.write_all(body).unwrap();
-    buf
-}
-
 /// The Home-chain Nomad object
 #[derive(Debug, Clone)]
 pub struct Home<S> {
     local: u32,
     updater: Address,
     committed_root: H256,
+    nonces: HashMap<u32, u32>,
     state: S,
 }
 
@@ -80,6 +65,16 @@ impl<S> Home<S> {
         self.updater
     }
 
+    /// The last root accepted by an update
+    pub fn committed_root(&self) -> H256 {
+        self.committed_root
+    }
+
+    /// The nonce of the next message to `destination`
+    pub fn nonces(&self, destination: u32) -> u32 {
+        self.nonces.get(&destination).copied().unwrap_or_default()
+    }
+
     /// Current state
     pub fn state(&self) -> &S {
         &self.state
@@ -96,6 +91,7 @@ impl From<Home<Waiting>> for Home<Failed> {
             local: h.local,
             updater: h.updater,
             committed_root: h.committed_root,
+            nonces: h.nonces,
             state: Failed {
                 accumulator: h.state.accumulator,
                 queue: h.state.queue,
@@ -116,16 +112,42 @@ impl Home<Waiting> {
             local,
             updater,
             committed_root: Default::default(),
+            nonces: Default::default(),
             state: Waiting::default(),
         }
     }
 
-    /// Dispatch a message
-    pub fn dispatch(&mut self, sender: H256, destination: u32, recipient: H256, body: &[u8]) {
-        let message = format_message(self.local, sender, destination, recipient, body);
-        let message_hash = hash(&message);
-        self.state.accumulator.ingest(message_hash);
+    /// Dispatch a message. Returns the message as it is committed to the
+    /// tree, mirroring the contract's `Dispatch` event.
+    pub fn dispatch(
+        &mut self,
+        sender: H256,
+        destination: u32,
+        recipient: H256,
+        body: &[u8],
+    ) -> RawCommittedMessage {
+        let nonce = self.nonces.entry(destination).or_default();
+        let message = NomadMessage {
+            version: MessageVersion::LATEST,
+            origin: self.local,
+            sender,
+            nonce: *nonce,
+            destination,
+            recipient,
+            body: body.to_vec(),
+        };
+        *nonce += 1;
+
+        let committed_root = self.committed_root;
+        self.state.accumulator.ingest(message.to_leaf());
         self.state.queue.push_back(self.state.accumulator.root());
+
+        RawCommittedMessage {
+            leaf_index: self.state.accumulator.count() as u32 - 1,
+            committed_root,
+            version: message.version,
+            message: message.to_vec(),
+        }
     }
 
     fn _update(&mut self, update: &Update) -> Result<(), NomadError> {
@@ -140,6 +162,7 @@ impl Home<Waiting> {
             loop {
                 let item = self.state.queue.pop_front().unwrap();
                 if item == update.new_root {
+                    self.committed_root = item;
                     return Ok(());
                 }
             }
@@ -149,6 +172,8 @@ impl Home<Waiting> {
     }
 
     /// Produce an update from the current root to the new root.
+    ///
+    /// Note that this does not check whether any messages are queued.
     pub fn produce_update(&self) -> Update {
         Update {
             home_domain: self.local,
@@ -8,7 +8,6 @@ pub struct Waiting {
 }
 
 /// Pending update state
-#[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub struct Pending {
     root: H256,
@@ -128,4 +127,14 @@ impl Replica<Pending> {
     pub fn root(&self) -> H256 {
         self.state().root
     }
+
+    /// Get the root of the pending update
+    pub fn new_root(&self) -> H256 {
+        self.state().new_root
+    }
+
+    /// Get the time at which the pending update may be confirmed
+    pub fn timeout(&self) -> U256 {
+        self.state().timeout
+    }
 }
@@ -36,6 +36,7 @@ pub mod output_functions {
                 H160::from_str("0x2222222222222222222222222222222222222222").unwrap(),
             ),
             body: Vec::from_hex("1234").unwrap(),
+            ..Default::default()
         };
 
         let message_json = json!({
@@ -1,5 +1,5 @@
 use crate::NomadError;
-use ethers::prelude::{Signature, SignatureError, H256};
+use ethers::prelude::{Signature, H256, U256};
 use std::convert::TryFrom;
 
 /// Simple trait for types with a canonical encoding
@@ -42,12 +42,8 @@ impl Decode for Signature {
         R: std::io::Read,
     {
         let mut buf = [0u8; 65];
-        let len = reader.read(&mut buf)?;
-        if len != 65 {
-            Err(SignatureError::InvalidLength(len).into())
-        } else {
-            Ok(Self::try_from(buf.as_ref())?)
-        }
+        reader.read_exact(&mut buf)?;
+        Ok(Self::try_from(buf.as_ref())?)
     }
 }
 
@@ -95,6 +91,28 @@ impl Decode for u32 {
     }
 }
 
+impl Encode for bool {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        writer.write_all(&[*self as u8])?;
+        Ok(1)
+    }
+}
+
+impl Decode for bool {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+        Self: Sized,
+    {
+        let mut buf = [0; 1];
+        reader.read_exact(&mut buf)?;
+        Ok(buf[0] != 0)
+    }
+}
+
 impl Encode for u64 {
     fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
     where
@@ -116,3 +134,195 @@ impl Decode for u64 {
         Ok(u64::from_be_bytes(buf))
     }
 }
+
+impl Encode for U256 {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        let mut buf = [0; 32];
+        self.to_big_endian(&mut buf);
+        writer.write_all(&buf)?;
+        Ok(32)
+    }
+}
+
+impl Decode for U256 {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+        Self: Sized,
+    {
+        let mut buf = [0; 32];
+        reader.read_exact(&mut buf)?;
+        Ok(U256::from_big_endian(&buf))
+    }
+}
+
+/// Encoded as a `u64`, independent of the platform's pointer width
+impl Encode for usize {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        (*self as u64).write_to(writer)
+    }
+}
+
+impl Decode for usize {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+        Self: Sized,
+    {
+        Ok(u64::read_from(reader)? as usize)
+    }
+}
+
+/// Encoded as its elements in order, without a length prefix
+impl<T, const N: usize> Encode for [T; N]
+where
+    T: Encode,
+{
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        let mut written = 0;
+        for item in self.iter() {
+            written += item.write_to(writer)?;
+        }
+        Ok(written)
+    }
+}
+
+impl<T, const N: usize> Decode for [T; N]
+where
+    T: Decode + Default + Copy,
+{
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+        Self: Sized,
+    {
+        let mut items = [T::default(); N];
+        for item in items.iter_mut() {
+            *item = T::read_from(reader)?;
+        }
+        Ok(items)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        accumulator::merkle::Proof, MessageVersion, NomadIdentifier, RawCommittedMessage,
+        SignedUpdate, Update, MAX_MESSAGE_BODY_BYTES,
+    };
+
+    fn roundtrip<T>(value: &T, len: usize)
+    where
+        T: Encode + Decode + PartialEq + std::fmt::Debug,
+    {
+        let bytes = value.to_vec();
+        assert_eq!(bytes.len(), len);
+        assert_eq!(value.write_to(&mut vec![]).unwrap(), len);
+        assert_eq!(T::read_from(&mut bytes.as_slice()).unwrap(), *value);
+    }
+
+    #[test]
+    fn it_roundtrips_derived_types() {
+        let update = Update {
+            home_domain: 1000,
+            previous_root: H256::repeat_byte(1),
+            new_root: H256::repeat_byte(2),
+        };
+        roundtrip(&update, 4 + 32 + 32);
+        assert_eq!(&update.to_vec()[..4], &1000u32.to_be_bytes());
+
+        let signed_update = SignedUpdate {
+            update,
+            signature: Signature {
+                r: 3.into(),
+                s: 4.into(),
+                v: 27,
+            },
+        };
+        roundtrip(&signed_update, 68 + 65);
+
+        roundtrip(&NomadIdentifier::from(H256::repeat_byte(5)), 32);
+
+        let mut path = [H256::zero(); 32];
+        path[31] = H256::repeat_byte(6);
+        roundtrip(
+            &Proof {
+                leaf: H256::repeat_byte(7),
+                index: 8,
+                path,
+            },
+            32 + 8 + 32 * 32,
+        );
+
+        let raw = RawCommittedMessage {
+            leaf_index: 9,
+            committed_root: H256::repeat_byte(10),
+            version: MessageVersion::V0,
+            message: vec![11; 100],
+        };
+        roundtrip(&raw, 4 + 32 + 1 + 100);
+    }
+
+    #[test]
+    fn it_bounds_trailing_fields() {
+        #[derive(Debug, PartialEq, Encode, Decode)]
+        struct Unbounded(u32, #[encode(trailing)] Vec<u8>);
+        roundtrip(&Unbounded(1, vec![2; 10_000]), 4 + 10_000);
+
+        let max = MessageVersion::V0.prefix_len() + MAX_MESSAGE_BODY_BYTES;
+        let raw = RawCommittedMessage {
+            message: vec![1; max],
+            ..Default::default()
+        };
+        roundtrip(&raw, 37 + max);
+
+        // Reported like oversized message bodies
+        let mut too_long = raw.to_vec();
+        too_long.push(1);
+        assert!(matches!(
+            RawCommittedMessage::read_from(&mut too_long.as_slice()),
+            Err(NomadError::MessageBodyTooLarge { max: m, .. }) if m == MAX_MESSAGE_BODY_BYTES
+        ));
+
+        #[derive(Debug, PartialEq, Encode, Decode)]
+        struct Bounded {
+            leaf_index: u32,
+            #[encode(trailing, max_len = 8)]
+            message: Vec<u8>,
+        }
+        let bounded = Bounded {
+            leaf_index: 1,
+            message: vec![2; 8],
+        };
+        roundtrip(&bounded, 4 + 8);
+
+        let mut too_long = bounded.to_vec();
+        too_long.push(2);
+        assert!(matches!(
+            Bounded::read_from(&mut too_long.as_slice()),
+            Err(NomadError::EncodedFieldTooLong {
+                field: "message",
+                max: 8,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn it_errors_on_short_signatures() {
+        assert!(matches!(
+            Signature::read_from(&mut [0u8; 64].as_ref()),
+            Err(NomadError::IoError(_))
+        ));
+    }
+}
@@ -1,71 +1,89 @@
-use std::convert::TryFrom;
+use std::{convert::TryFrom, io::Read};
 
 use crate::{
     db::DbError,
     traits::{ChainCommunicationError, Common, TxOutcome},
     utils::home_domain_hash,
-    Decode, Encode, Message, NomadError, NomadMessage, SignedUpdate, Update,
+    Decode, Encode, Message, MessageVersion, NomadError, NomadMessage, NomadMessageRef,
+    SignedUpdate, TxMeta, Update, MAX_MESSAGE_BODY_BYTES,
 };
 use async_trait::async_trait;
 use color_eyre::Result;
 use ethers::{core::types::H256, utils::keccak256};
 
 /// A Stamped message that has been committed at some leaf index
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq, Encode)]
 pub struct RawCommittedMessage {
     /// The index at which the message is committed
     pub leaf_index: u32,
     /// The home's current root when the message was committed.
     pub committed_root: H256,
-    /// The fully detailed message that was committed
+    /// Format of the message, the `VERSION()` of the home when it was
+    /// dispatched
+    pub version: MessageVersion,
+    /// The fully detailed message that was committed. Bounded like the body
+    /// of the message it holds.
+    #[encode(trailing)]
     pub message: Vec<u8>,
 }
 
-impl RawCommittedMessage {
-    /// Return the `leaf` for this raw message
-    ///
-    /// The leaf is the keccak256 digest of the message, which is committed
-    /// in the message tree
-    pub fn leaf(&self) -> H256 {
-        keccak256(&self.message).into()
-    }
-}
-
-impl Encode for RawCommittedMessage {
-    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
-    where
-        W: std::io::Write,
-    {
-        writer.write_all(&self.leaf_index.to_be_bytes())?;
-        writer.write_all(self.committed_root.as_ref())?;
-        writer.write_all(&self.message)?;
-        Ok(4 + 32 + self.message.len())
-    }
-}
-
+// Not derived, so oversized messages error like oversized `NomadMessage`
+// bodies do
 impl Decode for RawCommittedMessage {
     fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
     where
         R: std::io::Read,
         Self: Sized,
     {
-        let mut idx = [0u8; 4];
-        reader.read_exact(&mut idx)?;
-
-        let mut hash = [0u8; 32];
-        reader.read_exact(&mut hash)?;
+        let leaf_index = u32::read_from(reader)?;
+        let committed_root = H256::read_from(reader)?;
+        let version = MessageVersion::read_from(reader)?;
 
+        let max = version.prefix_len() + MAX_MESSAGE_BODY_BYTES;
         let mut message = vec![];
-        reader.read_to_end(&mut message)?;
+        reader.take(max as u64 + 1).read_to_end(&mut message)?;
+        if message.len() > max {
+            return Err(NomadError::MessageBodyTooLarge {
+                size: message.len() - version.prefix_len(),
+                max: MAX_MESSAGE_BODY_BYTES,
+            });
+        }
 
         Ok(Self {
-            leaf_index: u32::from_be_bytes(idx),
-            committed_root: hash.into(),
+            leaf_index,
+            committed_root,
+            version,
             message,
         })
     }
 }
 
+impl RawCommittedMessage {
+    /// Return the `leaf` for this raw message
+    ///
+    /// The leaf is the keccak256 digest of the message, which is committed
+    /// in the message tree
+    pub fn leaf(&self) -> H256 {
+        keccak256(&self.message).into()
+    }
+
+    /// View the committed message without decoding it into an owned
+    /// `NomadMessage`
+    pub fn message_ref(&self) -> Result<NomadMessageRef<'_>, NomadError> {
+        NomadMessageRef::new(&self.message, self.version)
+    }
+}
+
+/// A raw committed message with the metadata of the transaction it was
+/// dispatched in
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RawCommittedMessageWithMeta {
+    /// Raw committed message
+    pub raw_message: RawCommittedMessage,
+    /// Metadata
+    pub metadata: TxMeta,
+}
+
 // ember: tracingify these across usage points
 /// A Stamped message that has been committed at some leaf index
 #[derive(Debug, Default, Clone)]
@@ -98,7 +116,7 @@ impl TryFrom<RawCommittedMessage> for CommittedMessage {
         Ok(Self {
             leaf_index: raw.leaf_index,
             committed_root: raw.committed_root,
-            message: NomadMessage::read_from(&mut &raw.message[..])?,
+            message: NomadMessage::read_versioned(raw.version, &mut &raw.message[..])?,
         })
     }
 }
@@ -118,7 +136,17 @@ pub trait Home: Common + Send + Sync + std::fmt::Debug {
     /// Fetch the nonce
     async fn nonces(&self, destination: u32) -> Result<u32, ChainCommunicationError>;
 
-    /// Dispatch a message.
+    /// Fetch the home's `VERSION()`, the format of the messages it
+    /// dispatches. Errors with `NomadError::UnsupportedMessageVersion` if
+    /// this build cannot decode them.
+    async fn version(&self) -> Result<MessageVersion, ChainCommunicationError>;
+
+    /// Fetch the maximum message body size the home accepts
+    async fn max_message_body_bytes(&self) -> Result<usize, ChainCommunicationError>;
+
+    /// Dispatch a message. Errors with `NomadError::MessageBodyTooLarge`
+    /// without submitting a transaction if the body exceeds
+    /// `max_message_body_bytes`.
     async fn dispatch(&self, message: &Message) -> Result<TxOutcome, ChainCommunicationError>;
 
     /// Check if queue contains root.
@@ -9,8 +9,9 @@
 
 use async_trait::async_trait;
 use color_eyre::Result;
+use ethers::core::types::H256;
 
-use crate::{RawCommittedMessage, SignedUpdateWithMeta};
+use crate::{RawCommittedMessageWithMeta, SignedUpdateWithMeta};
 
 /// Interface for Common contract indexer. Interface that allows for other
 /// entities to retrieve chain-specific data from a home or replica.
@@ -19,6 +20,10 @@ pub trait CommonIndexer: Send + Sync + std::fmt::Debug {
     /// Get chain's latest block number
     async fn get_block_number(&self) -> Result<u32>;
 
+    /// Get the hash of the block at `height`, or `None` if the chain has no
+    /// block at that height
+    async fn get_block_hash(&self, height: u32) -> Result<Option<H256>>;
+
     /// Fetch sequentially sorted list of updates between blocks `from` and `to`
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>>;
 }
@@ -28,6 +33,9 @@ pub trait CommonIndexer: Send + Sync + std::fmt::Debug {
 #[async_trait]
 pub trait HomeIndexer: CommonIndexer + Send + Sync + std::fmt::Debug {
     /// Fetch list of messages between blocks `from` and `to`.
-    async fn fetch_sorted_messages(&self, _from: u32, _to: u32)
-        -> Result<Vec<RawCommittedMessage>>;
+    async fn fetch_sorted_messages(
+        &self,
+        _from: u32,
+        _to: u32,
+    ) -> Result<Vec<RawCommittedMessageWithMeta>>;
 }
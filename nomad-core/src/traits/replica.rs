@@ -9,6 +9,7 @@ use crate::{
 };
 
 /// The status of a message in the replica
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum MessageStatus {
     /// Message is unknown
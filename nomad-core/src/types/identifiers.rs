@@ -6,7 +6,9 @@ use crate::{Decode, Encode};
 ///
 /// Normally these will map to address types for different networks. For Nomad,
 /// we choose to _always_ serialize as 32 bytes
-#[derive(Debug, Default, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize, Encode, Decode,
+)]
 pub struct NomadIdentifier(H256);
 
 impl NomadIdentifier {
@@ -59,22 +61,3 @@ impl From<NomadIdentifier> for [u8; 32] {
         addr.0.into()
     }
 }
-
-impl Encode for NomadIdentifier {
-    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
-    where
-        W: std::io::Write,
-    {
-        self.0.write_to(writer)
-    }
-}
-
-impl Decode for NomadIdentifier {
-    fn read_from<R>(reader: &mut R) -> Result<Self, crate::NomadError>
-    where
-        R: std::io::Read,
-        Self: Sized,
-    {
-        Ok(NomadIdentifier(H256::read_from(reader)?))
-    }
-}
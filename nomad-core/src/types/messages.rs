@@ -1,12 +1,117 @@
 use ethers::{types::H256, utils::keccak256};
+use std::{convert::TryFrom, io::Read};
 
 use crate::{utils, Decode, Encode, NomadError};
 
-const NOMAD_MESSAGE_PREFIX_LEN: usize = 76;
+/// Length of the prefix of `MessageVersion::V0` messages
+const V0_MESSAGE_PREFIX_LEN: usize = 76;
+
+/// Format of an encoded message. Messages do not carry their version: it is
+/// the `VERSION()` of the Home that dispatched them, recorded alongside each
+/// message when it is indexed. Only versions this build can encode and
+/// decode can be constructed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageVersion {
+    /// `Message.formatMessage` of Homes whose `VERSION()` is 0:
+    /// `origin | sender | nonce | destination | recipient | body`
+    V0,
+}
+
+impl MessageVersion {
+    /// Latest message format version this build can encode and decode
+    pub const LATEST: Self = Self::V0;
+
+    /// Length of the fixed prefix preceding the body of messages in this
+    /// format
+    pub fn prefix_len(self) -> usize {
+        match self {
+            Self::V0 => V0_MESSAGE_PREFIX_LEN,
+        }
+    }
+}
+
+impl Default for MessageVersion {
+    fn default() -> Self {
+        Self::LATEST
+    }
+}
+
+impl TryFrom<u8> for MessageVersion {
+    type Error = NomadError;
+
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            0 => Ok(Self::V0),
+            version => Err(NomadError::UnsupportedMessageVersion(version)),
+        }
+    }
+}
+
+impl From<MessageVersion> for u8 {
+    fn from(version: MessageVersion) -> Self {
+        match version {
+            MessageVersion::V0 => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for MessageVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", u8::from(*self))
+    }
+}
+
+impl Encode for MessageVersion {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        writer.write_all(&[u8::from(*self)])?;
+        Ok(1)
+    }
+}
+
+impl Decode for MessageVersion {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+    {
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version)?;
+        Self::try_from(version[0])
+    }
+}
+
+/// Maximum message body size the Home contract accepts
+/// (`MAX_MESSAGE_BODY_BYTES`). Decoding stops at bodies larger than this.
+pub const MAX_MESSAGE_BODY_BYTES: usize = 2 * 1024;
+
+/// Read the rest of `reader` as a message body, erroring once it runs past
+/// `MAX_MESSAGE_BODY_BYTES`
+fn read_body<R: std::io::Read>(reader: &mut R) -> Result<Vec<u8>, NomadError> {
+    let mut body = vec![];
+    reader
+        .take(MAX_MESSAGE_BODY_BYTES as u64 + 1)
+        .read_to_end(&mut body)?;
+    check_body_size(&body, MAX_MESSAGE_BODY_BYTES)?;
+    Ok(body)
+}
+
+fn check_body_size(body: &[u8], max: usize) -> Result<(), NomadError> {
+    if body.len() > max {
+        return Err(NomadError::MessageBodyTooLarge {
+            size: body.len(),
+            max,
+        });
+    }
+    Ok(())
+}
 
 /// A full Nomad message between chains
 #[derive(Debug, Default, Clone)]
 pub struct NomadMessage {
+    /// 0   Format version. Not encoded, see [`MessageVersion`].
+    pub version: MessageVersion,
     /// 4   SLIP-44 ID
     pub origin: u32,
     /// 32  Address in home convention
@@ -37,51 +142,59 @@ impl Encode for NomadMessage {
     where
         W: std::io::Write,
     {
-        writer.write_all(&self.origin.to_be_bytes())?;
-        writer.write_all(self.sender.as_ref())?;
-        writer.write_all(&self.nonce.to_be_bytes())?;
-        writer.write_all(&self.destination.to_be_bytes())?;
-        writer.write_all(self.recipient.as_ref())?;
+        match self.version {
+            MessageVersion::V0 => {
+                writer.write_all(&self.origin.to_be_bytes())?;
+                writer.write_all(self.sender.as_ref())?;
+                writer.write_all(&self.nonce.to_be_bytes())?;
+                writer.write_all(&self.destination.to_be_bytes())?;
+                writer.write_all(self.recipient.as_ref())?;
+            }
+        }
         writer.write_all(&self.body)?;
-        Ok(NOMAD_MESSAGE_PREFIX_LEN + self.body.len())
+        Ok(self.version.prefix_len() + self.body.len())
     }
 }
 
-impl Decode for NomadMessage {
-    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+impl NomadMessage {
+    /// Decode a message in the format of `version`. Messages do not carry
+    /// their version, so it must come from the Home that dispatched them.
+    pub fn read_versioned<R>(version: MessageVersion, reader: &mut R) -> Result<Self, NomadError>
     where
         R: std::io::Read,
     {
-        let mut origin = [0u8; 4];
-        reader.read_exact(&mut origin)?;
+        match version {
+            MessageVersion::V0 => {
+                let mut origin = [0u8; 4];
+                reader.read_exact(&mut origin)?;
 
-        let mut sender = H256::zero();
-        reader.read_exact(sender.as_mut())?;
+                let mut sender = H256::zero();
+                reader.read_exact(sender.as_mut())?;
 
-        let mut nonce = [0u8; 4];
-        reader.read_exact(&mut nonce)?;
+                let mut nonce = [0u8; 4];
+                reader.read_exact(&mut nonce)?;
 
-        let mut destination = [0u8; 4];
-        reader.read_exact(&mut destination)?;
+                let mut destination = [0u8; 4];
+                reader.read_exact(&mut destination)?;
 
-        let mut recipient = H256::zero();
-        reader.read_exact(recipient.as_mut())?;
+                let mut recipient = H256::zero();
+                reader.read_exact(recipient.as_mut())?;
 
-        let mut body = vec![];
-        reader.read_to_end(&mut body)?;
+                let body = read_body(reader)?;
 
-        Ok(Self {
-            origin: u32::from_be_bytes(origin),
-            sender,
-            destination: u32::from_be_bytes(destination),
-            recipient,
-            nonce: u32::from_be_bytes(nonce),
-            body,
-        })
+                Ok(Self {
+                    version,
+                    origin: u32::from_be_bytes(origin),
+                    sender,
+                    destination: u32::from_be_bytes(destination),
+                    recipient,
+                    nonce: u32::from_be_bytes(nonce),
+                    body,
+                })
+            }
+        }
     }
-}
 
-impl NomadMessage {
     /// Convert the message to a leaf
     pub fn to_leaf(&self) -> H256 {
         keccak256(self.to_vec()).into()
@@ -93,6 +206,127 @@ impl NomadMessage {
     }
 }
 
+/// A borrowed view of an encoded [`NomadMessage`]. Fields are read from the
+/// encoded bytes on access, so viewing a message copies nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NomadMessageRef<'a> {
+    bytes: &'a [u8],
+    version: MessageVersion,
+}
+
+impl<'a> NomadMessageRef<'a> {
+    /// View `bytes` as a message encoded in the format of `version`. Errors
+    /// under the same conditions decoding a `NomadMessage` from them would.
+    pub fn new(bytes: &'a [u8], version: MessageVersion) -> Result<Self, NomadError> {
+        if bytes.len() < version.prefix_len() {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        check_body_size(&bytes[version.prefix_len()..], MAX_MESSAGE_BODY_BYTES)?;
+        Ok(Self { bytes, version })
+    }
+
+    fn field(&self, start: usize, len: usize) -> &'a [u8] {
+        &self.bytes[start..start + len]
+    }
+
+    fn u32_field(&self, start: usize) -> u32 {
+        u32::from_be_bytes(self.field(start, 4).try_into().expect("!len"))
+    }
+
+    /// Format version
+    pub fn version(&self) -> MessageVersion {
+        self.version
+    }
+
+    /// Origin domain
+    pub fn origin(&self) -> u32 {
+        match self.version {
+            MessageVersion::V0 => self.u32_field(0),
+        }
+    }
+
+    /// Sender, in home convention
+    pub fn sender(&self) -> H256 {
+        match self.version {
+            MessageVersion::V0 => H256::from_slice(self.field(4, 32)),
+        }
+    }
+
+    /// Count of all previous messages to the destination
+    pub fn nonce(&self) -> u32 {
+        match self.version {
+            MessageVersion::V0 => self.u32_field(36),
+        }
+    }
+
+    /// Destination domain
+    pub fn destination(&self) -> u32 {
+        match self.version {
+            MessageVersion::V0 => self.u32_field(40),
+        }
+    }
+
+    /// Recipient, in destination convention
+    pub fn recipient(&self) -> H256 {
+        match self.version {
+            MessageVersion::V0 => H256::from_slice(self.field(44, 32)),
+        }
+    }
+
+    /// Message contents
+    pub fn body(&self) -> &'a [u8] {
+        &self.bytes[self.version.prefix_len()..]
+    }
+
+    /// The encoded message
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Get the encoded destination + nonce
+    pub fn destination_and_nonce(&self) -> u64 {
+        utils::destination_and_nonce(self.destination(), self.nonce())
+    }
+
+    /// Convert the message to a leaf, without re-encoding it
+    pub fn to_leaf(&self) -> H256 {
+        keccak256(self.bytes).into()
+    }
+
+    /// Copy the viewed message into an owned `NomadMessage`
+    pub fn to_message(&self) -> NomadMessage {
+        NomadMessage {
+            version: self.version,
+            origin: self.origin(),
+            sender: self.sender(),
+            nonce: self.nonce(),
+            destination: self.destination(),
+            recipient: self.recipient(),
+            body: self.body().to_vec(),
+        }
+    }
+}
+
+impl std::fmt::Display for NomadMessageRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "NomadMessage {}->{}:{}",
+            self.origin(),
+            self.destination(),
+            self.nonce(),
+        )
+    }
+}
+
+impl Message {
+    /// Check the body against the Home's maximum body size (see
+    /// [`crate::Home::max_message_body_bytes`]) before dispatching
+    pub fn check_body_size(&self, max_message_body_bytes: usize) -> Result<(), NomadError> {
+        check_body_size(&self.body, max_message_body_bytes)
+    }
+}
+
 impl std::fmt::Display for NomadMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -102,3 +336,100 @@ impl std::fmt::Display for NomadMessage {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_decodes_messages_by_version() {
+        let message = NomadMessage {
+            origin: 1000,
+            sender: H256::repeat_byte(1),
+            nonce: 3,
+            destination: 2000,
+            recipient: H256::repeat_byte(2),
+            body: vec![1, 2, 3],
+            ..Default::default()
+        };
+
+        // The version is not part of the encoding
+        let bytes = message.to_vec();
+        assert_eq!(bytes.len(), V0_MESSAGE_PREFIX_LEN + 3);
+        assert_eq!(&bytes[..4], &1000u32.to_be_bytes()[..]);
+
+        let decoded =
+            NomadMessage::read_versioned(MessageVersion::V0, &mut bytes.as_slice()).unwrap();
+        assert_eq!(decoded.version, MessageVersion::V0);
+        assert_eq!(decoded.origin, message.origin);
+        assert_eq!(decoded.body, message.body);
+        assert_eq!(decoded.to_vec(), bytes);
+
+        assert_eq!(
+            MessageVersion::try_from(u8::from(MessageVersion::LATEST)).unwrap(),
+            MessageVersion::LATEST
+        );
+        assert!(matches!(
+            MessageVersion::try_from(1u8),
+            Err(NomadError::UnsupportedMessageVersion(1))
+        ));
+        assert!(matches!(
+            MessageVersion::read_from(&mut &[1u8][..]),
+            Err(NomadError::UnsupportedMessageVersion(1))
+        ));
+    }
+
+    #[test]
+    fn it_views_encoded_messages() {
+        let message = NomadMessage {
+            origin: 1000,
+            sender: H256::repeat_byte(1),
+            nonce: 3,
+            destination: 2000,
+            recipient: H256::repeat_byte(2),
+            body: vec![1, 2, 3],
+            ..Default::default()
+        };
+        let bytes = message.to_vec();
+        let view = NomadMessageRef::new(&bytes, MessageVersion::V0).unwrap();
+
+        assert_eq!(view.version(), MessageVersion::V0);
+        assert_eq!(view.origin(), message.origin);
+        assert_eq!(view.sender(), message.sender);
+        assert_eq!(view.nonce(), message.nonce);
+        assert_eq!(view.destination(), message.destination);
+        assert_eq!(view.recipient(), message.recipient);
+        assert_eq!(view.body(), message.body.as_slice());
+        assert_eq!(view.to_leaf(), message.to_leaf());
+        assert_eq!(view.to_message().to_vec(), bytes);
+
+        assert!(
+            NomadMessageRef::new(&bytes[..V0_MESSAGE_PREFIX_LEN - 1], MessageVersion::V0).is_err()
+        );
+    }
+
+    #[test]
+    fn it_bounds_message_bodies() {
+        let mut message = NomadMessage {
+            body: vec![1; MAX_MESSAGE_BODY_BYTES],
+            ..Default::default()
+        };
+        let decoded =
+            NomadMessage::read_versioned(message.version, &mut message.to_vec().as_slice())
+                .unwrap();
+        assert_eq!(decoded.body, message.body);
+
+        message.body.push(1);
+        assert!(matches!(
+            NomadMessage::read_versioned(message.version, &mut message.to_vec().as_slice()),
+            Err(NomadError::MessageBodyTooLarge { max, .. }) if max == MAX_MESSAGE_BODY_BYTES
+        ));
+
+        let dispatch = Message {
+            body: message.body,
+            ..Default::default()
+        };
+        assert!(dispatch.check_body_size(MAX_MESSAGE_BODY_BYTES).is_err());
+        assert!(dispatch.check_body_size(MAX_MESSAGE_BODY_BYTES + 1).is_ok());
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{Decode, Encode, NomadError};
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+
+/// Metadata of the transaction and log an indexed event was emitted in
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TxMeta {
+    /// Block number
+    pub block_number: u64,
+    /// Timestamp seconds (optional because fetching timestamp is fallible)
+    pub timestamp: Option<u64>,
+    /// Transaction hash. Zero if indexed before transaction metadata was
+    /// recorded.
+    pub transaction_hash: H256,
+    /// Block hash. Zero if indexed before transaction metadata was recorded.
+    pub block_hash: H256,
+    /// Index of the log in the block
+    pub log_index: u64,
+}
+
+// Not derived: a missing timestamp is encoded as zero
+impl Encode for TxMeta {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        let mut written = 0;
+        written += self.block_number.write_to(writer)?;
+        written += self.timestamp.unwrap_or_default().write_to(writer)?;
+        written += self.transaction_hash.write_to(writer)?;
+        written += self.block_hash.write_to(writer)?;
+        written += self.log_index.write_to(writer)?;
+        Ok(written)
+    }
+}
+
+impl Decode for TxMeta {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+        Self: Sized,
+    {
+        let block_number = u64::read_from(reader)?;
+        let timestamp = match u64::read_from(reader)? {
+            0 => None,
+            timestamp => Some(timestamp),
+        };
+
+        Ok(Self {
+            block_number,
+            timestamp,
+            transaction_hash: H256::read_from(reader)?,
+            block_hash: H256::read_from(reader)?,
+            log_index: u64::read_from(reader)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_roundtrips_tx_meta() {
+        let meta = TxMeta {
+            block_number: 12,
+            timestamp: Some(1_600_000_000),
+            transaction_hash: H256::repeat_byte(1),
+            block_hash: H256::repeat_byte(2),
+            log_index: 3,
+        };
+        assert_eq!(
+            TxMeta::read_from(&mut meta.to_vec().as_slice()).unwrap(),
+            meta
+        );
+
+        let no_timestamp = TxMeta {
+            timestamp: None,
+            ..meta
+        };
+        assert_eq!(
+            TxMeta::read_from(&mut no_timestamp.to_vec().as_slice()).unwrap(),
+            no_timestamp
+        );
+    }
+}
@@ -1,5 +1,6 @@
 mod failure;
 mod messages;
+mod meta;
 mod update;
 
 /// Unified 32-byte identifier with convenience tooling for handling
@@ -8,4 +9,5 @@ pub mod identifiers;
 
 pub use failure::*;
 pub use messages::*;
+pub use meta::*;
 pub use update::*;
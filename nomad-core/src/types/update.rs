@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-use crate::{utils::home_domain_hash, Decode, Encode, NomadError, SignerExt};
+use crate::{utils::home_domain_hash, Decode, Encode, NomadError, SignerExt, TxMeta};
 use ethers::{
     prelude::{Address, Signature},
     types::H256,
@@ -11,7 +11,7 @@ use serde::{Deserialize, Serialize};
 use sha3::{Digest, Keccak256};
 
 /// An Nomad update message
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encode, Decode)]
 pub struct Update {
     /// The home chain
     pub home_domain: u32,
@@ -31,41 +31,6 @@ impl std::fmt::Display for Update {
     }
 }
 
-impl Encode for Update {
-    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
-    where
-        W: std::io::Write,
-    {
-        writer.write_all(&self.home_domain.to_be_bytes())?;
-        writer.write_all(self.previous_root.as_ref())?;
-        writer.write_all(self.new_root.as_ref())?;
-        Ok(4 + 32 + 32)
-    }
-}
-
-impl Decode for Update {
-    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
-    where
-        R: std::io::Read,
-        Self: Sized,
-    {
-        let mut home_domain = [0u8; 4];
-        reader.read_exact(&mut home_domain)?;
-
-        let mut previous_root = H256::zero();
-        reader.read_exact(previous_root.as_mut())?;
-
-        let mut new_root = H256::zero();
-        reader.read_exact(new_root.as_mut())?;
-
-        Ok(Self {
-            home_domain: u32::from_be_bytes(home_domain),
-            previous_root,
-            new_root,
-        })
-    }
-}
-
 impl Update {
     fn signing_hash(&self) -> H256 {
         // sign:
@@ -96,69 +61,17 @@ impl Update {
     }
 }
 
-/// Metadata stored about an update
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
-pub struct UpdateMeta {
-    /// Block number
-    pub block_number: u64,
-    /// Timestamp seconds (optional because fetching timestamp is fallible)
-    pub timestamp: Option<u64>,
-}
-
-impl Encode for UpdateMeta {
-    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
-    where
-        W: std::io::Write,
-    {
-        let mut written = 0;
-        written += self.block_number.write_to(writer)?;
-
-        if let Some(timestamp) = self.timestamp {
-            written += timestamp.write_to(writer)?;
-        } else {
-            written += 0_u64.write_to(writer)?;
-        }
-
-        Ok(written)
-    }
-}
-
-impl Decode for UpdateMeta {
-    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
-    where
-        R: std::io::Read,
-        Self: Sized,
-    {
-        let mut block_number = [0u8; 8];
-        let mut timestamp_bytes = [0u8; 8];
-        reader.read_exact(&mut block_number)?;
-        reader.read_exact(&mut timestamp_bytes)?;
-
-        let timestamp_num = u64::from_be_bytes(timestamp_bytes);
-        let timestamp = if timestamp_num == 0 {
-            None
-        } else {
-            Some(timestamp_num)
-        };
-
-        Ok(Self {
-            block_number: u64::from_be_bytes(block_number),
-            timestamp,
-        })
-    }
-}
-
 /// A Signed Nomad Update with Metadata
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct SignedUpdateWithMeta {
     /// Signed update
     pub signed_update: SignedUpdate,
     /// Metadata
-    pub metadata: UpdateMeta,
+    pub metadata: TxMeta,
 }
 
 /// A Signed Nomad Update
-#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encode, Decode)]
 pub struct SignedUpdate {
     /// The update
     pub update: Update,
@@ -175,30 +88,6 @@ impl Display for SignedUpdate {
     }
 }
 
-impl Encode for SignedUpdate {
-    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
-    where
-        W: std::io::Write,
-    {
-        let mut written = 0;
-        written += self.update.write_to(writer)?;
-        written += self.signature.write_to(writer)?;
-        Ok(written)
-    }
-}
-
-impl Decode for SignedUpdate {
-    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
-    where
-        R: std::io::Read,
-        Self: Sized,
-    {
-        let update = Update::read_from(reader)?;
-        let signature = Signature::read_from(reader)?;
-        Ok(Self { update, signature })
-    }
-}
-
 impl SignedUpdate {
     /// Recover the Ethereum address of the signer
     pub fn recover(&self) -> Result<Address, NomadError> {
@@ -0,0 +1,229 @@
+use ethers::{
+    types::{H256, U256},
+    utils::keccak256,
+};
+
+use crate::{Decode, Encode, NomadError};
+
+/// Type tag of transfer actions
+const TRANSFER: u8 = 3;
+/// Type tag of fast transfer actions
+const FAST_TRANSFER: u8 = 4;
+/// Type tag of actions announcing a token's details
+const DETAILS: u8 = 5;
+/// Type tag of actions requesting a token's details
+const REQUEST_DETAILS: u8 = 6;
+
+/// Length of the name and symbol fields of details actions
+const DETAILS_FIELD_LEN: usize = 32;
+
+/// A token, identified by its domain of origin and its address there
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct TokenId {
+    /// 4   Domain the token originates on
+    pub domain: u32,
+    /// 32  Address of the token on its origin domain
+    pub id: H256,
+}
+
+impl std::fmt::Display for TokenId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{:?}", self.domain, self.id)
+    }
+}
+
+/// Token metadata. Transfers carry a hash of the details rather than the
+/// details themselves. Encoded as in details actions: name and symbol
+/// right-padded with zeros to 32 bytes each, then decimals.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TokenDetails {
+    /// Token name
+    pub name: String,
+    /// Token symbol
+    pub symbol: String,
+    /// Token decimals
+    pub decimals: u8,
+}
+
+impl TokenDetails {
+    /// The hash transfers of the token commit to, as computed by the
+    /// bridge's `getDetailsHash`
+    pub fn hash(&self) -> H256 {
+        let mut preimage = vec![];
+        for field in [self.name.as_bytes(), self.symbol.as_bytes()].iter() {
+            let mut len = [0u8; 32];
+            U256::from(field.len()).to_big_endian(&mut len);
+            preimage.extend_from_slice(&len);
+            preimage.extend_from_slice(field);
+        }
+        preimage.push(self.decimals);
+        keccak256(preimage).into()
+    }
+}
+
+/// Write `field` right-padded with zeros to `DETAILS_FIELD_LEN` bytes
+fn write_details_field<W>(writer: &mut W, field: &str) -> std::io::Result<usize>
+where
+    W: std::io::Write,
+{
+    if field.len() > DETAILS_FIELD_LEN {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "Token details field {:?} is longer than {} bytes",
+                field, DETAILS_FIELD_LEN
+            ),
+        ));
+    }
+
+    let mut buf = [0u8; DETAILS_FIELD_LEN];
+    buf[..field.len()].copy_from_slice(field.as_bytes());
+    writer.write_all(&buf)?;
+    Ok(DETAILS_FIELD_LEN)
+}
+
+/// Read a field written by `write_details_field`, stripping the padding
+fn read_details_field<R>(reader: &mut R) -> Result<String, NomadError>
+where
+    R: std::io::Read,
+{
+    let mut buf = [0u8; DETAILS_FIELD_LEN];
+    reader.read_exact(&mut buf)?;
+
+    let len = buf.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    String::from_utf8(buf[..len].to_vec())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e).into())
+}
+
+impl Encode for TokenDetails {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        let mut written = write_details_field(writer, &self.name)?;
+        written += write_details_field(writer, &self.symbol)?;
+        writer.write_all(&[self.decimals])?;
+        Ok(written + 1)
+    }
+}
+
+impl Decode for TokenDetails {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+    {
+        let name = read_details_field(reader)?;
+        let symbol = read_details_field(reader)?;
+        let mut decimals = [0u8; 1];
+        reader.read_exact(&mut decimals)?;
+
+        Ok(Self {
+            name,
+            symbol,
+            decimals: decimals[0],
+        })
+    }
+}
+
+/// Tokens sent to a recipient on the destination domain
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Encode, Decode)]
+pub struct Transfer {
+    /// 32  Recipient on the destination domain
+    pub recipient: H256,
+    /// 32  Amount of tokens, in the token's smallest unit
+    pub amount: U256,
+    /// 32  Hash of the token's details. See [`TokenDetails::hash`].
+    pub details_hash: H256,
+}
+
+/// An action of the token bridge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BridgeAction {
+    /// Tokens sent to the recipient once the message is processed
+    Transfer(Transfer),
+    /// Tokens sent to the recipient, which liquidity providers may front
+    /// before the message is processed
+    FastTransfer(Transfer),
+    /// The token's details, sent by its domain of origin
+    Details(TokenDetails),
+    /// A request for the token's details, sent to its domain of origin
+    RequestDetails,
+}
+
+impl Encode for BridgeAction {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        match self {
+            Self::Transfer(transfer) => {
+                writer.write_all(&[TRANSFER])?;
+                Ok(1 + transfer.write_to(writer)?)
+            }
+            Self::FastTransfer(transfer) => {
+                writer.write_all(&[FAST_TRANSFER])?;
+                Ok(1 + transfer.write_to(writer)?)
+            }
+            Self::Details(details) => {
+                writer.write_all(&[DETAILS])?;
+                Ok(1 + details.write_to(writer)?)
+            }
+            Self::RequestDetails => {
+                writer.write_all(&[REQUEST_DETAILS])?;
+                Ok(1)
+            }
+        }
+    }
+}
+
+impl Decode for BridgeAction {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+    {
+        let mut tag = [0u8; 1];
+        reader.read_exact(&mut tag)?;
+
+        match tag[0] {
+            TRANSFER => Ok(Self::Transfer(Transfer::read_from(reader)?)),
+            FAST_TRANSFER => Ok(Self::FastTransfer(Transfer::read_from(reader)?)),
+            DETAILS => Ok(Self::Details(TokenDetails::read_from(reader)?)),
+            REQUEST_DETAILS => Ok(Self::RequestDetails),
+            tag => Err(NomadError::UnknownMessageType(tag)),
+        }
+    }
+}
+
+/// A message to the token bridge router: an action on a token
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct BridgeMessage {
+    /// 36  The token acted on
+    pub token: TokenId,
+    /// The action: 97 bytes for transfers, 66 for details and 1 for details
+    /// requests
+    pub action: BridgeAction,
+}
+
+impl std::fmt::Display for BridgeMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (kind, transfer) = match &self.action {
+            BridgeAction::Transfer(transfer) => ("Transfer", transfer),
+            BridgeAction::FastTransfer(transfer) => ("FastTransfer", transfer),
+            BridgeAction::Details(details) => {
+                return write!(
+                    f,
+                    "Details of token {}: {} ({}) with {} decimals",
+                    self.token, details.name, details.symbol, details.decimals
+                )
+            }
+            BridgeAction::RequestDetails => {
+                return write!(f, "RequestDetails of token {}", self.token)
+            }
+        };
+        write!(
+            f,
+            "{} of {} of token {} to {:?}",
+            kind, transfer.amount, self.token, transfer.recipient
+        )
+    }
+}
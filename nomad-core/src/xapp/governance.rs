@@ -0,0 +1,122 @@
+use ethers::{types::H256, utils::keccak256};
+use std::io::Read;
+
+use crate::{Decode, Encode, NomadError};
+
+/// Type tag of batch messages
+const BATCH: u8 = 1;
+/// Type tag of governor transfer messages
+const TRANSFER_GOVERNOR: u8 = 2;
+
+/// A call made by the governance router
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Call {
+    /// 32  Address called, in destination convention
+    pub to: H256,
+    /// 4+  Calldata, prefixed by its length
+    pub data: Vec<u8>,
+}
+
+impl Encode for Call {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        self.to.write_to(writer)?;
+        (self.data.len() as u32).write_to(writer)?;
+        writer.write_all(&self.data)?;
+        Ok(36 + self.data.len())
+    }
+}
+
+impl Decode for Call {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+    {
+        let to = H256::read_from(reader)?;
+        let len = u32::read_from(reader)? as usize;
+
+        // The length is untrusted, so only allocate for bytes actually read
+        let mut data = Vec::new();
+        Read::take(&mut *reader, len as u64).read_to_end(&mut data)?;
+        if data.len() != len {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        Ok(Self { to, data })
+    }
+}
+
+/// The hash a batch message commits to `calls` with, as computed by the
+/// governance router's `getBatchHash`
+pub fn batch_hash(calls: &[Call]) -> H256 {
+    let mut preimage = vec![calls.len() as u8];
+    for call in calls {
+        call.write_to(&mut preimage).expect("!alloc");
+    }
+    keccak256(preimage).into()
+}
+
+/// A message to the governance router
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GovernanceMessage {
+    /// A batch of calls, committed to by their hash. See [`batch_hash`].
+    /// The calls are executed once submitted on the destination.
+    Batch(H256),
+    /// Governorship moved to `governor` on `domain`
+    TransferGovernor {
+        /// Domain of the new governor
+        domain: u32,
+        /// Address of the new governor. Zero if the governor is on another
+        /// domain.
+        governor: H256,
+    },
+}
+
+impl Encode for GovernanceMessage {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        match self {
+            Self::Batch(batch_hash) => {
+                writer.write_all(&[BATCH])?;
+                Ok(1 + batch_hash.write_to(writer)?)
+            }
+            Self::TransferGovernor { domain, governor } => {
+                writer.write_all(&[TRANSFER_GOVERNOR])?;
+                Ok(1 + domain.write_to(writer)? + governor.write_to(writer)?)
+            }
+        }
+    }
+}
+
+impl Decode for GovernanceMessage {
+    fn read_from<R>(reader: &mut R) -> Result<Self, NomadError>
+    where
+        R: std::io::Read,
+    {
+        let mut tag = [0u8; 1];
+        reader.read_exact(&mut tag)?;
+
+        match tag[0] {
+            BATCH => Ok(Self::Batch(H256::read_from(reader)?)),
+            TRANSFER_GOVERNOR => Ok(Self::TransferGovernor {
+                domain: u32::read_from(reader)?,
+                governor: H256::read_from(reader)?,
+            }),
+            tag => Err(NomadError::UnknownMessageType(tag)),
+        }
+    }
+}
+
+impl std::fmt::Display for GovernanceMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Batch(batch_hash) => write!(f, "Batch {:?}", batch_hash),
+            Self::TransferGovernor { domain, governor } => {
+                write!(f, "TransferGovernor to {:?} on {}", governor, domain)
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use ethers::types::H256;
+use std::collections::HashSet;
+
+use crate::{Decode, Encode, NomadMessage};
+
+mod bridge;
+mod governance;
+
+pub use bridge::*;
+pub use governance::*;
+
+/// The body of a Nomad message, decoded according to its recipient
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MessageBody {
+    /// A message to a token bridge router
+    Bridge(BridgeMessage),
+    /// A message to a governance router
+    Governance(GovernanceMessage),
+    /// A message to any other recipient, or one that failed to decode
+    Raw(Vec<u8>),
+}
+
+impl Encode for MessageBody {
+    fn write_to<W>(&self, writer: &mut W) -> std::io::Result<usize>
+    where
+        W: std::io::Write,
+    {
+        match self {
+            Self::Bridge(message) => message.write_to(writer),
+            Self::Governance(message) => message.write_to(writer),
+            Self::Raw(body) => {
+                writer.write_all(body)?;
+                Ok(body.len())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for MessageBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bridge(message) => write!(f, "{}", message),
+            Self::Governance(message) => write!(f, "{}", message),
+            Self::Raw(body) => write!(f, "0x{}", hex::encode(body)),
+        }
+    }
+}
+
+/// Decode the whole of `body` as a `T`. `None` if it fails to decode or has
+/// trailing bytes.
+fn decode_exact<T: Decode>(body: &[u8]) -> Option<T> {
+    let mut reader = body;
+    T::read_from(&mut reader).ok().filter(|_| reader.is_empty())
+}
+
+/// Addresses of the xApp routers whose message bodies can be decoded
+#[derive(Debug, Default, Clone)]
+pub struct XAppRouters {
+    /// Token bridge routers
+    pub bridge: HashSet<H256>,
+    /// Governance routers
+    pub governance: HashSet<H256>,
+}
+
+impl XAppRouters {
+    /// Decode the body of a message sent to `recipient`. Bodies of messages
+    /// to unknown recipients, and those that do not decode, are kept raw.
+    pub fn decode_body(&self, recipient: H256, body: &[u8]) -> MessageBody {
+        let decoded = if self.bridge.contains(&recipient) {
+            decode_exact(body).map(MessageBody::Bridge)
+        } else if self.governance.contains(&recipient) {
+            decode_exact(body).map(MessageBody::Governance)
+        } else {
+            None
+        };
+        decoded.unwrap_or_else(|| MessageBody::Raw(body.to_vec()))
+    }
+
+    /// Decode the body of `message`
+    pub fn decode_message(&self, message: &NomadMessage) -> MessageBody {
+        self.decode_body(message.recipient, &message.body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::types::U256;
+
+    #[test]
+    fn it_decodes_bodies_by_recipient() {
+        let bridge_router = H256::repeat_byte(1);
+        let governance_router = H256::repeat_byte(2);
+        let routers = XAppRouters {
+            bridge: [bridge_router].iter().copied().collect(),
+            governance: [governance_router].iter().copied().collect(),
+        };
+
+        let details = TokenDetails {
+            name: "Token".to_owned(),
+            symbol: "TKN".to_owned(),
+            decimals: 18,
+        };
+        let transfer = BridgeMessage {
+            token: TokenId {
+                domain: 6648936,
+                id: H256::repeat_byte(3),
+            },
+            action: BridgeAction::Transfer(Transfer {
+                recipient: H256::repeat_byte(4),
+                amount: U256::exp10(18),
+                details_hash: details.hash(),
+            }),
+        };
+        let body = transfer.to_vec();
+        assert_eq!(body.len(), 36 + 97);
+        assert_eq!(
+            routers.decode_body(bridge_router, &body),
+            MessageBody::Bridge(transfer)
+        );
+
+        let calls = vec![Call {
+            to: H256::repeat_byte(5),
+            data: vec![1, 2, 3],
+        }];
+        let batch = GovernanceMessage::Batch(batch_hash(&calls));
+        assert_eq!(
+            routers.decode_body(governance_router, &batch.to_vec()),
+            MessageBody::Governance(batch)
+        );
+        let transfer_governor = GovernanceMessage::TransferGovernor {
+            domain: 1000,
+            governor: H256::repeat_byte(6),
+        };
+        assert_eq!(
+            routers.decode_body(governance_router, &transfer_governor.to_vec()),
+            MessageBody::Governance(transfer_governor)
+        );
+
+        // Unknown recipients, unknown types and trailing bytes stay raw
+        assert_eq!(
+            routers.decode_body(H256::zero(), &body),
+            MessageBody::Raw(body.clone())
+        );
+        let mut unknown_type = body.clone();
+        unknown_type[36] = 9;
+        assert_eq!(
+            routers.decode_body(bridge_router, &unknown_type),
+            MessageBody::Raw(unknown_type)
+        );
+        let trailing = [body.as_slice(), &[0u8][..]].concat();
+        assert_eq!(
+            routers.decode_body(bridge_router, &trailing),
+            MessageBody::Raw(trailing)
+        );
+    }
+
+    #[test]
+    fn it_round_trips_details_actions() {
+        let token = TokenId {
+            domain: 6648936,
+            id: H256::repeat_byte(3),
+        };
+        let details = BridgeMessage {
+            token,
+            action: BridgeAction::Details(TokenDetails {
+                name: "Token".to_owned(),
+                symbol: "TKN".to_owned(),
+                decimals: 18,
+            }),
+        };
+        let encoded = details.to_vec();
+        assert_eq!(encoded.len(), 36 + 66);
+        assert_eq!(
+            BridgeMessage::read_from(&mut encoded.as_slice()).unwrap(),
+            details
+        );
+        assert_eq!(
+            details.to_string(),
+            format!("Details of token {}: Token (TKN) with 18 decimals", token)
+        );
+
+        let request = BridgeMessage {
+            token,
+            action: BridgeAction::RequestDetails,
+        };
+        let encoded = request.to_vec();
+        assert_eq!(encoded.len(), 36 + 1);
+        assert_eq!(
+            BridgeMessage::read_from(&mut encoded.as_slice()).unwrap(),
+            request
+        );
+        assert_eq!(
+            request.to_string(),
+            format!("RequestDetails of token {}", token)
+        );
+
+        // Names and symbols must fit their 32 byte fields
+        let too_long = BridgeMessage {
+            token,
+            action: BridgeAction::Details(TokenDetails {
+                name: "T".repeat(33),
+                ..Default::default()
+            }),
+        };
+        assert!(too_long.write_to(&mut vec![]).is_err());
+    }
+
+    #[test]
+    fn it_rejects_truncated_calls() {
+        let call = Call {
+            to: H256::repeat_byte(1),
+            data: vec![1, 2, 3],
+        };
+        let encoded = call.to_vec();
+        assert_eq!(Call::read_from(&mut encoded.as_slice()).unwrap(), call);
+
+        // A huge length prefix errors instead of allocating for it
+        let mut truncated = encoded[..32].to_vec();
+        truncated.extend_from_slice(&u32::MAX.to_be_bytes());
+        truncated.extend_from_slice(&[1, 2, 3]);
+        assert!(Call::read_from(&mut truncated.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+//! Derive macros for the `Encode` and `Decode` traits of `nomad-core`.
+//!
+//! A derived encoding is the concatenation of the encodings of the struct's
+//! fields, in declaration order, with no length prefixes or padding. Each
+//! field must implement `Encode` (resp. `Decode`), except a last field marked
+//! `#[encode(trailing)]`. Trailing fields are raw bytes (e.g. `Vec<u8>`) that
+//! take up the rest of the encoding. Decoding them reads to the end of the
+//! reader, or errors past `max_len` bytes if given:
+//!
+//! ```ignore
+//! #[derive(Encode, Decode)]
+//! struct Committed {
+//!     leaf_index: u32,
+//!     #[encode(trailing, max_len = MAX_LEN)]
+//!     message: Vec<u8>,
+//! }
+//! ```
+
+#![warn(missing_docs)]
+#![forbid(unsafe_code)]
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    spanned::Spanned,
+    Data, DeriveInput, Expr, Ident, Index, Member, Token,
+};
+
+/// Derive `Encode` for a struct whose fields are encodable
+#[proc_macro_derive(Encode, attributes(encode))]
+pub fn derive_encode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_encode(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `Decode` for a struct whose fields are decodable
+#[proc_macro_derive(Decode, attributes(encode))]
+pub fn derive_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_decode(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// How a field is encoded
+enum Layout {
+    /// By its own `Encode` and `Decode` impls
+    Field,
+    /// As raw bytes taking up the rest of the encoding
+    Trailing {
+        /// Maximum length to decode
+        max_len: Option<Expr>,
+    },
+}
+
+/// A field of the derived struct
+struct Field {
+    member: Member,
+    name: String,
+    ty: syn::Type,
+    layout: Layout,
+    span: Span,
+}
+
+/// One item of an `#[encode(..)]` attribute: `trailing` or `max_len = <expr>`
+struct AttrItem {
+    key: Ident,
+    value: Option<Expr>,
+}
+
+impl Parse for AttrItem {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key = input.parse()?;
+        let value = if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        Ok(Self { key, value })
+    }
+}
+
+fn parse_layout(field: &syn::Field) -> syn::Result<Layout> {
+    let mut trailing = false;
+    let mut max_len = None;
+
+    for attr in field.attrs.iter().filter(|a| a.path.is_ident("encode")) {
+        let items = attr.parse_args_with(Punctuated::<AttrItem, Token![,]>::parse_terminated)?;
+        for item in items {
+            match (item.key.to_string().as_str(), item.value) {
+                ("trailing", None) => trailing = true,
+                ("max_len", Some(value)) => max_len = Some(value),
+                _ => {
+                    return Err(syn::Error::new(
+                        item.key.span(),
+                        "expected `trailing` or `max_len = <expr>`",
+                    ))
+                }
+            }
+        }
+    }
+
+    match (trailing, max_len) {
+        (true, max_len) => Ok(Layout::Trailing { max_len }),
+        (false, None) => Ok(Layout::Field),
+        (false, Some(max_len)) => Err(syn::Error::new(
+            max_len.span(),
+            "`max_len` only applies to `trailing` fields",
+        )),
+    }
+}
+
+/// The fields of a struct, in declaration order. Errors on enums and unions,
+/// and on trailing fields that are not last.
+fn parse_fields(input: &DeriveInput) -> syn::Result<Vec<Field>> {
+    let data = match &input.data {
+        Data::Struct(data) => data,
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "Encode and Decode can only be derived for structs",
+            ))
+        }
+    };
+
+    let fields = data
+        .fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let (member, name) = match &field.ident {
+                Some(ident) => (Member::Named(ident.clone()), ident.to_string()),
+                None => (Member::Unnamed(Index::from(i)), i.to_string()),
+            };
+            Ok(Field {
+                member,
+                name,
+                ty: field.ty.clone(),
+                layout: parse_layout(field)?,
+                span: field.span(),
+            })
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let last = fields.len().saturating_sub(1);
+    if let Some((_, field)) = fields
+        .iter()
+        .enumerate()
+        .find(|(i, f)| *i != last && matches!(f.layout, Layout::Trailing { .. }))
+    {
+        return Err(syn::Error::new(
+            field.span,
+            "only the last field can be `trailing`",
+        ));
+    }
+
+    Ok(fields)
+}
+
+fn expand_encode(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = parse_fields(&input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let writes = fields.iter().map(|field| {
+        let member = &field.member;
+        match field.layout {
+            Layout::Field => quote_spanned! {field.span=>
+                written += ::nomad_core::Encode::write_to(&self.#member, writer)?;
+            },
+            Layout::Trailing { .. } => quote_spanned! {field.span=>
+                let bytes: &[u8] = ::std::convert::AsRef::as_ref(&self.#member);
+                ::std::io::Write::write_all(writer, bytes)?;
+                written += bytes.len();
+            },
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::nomad_core::Encode for #name #ty_generics #where_clause {
+            fn write_to<W>(&self, writer: &mut W) -> ::std::io::Result<usize>
+            where
+                W: ::std::io::Write,
+            {
+                let mut written = 0;
+                #(#writes)*
+                Ok(written)
+            }
+        }
+    })
+}
+
+fn expand_decode(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = parse_fields(&input)?;
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    // Read into locals first, so fields are read in declaration order
+    let locals: Vec<_> = (0..fields.len())
+        .map(|i| format_ident!("field_{}", i))
+        .collect();
+    let reads = fields.iter().zip(&locals).map(|(field, local)| {
+        let ty = &field.ty;
+        match &field.layout {
+            Layout::Field => quote_spanned! {field.span=>
+                let #local = <#ty as ::nomad_core::Decode>::read_from(reader)?;
+            },
+            Layout::Trailing { max_len: None } => quote_spanned! {field.span=>
+                let mut bytes = ::std::vec::Vec::new();
+                ::std::io::Read::read_to_end(reader, &mut bytes)?;
+                let #local = ::std::convert::From::from(bytes);
+            },
+            Layout::Trailing {
+                max_len: Some(max_len),
+            } => {
+                let field_name = &field.name;
+                quote_spanned! {field.span=>
+                    let max: usize = #max_len;
+                    let mut bytes = ::std::vec::Vec::new();
+                    ::std::io::Read::read_to_end(
+                        &mut ::std::io::Read::take(&mut *reader, max as u64 + 1),
+                        &mut bytes,
+                    )?;
+                    if bytes.len() > max {
+                        return Err(::nomad_core::NomadError::EncodedFieldTooLong {
+                            field: #field_name,
+                            size: bytes.len(),
+                            max,
+                        });
+                    }
+                    let #local = ::std::convert::From::from(bytes);
+                }
+            }
+        }
+    });
+
+    let members = fields.iter().map(|field| &field.member);
+    let construct = match fields.first().map(|field| &field.member) {
+        Some(Member::Unnamed(_)) => quote! { Self(#(#locals),*) },
+        _ => quote! { Self { #(#members: #locals),* } },
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::nomad_core::Decode for #name #ty_generics #where_clause {
+            fn read_from<R>(reader: &mut R) -> ::std::result::Result<Self, ::nomad_core::NomadError>
+            where
+                R: ::std::io::Read,
+                Self: Sized,
+            {
+                #(#reads)*
+                Ok(#construct)
+            }
+        }
+    })
+}
@@ -33,6 +33,10 @@ mock! {
 
         pub fn _nonces(&self, destination: u32) -> Result<u32, ChainCommunicationError> {}
 
+        pub fn _version(&self) -> Result<MessageVersion, ChainCommunicationError> {}
+
+        pub fn _max_message_body_bytes(&self) -> Result<usize, ChainCommunicationError> {}
+
         pub fn _dispatch(&self, message: &Message) -> Result<TxOutcome, ChainCommunicationError> {}
 
         pub fn _queue_contains(&self, root: H256) -> Result<bool, ChainCommunicationError> {}
@@ -84,6 +88,14 @@ impl Home for MockHomeContract {
         self._nonces(destination)
     }
 
+    async fn version(&self) -> Result<MessageVersion, ChainCommunicationError> {
+        self._version()
+    }
+
+    async fn max_message_body_bytes(&self) -> Result<usize, ChainCommunicationError> {
+        self._max_message_body_bytes()
+    }
+
     async fn dispatch(&self, message: &Message) -> Result<TxOutcome, ChainCommunicationError> {
         self._dispatch(message)
     }
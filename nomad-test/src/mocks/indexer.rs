@@ -2,6 +2,7 @@
 
 use async_trait::async_trait;
 use color_eyre::Result;
+use ethers::core::types::H256;
 use mockall::*;
 
 use nomad_core::*;
@@ -10,9 +11,11 @@ mock! {
     pub Indexer {
         pub fn _get_block_number(&self) -> Result<u32> {}
 
+        pub fn _get_block_hash(&self, height: u32) -> Result<Option<H256>> {}
+
         pub fn _fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {}
 
-        pub fn _fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessage>> {}
+        pub fn _fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessageWithMeta>> {}
     }
 }
 
@@ -28,6 +31,10 @@ impl CommonIndexer for MockIndexer {
         self._get_block_number()
     }
 
+    async fn get_block_hash(&self, height: u32) -> Result<Option<H256>> {
+        self._get_block_hash(height)
+    }
+
     async fn fetch_sorted_updates(&self, from: u32, to: u32) -> Result<Vec<SignedUpdateWithMeta>> {
         self._fetch_sorted_updates(from, to)
     }
@@ -35,7 +42,11 @@ impl CommonIndexer for MockIndexer {
 
 #[async_trait]
 impl HomeIndexer for MockIndexer {
-    async fn fetch_sorted_messages(&self, from: u32, to: u32) -> Result<Vec<RawCommittedMessage>> {
+    async fn fetch_sorted_messages(
+        &self,
+        from: u32,
+        to: u32,
+    ) -> Result<Vec<RawCommittedMessageWithMeta>> {
         self._fetch_sorted_messages(from, to)
     }
 }
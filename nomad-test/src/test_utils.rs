@@ -1,5 +1,5 @@
 use futures_util::FutureExt;
-use nomad_core::db::DB;
+use nomad_core::db::{RocksStorage, DB};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use std::{future::Future, panic};
@@ -8,15 +8,23 @@ use rocksdb::Options;
 
 /// Sets up a db
 pub fn setup_db(db_path: String) -> DB {
-    let mut opts = Options::default();
-    opts.create_if_missing(true);
-    rocksdb::DB::open(&opts, db_path)
+    RocksStorage::open(db_path)
         .expect("Failed to open db path")
         .into()
 }
 
-/// Runs test for a db
+/// Runs test for a db kept in memory
 pub async fn run_test_db<T, Fut>(test: T)
+where
+    T: FnOnce(DB) -> Fut + panic::UnwindSafe,
+    Fut: Future<Output = ()>,
+{
+    let func = panic::AssertUnwindSafe(async { test(DB::in_memory()).await });
+    assert!(func.catch_unwind().await.is_ok())
+}
+
+/// Runs test for a db backed by RocksDB
+pub async fn run_test_rocks_db<T, Fut>(test: T)
 where
     T: FnOnce(DB) -> Fut + panic::UnwindSafe,
     Fut: Future<Output = ()>,
@@ -148,6 +148,8 @@ async fn mainnet_works() {
                     url: "wss://main-light.eth.linkpool.io/ws".into(),
                 }),
                 address: "0xcEc158A719d11005Bd9339865965bed938BEafA3".into(),
+                gas: None,
+                tx_manager: None,
                 disabled: None,
             }],
         },
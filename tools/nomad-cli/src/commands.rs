@@ -1,6 +1,9 @@
 use structopt::StructOpt;
 
-use crate::subcommands::{db_state::DbStateCommand, prove::ProveCommand};
+use crate::subcommands::{
+    db_state::DbStateCommand, prove::ProveCommand, restore::RestoreCommand,
+    snapshot::SnapshotCommand, verify_updates::VerifyUpdatesCommand,
+};
 
 #[derive(StructOpt)]
 pub enum Commands {
@@ -8,4 +11,11 @@ pub enum Commands {
     Prove(ProveCommand),
     /// Print the processor's db state
     DbState(DbStateCommand),
+    /// Have a running agent write a validated snapshot of its db
+    Snapshot(SnapshotCommand),
+    /// Restore an agent db from a snapshot, validating it first
+    Restore(RestoreCommand),
+    /// Check with consistency proofs that the home's stored updates only
+    /// appended the stored leaves
+    VerifyUpdates(VerifyUpdatesCommand),
 }
@@ -15,5 +15,8 @@ async fn main() -> Result<()> {
     match command {
         Commands::Prove(prove) => prove.run().await,
         Commands::DbState(db_state) => db_state.run().await,
+        Commands::Snapshot(snapshot) => snapshot.run().await,
+        Commands::Restore(restore) => restore.run().await,
+        Commands::VerifyUpdates(verify_updates) => verify_updates.run().await,
     }
 }
@@ -6,6 +6,8 @@ use structopt::StructOpt;
 use nomad_base::NomadDB;
 use nomad_core::{db::DB, CommittedMessage};
 
+use super::read_mode;
+
 use ethers::types::H256;
 
 #[derive(StructOpt, Debug)]
@@ -14,6 +16,12 @@ pub struct DbStateCommand {
     #[structopt(long)]
     db_path: String,
 
+    /// Open the db as a RocksDB secondary instance keeping its logs at this
+    /// path, instead of read-only. Secondary instances see the agent's
+    /// latest writes.
+    #[structopt(long)]
+    secondary_path: Option<String>,
+
     /// Name of associated home
     #[structopt(long)]
     home_name: String,
@@ -27,7 +35,10 @@ type OutputVec = Vec<((H256, u64), Vec<CommittedMessage>)>;
 
 impl DbStateCommand {
     pub async fn run(&self) -> Result<()> {
-        let db = NomadDB::new(&self.home_name, DB::from_path(&self.db_path)?);
+        let db = NomadDB::new(
+            &self.home_name,
+            DB::from_path_with_mode(&self.db_path, read_mode(&self.secondary_path))?,
+        );
 
         let messages_by_committed_roots = self.create_comitted_root_to_message_map(&db)?;
 
@@ -47,33 +58,23 @@ impl DbStateCommand {
         db: &NomadDB,
     ) -> Result<HashMap<H256, Vec<CommittedMessage>>> {
         let mut messages_by_committed_roots: HashMap<H256, Vec<CommittedMessage>> = HashMap::new();
-        for index in 0.. {
-            match db.message_by_leaf_index(index)? {
-                Some(message) => {
-                    if db.proof_by_leaf_index(index)?.is_none() {
-                        println!("Failed to find proof for leaf index {}!", index);
-                    }
-
-                    let committed_root = message.committed_root;
-                    let bucket_opt = messages_by_committed_roots.get_mut(&committed_root);
-
-                    // Get reference to bucket for committed root
-                    let bucket = match bucket_opt {
-                        Some(bucket) => bucket,
-                        None => {
-                            messages_by_committed_roots
-                                .insert(committed_root, Vec::<CommittedMessage>::new());
-                            messages_by_committed_roots
-                                .get_mut(&committed_root)
-                                .unwrap()
-                        }
-                    };
-
-                    // Add message to bucket for committed root
-                    bucket.push(message.try_into()?);
-                }
-                None => break,
+        for message in db.messages_by_leaf_index(..) {
+            let message = message?;
+            // Pruned proofs can be regenerated
+            if db.proof_by_leaf_index(message.leaf_index)?.is_none()
+                && !db.is_pruned(message.leaf_index)?
+            {
+                println!(
+                    "Failed to find proof for leaf index {}!",
+                    message.leaf_index
+                );
             }
+
+            // Add message to bucket for committed root
+            messages_by_committed_roots
+                .entry(message.committed_root)
+                .or_default()
+                .push(message.try_into()?);
         }
 
         Ok(messages_by_committed_roots)
@@ -84,31 +85,25 @@ impl DbStateCommand {
         db: &NomadDB,
         messages_by_committed_roots: HashMap<H256, Vec<CommittedMessage>>,
     ) -> Result<OutputVec> {
-        // Create mapping of (update root, block_number) to [messages]
-        let mut output_map: HashMap<(H256, u64), Vec<CommittedMessage>> = HashMap::new();
+        // Pair each bucket with the update off its committed root. Looked up
+        // per bucket, as the db may have been indexed from after the first
+        // update.
+        let mut output_vec = OutputVec::new();
         for (committed_root, bucket) in messages_by_committed_roots {
-            let containing_update_opt = db.update_by_previous_root(committed_root)?;
-
-            match containing_update_opt {
-                Some(containing_update) => {
-                    let new_root = containing_update.update.new_root;
-                    let update_metadata =
-                        db.retrieve_update_metadata(new_root)?.unwrap_or_else(|| {
-                            panic!("Couldn't find metadata for update {:?}", containing_update)
-                        });
-
-                    output_map.insert((new_root, update_metadata.block_number), bucket);
-                }
-                // No more updates left
-                None => break,
-            }
-        }
+            let update = match db.update_by_previous_root(committed_root)? {
+                Some(update) => update,
+                None => continue,
+            };
 
-        // Convert hashmap into vector of k,v pairs and sort the entries by
-        // update block number
-        let mut output_vec: Vec<_> = output_map.into_iter().collect();
-        output_vec.sort_by(|x, y| x.0 .1.cmp(&y.0 .1));
+            let new_root = update.update.new_root;
+            let update_metadata = db
+                .retrieve_update_metadata(new_root)?
+                .unwrap_or_else(|| panic!("Couldn't find metadata for update {:?}", update));
+
+            output_vec.push(((new_root, update_metadata.block_number), bucket));
+        }
 
+        output_vec.sort_by_key(|((_, block_number), _)| *block_number);
         Ok(output_vec)
     }
 
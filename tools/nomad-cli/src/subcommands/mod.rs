@@ -1,5 +1,24 @@
 pub mod db_state;
 pub mod prove;
+pub mod restore;
+pub mod snapshot;
+pub mod verify_updates;
 
 pub use db_state::*;
 pub use prove::*;
+pub use restore::*;
+pub use snapshot::*;
+pub use verify_updates::*;
+
+use nomad_core::db::OpenMode;
+
+/// Open agent dbs read-only, or as a secondary instance keeping its logs in
+/// `secondary_path` if given, so tooling can read a running agent's db
+fn read_mode(secondary_path: &Option<String>) -> OpenMode {
+    match secondary_path {
+        Some(path) => OpenMode::Secondary {
+            secondary_path: path.into(),
+        },
+        None => OpenMode::ReadOnly,
+    }
+}
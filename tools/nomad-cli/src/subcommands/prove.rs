@@ -3,9 +3,11 @@ use structopt::StructOpt;
 
 use crate::{replicas, rpc};
 
+use super::read_mode;
+
 use nomad_core::{
-    accumulator::merkle::Proof, db::DB, ContractLocator, Decode, MessageStatus, NomadMessage,
-    Replica, Signers,
+    accumulator::merkle::Proof, db::DB, xapp::XAppRouters, ContractLocator, MessageStatus,
+    NomadMessage, Replica, Signers,
 };
 
 use nomad_base::NomadDB;
@@ -45,6 +47,12 @@ pub struct ProveCommand {
     #[structopt(long)]
     db_path: String,
 
+    /// Open the db as a RocksDB secondary instance keeping its logs at this
+    /// path, instead of read-only. Secondary instances see the agent's
+    /// latest writes.
+    #[structopt(long)]
+    secondary_path: Option<String>,
+
     /// HexKey to use (please be careful)
     #[structopt(long)]
     key: Option<String>,
@@ -64,12 +72,28 @@ pub struct ProveCommand {
     /// RPC connection details
     #[structopt(long)]
     rpc: Option<String>,
+
+    /// Token bridge router whose message bodies are decoded. Can be repeated.
+    #[structopt(long)]
+    bridge_router: Vec<H256>,
+
+    /// Governance router whose message bodies are decoded. Can be repeated.
+    #[structopt(long)]
+    governance_router: Vec<H256>,
 }
 
 impl ProveCommand {
     pub async fn run(&self) -> Result<()> {
-        let db = NomadDB::new(&self.home_name, DB::from_path(&self.db_path)?);
+        let db = NomadDB::new(
+            &self.home_name,
+            DB::from_path_with_mode(&self.db_path, read_mode(&self.secondary_path))?,
+        );
         let (message, proof) = self.fetch_proof(db)?;
+        let routers = XAppRouters {
+            bridge: self.bridge_router.iter().copied().collect(),
+            governance: self.governance_router.iter().copied().collect(),
+        };
+        println!("Message body: {}", routers.decode_message(&message));
         let replica = self.replica(message.origin, message.destination).await?;
 
         let status = replica.message_status(message.to_leaf()).await?;
@@ -121,9 +145,20 @@ impl ProveCommand {
             (None, None) => bail!("Must provide leaf index or leaf hash"),
         };
 
-        let proof = db.proof_by_leaf_index(idx)?.expect("no proof");
-        let message = db.message_by_leaf_index(idx)?.expect("no message");
-        let message = NomadMessage::read_from(&mut message.message.as_slice())?;
+        let proof = match db.proof_by_leaf_index(idx)? {
+            Some(proof) => proof,
+            // Pruned by the processor's retention policy
+            None if db.is_pruned(idx)? => db.regenerate_proof(idx)?,
+            None => bail!("No proof found for leaf index {}", idx),
+        };
+        let message = match db.message_by_leaf_index(idx)? {
+            Some(message) => message,
+            None if db.is_pruned(idx)? => {
+                bail!("Message body at leaf index {} was pruned", idx)
+            }
+            None => bail!("No message found for leaf index {}", idx),
+        };
+        let message = message.message_ref()?.to_message();
 
         Ok((message, proof))
     }
@@ -0,0 +1,34 @@
+use color_eyre::Result;
+use structopt::StructOpt;
+
+use nomad_base::restore_snapshot;
+
+#[derive(StructOpt, Debug)]
+pub struct RestoreCommand {
+    /// Path to the snapshot to restore
+    #[structopt(long)]
+    snapshot_path: String,
+
+    /// Path to restore the agent db to. Must not exist.
+    #[structopt(long)]
+    db_path: String,
+
+    /// Name of the home, used to validate the snapshot
+    #[structopt(long)]
+    home_name: String,
+
+    /// Names of the replicas, used to validate the snapshot
+    #[structopt(long)]
+    replica_names: Vec<String>,
+}
+
+impl RestoreCommand {
+    pub async fn run(&self) -> Result<()> {
+        let mut entities = vec![self.home_name.clone()];
+        entities.extend(self.replica_names.iter().cloned());
+
+        let restored = restore_snapshot(&self.snapshot_path, &self.db_path, &entities)?;
+        println!("{}", serde_json::to_string_pretty(&restored)?);
+        Ok(())
+    }
+}
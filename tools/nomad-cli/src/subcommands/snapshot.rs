@@ -0,0 +1,31 @@
+use color_eyre::{eyre::bail, Result};
+use serde_json::Value;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct SnapshotCommand {
+    /// Url of the agent's admin API, e.g. `http://localhost:9090`. The agent
+    /// must have snapshots enabled and writes the snapshot to its snapshot
+    /// directory.
+    #[structopt(long)]
+    admin_url: String,
+}
+
+impl SnapshotCommand {
+    pub async fn run(&self) -> Result<()> {
+        // The agent owning the db takes the snapshot. RocksDB can't
+        // checkpoint a db opened read-only, and opening it read-write would
+        // take its lock and migrate it.
+        let url = format!("{}/admin/snapshot", self.admin_url.trim_end_matches('/'));
+        let res = reqwest::Client::new().post(&url).send().await?;
+
+        let status = res.status();
+        let report: Value = serde_json::from_str(&res.text().await?)?;
+        if !status.is_success() {
+            bail!("Agent failed to snapshot its db ({}): {}", status, report);
+        }
+
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        Ok(())
+    }
+}
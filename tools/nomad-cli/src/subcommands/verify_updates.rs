@@ -0,0 +1,102 @@
+use color_eyre::{eyre::bail, Result};
+use structopt::StructOpt;
+
+use ethers::types::H256;
+use nomad_base::NomadDB;
+use nomad_core::{
+    accumulator::{
+        merkle::{verify_consistency_proof, MerkleTree},
+        TREE_DEPTH,
+    },
+    db::DB,
+};
+
+use super::read_mode;
+
+#[derive(StructOpt, Debug)]
+pub struct VerifyUpdatesCommand {
+    /// Path to the agent db
+    #[structopt(long)]
+    db_path: String,
+
+    /// Open the db as a RocksDB secondary instance keeping its logs at this
+    /// path, instead of read-only. Secondary instances see the agent's
+    /// latest writes.
+    #[structopt(long)]
+    secondary_path: Option<String>,
+
+    /// Name of the home whose updates are verified
+    #[structopt(long)]
+    home_name: String,
+}
+
+/// Root of `tree` as the Home reports it. The empty tree's root is zero.
+fn committed_root(tree: &MerkleTree, count: usize) -> H256 {
+    if count == 0 {
+        H256::zero()
+    } else {
+        tree.hash()
+    }
+}
+
+impl VerifyUpdatesCommand {
+    /// Walk the home's update chain, rebuilding the tree from the stored
+    /// leaves, and check each update with a consistency proof that it only
+    /// appended leaves
+    pub async fn run(&self) -> Result<()> {
+        let db = NomadDB::new(
+            &self.home_name,
+            DB::from_path_with_mode(&self.db_path, read_mode(&self.secondary_path))?,
+        );
+
+        let mut tree = MerkleTree::create(&[], TREE_DEPTH);
+        let mut count = 0;
+        let mut leaves = db.leaves_by_leaf_index(..);
+
+        for update in db.updates_from(H256::zero()) {
+            let update = update?.update;
+            if update.previous_root != committed_root(&tree, count) {
+                bail!(
+                    "Update to {:?} does not build on the local root {:?}",
+                    update.new_root,
+                    committed_root(&tree, count)
+                );
+            }
+
+            let (old_root, old_count) = (tree.hash(), count);
+            while committed_root(&tree, count) != update.new_root {
+                match leaves.next().transpose()? {
+                    Some((leaf_index, leaf)) if leaf_index as usize == count => {
+                        tree.push_leaf(leaf, TREE_DEPTH)?;
+                        count += 1;
+                    }
+                    _ => bail!(
+                        "No leaf at index {} to reach the root {:?} of the update from {:?}",
+                        count,
+                        update.new_root,
+                        update.previous_root
+                    ),
+                }
+            }
+            if count == old_count {
+                bail!("Update to {:?} appends no leaves", update.new_root);
+            }
+
+            let proof = tree.generate_consistency_proof(old_count);
+            if !verify_consistency_proof(old_root, old_count, tree.hash(), count, &proof) {
+                bail!(
+                    "Update from {:?} to {:?} is not append-only",
+                    update.previous_root,
+                    update.new_root
+                );
+            }
+
+            println!(
+                "Verified update {:?} -> {:?}: leaves {}..{}",
+                update.previous_root, update.new_root, old_count, count
+            );
+        }
+
+        Ok(())
+    }
+}